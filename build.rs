@@ -0,0 +1,5 @@
+fn main() {
+    // Link flags the Node-API addon needs (see `src/node.rs`)
+    #[cfg(feature = "napi")]
+    napi_build::setup();
+}
@@ -0,0 +1,90 @@
+//! geofront/src/acceptors.rs
+//! Listeners accepting on several sockets bound with `SO_REUSEPORT`
+//! (`ListenerOptions.acceptors`, Linux only), so the kernel spreads incoming
+//! connections over accept loops running on different runtime workers
+//! instead of funnelling them through one. Accepts are counted per acceptor
+//! in the listener metrics to check the balance.
+
+use crate::{
+    connection,
+    transport::ListenerTransport,
+    types::{ListenerOptions, ProxyListener},
+};
+use std::{io, sync::Arc};
+use tokio::{net::TcpListener, task::JoinSet};
+#[cfg(not(target_os = "linux"))]
+use tracing::warn;
+
+/// Pending connections queued per acceptor socket.
+#[cfg(target_os = "linux")]
+const BACKLOG: u32 = 1024;
+
+/// Binds `addr:port` once per acceptor; a single plain socket unless
+/// `acceptors` asks for more.
+pub async fn bind(addr: &str, port: u16, acceptors: Option<usize>) -> io::Result<Vec<TcpListener>> {
+    let count = acceptors.unwrap_or(1).max(1);
+    if count == 1 {
+        return Ok(vec![TcpListener::bind((addr, port)).await?]);
+    }
+    bind_reuseport(addr, port, count).await
+}
+
+#[cfg(target_os = "linux")]
+async fn bind_reuseport(addr: &str, port: u16, count: usize) -> io::Result<Vec<TcpListener>> {
+    let Some(local) = tokio::net::lookup_host((addr, port)).await?.next() else {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("{} resolves to no address", addr),
+        ));
+    };
+    // Every socket must be bound to the same address, including the port
+    // the first one was given when asked for port 0.
+    let mut local = local;
+    let mut listeners = Vec::with_capacity(count);
+    for _ in 0..count {
+        let socket = if local.is_ipv4() {
+            tokio::net::TcpSocket::new_v4()?
+        } else {
+            tokio::net::TcpSocket::new_v6()?
+        };
+        socket.set_reuseaddr(true)?;
+        socket.set_reuseport(true)?;
+        socket.bind(local)?;
+        let listener = socket.listen(BACKLOG)?;
+        local = listener.local_addr()?;
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn bind_reuseport(addr: &str, port: u16, count: usize) -> io::Result<Vec<TcpListener>> {
+    warn!(acceptors = count, "SO_REUSEPORT acceptors need Linux, using one");
+    Ok(vec![TcpListener::bind((addr, port)).await?])
+}
+
+/// Runs an accept loop per socket, each as its own task, until the listener
+/// is stopped; aborting the returned future aborts them all.
+pub async fn serve(
+    listener_id: ProxyListener,
+    mut listeners: Vec<TcpListener>,
+    transport: ListenerTransport,
+    options: Arc<ListenerOptions>,
+) {
+    if listeners.len() == 1
+        && let Some(listener) = listeners.pop()
+    {
+        return connection::serve(listener_id, 0, listener, transport, options).await;
+    }
+    let mut loops = JoinSet::new();
+    for (acceptor, listener) in listeners.into_iter().enumerate() {
+        loops.spawn(connection::serve(
+            listener_id,
+            acceptor,
+            listener,
+            transport.clone(),
+            options.clone(),
+        ));
+    }
+    while loops.join_next().await.is_some() {}
+}
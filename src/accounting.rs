@@ -0,0 +1,164 @@
+//! geofront/src/accounting.rs
+//! Bandwidth accounted per authenticated username and per peer IP across
+//! connections (`proxy_get_usage`), so hosts can enforce quotas without
+//! summing disconnection events themselves. Closed connections are folded
+//! in under the `LISTENER_TOTALS` lock and live ones are added when queried,
+//! as for the listener totals. Keys idle for longer than
+//! `accounting.retentionMs` are dropped.
+
+use crate::{
+    events,
+    state::{ACCOUNTING, CONN_INFO, CONN_METRICS, LISTENER_TOTALS, OPTIONS},
+    types::{ConnInfo, USAGE_BY_IP, USAGE_BY_USERNAME, UsageKey},
+};
+use serde::Serialize;
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+/// How long a key is kept after its last connection closed, by default.
+pub const DEFAULT_RETENTION_MS: u64 = 24 * 60 * 60 * 1000;
+/// Closing connections prune idle keys at most this often.
+const PRUNE_INTERVAL_MS: u64 = 60_000;
+
+/// Traffic of one username or IP; bytes include its live connections.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    /// Connections closed or still open.
+    pub connections: u64,
+    pub active: u64,
+    /// When the last connection closed, or now while one is open.
+    pub last_seen_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Accounting {
+    users: HashMap<String, Usage>,
+    ips: HashMap<String, Usage>,
+    last_pruned_ms: u64,
+}
+
+impl Accounting {
+    fn prune(&mut self, now_ms: u64) {
+        let cutoff = now_ms.saturating_sub(retention_ms());
+        self.users.retain(|_, usage| usage.last_seen_ms >= cutoff);
+        self.ips.retain(|_, usage| usage.last_seen_ms >= cutoff);
+        self.last_pruned_ms = now_ms;
+    }
+}
+
+fn retention_ms() -> u64 {
+    OPTIONS
+        .read()
+        .unwrap()
+        .accounting
+        .as_ref()
+        .map_or(DEFAULT_RETENTION_MS, |config| config.retention_ms)
+}
+
+fn add(usage: &mut Usage, bytes_sent: u64, bytes_recv: u64, now_ms: u64) {
+    usage.bytes_sent += bytes_sent;
+    usage.bytes_recv += bytes_recv;
+    usage.connections += 1;
+    usage.last_seen_ms = now_ms;
+}
+
+/// Folds a closed connection into the usage of its username and IP. Called
+/// from `cleanup_conn` with `LISTENER_TOTALS` held.
+pub fn release(info: &ConnInfo, bytes_sent: u64, bytes_recv: u64) {
+    let now_ms = events::now_ms();
+    let mut accounting = ACCOUNTING.lock().unwrap();
+    if let Some(username) = &info.username {
+        let usage = accounting.users.entry(username.clone()).or_default();
+        add(usage, bytes_sent, bytes_recv, now_ms);
+    }
+    if !info.peer_ip.is_empty() {
+        let usage = accounting.ips.entry(info.peer_ip.clone()).or_default();
+        add(usage, bytes_sent, bytes_recv, now_ms);
+    }
+    if now_ms.saturating_sub(accounting.last_pruned_ms) >= PRUNE_INTERVAL_MS {
+        accounting.prune(now_ms);
+    }
+}
+
+/// Bytes of the closed connections of `username` still retained.
+pub fn closed_bytes_of_user(username: &str) -> u64 {
+    ACCOUNTING
+        .lock()
+        .unwrap()
+        .users
+        .get(username)
+        .map_or(0, |usage| usage.bytes_sent + usage.bytes_recv)
+}
+
+/// Usage keyed by username (`USAGE_BY_USERNAME`) or by IP (`USAGE_BY_IP`),
+/// including live connections; `None` for an unknown key type.
+pub fn usage(key: UsageKey) -> Option<HashMap<String, Usage>> {
+    if key != USAGE_BY_USERNAME && key != USAGE_BY_IP {
+        return None;
+    }
+    let now_ms = events::now_ms();
+    // Taken first, as `cleanup_conn` does, so closing connections' bytes
+    // are counted exactly once.
+    let _listener_totals = LISTENER_TOTALS.lock().unwrap();
+    let mut totals = {
+        let mut accounting = ACCOUNTING.lock().unwrap();
+        accounting.prune(now_ms);
+        if key == USAGE_BY_USERNAME {
+            accounting.users.clone()
+        } else {
+            accounting.ips.clone()
+        }
+    };
+    for entry in CONN_METRICS.iter() {
+        let Some(info) = CONN_INFO.get(entry.key()) else {
+            continue;
+        };
+        let name = if key == USAGE_BY_USERNAME {
+            info.username.as_ref()
+        } else {
+            Some(&info.peer_ip).filter(|ip| !ip.is_empty())
+        };
+        if let Some(name) = name {
+            let usage = totals.entry(name.clone()).or_default();
+            add(
+                usage,
+                entry.bytes_sent.load(Ordering::SeqCst),
+                entry.bytes_recv.load(Ordering::SeqCst),
+                now_ms,
+            );
+            usage.active += 1;
+        }
+    }
+    Some(totals)
+}
+
+/// Forgets all usage.
+pub fn reset() {
+    *ACCOUNTING.lock().unwrap() = Accounting::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_by_username_and_ip() {
+        let info = ConnInfo {
+            peer_ip: "198.51.100.77".to_string(),
+            username: Some("AccountingTest".to_string()),
+            ..Default::default()
+        };
+        release(&info, 100, 40);
+        release(&ConnInfo { username: None, ..info.clone() }, 5, 5);
+
+        let users = usage(USAGE_BY_USERNAME).unwrap();
+        let user = &users["AccountingTest"];
+        assert_eq!((user.bytes_sent, user.bytes_recv, user.connections), (100, 40, 1));
+        let ips = usage(USAGE_BY_IP).unwrap();
+        let ip = &ips["198.51.100.77"];
+        assert_eq!((ip.bytes_sent, ip.bytes_recv, ip.connections), (105, 45, 2));
+        assert!(usage(7).is_none());
+    }
+}
@@ -0,0 +1,350 @@
+//! geofront/src/audit_db.rs
+//! Embedded SQLite history of completed connections (`sqlite` feature).
+
+use crate::{
+    events::ProxyEvent,
+    state::AUDIT_SINK,
+    types::{AuditDbConfig, AuditQuery},
+};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::error;
+
+/// One row of the `sessions` table.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub conn_id: u64,
+    pub peer_ip: String,
+    pub username: Option<String>,
+    pub host: Option<String>,
+    pub protocol: Option<i32>,
+    pub backend: Option<String>,
+    pub proxy: Option<String>,
+    pub connected_at_ms: u64,
+    pub closed_at_ms: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub reason: String,
+    /// `metadata` of the routing decision.
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Opens (or closes, when `config` is `None`) the audit database.
+pub fn configure(config: Option<&AuditDbConfig>) {
+    *AUDIT_SINK.write().unwrap() = config.and_then(start);
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn start(_config: &AuditDbConfig) -> Option<mpsc::Sender<ProxyEvent>> {
+    error!("Audit database is not available: geofront was built without the `sqlite` feature");
+    None
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn query(_config: &AuditDbConfig, _filter: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+    Err("geofront was built without the `sqlite` feature".to_string())
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::{query, start};
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use crate::events;
+    use crate::types::DisconnectReason;
+    use rusqlite::{Connection, OpenFlags, params, params_from_iter, types::Value};
+    use std::time::{Duration, Instant};
+    use tracing::{info, warn};
+
+    /// Events buffered between connection tasks and the writer thread.
+    const WRITE_BUFFER: usize = 8192;
+    /// Rows inserted per transaction at most.
+    const MAX_BATCH: usize = 512;
+    /// How often retention rules are applied.
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+    /// Upper bound for `limit` in queries.
+    const MAX_QUERY_ROWS: u32 = 10_000;
+
+    const SCHEMA: &str = "
+        PRAGMA journal_mode = WAL;
+        CREATE TABLE IF NOT EXISTS sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            conn_id INTEGER NOT NULL,
+            peer_ip TEXT NOT NULL,
+            username TEXT,
+            host TEXT,
+            protocol INTEGER,
+            backend TEXT,
+            proxy TEXT,
+            connected_at_ms INTEGER NOT NULL,
+            closed_at_ms INTEGER NOT NULL,
+            bytes_sent INTEGER NOT NULL,
+            bytes_recv INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            metadata TEXT
+        );
+        CREATE INDEX IF NOT EXISTS sessions_closed_at ON sessions (closed_at_ms);
+        CREATE INDEX IF NOT EXISTS sessions_username ON sessions (username);
+        CREATE INDEX IF NOT EXISTS sessions_peer_ip ON sessions (peer_ip);
+    ";
+
+    pub fn start(config: &AuditDbConfig) -> Option<mpsc::Sender<ProxyEvent>> {
+        spawn(config).map(|(tx, _)| tx)
+    }
+
+    /// Opens the database and starts its writer thread, which finishes once
+    /// every sender is dropped and the queued events are written.
+    pub(super) fn spawn(config: &AuditDbConfig) -> Option<(mpsc::Sender<ProxyEvent>, std::thread::JoinHandle<()>)> {
+        let conn = match Connection::open(&config.path).and_then(|c| {
+            c.execute_batch(SCHEMA)?;
+            migrate(&c)?;
+            Ok(c)
+        }) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(path = %config.path, "Failed to open audit database: {}", e);
+                return None;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(WRITE_BUFFER);
+        let config = config.clone();
+        let spawned = std::thread::Builder::new()
+            .name("geofront-audit".to_string())
+            .spawn(move || writer_loop(conn, rx, config));
+        let writer = match spawned {
+            Ok(writer) => writer,
+            Err(e) => {
+                error!("Failed to start audit writer thread: {}", e);
+                return None;
+            }
+        };
+        info!("Audit database enabled");
+        Some((tx, writer))
+    }
+
+    /// Drains events into the database until the sender side is dropped.
+    fn writer_loop(mut conn: Connection, mut rx: mpsc::Receiver<ProxyEvent>, config: AuditDbConfig) {
+        let mut last_prune = Instant::now();
+        prune(&conn, &config);
+        while let Some(first) = rx.blocking_recv() {
+            let mut batch = vec![first];
+            while batch.len() < MAX_BATCH {
+                match rx.try_recv() {
+                    Ok(ev) => batch.push(ev),
+                    Err(_) => break,
+                }
+            }
+            if let Err(e) = insert_batch(&mut conn, &batch, &config) {
+                warn!("Failed to write audit records: {}", e);
+            }
+            if last_prune.elapsed() >= PRUNE_INTERVAL {
+                prune(&conn, &config);
+                last_prune = Instant::now();
+            }
+        }
+    }
+
+    fn insert_batch(
+        conn: &mut Connection,
+        batch: &[ProxyEvent],
+        config: &AuditDbConfig,
+    ) -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO sessions (conn_id, peer_ip, username, host, protocol, backend, proxy,
+                    connected_at_ms, closed_at_ms, bytes_sent, bytes_recv, reason, metadata)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+            for event in batch {
+                let ProxyEvent::Disconnected {
+                    conn_id,
+                    timestamp_ms,
+                    reason,
+                    bytes_sent,
+                    bytes_recv,
+                    info,
+                } = event
+                else {
+                    continue;
+                };
+                if *reason == DisconnectReason::StatusDone && !config.include_status {
+                    continue;
+                }
+                stmt.execute(params![
+                    *conn_id as i64,
+                    info.peer_ip,
+                    info.username,
+                    info.host,
+                    info.protocol,
+                    info.backend,
+                    info.proxy,
+                    info.connected_at_ms as i64,
+                    *timestamp_ms as i64,
+                    *bytes_sent as i64,
+                    *bytes_recv as i64,
+                    reason.as_str(),
+                    info.metadata
+                        .as_ref()
+                        .map(|m| serde_json::Value::Object(m.clone()).to_string()),
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Adds columns introduced after a database was created.
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        let has_metadata = conn
+            .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'metadata'")?
+            .exists([])?;
+        if !has_metadata {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN metadata TEXT")?;
+        }
+        Ok(())
+    }
+
+    /// Applies the age and row-count retention rules.
+    fn prune(conn: &Connection, config: &AuditDbConfig) {
+        if let Some(days) = config.retention_days {
+            let cutoff = events::now_ms().saturating_sub(days * 24 * 60 * 60 * 1000);
+            if let Err(e) = conn.execute(
+                "DELETE FROM sessions WHERE closed_at_ms < ?1",
+                params![cutoff as i64],
+            ) {
+                warn!("Failed to prune audit records by age: {}", e);
+            }
+        }
+        if let Some(max_rows) = config.max_rows
+            && let Err(e) = conn.execute(
+                "DELETE FROM sessions WHERE id <= (SELECT id FROM sessions ORDER BY id DESC LIMIT 1 OFFSET ?1)",
+                params![max_rows as i64],
+            )
+        {
+            warn!("Failed to prune audit records by count: {}", e);
+        }
+    }
+
+    /// Runs a filtered query on a separate read-only connection, newest first.
+    pub fn query(config: &AuditDbConfig, filter: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+        let conn = Connection::open_with_flags(&config.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+
+        let mut sql = String::from(
+            "SELECT conn_id, peer_ip, username, host, protocol, backend, proxy,
+                connected_at_ms, closed_at_ms, bytes_sent, bytes_recv, reason, metadata
+             FROM sessions WHERE 1 = 1",
+        );
+        let mut args: Vec<Value> = Vec::new();
+        let text_filters = [
+            ("username", &filter.username),
+            ("peer_ip", &filter.peer_ip),
+            ("host", &filter.host),
+            ("backend", &filter.backend),
+        ];
+        for (column, value) in text_filters {
+            if let Some(v) = value {
+                args.push(Value::Text(v.clone()));
+                sql.push_str(&format!(" AND {} = ?{}", column, args.len()));
+            }
+        }
+        if let Some(since) = filter.since_ms {
+            args.push(Value::Integer(since as i64));
+            sql.push_str(&format!(" AND closed_at_ms >= ?{}", args.len()));
+        }
+        if let Some(until) = filter.until_ms {
+            args.push(Value::Integer(until as i64));
+            sql.push_str(&format!(" AND closed_at_ms < ?{}", args.len()));
+        }
+        let limit = filter.limit.unwrap_or(100).min(MAX_QUERY_ROWS);
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT {}", limit));
+
+        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params_from_iter(args), |row| {
+                Ok(AuditRecord {
+                    conn_id: row.get::<_, i64>(0)? as u64,
+                    peer_ip: row.get(1)?,
+                    username: row.get(2)?,
+                    host: row.get(3)?,
+                    protocol: row.get(4)?,
+                    backend: row.get(5)?,
+                    proxy: row.get(6)?,
+                    connected_at_ms: row.get::<_, i64>(7)? as u64,
+                    closed_at_ms: row.get::<_, i64>(8)? as u64,
+                    bytes_sent: row.get::<_, i64>(9)? as u64,
+                    bytes_recv: row.get::<_, i64>(10)? as u64,
+                    reason: row.get(11)?,
+                    metadata: row
+                        .get::<_, Option<String>>(12)?
+                        .and_then(|m| serde_json::from_str(&m).ok()),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::types::{ConnInfo, DisconnectReason};
+
+    fn closed(conn_id: u64, username: &str, reason: DisconnectReason) -> ProxyEvent {
+        ProxyEvent::Disconnected {
+            conn_id,
+            timestamp_ms: 1_000 + conn_id,
+            reason,
+            bytes_sent: 10,
+            bytes_recv: 20,
+            info: ConnInfo {
+                peer_ip: "10.0.0.1".to_string(),
+                username: Some(username.to_string()),
+                metadata: serde_json::json!({ "order": conn_id })
+                    .as_object()
+                    .cloned(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let path = std::env::temp_dir().join(format!("geofront-audit-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = AuditDbConfig {
+            path: path.to_string_lossy().into_owned(),
+            retention_days: None,
+            max_rows: None,
+            include_status: false,
+        };
+
+        let (tx, writer) = sqlite::spawn(&config).expect("audit db should open");
+        tx.blocking_send(closed(1, "alice", DisconnectReason::Closed)).unwrap();
+        tx.blocking_send(closed(2, "bob", DisconnectReason::Kicked)).unwrap();
+        // Status pings are skipped unless `include_status` is set
+        tx.blocking_send(closed(3, "", DisconnectReason::StatusDone)).unwrap();
+        drop(tx);
+        // The writer exits once it has drained the closed channel
+        writer.join().unwrap();
+
+        let all = query(&config, &AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].username.as_deref(), Some("bob"));
+        assert_eq!(all[0].reason, "kicked");
+
+        let filter = AuditQuery {
+            username: Some("alice".to_string()),
+            ..Default::default()
+        };
+        let alice = query(&config, &filter).unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].bytes_recv, 20);
+        assert_eq!(alice[0].metadata.as_ref().unwrap()["order"], 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,454 @@
+//! geofront/src/auth.rs
+//! Online-mode login termination. For routes with `authenticate` set,
+//! geofront answers the login with its own encryption request, verifies the
+//! player with the Mojang session server and encrypts the client side of the
+//! relay, while the backend runs in offline mode and receives the verified
+//! profile through BungeeCord-style forwarding (see `forwarding.rs`).
+//!
+//! Requires the `auth` feature.
+
+use crate::{transport::ClientTransport, types::ForwardedIdentity};
+use std::{
+    io::{ErrorKind, Result},
+    net::IpAddr,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+/// A login verified by the session server.
+pub struct Authenticated {
+    /// The client, encrypted from here on.
+    pub stream: ClientStream,
+    /// Forwarded to the backend in the handshake.
+    pub identity: ForwardedIdentity,
+    /// Client bytes read past the encryption response, already decrypted.
+    pub pipelined: Vec<u8>,
+}
+
+/// Client side of a connection: plain, or encrypted once geofront has
+/// authenticated the login.
+pub struct ClientStream {
+    inner: ClientTransport,
+    cipher: Option<Cipher>,
+    /// Encrypted bytes already reported as written but not sent yet.
+    pending: Vec<u8>,
+    sent: usize,
+}
+
+impl ClientStream {
+    pub fn new(inner: ClientTransport) -> Self {
+        Self {
+            inner,
+            cipher: None,
+            pending: Vec::new(),
+            sent: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &ClientTransport {
+        &self.inner
+    }
+
+    /// The TCP stream, when bytes may be copied to and from it untouched.
+    pub fn plain_mut(&mut self) -> Option<&mut TcpStream> {
+        match self.cipher {
+            None => self.inner.plain_mut(),
+            Some(_) => None,
+        }
+    }
+
+    /// Waits until the client has sent something. Decryption happens in
+    /// place as bytes are read, so nothing readable is ever held back here;
+    /// TLS records are only waited for on the socket.
+    pub async fn readable(&self) -> Result<()> {
+        self.inner.tcp().readable().await
+    }
+
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.sent < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.sent..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.sent += n;
+        }
+        self.pending.clear();
+        self.sent = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if let Some(cipher) = &mut this.cipher {
+            cipher.decrypt(&mut buf.filled_mut()[start..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        if this.cipher.is_none() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        // The cipher state advances as bytes are encrypted, so a chunk is
+        // taken whole and whatever does not go out now is sent before the
+        // next chunk or on flush.
+        ready!(this.poll_send_pending(cx))?;
+        this.pending.extend_from_slice(buf);
+        if let Some(cipher) = &mut this.cipher {
+            cipher.encrypt(&mut this.pending);
+        }
+        if let Poll::Ready(Err(e)) = this.poll_send_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Minecraft's SHA-1 "server hash": the digest as a signed two's-complement
+/// number in hex, without leading zeros.
+#[cfg(feature = "auth")]
+pub fn server_hash(parts: &[&[u8]]) -> String {
+    use sha1::{Digest, Sha1};
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut digest: [u8; 20] = hasher.finalize().into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                (*byte, carry) = byte.overflowing_add(1);
+            }
+        }
+    }
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    let hex = hex.trim_start_matches('0');
+    format!("{}{}", if negative { "-" } else { "" }, hex)
+}
+
+#[cfg(not(feature = "auth"))]
+pub use disabled::{Cipher, authenticate};
+
+#[cfg(feature = "auth")]
+pub use mojang::{Cipher, authenticate};
+
+#[cfg(not(feature = "auth"))]
+mod disabled {
+    use super::*;
+
+    /// Never constructed without the `auth` feature.
+    pub enum Cipher {}
+
+    impl Cipher {
+        pub fn encrypt(&mut self, _buf: &mut [u8]) {
+            match *self {}
+        }
+
+        pub fn decrypt(&mut self, _buf: &mut [u8]) {
+            match *self {}
+        }
+    }
+
+    pub async fn authenticate(
+        mut inbound: ClientTransport,
+        _pipelined: Vec<u8>,
+        _username: &str,
+        _peer_ip: IpAddr,
+        protocol: i32,
+    ) -> std::result::Result<Authenticated, String> {
+        let _ = crate::protocol::write_disconnect(
+            &mut inbound,
+            &crate::messages::builtin(crate::messages::AUTH_FAILED),
+            protocol,
+        )
+        .await;
+        Err("geofront was built without the `auth` feature".to_string())
+    }
+}
+
+#[cfg(feature = "auth")]
+mod mojang {
+    use super::*;
+    use crate::protocol::{self, ConnReader, write_disconnect};
+    use aes::{
+        Aes128,
+        cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, inout::InOutBuf},
+    };
+    use rand::RngCore;
+    use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey, pkcs8::EncodePublicKey};
+    use serde::Deserialize;
+    use std::{io::Error, sync::OnceLock, time::Duration};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const SESSION_SERVER: &str = "https://sessionserver.mojang.com/session/minecraft/hasJoined";
+    /// The client contacts the session server itself before answering.
+    const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+    const SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+    /// First protocol version (1.20.5) whose encryption request carries
+    /// `shouldAuthenticate`.
+    const SHOULD_AUTHENTICATE_PROTOCOL: i32 = 766;
+
+    /// AES-128/CFB8 keyed with the shared secret, one state per direction.
+    pub struct Cipher {
+        encryptor: cfb8::Encryptor<Aes128>,
+        decryptor: cfb8::Decryptor<Aes128>,
+    }
+
+    impl Cipher {
+        fn new(secret: &[u8]) -> Option<Self> {
+            Some(Self {
+                encryptor: cfb8::Encryptor::new_from_slices(secret, secret).ok()?,
+                decryptor: cfb8::Decryptor::new_from_slices(secret, secret).ok()?,
+            })
+        }
+
+        pub fn encrypt(&mut self, buf: &mut [u8]) {
+            // CFB8 has one-byte blocks, so there is never a remainder.
+            let (blocks, _) = InOutBuf::from(buf).into_chunks();
+            self.encryptor.encrypt_blocks_inout_mut(blocks);
+        }
+
+        pub fn decrypt(&mut self, buf: &mut [u8]) {
+            let (blocks, _) = InOutBuf::from(buf).into_chunks();
+            self.decryptor.decrypt_blocks_inout_mut(blocks);
+        }
+    }
+
+    struct ServerKey {
+        private: RsaPrivateKey,
+        public_der: Vec<u8>,
+    }
+
+    static SERVER_KEY: OnceLock<ServerKey> = OnceLock::new();
+
+    /// The RSA key pair of this process, generated on first use.
+    async fn server_key() -> std::result::Result<&'static ServerKey, String> {
+        if let Some(key) = SERVER_KEY.get() {
+            return Ok(key);
+        }
+        let key = tokio::task::spawn_blocking(|| {
+            let private = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).map_err(|e| e.to_string())?;
+            let public_der = RsaPublicKey::from(&private)
+                .to_public_key_der()
+                .map_err(|e| e.to_string())?
+                .into_vec();
+            Ok::<_, String>(ServerKey { private, public_der })
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        Ok(SERVER_KEY.get_or_init(|| key))
+    }
+
+    #[derive(Deserialize)]
+    struct Profile {
+        id: String,
+        #[serde(default)]
+        properties: serde_json::Value,
+    }
+
+    fn http() -> &'static reqwest::Client {
+        static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+        CLIENT.get_or_init(|| {
+            reqwest::Client::builder()
+                .timeout(SESSION_TIMEOUT)
+                .build()
+                .expect("Failed to build HTTP client")
+        })
+    }
+
+    /// Asks the session server whether `username` joined with `server_hash`.
+    async fn has_joined(username: &str, server_hash: &str) -> std::result::Result<Option<Profile>, String> {
+        let response = http()
+            .get(SESSION_SERVER)
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        // 204 No Content: the client did not join.
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("session server returned {}", response.status()));
+        }
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        serde_json::from_slice(&body).map(Some).map_err(|e| e.to_string())
+    }
+
+    fn encryption_request(public_der: &[u8], verify_token: &[u8], protocol: i32) -> Vec<u8> {
+        let mut payload = Vec::new();
+        protocol::write_varint(&mut payload, 0x01);
+        protocol::write_string(&mut payload, "");
+        protocol::write_varint(&mut payload, public_der.len() as i32);
+        payload.extend_from_slice(public_der);
+        protocol::write_varint(&mut payload, verify_token.len() as i32);
+        payload.extend_from_slice(verify_token);
+        if protocol >= SHOULD_AUTHENTICATE_PROTOCOL {
+            payload.push(1);
+        }
+        let mut packet = Vec::new();
+        protocol::write_varint(&mut packet, payload.len() as i32);
+        packet.extend(payload);
+        packet
+    }
+
+    async fn read_byte_array<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+        let len = protocol::read_varint(reader).await?;
+        if !(0..=1024).contains(&len) {
+            return Err(Error::new(ErrorKind::InvalidData, "byte array too long"));
+        }
+        let mut buf = vec![0u8; len as usize];
+        reader.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Reads the encryption response: the encrypted shared secret and verify
+    /// token.
+    async fn read_encryption_response<R: AsyncReadExt + Unpin>(
+        reader: &mut R,
+        protocol: i32,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let _packet_len = protocol::read_varint(reader).await?;
+        if protocol::read_varint(reader).await? != 0x01 {
+            return Err(Error::new(ErrorKind::InvalidData, "expected encryption response"));
+        }
+        let secret = read_byte_array(reader).await?;
+        // 1.19 to 1.19.2 may send a signed salt instead of the verify token.
+        if (759..=760).contains(&protocol) && reader.read_u8().await? == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "signed encryption responses are not supported"));
+        }
+        let token = read_byte_array(reader).await?;
+        Ok((secret, token))
+    }
+
+    /// Runs the encryption handshake with the client and verifies it with the
+    /// session server. On failure the client is disconnected here, encrypted
+    /// if the handshake got that far.
+    pub async fn authenticate(
+        inbound: ClientTransport,
+        pipelined: Vec<u8>,
+        username: &str,
+        peer_ip: IpAddr,
+        protocol: i32,
+    ) -> std::result::Result<Authenticated, String> {
+        let mut stream = ClientStream::new(inbound);
+        match exchange(&mut stream, pipelined, username, peer_ip, protocol).await {
+            Ok((identity, pipelined)) => Ok(Authenticated {
+                stream,
+                identity,
+                pipelined,
+            }),
+            Err(e) => {
+                let message = crate::messages::builtin(crate::messages::AUTH_FAILED);
+                let _ = write_disconnect(&mut stream, &message, protocol).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Enables encryption on `stream` as soon as the client has sent its
+    /// shared secret.
+    async fn exchange(
+        stream: &mut ClientStream,
+        pipelined: Vec<u8>,
+        username: &str,
+        peer_ip: IpAddr,
+        protocol: i32,
+    ) -> std::result::Result<(ForwardedIdentity, Vec<u8>), String> {
+        let key = server_key().await?;
+        let mut verify_token = [0u8; 4];
+        rand::thread_rng().fill_bytes(&mut verify_token);
+        stream
+            .inner
+            .write_all(&encryption_request(&key.public_der, &verify_token, protocol))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut reader = ConnReader::with_buffered(&mut stream.inner, pipelined);
+        let (secret, token) = tokio::time::timeout(RESPONSE_TIMEOUT, read_encryption_response(&mut reader, protocol))
+            .await
+            .map_err(|_| "timed out waiting for encryption response".to_string())?
+            .map_err(|e| e.to_string())?;
+        let (_, mut pipelined) = reader.into_parts();
+
+        let secret = key
+            .private
+            .decrypt(Pkcs1v15Encrypt, &secret)
+            .map_err(|e| format!("invalid shared secret: {}", e))?;
+        let token = key
+            .private
+            .decrypt(Pkcs1v15Encrypt, &token)
+            .map_err(|e| format!("invalid verify token: {}", e))?;
+        if token != verify_token {
+            return Err("verify token mismatch".to_string());
+        }
+        let mut cipher = Cipher::new(&secret).ok_or("invalid shared secret length")?;
+        cipher.decrypt(&mut pipelined);
+        stream.cipher = Some(cipher);
+
+        let hash = server_hash(&[b"", &secret, &key.public_der]);
+        let profile = has_joined(username, &hash)
+            .await?
+            .ok_or_else(|| format!("session server did not verify {}", username))?;
+        if profile.id.len() != 32 || !profile.id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid profile id {:?}", profile.id));
+        }
+        let properties = match profile.properties {
+            serde_json::Value::Array(properties) if !properties.is_empty() => {
+                Some(serde_json::Value::Array(properties).to_string())
+            }
+            _ => None,
+        };
+        Ok((
+            ForwardedIdentity {
+                ip: peer_ip,
+                uuid: profile.id,
+                properties,
+            },
+            pipelined,
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "auth"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_hash() {
+        assert_eq!(server_hash(&[b"Notch"]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash(&[b"jeb_"]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash(&[b"sim", b"on"]), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+}
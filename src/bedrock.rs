@@ -0,0 +1,384 @@
+//! geofront/src/bedrock.rs
+//! UDP listeners for Bedrock clients, relaying RakNet datagrams to a backend
+//! such as a Geyser or Bedrock dedicated server. Every client address gets
+//! an association with a socket of its own towards the backend.
+//!
+//! Only the offline handshake is looked at. Its Open Connection Request 2
+//! carries the server address the client connected to, which picks the
+//! backend from `routes` (by `ip:port`, then `ip`), for hosts reachable
+//! under several addresses. Pings and the first request go to
+//! `defaultBackend` before that; an association moved to another backend
+//! replays the first request there and the backend's duplicate reply is
+//! dropped. Associations without traffic for `idleTimeoutMs` are closed.
+
+use crate::{
+    events,
+    state::BEDROCK_SESSIONS,
+    types::{BedrockConfig, ProxyListener},
+};
+use serde::Serialize;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    net::{UdpSocket, lookup_host},
+    task::{AbortHandle, JoinSet},
+};
+use tracing::{debug, info, warn};
+
+/// Magic bytes of RakNet offline messages.
+const OFFLINE_MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+const OPEN_CONNECTION_REQUEST_2: u8 = 0x07;
+/// Largest datagram relayed; RakNet MTUs stay well below it.
+const MAX_DATAGRAM: usize = 2048;
+
+/// Relay state of one client address.
+pub struct Association {
+    listener: ProxyListener,
+    client: SocketAddr,
+    /// Set once the socket towards the first backend is open.
+    upstream: std::sync::Mutex<Option<Upstream>>,
+    /// Open Connection Request 1, replayed when the backend changes.
+    first_request: std::sync::Mutex<Option<Vec<u8>>>,
+    created_at_ms: u64,
+    last_seen_ms: AtomicU64,
+    packets_to_backend: AtomicU64,
+    bytes_to_backend: AtomicU64,
+    packets_to_client: AtomicU64,
+    bytes_to_client: AtomicU64,
+}
+
+struct Upstream {
+    backend: String,
+    socket: Arc<UdpSocket>,
+    relay: AbortHandle,
+}
+
+/// An association in `proxy_get_bedrock_sessions`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BedrockSession {
+    pub listener: ProxyListener,
+    pub client: String,
+    pub backend: String,
+    pub packets_to_backend: u64,
+    pub bytes_to_backend: u64,
+    pub packets_to_client: u64,
+    pub bytes_to_client: u64,
+    pub created_at_ms: u64,
+    pub last_seen_ms: u64,
+}
+
+impl Association {
+    fn snapshot(&self) -> BedrockSession {
+        BedrockSession {
+            listener: self.listener,
+            client: self.client.to_string(),
+            backend: self
+                .upstream
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|upstream| upstream.backend.clone())
+                .unwrap_or_default(),
+            packets_to_backend: self.packets_to_backend.load(Ordering::SeqCst),
+            bytes_to_backend: self.bytes_to_backend.load(Ordering::SeqCst),
+            packets_to_client: self.packets_to_client.load(Ordering::SeqCst),
+            bytes_to_client: self.bytes_to_client.load(Ordering::SeqCst),
+            created_at_ms: self.created_at_ms,
+            last_seen_ms: self.last_seen_ms.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Live associations of every Bedrock listener.
+pub fn sessions() -> Vec<BedrockSession> {
+    BEDROCK_SESSIONS
+        .iter()
+        .map(|entry| entry.value().snapshot())
+        .collect()
+}
+
+/// Forgets the associations of a listener once it stops.
+struct SessionsGuard(ProxyListener);
+
+impl Drop for SessionsGuard {
+    fn drop(&mut self) {
+        BEDROCK_SESSIONS.retain(|(listener, _), _| *listener != self.0);
+    }
+}
+
+/// The server address of an Open Connection Request 2, if `packet` is one.
+fn requested_address(packet: &[u8]) -> Option<SocketAddr> {
+    if packet.first() != Some(&OPEN_CONNECTION_REQUEST_2) || packet.get(1..17)? != OFFLINE_MAGIC {
+        return None;
+    }
+    read_address(&packet[17..])
+}
+
+/// Decodes a RakNet system address: IPv4 with its bytes inverted, or IPv6
+/// as a `sockaddr_in6`.
+fn read_address(data: &[u8]) -> Option<SocketAddr> {
+    match *data.first()? {
+        4 => {
+            let ip = data.get(1..5)?;
+            let port = u16::from_be_bytes(data.get(5..7)?.try_into().ok()?);
+            let ip = Ipv4Addr::new(!ip[0], !ip[1], !ip[2], !ip[3]);
+            Some(SocketAddr::new(IpAddr::V4(ip), port))
+        }
+        6 => {
+            // Family (2, little endian), port, flow info, address, scope.
+            let port = u16::from_be_bytes(data.get(3..5)?.try_into().ok()?);
+            let ip: [u8; 16] = data.get(9..25)?.try_into().ok()?;
+            Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port))
+        }
+        _ => None,
+    }
+}
+
+/// The backend for a client that asked for `requested`.
+fn route(config: &BedrockConfig, requested: SocketAddr) -> &str {
+    let requested = SocketAddr::new(requested.ip().to_canonical(), requested.port());
+    config
+        .routes
+        .get(&requested.to_string())
+        .or_else(|| config.routes.get(&requested.ip().to_string()))
+        .unwrap_or(&config.default_backend)
+}
+
+/// Opens a socket towards `backend` and starts relaying its datagrams to
+/// the client, skipping its first Open Connection Reply 1 if `skip_reply`.
+async fn connect_upstream(
+    association: &Arc<Association>,
+    backend: &str,
+    listener_socket: &Arc<UdpSocket>,
+    relays: &mut JoinSet<()>,
+    idle: Duration,
+    skip_reply: bool,
+) -> io::Result<Upstream> {
+    let backend_addr = lookup_host(backend)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} resolves to no address", backend)))?;
+    let local: SocketAddr = if backend_addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = Arc::new(UdpSocket::bind(local).await?);
+    socket.connect(backend_addr).await?;
+    let relay = relays.spawn(relay_to_client(
+        association.clone(),
+        socket.clone(),
+        listener_socket.clone(),
+        idle,
+        skip_reply,
+    ));
+    Ok(Upstream {
+        backend: backend.to_string(),
+        socket,
+        relay,
+    })
+}
+
+/// Relays the backend's datagrams to the client until the association has
+/// been idle for `idle` in both directions.
+async fn relay_to_client(
+    association: Arc<Association>,
+    upstream: Arc<UdpSocket>,
+    listener_socket: Arc<UdpSocket>,
+    idle: Duration,
+    mut skip_reply: bool,
+) {
+    let mut buf = vec![0; MAX_DATAGRAM];
+    loop {
+        let n = match tokio::time::timeout(idle, upstream.recv(&mut buf)).await {
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => {
+                debug!(client = %association.client, "Bedrock backend socket failed: {}", e);
+                continue;
+            }
+            Err(_) => {
+                let quiet_ms = events::now_ms().saturating_sub(association.last_seen_ms.load(Ordering::SeqCst));
+                if quiet_ms < idle.as_millis() as u64 {
+                    continue;
+                }
+                break;
+            }
+        };
+        if skip_reply && buf[..n].first() == Some(&OPEN_CONNECTION_REPLY_1) {
+            skip_reply = false;
+            continue;
+        }
+        association.packets_to_client.fetch_add(1, Ordering::SeqCst);
+        association.bytes_to_client.fetch_add(n as u64, Ordering::SeqCst);
+        association.last_seen_ms.store(events::now_ms(), Ordering::SeqCst);
+        if let Err(e) = listener_socket.send_to(&buf[..n], association.client).await {
+            debug!(client = %association.client, "Failed to relay Bedrock datagram: {}", e);
+        }
+    }
+    debug!(client = %association.client, "Bedrock association idle, closing");
+    BEDROCK_SESSIONS.remove_if(&(association.listener, association.client), |_, current| {
+        Arc::ptr_eq(current, &association)
+    });
+}
+
+/// Binds a Bedrock listener's UDP socket.
+pub async fn bind(addr: &str, port: u16) -> io::Result<UdpSocket> {
+    UdpSocket::bind((addr, port)).await
+}
+
+/// Relays datagrams between clients and their backends until the listener
+/// is stopped.
+pub async fn serve(listener_id: ProxyListener, socket: UdpSocket, config: BedrockConfig) {
+    let _sessions = SessionsGuard(listener_id);
+    let socket = Arc::new(socket);
+    let idle = Duration::from_millis(config.idle_timeout_ms.max(1000));
+    // Dropping the set when the listener is aborted ends every relay.
+    let mut relays = JoinSet::new();
+    let mut buf = vec![0; MAX_DATAGRAM];
+    loop {
+        let (n, client) = tokio::select! {
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    // ICMP errors of earlier sends surface here on some platforms.
+                    debug!(listener = listener_id, "Bedrock receive failed: {}", e);
+                    continue;
+                }
+            },
+            Some(_) = relays.join_next() => continue,
+        };
+        let packet = &buf[..n];
+        let key = (listener_id, client);
+        let existing = BEDROCK_SESSIONS.get(&key).map(|entry| entry.value().clone());
+        let association = match existing {
+            Some(association) => association,
+            None => {
+                if BEDROCK_SESSIONS.len() >= config.max_sessions {
+                    debug!(%client, "Bedrock association limit reached, dropping datagram");
+                    continue;
+                }
+                let now = events::now_ms();
+                let association = Arc::new(Association {
+                    listener: listener_id,
+                    client,
+                    upstream: std::sync::Mutex::new(None),
+                    first_request: std::sync::Mutex::new(None),
+                    created_at_ms: now,
+                    last_seen_ms: AtomicU64::new(now),
+                    packets_to_backend: AtomicU64::new(0),
+                    bytes_to_backend: AtomicU64::new(0),
+                    packets_to_client: AtomicU64::new(0),
+                    bytes_to_client: AtomicU64::new(0),
+                });
+                match connect_upstream(&association, &config.default_backend, &socket, &mut relays, idle, false).await {
+                    Ok(upstream) => *association.upstream.lock().unwrap() = Some(upstream),
+                    Err(e) => {
+                        warn!(backend = %config.default_backend, "Failed to open Bedrock backend socket: {}", e);
+                        continue;
+                    }
+                }
+                info!(listener = listener_id, %client, backend = %config.default_backend, "New Bedrock association");
+                BEDROCK_SESSIONS.insert(key, association.clone());
+                association
+            }
+        };
+        association.last_seen_ms.store(events::now_ms(), Ordering::SeqCst);
+
+        if packet.first() == Some(&OPEN_CONNECTION_REQUEST_1) {
+            *association.first_request.lock().unwrap() = Some(packet.to_vec());
+        } else if let Some(requested) = requested_address(packet) {
+            let backend = route(&config, requested);
+            let current = association.snapshot().backend;
+            if backend != current {
+                let first_request = association.first_request.lock().unwrap().clone();
+                match connect_upstream(&association, backend, &socket, &mut relays, idle, first_request.is_some()).await {
+                    Ok(upstream) => {
+                        if let Some(first_request) = first_request {
+                            let _ = upstream.socket.send(&first_request).await;
+                        }
+                        let previous = association.upstream.lock().unwrap().replace(upstream);
+                        if let Some(previous) = previous {
+                            previous.relay.abort();
+                        }
+                        info!(%client, %requested, %backend, "Bedrock association routed");
+                    }
+                    Err(e) => warn!(%backend, "Failed to open Bedrock backend socket: {}", e),
+                }
+            }
+        }
+
+        let upstream = association
+            .upstream
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|upstream| upstream.socket.clone());
+        let Some(upstream) = upstream else {
+            continue;
+        };
+        match upstream.send(packet).await {
+            Ok(_) => {
+                association.packets_to_backend.fetch_add(1, Ordering::SeqCst);
+                association.bytes_to_backend.fetch_add(n as u64, Ordering::SeqCst);
+            }
+            Err(e) => debug!(%client, "Failed to relay Bedrock datagram to backend: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request_2(address: &[u8]) -> Vec<u8> {
+        let mut packet = vec![OPEN_CONNECTION_REQUEST_2];
+        packet.extend(OFFLINE_MAGIC);
+        packet.extend(address);
+        packet.extend(1400u16.to_be_bytes());
+        packet.extend(42u64.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_requested_address() {
+        // 203.0.113.7:19132, inverted.
+        let v4 = request_2(&[4, !203, !0, !113, !7, 0x4a, 0xbc]);
+        assert_eq!(requested_address(&v4), "203.0.113.7:19132".parse().ok());
+
+        let mut v6 = vec![6, 23, 0, 0x4a, 0xbc, 0, 0, 0, 0];
+        v6.extend("2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        v6.extend([0; 4]);
+        assert_eq!(requested_address(&request_2(&v6)), "[2001:db8::1]:19132".parse().ok());
+
+        assert_eq!(requested_address(&[OPEN_CONNECTION_REQUEST_1; 40]), None);
+    }
+
+    #[test]
+    fn test_route() {
+        let config = BedrockConfig {
+            routes: HashMap::from([
+                ("203.0.113.7:19132".to_string(), "geyser-a:19132".to_string()),
+                ("203.0.113.8".to_string(), "geyser-b:19132".to_string()),
+            ]),
+            default_backend: "geyser-default:19132".to_string(),
+            idle_timeout_ms: 30_000,
+            max_sessions: 16,
+        };
+        assert_eq!(route(&config, "203.0.113.7:19132".parse().unwrap()), "geyser-a:19132");
+        assert_eq!(route(&config, "[::ffff:203.0.113.8]:19133".parse().unwrap()), "geyser-b:19132");
+        assert_eq!(route(&config, "203.0.113.9:19132".parse().unwrap()), "geyser-default:19132");
+    }
+}
@@ -0,0 +1,33 @@
+//! geofront/src/bin/loadgen.rs
+//! Command-line front end of the load generator.
+//!
+//! Usage: `geofront-loadgen '<LoadGenConfig JSON>'`, e.g.
+//! `geofront-loadgen '{"target":"127.0.0.1:25565","clients":500,"durationMs":30000}'`
+
+use geofront::{loadgen, types::LoadGenConfig};
+use std::process::ExitCode;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let Some(arg) = std::env::args().nth(1) else {
+        eprintln!("usage: geofront-loadgen '<config JSON>'");
+        return ExitCode::FAILURE;
+    };
+    let config: LoadGenConfig = match serde_json::from_str(&arg) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid config: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match loadgen::run(config).await {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("load generation failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
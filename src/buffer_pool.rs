@@ -0,0 +1,89 @@
+//! geofront/src/buffer_pool.rs
+//! Relay buffers shared by all connections. Each relay takes its buffers
+//! from the pool and hands them back when it ends, so busy proxies do not
+//! allocate per connection; `copyBufferSize` trades throughput (fewer, larger
+//! reads and writes) against memory per relayed connection.
+
+use crate::state::{BUFFER_POOL, COPY_BUFFER_SIZE};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::atomic::Ordering,
+};
+
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+const MIN_BUFFER_SIZE: usize = 1024;
+const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+/// Pooled buffers kept for reuse; more are freed when handed back.
+const MAX_POOLED: usize = 1024;
+
+/// Sets the size of buffers taken from now on, dropping pooled buffers of
+/// the previous size.
+pub fn configure(size: Option<usize>) {
+    let size = size
+        .unwrap_or(DEFAULT_BUFFER_SIZE)
+        .clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE);
+    if COPY_BUFFER_SIZE.swap(size, Ordering::SeqCst) != size {
+        BUFFER_POOL.lock().unwrap().clear();
+    }
+}
+
+/// Takes a buffer of the configured size, reusing a pooled one if any.
+pub fn take() -> PooledBuffer {
+    let size = COPY_BUFFER_SIZE.load(Ordering::SeqCst);
+    let buf = BUFFER_POOL
+        .lock()
+        .unwrap()
+        .pop()
+        .filter(|buf| buf.len() == size)
+        .unwrap_or_else(|| vec![0; size]);
+    PooledBuffer(buf)
+}
+
+/// A buffer that goes back to the pool when dropped.
+pub struct PooledBuffer(Vec<u8>);
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if self.0.len() != COPY_BUFFER_SIZE.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut pool = BUFFER_POOL.lock().unwrap();
+        if pool.len() < MAX_POOLED {
+            pool.push(std::mem::take(&mut self.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        configure(Some(2048));
+        let buf = take();
+        assert_eq!(buf.len(), 2048);
+        let ptr = buf.as_ptr();
+        drop(buf);
+        assert_eq!(take().as_ptr(), ptr);
+
+        // A resize drops the old buffers.
+        configure(Some(8192));
+        assert_eq!(take().len(), 8192);
+        configure(None);
+    }
+}
@@ -1,10 +1,35 @@
 //! geofront/src/cache.rs
 //! Router/MOTD cache implementation for high-performance caching in Rust layer
 
-use crate::types::{CacheConfig, CacheGranularity};
+#[cfg(feature = "redis")]
+use crate::shared_cache::SharedCache;
+use crate::types::{CacheConfig, CacheGranularity, SharedCacheConfig};
 use dashmap::DashMap;
+use serde::Serialize;
 use serde_json::Value;
+#[cfg(feature = "redis")]
+use std::sync::{Arc, RwLock};
+use crate::state::{CACHE_SWEEPER, ROUTER_MOTD_CACHE};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
+#[cfg(feature = "redis")]
+use tracing::warn;
+
+// 缓存的决策来源：登录路由与 MOTD 各自独立，互不命中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheScope {
+    Route,
+    Motd,
+}
+
+impl CacheScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheScope::Route => "route",
+            CacheScope::Motd => "motd",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct CacheEntry {
@@ -14,76 +39,337 @@ pub struct CacheEntry {
     pub expires_at: Instant,
 }
 
+// 后台清理过期条目的间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+// 本地条目及其最近一次访问的时钟值，超出容量时据此淘汰最久未用的条目
+struct Slot {
+    entry: CacheEntry,
+    last_used: AtomicU64,
+}
+
 pub struct RouterMotdCache {
     // 使用 DashMap 支持并发访问
-    cache: DashMap<String, CacheEntry>,
+    cache: DashMap<String, Slot>,
+    // 可选的 Redis 共享层（多节点共享封禁/路由结果），本地 DashMap 作为其前置缓存
+    #[cfg(feature = "redis")]
+    shared: RwLock<Option<Arc<SharedCache>>>,
+    // 本地条目上限（0 表示不限）；超出时淘汰至上限的 90%，摊薄扫描开销
+    max_entries: AtomicUsize,
+    evicting: AtomicBool,
+    clock: AtomicU64,
+    // 决策查询的命中/未命中次数，因容量淘汰及因过期移除的条目数
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    rejections: AtomicU64,
 }
 
 impl RouterMotdCache {
     pub fn new() -> Self {
         Self {
             cache: DashMap::new(),
+            #[cfg(feature = "redis")]
+            shared: RwLock::new(None),
+            max_entries: AtomicUsize::new(0),
+            evicting: AtomicBool::new(false),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+            rejections: AtomicU64::new(0),
+        }
+    }
+
+    // 设置本地条目上限（None 表示不限），立即淘汰超出部分
+    pub fn set_max_entries(&self, max_entries: Option<usize>) {
+        let max = max_entries.unwrap_or(0);
+        self.max_entries.store(max, Ordering::Relaxed);
+        if max > 0 && self.cache.len() > max {
+            self.evict_to(max);
+        }
+    }
+
+    // 配置（或移除）Redis 共享层；需在 Tokio runtime 上下文中调用
+    #[cfg(feature = "redis")]
+    pub fn configure_shared(&self, config: Option<&SharedCacheConfig>) {
+        let shared = config.and_then(|cfg| match SharedCache::new(cfg) {
+            Ok(s) => Some(Arc::new(s)),
+            Err(e) => {
+                tracing::error!("Failed to configure shared cache: {}", e);
+                None
+            }
+        });
+        *self.shared.write().unwrap() = shared;
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub fn configure_shared(&self, config: Option<&SharedCacheConfig>) {
+        if config.is_some() {
+            tracing::error!(
+                "Shared cache is not available: geofront was built without the `redis` feature"
+            );
         }
     }
 
+    #[cfg(feature = "redis")]
+    fn shared(&self) -> Option<Arc<SharedCache>> {
+        self.shared.read().unwrap().clone()
+    }
+
     // 生成缓存键
-    fn generate_key(&self, ip: &str, host: Option<&str>, granularity: &CacheGranularity) -> String {
+    fn generate_key(&self, scope: CacheScope, ip: &str, host: Option<&str>, granularity: &CacheGranularity) -> String {
         match granularity {
-            CacheGranularity::Ip => format!("ip:{}", ip),
-            CacheGranularity::IpHost => format!("ip:{}:host:{}", ip, host.unwrap_or("default")),
+            CacheGranularity::Ip => format!("{}:ip:{}", scope.as_str(), ip),
+            CacheGranularity::IpHost => {
+                format!("{}:ip:{}:host:{}", scope.as_str(), ip, host.unwrap_or("default"))
+            }
         }
     }
 
     // 获取缓存
     pub fn get(
         &self,
+        scope: CacheScope,
         ip: &str,
         host: Option<&str>,
         granularity: &CacheGranularity,
     ) -> Option<CacheEntry> {
-        let key = self.generate_key(ip, host, granularity);
+        let key = self.generate_key(scope, ip, host, granularity);
 
         if let Some(entry_ref) = self.cache.get(&key) {
-            if entry_ref.expires_at > Instant::now() {
-                return Some(entry_ref.clone());
+            if entry_ref.entry.expires_at > Instant::now() {
+                entry_ref.last_used.store(self.tick(), Ordering::Relaxed);
+                return Some(entry_ref.entry.clone());
             } else {
                 // 过期，需要删除。注意：必须先释放 entry_ref（释放分片读锁）再进行 remove，
                 // 否则 DashMap 可能出现同分片写锁获取阻塞，导致后续逻辑卡死（表现为后续 MOTD 请求无响应）。
-                let expired = entry_ref.expires_at <= Instant::now();
+                let expired = entry_ref.entry.expires_at <= Instant::now();
                 if expired {
                     drop(entry_ref); // 显式释放引用
-                    self.cache.remove(&key);
+                    if self.cache.remove(&key).is_some() {
+                        self.expirations.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // 分层获取：先查本地，未命中时再查 Redis 共享层，并将结果回填本地
+    pub async fn lookup(
+        &self,
+        scope: CacheScope,
+        ip: &str,
+        host: Option<&str>,
+        granularity: &CacheGranularity,
+    ) -> Option<CacheEntry> {
+        if let Some(entry) = self.get(scope, ip, host, granularity) {
+            return Some(entry);
+        }
+
+        #[cfg(feature = "redis")]
+        if let Some(shared) = self.shared() {
+            let key = self.generate_key(scope, ip, host, granularity);
+            match shared.get(&key).await {
+                Ok(Some(entry)) => {
+                    let mut local = entry.clone();
+                    if let Some(local_ttl) = shared.local_ttl {
+                        local.expires_at = local.expires_at.min(Instant::now() + local_ttl);
+                    }
+                    self.insert_local(key, local);
+                    return Some(entry);
                 }
+                Ok(None) => {}
+                Err(e) => warn!("Shared cache lookup failed: {}", e),
             }
         }
         None
     }
 
-    // 设置缓存
-    pub fn set(&self, ip: &str, host: Option<&str>, data: Value, cache_config: &CacheConfig) {
-        let key = self.generate_key(ip, host, &cache_config.granularity);
-        let expires_at = Instant::now() + Duration::from_millis(cache_config.ttl);
+    // 查询某次握手适用的缓存决策：优先 IP+Host 粒度，其次 IP 粒度；计入命中统计
+    pub async fn lookup_decision(&self, scope: CacheScope, ip: &str, host: &str) -> Option<CacheEntry> {
+        let entry = match self.lookup(scope, ip, Some(host), &CacheGranularity::IpHost).await {
+            Some(entry) => Some(entry),
+            None => self.lookup(scope, ip, None, &CacheGranularity::Ip).await,
+        };
+        let counter = if entry.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        entry
+    }
+
+    // 记录一次直接由缓存的拒绝决策断开、未询问路由器的连接
+    pub fn count_rejection(&self) {
+        self.rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 设置缓存；拒绝决策使用 rejectTtl（未设置时为 ttl）
+    pub fn set(&self, scope: CacheScope, ip: &str, host: Option<&str>, data: Value, cache_config: &CacheConfig) {
+        let key = self.generate_key(scope, ip, host, &cache_config.granularity);
+        let is_rejection = cache_config.reject.unwrap_or(false);
+        let ttl = match cache_config.reject_ttl {
+            Some(reject_ttl) if is_rejection => Duration::from_millis(reject_ttl),
+            _ => Duration::from_millis(cache_config.ttl),
+        };
 
         let entry = CacheEntry {
             data,
-            is_rejection: cache_config.reject.unwrap_or(false),
+            is_rejection,
             reject_reason: cache_config.reject_reason.clone(),
-            expires_at,
+            expires_at: Instant::now() + ttl,
+        };
+
+        // 写入共享层为异步 best-effort，不阻塞调用方
+        #[cfg(feature = "redis")]
+        if let (Some(shared), Ok(rt)) = (self.shared(), tokio::runtime::Handle::try_current()) {
+            let mut local = entry.clone();
+            if let Some(local_ttl) = shared.local_ttl {
+                local.expires_at = local.expires_at.min(Instant::now() + local_ttl);
+            }
+            let remote_key = key.clone();
+            rt.spawn(async move {
+                if let Err(e) = shared.set(&remote_key, &entry, ttl).await {
+                    warn!("Shared cache write failed: {}", e);
+                }
+            });
+            self.insert_local(key, local);
+            return;
+        }
+
+        self.insert_local(key, entry);
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // 写入本地条目，超出上限时触发淘汰
+    fn insert_local(&self, key: String, entry: CacheEntry) {
+        let slot = Slot {
+            entry,
+            last_used: AtomicU64::new(self.tick()),
         };
+        self.cache.insert(key, slot);
+        let max = self.max_entries.load(Ordering::Relaxed);
+        if max > 0 && self.cache.len() > max {
+            self.evict_to(max - max / 10);
+        }
+    }
 
-        self.cache.insert(key, entry);
+    // 先清理过期条目，仍超出 target 时淘汰最久未访问的条目；同一时间只有一个调用方执行
+    fn evict_to(&self, target: usize) {
+        if self.evicting.swap(true, Ordering::Acquire) {
+            return;
+        }
+        self.cleanup_expired();
+        let excess = self.cache.len().saturating_sub(target);
+        if excess > 0 {
+            let mut ages: Vec<(u64, String)> = self
+                .cache
+                .iter()
+                .map(|slot| (slot.last_used.load(Ordering::Relaxed), slot.key().clone()))
+                .collect();
+            let excess = excess.min(ages.len());
+            if excess > 0 {
+                ages.select_nth_unstable(excess - 1);
+                for (_, key) in &ages[..excess] {
+                    if self.cache.remove(key).is_some() {
+                        self.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        self.evicting.store(false, Ordering::Release);
     }
 
     // 清理过期缓存
     pub fn cleanup_expired(&self) {
         let now = Instant::now();
-        self.cache.retain(|_, entry| entry.expires_at > now);
+        let mut removed = 0;
+        self.cache.retain(|_, slot| {
+            let alive = slot.entry.expires_at > now;
+            if !alive {
+                removed += 1;
+            }
+            alive
+        });
+        self.expirations.fetch_add(removed, Ordering::Relaxed);
     }
 
     // 清除指定缓存
-    pub fn clear(&self, ip: &str, host: Option<&str>, granularity: &CacheGranularity) {
-        let key = self.generate_key(ip, host, granularity);
+    pub fn clear(&self, scope: CacheScope, ip: &str, host: Option<&str>, granularity: &CacheGranularity) {
+        let key = self.generate_key(scope, ip, host, granularity);
         self.cache.remove(&key);
+
+        #[cfg(feature = "redis")]
+        if let (Some(shared), Ok(rt)) = (self.shared(), tokio::runtime::Handle::try_current()) {
+            rt.spawn(async move {
+                if let Err(e) = shared.remove(&key).await {
+                    warn!("Shared cache delete failed: {}", e);
+                }
+            });
+        }
+    }
+
+    // 清除某个 IP 的缓存：IP 粒度条目，以及指定 Host 的 IP+Host 条目（未指定时为该 IP 的全部 IP+Host 条目）
+    pub fn clear_client(&self, ip: &str, host: Option<&str>) {
+        for scope in [CacheScope::Route, CacheScope::Motd] {
+            self.clear(scope, ip, None, &CacheGranularity::Ip);
+            match host {
+                Some(host) => self.clear(scope, ip, Some(host), &CacheGranularity::IpHost),
+                None => {
+                    let prefix = format!("{}:ip:{}:host:", scope.as_str(), ip);
+                    self.cache.retain(|key, _| !key.starts_with(&prefix));
+
+                    #[cfg(feature = "redis")]
+                    self.clear_shared_matching(format!("{}*", prefix));
+                }
+            }
+        }
+    }
+
+    // 列出本地当前生效的拒绝决策
+    pub fn blocked(&self) -> Vec<BlockedEntry> {
+        let now = Instant::now();
+        self.cache
+            .iter()
+            .filter(|slot| slot.entry.is_rejection && slot.entry.expires_at > now)
+            .filter_map(|slot| {
+                let (scope, rest) = slot.key().split_once(":ip:")?;
+                let (ip, host) = match rest.split_once(":host:") {
+                    Some((ip, host)) => (ip, Some(host.to_string())),
+                    None => (rest, None),
+                };
+                Some(BlockedEntry {
+                    scope: scope.to_string(),
+                    ip: ip.to_string(),
+                    host,
+                    reject_reason: slot.entry.reject_reason.clone(),
+                    expires_in_ms: slot.entry.expires_at.duration_since(now).as_millis() as u64,
+                })
+            })
+            .collect()
+    }
+
+    // 清空全部缓存（含 Redis 共享层中本前缀下的条目）
+    pub fn clear_all(&self) {
+        self.cache.clear();
+
+        #[cfg(feature = "redis")]
+        self.clear_shared_matching("*".to_string());
+    }
+
+    #[cfg(feature = "redis")]
+    fn clear_shared_matching(&self, pattern: String) {
+        if let (Some(shared), Ok(rt)) = (self.shared(), tokio::runtime::Handle::try_current()) {
+            rt.spawn(async move {
+                if let Err(e) = shared.remove_matching(&pattern).await {
+                    warn!("Shared cache delete failed: {}", e);
+                }
+            });
+        }
     }
 
     // 获取缓存统计信息
@@ -93,16 +379,69 @@ impl RouterMotdCache {
             expired_entries: self
                 .cache
                 .iter()
-                .filter(|entry| entry.expires_at <= Instant::now())
+                .filter(|slot| slot.entry.expires_at <= Instant::now())
                 .count(),
+            max_entries: match self.max_entries.load(Ordering::Relaxed) {
+                0 => None,
+                max => Some(max),
+            },
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            rejections: self.rejections.load(Ordering::Relaxed),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CacheStats {
     pub total_entries: usize,
     pub expired_entries: usize,
+    pub max_entries: Option<usize>,
+    pub hits: u64,
+    pub misses: u64,
+    // 因容量上限被淘汰的条目数
+    pub evictions: u64,
+    // 因过期被移除的条目数
+    pub expirations: u64,
+    // 直接由缓存的拒绝决策断开、未询问路由器的连接数
+    pub rejections: u64,
+}
+
+// 一条生效中的拒绝决策；host 仅 IP+Host 粒度的条目有
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedEntry {
+    pub scope: String,
+    pub ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_reason: Option<String>,
+    pub expires_in_ms: u64,
+}
+
+// 启动后台清理过期条目的任务（已在运行时不重复启动）；需在 Tokio runtime 上下文中调用
+pub fn start_sweeper() {
+    let mut sweeper = CACHE_SWEEPER.lock().unwrap();
+    if sweeper.is_some() {
+        return;
+    }
+    *sweeper = Some(tokio::spawn(async {
+        let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            ROUTER_MOTD_CACHE.cleanup_expired();
+        }
+    }));
+}
+
+pub fn stop_sweeper() {
+    if let Some(handle) = CACHE_SWEEPER.lock().unwrap().take() {
+        handle.abort();
+    }
 }
 
 impl Default for RouterMotdCache {
@@ -124,13 +463,14 @@ mod tests {
             ttl: 1000,
             reject: None,
             reject_reason: None,
+            reject_ttl: None,
         };
 
         // 测试设置和获取
         let data = json!({"test": "data"});
-        cache.set("127.0.0.1", None, data.clone(), &config);
+        cache.set(CacheScope::Route, "127.0.0.1", None, data.clone(), &config);
 
-        let result = cache.get("127.0.0.1", None, &CacheGranularity::Ip);
+        let result = cache.get(CacheScope::Route, "127.0.0.1", None, &CacheGranularity::Ip);
         assert!(result.is_some());
         assert_eq!(result.unwrap().data, data);
     }
@@ -143,20 +483,22 @@ mod tests {
             ttl: 1000,
             reject: None,
             reject_reason: None,
+            reject_ttl: None,
         };
         let ip_host_config = CacheConfig {
             granularity: CacheGranularity::IpHost,
             ttl: 1000,
             reject: None,
             reject_reason: None,
+            reject_ttl: None,
         };
 
         let data1 = json!({"type": "ip_only"});
         let data2 = json!({"type": "ip_host"});
 
         // 设置不同粒度的缓存
-        cache.set("127.0.0.1", None, data1.clone(), &ip_config);
-        cache.set(
+        cache.set(CacheScope::Route, "127.0.0.1", None, data1.clone(), &ip_config);
+        cache.set(CacheScope::Route, 
             "127.0.0.1",
             Some("example.com"),
             data2.clone(),
@@ -164,8 +506,8 @@ mod tests {
         );
 
         // 验证不同粒度缓存独立
-        let ip_result = cache.get("127.0.0.1", None, &CacheGranularity::Ip);
-        let ip_host_result = cache.get("127.0.0.1", Some("example.com"), &CacheGranularity::IpHost);
+        let ip_result = cache.get(CacheScope::Route, "127.0.0.1", None, &CacheGranularity::Ip);
+        let ip_host_result = cache.get(CacheScope::Route, "127.0.0.1", Some("example.com"), &CacheGranularity::IpHost);
 
         assert_eq!(ip_result.unwrap().data, data1);
         assert_eq!(ip_host_result.unwrap().data, data2);
@@ -179,12 +521,13 @@ mod tests {
             ttl: 1000,
             reject: Some(true),
             reject_reason: Some("Blocked".to_string()),
+            reject_ttl: None,
         };
 
         let data = json!(null);
-        cache.set("192.168.1.1", None, data, &reject_config);
+        cache.set(CacheScope::Route, "192.168.1.1", None, data, &reject_config);
 
-        let result = cache.get("192.168.1.1", None, &CacheGranularity::Ip);
+        let result = cache.get(CacheScope::Route, "192.168.1.1", None, &CacheGranularity::Ip);
         assert!(result.is_some());
         let entry = result.unwrap();
         assert!(entry.is_rejection);
@@ -199,15 +542,104 @@ mod tests {
             ttl: 10, // 10ms
             reject: None,
             reject_reason: None,
+            reject_ttl: None,
         };
 
         let data = json!({"k":"v"});
-        cache.set("10.0.0.1", None, data, &short_cfg);
+        cache.set(CacheScope::Route, "10.0.0.1", None, data, &short_cfg);
         // 立即命中
-        assert!(cache.get("10.0.0.1", None, &CacheGranularity::Ip).is_some());
+        assert!(cache.get(CacheScope::Route, "10.0.0.1", None, &CacheGranularity::Ip).is_some());
         // 等待过期
         std::thread::sleep(std::time::Duration::from_millis(20));
         // 过期访问应返回 None
-        assert!(cache.get("10.0.0.1", None, &CacheGranularity::Ip).is_none());
+        assert!(cache.get(CacheScope::Route, "10.0.0.1", None, &CacheGranularity::Ip).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_clear_and_stats() {
+        let cache = RouterMotdCache::new();
+        let ip_config = CacheConfig {
+            granularity: CacheGranularity::Ip,
+            ttl: 1000,
+            reject: None,
+            reject_reason: None,
+            reject_ttl: None,
+        };
+        let ip_host_config = CacheConfig {
+            granularity: CacheGranularity::IpHost,
+            ..ip_config.clone()
+        };
+
+        cache.set(CacheScope::Route, "10.0.0.2", Some("a.example.com"), json!(1), &ip_host_config);
+        cache.set(CacheScope::Route, "10.0.0.2", Some("b.example.com"), json!(2), &ip_host_config);
+        cache.set(CacheScope::Route, "10.0.0.3", None, json!(3), &ip_config);
+
+        // IP+Host 条目优先命中，未缓存的 host 回落到 IP 粒度条目
+        assert_eq!(cache.lookup_decision(CacheScope::Route, "10.0.0.2", "a.example.com").await.unwrap().data, json!(1));
+        assert_eq!(cache.lookup_decision(CacheScope::Route, "10.0.0.3", "c.example.com").await.unwrap().data, json!(3));
+        assert!(cache.lookup_decision(CacheScope::Route, "10.0.0.4", "a.example.com").await.is_none());
+
+        cache.clear_client("10.0.0.2", Some("a.example.com"));
+        assert!(cache.lookup_decision(CacheScope::Route, "10.0.0.2", "a.example.com").await.is_none());
+        assert!(cache.lookup_decision(CacheScope::Route, "10.0.0.2", "b.example.com").await.is_some());
+        cache.clear_client("10.0.0.2", None);
+        assert!(cache.lookup_decision(CacheScope::Route, "10.0.0.2", "b.example.com").await.is_none());
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+        cache.clear_all();
+        assert_eq!(cache.get_stats().total_entries, 0);
+    }
+
+    #[test]
+    fn test_cache_lru_eviction() {
+        let cache = RouterMotdCache::new();
+        cache.set_max_entries(Some(10));
+        let config = CacheConfig {
+            granularity: CacheGranularity::Ip,
+            ttl: 1000,
+            reject: None,
+            reject_reason: None,
+            reject_ttl: None,
+        };
+
+        for i in 0..10 {
+            cache.set(CacheScope::Route, &format!("10.1.0.{i}"), None, json!(i), &config);
+        }
+        // 访问过的条目不会被优先淘汰
+        assert!(cache.get(CacheScope::Route, "10.1.0.0", None, &CacheGranularity::Ip).is_some());
+        cache.set(CacheScope::Route, "10.1.0.10", None, json!(10), &config);
+
+        // 超出上限后淘汰至 9 条，最久未访问的 10.1.0.1 与 10.1.0.2 被移除
+        let stats = cache.get_stats();
+        assert_eq!((stats.total_entries, stats.evictions), (9, 2));
+        assert!(cache.get(CacheScope::Route, "10.1.0.0", None, &CacheGranularity::Ip).is_some());
+        assert!(cache.get(CacheScope::Route, "10.1.0.1", None, &CacheGranularity::Ip).is_none());
+        assert!(cache.get(CacheScope::Route, "10.1.0.2", None, &CacheGranularity::Ip).is_none());
+    }
+
+    #[test]
+    fn test_cache_rejection_ttl_and_blocked() {
+        let cache = RouterMotdCache::new();
+        let config = CacheConfig {
+            granularity: CacheGranularity::IpHost,
+            ttl: 10,
+            reject: Some(true),
+            reject_reason: Some("Banned".to_string()),
+            reject_ttl: Some(60_000),
+        };
+        cache.set(CacheScope::Route, "10.2.0.1", Some("mc.example.com"), json!(null), &config);
+
+        // 拒绝决策按 rejectTtl 保留，且与 MOTD 缓存互不影响
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(cache.get(CacheScope::Route, "10.2.0.1", Some("mc.example.com"), &CacheGranularity::IpHost).is_some());
+        assert!(cache.get(CacheScope::Motd, "10.2.0.1", Some("mc.example.com"), &CacheGranularity::IpHost).is_none());
+
+        let blocked = cache.blocked();
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].scope, "route");
+        assert_eq!(blocked[0].ip, "10.2.0.1");
+        assert_eq!(blocked[0].host.as_deref(), Some("mc.example.com"));
+        assert_eq!(blocked[0].reject_reason.as_deref(), Some("Banned"));
     }
 }
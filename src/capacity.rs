@@ -0,0 +1,162 @@
+//! geofront/src/capacity.rs
+//! Global and per-tenant player caps, with optional eviction of
+//! lower-priority players when a cap is reached.
+
+use crate::{
+    connection,
+    state::{ADMITTED, OPTIONS},
+    types::{CapacityConfig, DisconnectReason, ProxyConnection, RouteDecision},
+};
+use std::{collections::HashMap, time::Instant};
+use tracing::info;
+
+/// A player counted against the caps.
+#[derive(Clone, Debug)]
+pub struct Admission {
+    pub priority: i32,
+    pub tenant: Option<String>,
+    pub since: Instant,
+}
+
+/// Counts `conn_id` against the caps for its routing decision. Returns false
+/// when the player must be refused; may evict another player to make room.
+pub fn admit(conn_id: ProxyConnection, decision: &RouteDecision) -> bool {
+    let config = OPTIONS.read().unwrap().capacity.clone();
+    let admission = Admission {
+        priority: decision.priority.unwrap_or(0),
+        tenant: decision.tenant.clone(),
+        since: Instant::now(),
+    };
+
+    let victim = {
+        let mut admitted = ADMITTED.lock().unwrap();
+        let victim = match &config {
+            Some(config) => match make_room(&admitted, config, &admission) {
+                Ok(victim) => victim,
+                Err(()) => return false,
+            },
+            None => None,
+        };
+        if let Some(victim) = victim {
+            admitted.remove(&victim);
+        }
+        admitted.insert(conn_id, admission);
+        victim
+    };
+
+    if let Some(victim) = victim {
+        info!(conn = victim, by = conn_id, "Evicting player to make room");
+        connection::kick(victim, DisconnectReason::Evicted);
+    }
+    true
+}
+
+/// Stops counting `conn_id`; called from `cleanup_conn`.
+pub fn release(conn_id: ProxyConnection) {
+    ADMITTED.lock().unwrap().remove(&conn_id);
+}
+
+/// `Ok(None)` if there is room, `Ok(Some(victim))` if a player must be
+/// evicted first, `Err(())` if `new` cannot be admitted.
+fn make_room(
+    admitted: &HashMap<ProxyConnection, Admission>,
+    config: &CapacityConfig,
+    new: &Admission,
+) -> Result<Option<ProxyConnection>, ()> {
+    let tenant_limit = new
+        .tenant
+        .as_ref()
+        .and_then(|t| config.tenant_max_players.get(t));
+    let tenant_full = tenant_limit.is_some_and(|&limit| {
+        admitted.values().filter(|a| a.tenant == new.tenant).count() as u64 >= limit
+    });
+    let global_full = config
+        .max_players
+        .is_some_and(|limit| admitted.len() as u64 >= limit);
+    if !tenant_full && !global_full {
+        return Ok(None);
+    }
+    if !config.evict {
+        return Err(());
+    }
+
+    // Evicting within the tenant frees a slot under both caps.
+    admitted
+        .iter()
+        .filter(|(_, a)| !tenant_full || a.tenant == new.tenant)
+        .filter(|(_, a)| a.priority < new.priority)
+        .min_by_key(|(_, a)| (a.priority, a.since))
+        .map(|(id, _)| Some(*id))
+        .ok_or(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn admission(priority: i32, tenant: Option<&str>, age_secs: u64) -> Admission {
+        Admission {
+            priority,
+            tenant: tenant.map(str::to_string),
+            since: Instant::now() - Duration::from_secs(age_secs),
+        }
+    }
+
+    #[test]
+    fn test_global_cap() {
+        let admitted = HashMap::from([
+            (1, admission(0, None, 30)),
+            (2, admission(0, None, 60)),
+            (3, admission(5, None, 90)),
+        ]);
+        let mut config = CapacityConfig {
+            max_players: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            make_room(&admitted, &config, &admission(10, None, 0)),
+            Err(())
+        );
+
+        config.evict = true;
+        // Lowest priority first, then the oldest.
+        assert_eq!(
+            make_room(&admitted, &config, &admission(10, None, 0)),
+            Ok(Some(2))
+        );
+        // Equal priorities never evict each other.
+        assert_eq!(
+            make_room(&admitted, &config, &admission(0, None, 0)),
+            Err(())
+        );
+
+        config.max_players = Some(4);
+        assert_eq!(
+            make_room(&admitted, &config, &admission(0, None, 0)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_tenant_cap() {
+        let admitted = HashMap::from([
+            (1, admission(0, Some("a"), 90)),
+            (2, admission(1, Some("b"), 30)),
+        ]);
+        let config = CapacityConfig {
+            max_players: Some(10),
+            tenant_max_players: HashMap::from([("b".to_string(), 1)]),
+            evict: true,
+        };
+        // Tenant "b" is full; the older, lower player of tenant "a" is spared.
+        assert_eq!(
+            make_room(&admitted, &config, &admission(2, Some("b"), 0)),
+            Ok(Some(2))
+        );
+        assert_eq!(
+            make_room(&admitted, &config, &admission(2, Some("a"), 0)),
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! geofront/src/capture.rs
+//! Packet captures of single connections (`proxy_start_capture`), for
+//! debugging protocol issues with specific clients. The bytes relayed each
+//! way, as geofront sees them before forwarding (so decrypted for clients
+//! it authenticates), are written to a pcap file as raw IP packets with
+//! synthetic TCP headers that Wireshark follows as one stream. The client
+//! side shows the peer IP with a port made up from the connection id.
+//!
+//! A capture stops on its own before the file would exceed its byte cap,
+//! and when the connection closes. Captured sessions relay in userspace;
+//! one already relaying through sockmap is only captured from its next
+//! userspace relay on.
+
+use crate::{
+    events,
+    state::{CAPTURES, CONN_INFO},
+    types::ProxyConnection,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Result, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+use tracing::{info, warn};
+
+/// `LINKTYPE_RAW`: packets start with an IPv4 or IPv6 header.
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+/// Payload per synthetic packet, so that each fits the snap length.
+const MAX_SEGMENT: usize = 65535 - 60;
+const PCAP_HEADER_LEN: u64 = 24;
+const RECORD_HEADER_LEN: u64 = 16;
+/// Backend port shown when the backend address is not known.
+const DEFAULT_BACKEND_PORT: u16 = 25565;
+
+/// An open capture and where each side's stream is at.
+pub struct Capture {
+    writer: BufWriter<File>,
+    written: u64,
+    max_bytes: u64,
+    client: SocketAddr,
+    server: SocketAddr,
+    client_seq: u32,
+    server_seq: u32,
+}
+
+impl Capture {
+    fn create(path: &str, max_bytes: u64, client: SocketAddr, server: SocketAddr) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&0xa1b2_c3d4u32.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        Ok(Capture {
+            writer,
+            written: PCAP_HEADER_LEN,
+            max_bytes,
+            client,
+            server,
+            client_seq: 1,
+            server_seq: 1,
+        })
+    }
+
+    /// Writes `bytes` as packets from the client (`sent`) or the backend.
+    /// Returns `false` once the cap is reached, writing nothing more.
+    fn record(&mut self, bytes: &[u8], sent: bool) -> Result<bool> {
+        for segment in bytes.chunks(MAX_SEGMENT) {
+            let packet = if sent {
+                packet(self.client, self.server, self.client_seq, self.server_seq, segment)
+            } else {
+                packet(self.server, self.client, self.server_seq, self.client_seq, segment)
+            };
+            let len = RECORD_HEADER_LEN + packet.len() as u64;
+            if self.written + len > self.max_bytes {
+                return Ok(false);
+            }
+            let now_ms = events::now_ms();
+            self.writer.write_all(&((now_ms / 1000) as u32).to_le_bytes())?;
+            self.writer.write_all(&((now_ms % 1000 * 1000) as u32).to_le_bytes())?;
+            self.writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&packet)?;
+            self.written += len;
+            let seq = if sent { &mut self.client_seq } else { &mut self.server_seq };
+            *seq = seq.wrapping_add(segment.len() as u32);
+        }
+        Ok(true)
+    }
+}
+
+/// An IP packet carrying `payload` in a TCP segment from `src` to `dst`.
+fn packet(src: SocketAddr, dst: SocketAddr, seq: u32, ack: u32, payload: &[u8]) -> Vec<u8> {
+    let mut tcp = Vec::with_capacity(20 + payload.len());
+    tcp.extend_from_slice(&src.port().to_be_bytes());
+    tcp.extend_from_slice(&dst.port().to_be_bytes());
+    tcp.extend_from_slice(&seq.to_be_bytes());
+    tcp.extend_from_slice(&ack.to_be_bytes());
+    // Data offset 5 words; PSH and ACK.
+    tcp.extend_from_slice(&[0x50, 0x18]);
+    tcp.extend_from_slice(&u16::MAX.to_be_bytes());
+    // Checksum left unset, urgent pointer.
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    tcp.extend_from_slice(payload);
+
+    let mut ip = Vec::with_capacity(40 + tcp.len());
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            ip.extend_from_slice(&[0x45, 0]);
+            ip.extend_from_slice(&((20 + tcp.len()) as u16).to_be_bytes());
+            // Identification, don't fragment, TTL 64, TCP, checksum below.
+            ip.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+            ip.extend_from_slice(&src.octets());
+            ip.extend_from_slice(&dst.octets());
+            let checksum = ipv4_checksum(&ip);
+            ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        (src, dst) => {
+            ip.extend_from_slice(&[0x60, 0, 0, 0]);
+            ip.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+            ip.extend_from_slice(&[6, 64]);
+            ip.extend_from_slice(&to_v6(src).octets());
+            ip.extend_from_slice(&to_v6(dst).octets());
+        }
+    }
+    ip.extend_from_slice(&tcp);
+    ip
+}
+
+fn to_v6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let sum = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+    let folded = (sum & 0xffff) + (sum >> 16);
+    !((folded & 0xffff) + (folded >> 16)) as u16
+}
+
+/// The client and backend as shown in the capture.
+fn endpoints(conn_id: ProxyConnection) -> Option<(SocketAddr, SocketAddr)> {
+    let info = CONN_INFO.get(&conn_id)?;
+    let client_ip = info.peer_ip.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let client = SocketAddr::new(client_ip, 0xC000 | (conn_id % 0x4000) as u16);
+    let server = info
+        .backend
+        .as_deref()
+        .and_then(|backend| backend.parse().ok())
+        .unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DEFAULT_BACKEND_PORT));
+    Some((client, server))
+}
+
+/// Starts capturing a connection into a new pcap file at `path`, replacing
+/// any capture it had; returns `Ok(false)` if the connection is unknown.
+pub fn start(conn_id: ProxyConnection, path: &str, max_bytes: u64) -> Result<bool> {
+    let Some((client, server)) = endpoints(conn_id) else {
+        return Ok(false);
+    };
+    let capture = Capture::create(path, max_bytes, client, server)?;
+    info!(conn = conn_id, path, max_bytes, "Capturing connection");
+    if let Some(previous) = CAPTURES.insert(conn_id, capture) {
+        finish(conn_id, previous);
+    }
+    Ok(true)
+}
+
+/// Stops capturing a connection; returns `false` if it was not.
+pub fn stop(conn_id: ProxyConnection) -> bool {
+    match CAPTURES.remove(&conn_id) {
+        Some((_, capture)) => {
+            finish(conn_id, capture);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn is_active(conn_id: ProxyConnection) -> bool {
+    CAPTURES.contains_key(&conn_id)
+}
+
+fn finish(conn_id: ProxyConnection, mut capture: Capture) {
+    if let Err(e) = capture.writer.flush() {
+        warn!(conn = conn_id, "Failed to write capture: {}", e);
+    }
+    info!(conn = conn_id, bytes = capture.written, "Capture stopped");
+}
+
+/// Records bytes relayed client to backend (`sent`) or back, if the
+/// connection is captured.
+pub fn feed(conn_id: ProxyConnection, bytes: &[u8], sent: bool) {
+    let recorded = match CAPTURES.get_mut(&conn_id) {
+        Some(mut capture) => capture.record(bytes, sent),
+        None => return,
+    };
+    match recorded {
+        Ok(true) => {}
+        Ok(false) => {
+            info!(conn = conn_id, "Capture reached its byte cap");
+            stop(conn_id);
+        }
+        Err(e) => {
+            warn!(conn = conn_id, "Failed to write capture: {}", e);
+            stop(conn_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_stops_at_cap() {
+        let path = std::env::temp_dir().join(format!("geofront-capture-{}.pcap", std::process::id()));
+        let client = "198.51.100.7:49152".parse().unwrap();
+        let server = "203.0.113.1:25565".parse().unwrap();
+        // Room for the file header and one 5-byte packet.
+        let mut capture = Capture::create(path.to_str().unwrap(), 24 + 16 + 45, client, server).unwrap();
+        assert!(capture.record(b"hello", true).unwrap());
+        assert!(!capture.record(b"world", false).unwrap());
+        capture.writer.flush().unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(file.len(), 24 + 16 + 45);
+        assert_eq!(&file[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        let ip = &file[40..];
+        assert_eq!(ip[0], 0x45);
+        assert_eq!(ipv4_checksum(&ip[..20]), 0);
+        assert_eq!(&ip[12..16], &[198, 51, 100, 7]);
+        assert_eq!(&ip[40..], b"hello");
+    }
+}
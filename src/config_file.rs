@@ -0,0 +1,184 @@
+//! geofront/src/config_file.rs
+//! Configuration loaded from a JSON file (`proxy_load_config`) and reloaded
+//! when it changes. The file may hold `options` (`GeofrontOptions`, which
+//! include the per-IP limits and trusted CIDRs), `vhosts` and `routes`;
+//! sections left out keep their current value. Every section is parsed
+//! before any is applied, so a file that fails to parse changes nothing.
+//!
+//! Each load publishes a `config_reloaded` event summarising what changed:
+//! the options by name and the vhosts and routes by host pattern. The
+//! watcher polls the file's modification time and size, as edits through
+//! editors that replace the file are missed by inotify-style watches.
+
+use crate::{
+    embed::Geofront,
+    events::{self, ProxyEvent},
+    state::{CONFIG_RELOAD_QUEUE, CONFIG_WATCHER, LISTENER_STATE, OPTIONS, STATIC_ROUTES, VHOSTS},
+    types::{ConfigReload, GeofrontOptions, HostDiff, StaticRoute, VirtualHost},
+    wakeup,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+
+/// Reload events kept for polling; older ones are dropped first.
+const MAX_PENDING_RELOADS: usize = 64;
+/// Shortest interval the watcher polls at.
+const MIN_WATCH_INTERVAL_MS: u64 = 100;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ConfigFile {
+    options: Option<GeofrontOptions>,
+    vhosts: Option<Vec<VirtualHost>>,
+    routes: Option<Vec<StaticRoute>>,
+}
+
+/// Loads `path` and applies it, publishing what changed.
+pub fn load(path: &str) -> Result<ConfigReload, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let file: ConfigFile = serde_json::from_str(&text).map_err(|e| format!("invalid config {}: {}", path, e))?;
+
+    let mut reload = ConfigReload {
+        timestamp_ms: events::now_ms(),
+        path: path.to_string(),
+        ..Default::default()
+    };
+    let geofront = Geofront::new();
+    if let Some(options) = file.options {
+        reload.options_changed = changed_options(&OPTIONS.read().unwrap(), &options);
+        geofront.set_options(options);
+    }
+    if let Some(vhosts) = file.vhosts {
+        reload.vhosts = diff_hosts(&VHOSTS.read().unwrap(), &vhosts, |vhost| &vhost.host);
+        geofront.set_vhosts(vhosts);
+    }
+    if let Some(routes) = file.routes {
+        reload.routes = diff_hosts(&STATIC_ROUTES.read().unwrap(), &routes, |route| &route.host);
+        geofront.set_routes(routes);
+    }
+    info!(
+        path,
+        options = reload.options_changed.len(),
+        vhosts = reload.vhosts.len(),
+        routes = reload.routes.len(),
+        "Loaded config file"
+    );
+    publish(reload.clone());
+    Ok(reload)
+}
+
+fn publish(reload: ConfigReload) {
+    wakeup::push_bounded(&mut CONFIG_RELOAD_QUEUE.lock().unwrap(), reload.clone(), MAX_PENDING_RELOADS);
+    events::emit(ProxyEvent::ConfigReloaded(reload));
+}
+
+/// Names of the options whose value differs.
+fn changed_options(old: &GeofrontOptions, new: &GeofrontOptions) -> Vec<String> {
+    let (Ok(Value::Object(old)), Ok(Value::Object(new))) = (serde_json::to_value(old), serde_json::to_value(new)) else {
+        return Vec::new();
+    };
+    let mut changed: Vec<String> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    changed.extend(old.keys().filter(|key| !new.contains_key(*key)).cloned());
+    changed.sort();
+    changed
+}
+
+/// Host patterns added, removed or changed between two tables.
+fn diff_hosts<T: Serialize>(old: &[T], new: &[T], host: impl Fn(&T) -> &str) -> HostDiff {
+    let by_host = |entries: &[T]| -> BTreeMap<String, Value> {
+        entries
+            .iter()
+            .map(|entry| (host(entry).to_string(), serde_json::to_value(entry).unwrap_or_default()))
+            .collect()
+    };
+    let (old, new) = (by_host(old), by_host(new));
+    let mut diff = HostDiff::default();
+    for (host, entry) in &new {
+        match old.get(host) {
+            None => diff.added.push(host.clone()),
+            Some(previous) if previous != entry => diff.changed.push(host.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = old.keys().filter(|host| !new.contains_key(*host)).cloned().collect();
+    diff
+}
+
+/// Reloads `path` whenever it changes, polling every `interval_ms`; `None`
+/// stops watching. Replaces any previous watcher.
+pub fn watch(path: &str, interval_ms: Option<u64>) {
+    let mut watcher = CONFIG_WATCHER.lock().unwrap();
+    if let Some(handle) = watcher.take() {
+        handle.abort();
+    }
+    let Some(interval_ms) = interval_ms else {
+        return;
+    };
+    let path = path.to_string();
+    let interval = Duration::from_millis(interval_ms.max(MIN_WATCH_INTERVAL_MS));
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    *watcher = Some(runtime.spawn(async move {
+        let mut seen = stamp(&path);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let current = stamp(&path);
+            if current == seen || current.is_none() {
+                continue;
+            }
+            seen = current;
+            if let Err(e) = load(&path) {
+                warn!(path, "Config file changed but was not applied: {}", e);
+                publish(ConfigReload {
+                    timestamp_ms: events::now_ms(),
+                    path: path.clone(),
+                    error: Some(e),
+                    ..Default::default()
+                });
+            }
+        }
+    }));
+}
+
+/// Modification time and size, which change with every write.
+fn stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(host: &str, port: u16) -> StaticRoute {
+        serde_json::from_value(serde_json::json!({
+            "host": host,
+            "route": { "remoteHost": "10.0.0.1", "remotePort": port }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diffs() {
+        let old = [route("a.example.com", 25565), route("b.example.com", 25565)];
+        let new = [route("b.example.com", 25566), route("*.example.com", 25565)];
+        let diff = diff_hosts(&old, &new, |route| &route.host);
+        assert_eq!(diff.added, ["*.example.com"]);
+        assert_eq!(diff.removed, ["a.example.com"]);
+        assert_eq!(diff.changed, ["b.example.com"]);
+
+        let old = GeofrontOptions::default();
+        let new: GeofrontOptions = serde_json::from_value(serde_json::json!({ "handshakeTimeoutMs": 500 })).unwrap();
+        assert_eq!(changed_options(&old, &new), ["handshakeTimeoutMs"]);
+    }
+}
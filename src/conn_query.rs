@@ -0,0 +1,165 @@
+//! geofront/src/conn_query.rs
+//! Live connections selected by tag, host, backend, username and client IP
+//! (`proxy_list_connections`, `proxy_kick_where`), for hosts acting on
+//! groups of players at once, such as draining one backend. Tags come from
+//! `RouteDecision.tags`, stored as `true` entries of the connection's tags,
+//! or from `proxy_set_connection_tag`.
+
+use crate::{
+    connection::kick,
+    limiter,
+    schedule::host_matches,
+    snapshot,
+    state::CONN_INFO,
+    types::{ConnFilter, ConnInfo, ConnectionDetails, DisconnectReason, ProxyConnection, RateLimitConfig},
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, de::Error};
+use serde_json::Value;
+use std::net::IpAddr;
+
+/// Whether a connection passes every criterion the filter sets.
+pub fn matches(filter: &ConnFilter, info: &ConnInfo) -> bool {
+    let has_tag = |tag: &String| info.tags.get(tag).is_some_and(|value| !matches!(value, Value::Null | Value::Bool(false)));
+    filter.tags.iter().all(has_tag)
+        && filter
+            .host
+            .as_deref()
+            .is_none_or(|pattern| info.host.as_deref().is_some_and(|host| host_matches(pattern, host)))
+        && filter
+            .backend
+            .as_deref()
+            .is_none_or(|backend| info.backend.as_deref() == Some(backend))
+        && filter.username.as_deref().is_none_or(|username| {
+            info.username
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(username))
+        })
+        && filter.ip.is_none_or(|net| {
+            info.peer_ip
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| net.contains(&ip.to_canonical()))
+        })
+}
+
+/// Whether `filter` sets no criterion, and so matches every connection.
+pub fn is_unfiltered(filter: &ConnFilter) -> bool {
+    filter.tags.is_empty()
+        && filter.host.is_none()
+        && filter.backend.is_none()
+        && filter.username.is_none()
+        && filter.ip.is_none()
+}
+
+/// Ids of the live connections matching `filter`.
+pub fn select(filter: &ConnFilter) -> Vec<ProxyConnection> {
+    let mut ids: Vec<ProxyConnection> = CONN_INFO
+        .iter()
+        .filter(|entry| matches(filter, entry.value()))
+        .map(|entry| *entry.key())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Everything known about the connections matching `filter`, oldest first.
+pub fn list(filter: &ConnFilter) -> Vec<ConnectionDetails> {
+    select(filter).into_iter().filter_map(snapshot::connection).collect()
+}
+
+/// Replaces the rate limits of the connections matching `filter`; returns
+/// how many were changed.
+pub fn set_rate_limit(filter: &ConnFilter, limit: &RateLimitConfig) -> usize {
+    select(filter)
+        .into_iter()
+        .filter(|&conn_id| limiter::set_limits(conn_id, limit))
+        .count()
+}
+
+/// Closes the connections matching `filter`, queueing a disconnection event
+/// for each; returns how many were closed.
+pub fn kick_where(filter: &ConnFilter) -> usize {
+    select(filter)
+        .into_iter()
+        .filter(|&conn_id| kick(conn_id, DisconnectReason::Kicked))
+        .count()
+}
+
+/// Reads `ip` as a CIDR or a single address.
+pub fn deserialize_ip<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<IpNet>, D::Error> {
+    let Some(text) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    text.parse::<IpNet>()
+        .or_else(|_| text.parse::<IpAddr>().map(IpNet::from))
+        .map(Some)
+        .map_err(|_| D::Error::custom(format!("invalid IP or CIDR: {}", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{CONN_MANAGER, DISCONNECTION_EVENT_QUEUE};
+
+    fn filter(json: serde_json::Value) -> ConnFilter {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut info = ConnInfo {
+            peer_ip: "::ffff:198.51.100.7".to_string(),
+            host: Some("eu.play.example.com".to_string()),
+            backend: Some("10.0.0.1:25565".to_string()),
+            username: Some("Steve".to_string()),
+            ..Default::default()
+        };
+        info.tags.insert("vip".to_string(), Value::Bool(true));
+        info.tags.insert("trial".to_string(), Value::Bool(false));
+
+        assert!(matches(&filter(serde_json::json!({})), &info));
+        assert!(matches(
+            &filter(serde_json::json!({
+                "tags": ["vip"],
+                "host": "*.play.example.com",
+                "backend": "10.0.0.1:25565",
+                "ip": "198.51.100.0/24"
+            })),
+            &info
+        ));
+        assert!(matches(&filter(serde_json::json!({ "ip": "198.51.100.7", "username": "steve" })), &info));
+        assert!(!matches(&filter(serde_json::json!({ "username": "Alex" })), &info));
+        assert!(!matches(&filter(serde_json::json!({ "tags": ["vip", "trial"] })), &info));
+        assert!(!matches(&filter(serde_json::json!({ "backend": "10.0.0.2:25565" })), &info));
+        assert!(serde_json::from_value::<ConnFilter>(serde_json::json!({ "ip": "nope" })).is_err());
+    }
+
+    #[test]
+    fn test_is_unfiltered() {
+        assert!(is_unfiltered(&filter(serde_json::json!({}))));
+        assert!(is_unfiltered(&filter(serde_json::json!({ "tags": [] }))));
+        assert!(!is_unfiltered(&filter(serde_json::json!({ "username": "Steve" }))));
+    }
+
+    #[tokio::test]
+    async fn test_kick_where_queues_one_event_per_connection() {
+        let tag = "test-kick-where";
+        let ids = [u64::MAX - 20, u64::MAX - 21, u64::MAX - 22];
+        for conn_id in ids {
+            let mut info = ConnInfo::default();
+            info.tags.insert(tag.to_string(), Value::Bool(true));
+            CONN_INFO.insert(conn_id, info);
+            CONN_MANAGER.insert(conn_id, tokio::spawn(std::future::pending()));
+        }
+
+        assert_eq!(kick_where(&filter(serde_json::json!({ "tags": [tag] }))), ids.len());
+        let queue = DISCONNECTION_EVENT_QUEUE.lock().unwrap();
+        for conn_id in ids {
+            let events: Vec<_> = queue.iter().filter(|event| event.conn_id == conn_id).collect();
+            assert_eq!(events.len(), 1, "connection {} queued {} events", conn_id, events.len());
+            assert_eq!(events[0].reason, DisconnectReason::Kicked);
+        }
+        drop(queue);
+        assert_eq!(kick_where(&filter(serde_json::json!({ "tags": [tag] }))), 0);
+    }
+}
@@ -2,37 +2,201 @@
 //! Core connection handling logic.
 
 use crate::{
-    protocol::{self, write_disconnect},
+    accounting,
+    auth::{self, ClientStream},
+    buffer_pool,
+    cache::{self, CacheScope},
+    capacity,
+    capture,
+    default_motd,
+    discovery,
+    events::{self, ProxyEvent},
+    forwarding,
+    geoip,
+    handler::{self, NoRoute},
+    health,
+    health_check,
+    latency,
+    lifecycle,
+    limiter::{self, ConnLimiter},
+    limits,
+    logging,
+    login_phase::LoginTracker,
+    login_throttle,
+    messages,
+    mirror,
+    motd_players,
+    outbound::{self, SocketConfig},
+    overflow, pause,
+    protocol::{self, ConnReader, write_disconnect},
+    protocol_errors::{self, SampledReader},
+    quota,
+    route_audit,
+    route_metrics,
+    schedule,
+    static_routes,
+    tarpit,
+    tcp_options,
+    tls,
+    transfer::{self, FrameTracker},
+    transport::{ClientTransport, ListenerTransport},
     state::{
-        ACTIVE_CONN, CONN_MANAGER, CONN_METRICS, DISCONNECTION_EVENT_QUEUE, FFI_MOTD_LOCK,
-        FFI_ROUTER_LOCK, MOTD_REQUEST_QUEUE, OPTIONS, PENDING_MOTDS, PENDING_ROUTES, RATE_LIMITERS,
-        ROUTE_REQUEST_QUEUE, ROUTER_MOTD_CACHE, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT,
+        ACTIVE_CONN, BACKEND_CONNECT_HISTOGRAM, CONN_COUNTER, CONN_INFO, CONN_MANAGER, CONN_METRICS,
+        DISCONNECTION_EVENT_QUEUE, HANDSHAKE_TIMEOUTS, HANDSHAKES, LISTENER_TOTALS, LOGIN_SOCKETS, LOGINS, OPTIONS,
+        RATE_LIMITERS, ROUTER_MOTD_CACHE, ROUTING_HISTOGRAM, SESSION_HISTOGRAM, STATUS_REQUESTS, TOTAL_BYTES_RECV,
+        TOTAL_BYTES_SENT, TOTAL_CONN, UNTRUSTED_PROXY_HEADERS,
     },
     types::{
-        AsyncStream, CacheGranularity, DisconnectionEvent, HandshakeData, MotdDecision,
-        MotdRequest, ProxyConnection, ProxyProtocolIn, RouteDecision, RouteRequest,
+        AsyncStream, CacheConfig, ConnInfo, ConnMetrics, DisconnectReason, DisconnectionEvent, HandshakeData,
+        LifecycleKind, ListenerOptions, MotdDecision, MotdRequest, ProtocolErrorKind, ProxyConnection, ProxyListener, ProxyProtocolIn, RouteAudit, RouteDecision,
+        RouteRequest, StatusPassthrough, TransportKind,
     },
+    upstream,
+    usage,
+    vhosts,
+    wakeup,
 };
 use ppp::PartialResult;
 use std::{
-    io::{Cursor, Error, ErrorKind},
-    net::SocketAddr,
-    num::NonZeroU32,
-    sync::atomic::Ordering,
+    io::{Cursor, Error, ErrorKind, IoSlice},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::Ordering,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::TcpStream,
-    sync::oneshot,
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
 };
-use tokio_socks::tcp::Socks5Stream;
-use tracing::{error, info, warn};
+use tracing::{Instrument, debug, error, info, warn};
 use url::Url;
 
+/// Limit on connecting to a status passthrough backend, and on the relayed
+/// status exchange.
+const STATUS_PASSTHROUGH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Limit on a TLS or WebSocket client completing its handshake.
+const TRANSPORT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default limit on a client sending its handshake and then its login start
+/// or status request.
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 5_000;
+
+/// Delay before racing the next backend address, as recommended by RFC 8305.
+const DEFAULT_HAPPY_EYEBALLS_DELAY_MS: u64 = 250;
+
+/// Most bytes written per rate limiter wait, less under smaller bursts;
+/// relay buffers may be larger.
+const LIMITED_CHUNK_SIZE: usize = 4096;
+
+/// Pause between checks for a backend to come back, while a client whose
+/// backend dropped is held (`reconnectGraceMs`), and the limit on each.
+const RESUME_PROBE_INTERVAL: Duration = Duration::from_millis(500);
+const RESUME_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Accepts connections on `listener` until accepting fails, registering and
+/// handling each one over `transport` with the listener's `options`.
+pub async fn serve(
+    listener_id: ProxyListener,
+    acceptor: usize,
+    listener: TcpListener,
+    transport: ListenerTransport,
+    options: Arc<ListenerOptions>,
+) {
+    // Expired cache entries are swept while anything is listening.
+    cache::start_sweeper();
+    loop {
+        pause::wait_accepting(listener_id).await;
+        match listener.accept().await {
+            Ok((inb, peer)) => {
+                let conn_id = CONN_COUNTER.fetch_add(1, Ordering::SeqCst);
+                // Only this loop counts connections in, so the check holds
+                // until the increment below.
+                let full = options.max_connections.is_some_and(|max| {
+                    LISTENER_TOTALS
+                        .lock()
+                        .unwrap()
+                        .get(&listener_id)
+                        .is_some_and(|totals| totals.active >= max)
+                });
+                if full {
+                    debug!(
+                        conn = conn_id,
+                        %peer,
+                        listener = listener_id,
+                        "Listener connection limit reached, dropping connection"
+                    );
+                    continue;
+                }
+                if !limits::admit(conn_id, peer.ip(), &options) {
+                    debug!(conn = conn_id, %peer, "Per-IP limit reached, dropping connection");
+                    let _ = tarpit::try_hold(inb);
+                    continue;
+                }
+                TOTAL_CONN.fetch_add(1, Ordering::SeqCst);
+                ACTIVE_CONN.fetch_add(1, Ordering::SeqCst);
+                {
+                    let mut listener_totals = LISTENER_TOTALS.lock().unwrap();
+                    let totals = listener_totals.entry(listener_id).or_default();
+                    totals.accepted += 1;
+                    if totals.acceptor_accepts.len() <= acceptor {
+                        totals.acceptor_accepts.resize(acceptor + 1, 0);
+                    }
+                    totals.acceptor_accepts[acceptor] += 1;
+                    totals.active += 1;
+                }
+                CONN_INFO.insert(
+                    conn_id,
+                    ConnInfo {
+                        peer_ip: peer.ip().to_string(),
+                        connected_at_ms: events::now_ms(),
+                        listener: Some(listener_id),
+                        ..Default::default()
+                    },
+                );
+                lifecycle::record(
+                    conn_id,
+                    LifecycleKind::Connected {
+                        peer_ip: peer.ip().to_string(),
+                        listener: listener_id,
+                    },
+                );
+                let cm = Arc::new(ConnMetrics::default());
+                CONN_METRICS.insert(conn_id, cm);
+                let unlimited = Arc::new(ConnLimiter::unlimited());
+                RATE_LIMITERS.insert(conn_id, (unlimited.clone(), unlimited));
+                let span = logging::conn_span(conn_id, peer.ip());
+                let h = tokio::spawn(handle_conn(conn_id, inb, transport.clone(), options.clone()).instrument(span));
+                CONN_MANAGER.insert(conn_id, h);
+            }
+            Err(e) => {
+                error!("Accept error: {}", e);
+                break;
+            }
+        }
+    }
+}
+
 /// Main connection workflow
-pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
-    let options = (*OPTIONS.read().unwrap()).clone();
+pub async fn handle_conn(
+    conn_id: ProxyConnection,
+    mut inbound: TcpStream,
+    transport: ListenerTransport,
+    listener_options: Arc<ListenerOptions>,
+) {
+    let mut options = (*OPTIONS.read().unwrap()).clone();
+    if let Some(mode) = listener_options.proxy_protocol_in {
+        options.proxy_protocol_in = mode;
+    }
+    if let Some(tcp) = &options.tcp {
+        tcp_options::apply(&inbound, tcp);
+    }
     let mut peer_addr_override: Option<SocketAddr> = None;
+    // Destination the client connected to, as the PROXY header tells it.
+    let mut local_addr_override: Option<SocketAddr> = None;
 
     // Handle Proxy Protocol
     if options.proxy_protocol_in != ProxyProtocolIn::None {
@@ -41,7 +205,7 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
             Ok(n) => n,
             Err(e) => {
                 error!(conn = conn_id, "Failed to peek for PROXY protocol: {}", e);
-                cleanup_conn(conn_id);
+                cleanup_conn(conn_id, DisconnectReason::ProtocolError);
                 return;
             }
         };
@@ -55,7 +219,13 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                     conn = conn_id,
                     "Incomplete PROXY protocol header in strict mode, disconnecting."
                 );
-                cleanup_conn(conn_id);
+                protocol_errors::report(
+                    conn_id,
+                    ProtocolErrorKind::ProxyProtocol,
+                    &"incomplete PROXY protocol header",
+                    &buf[..n],
+                );
+                cleanup_conn(conn_id, DisconnectReason::ProxyProtocol);
                 return;
             }
         } else if header_result.is_complete() {
@@ -73,7 +243,7 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                             conn = conn_id,
                             "Failed to read PROXY protocol header after peek"
                         );
-                        cleanup_conn(conn_id);
+                        cleanup_conn(conn_id, DisconnectReason::ProtocolError);
                         return;
                     }
 
@@ -84,6 +254,10 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                             tcp4.source_port,
                         ));
                         peer_addr_override = Some(source_addr);
+                        local_addr_override = Some(std::net::SocketAddr::V4(std::net::SocketAddrV4::new(
+                            tcp4.destination_address,
+                            tcp4.destination_port,
+                        )));
                         info!(conn = conn_id, real_ip = %source_addr.ip(), "Received PROXY protocol v1 header");
                     } else if let ppp::v1::Addresses::Tcp6(tcp6) = &header.addresses {
                         let source_addr = std::net::SocketAddr::V6(std::net::SocketAddrV6::new(
@@ -93,6 +267,12 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                             0,
                         ));
                         peer_addr_override = Some(source_addr);
+                        local_addr_override = Some(std::net::SocketAddr::V6(std::net::SocketAddrV6::new(
+                            tcp6.destination_address,
+                            tcp6.destination_port,
+                            0,
+                            0,
+                        )));
                         info!(conn = conn_id, real_ip = %source_addr.ip(), "Received PROXY protocol v1 header");
                     }
                 }
@@ -106,7 +286,7 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                             conn = conn_id,
                             "Failed to read PROXY protocol header after peek"
                         );
-                        cleanup_conn(conn_id);
+                        cleanup_conn(conn_id, DisconnectReason::ProtocolError);
                         return;
                     }
 
@@ -117,6 +297,10 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                                 std::net::SocketAddrV4::new(ipv4.source_address, ipv4.source_port),
                             );
                             peer_addr_override = Some(source_addr);
+                            local_addr_override = Some(std::net::SocketAddr::V4(std::net::SocketAddrV4::new(
+                                ipv4.destination_address,
+                                ipv4.destination_port,
+                            )));
                             info!(conn = conn_id, real_ip = %source_addr.ip(), "Received PROXY protocol v2 header");
                         }
                         ppp::v2::Addresses::IPv6(ipv6) => {
@@ -128,6 +312,12 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                                     0,
                                 ));
                             peer_addr_override = Some(source_addr);
+                            local_addr_override = Some(std::net::SocketAddr::V6(std::net::SocketAddrV6::new(
+                                ipv6.destination_address,
+                                ipv6.destination_port,
+                                0,
+                                0,
+                            )));
                             info!(conn = conn_id, real_ip = %source_addr.ip(), "Received PROXY protocol v2 header");
                         }
                         _ => {
@@ -143,7 +333,13 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                             conn = conn_id,
                             "Missing or invalid PROXY protocol header in strict mode, disconnecting."
                         );
-                        cleanup_conn(conn_id);
+                        protocol_errors::report(
+                            conn_id,
+                            ProtocolErrorKind::ProxyProtocol,
+                            &"invalid PROXY protocol header",
+                            &buf[..n],
+                        );
+                        cleanup_conn(conn_id, DisconnectReason::ProxyProtocol);
                         return;
                     }
                 }
@@ -155,44 +351,193 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                     conn = conn_id,
                     "Missing or invalid PROXY protocol header in strict mode, disconnecting."
                 );
-                cleanup_conn(conn_id);
+                protocol_errors::report(
+                    conn_id,
+                    ProtocolErrorKind::ProxyProtocol,
+                    &"missing or invalid PROXY protocol header",
+                    &buf[..n],
+                );
+                cleanup_conn(conn_id, DisconnectReason::ProxyProtocol);
                 return;
             }
         }
     }
 
+    // A header from outside the trusted CIDRs may claim any address.
+    if let Some(claimed) = peer_addr_override
+        && let Some(cidrs) = &options.proxy_protocol_trusted_cidrs
+        && let Ok(peer) = inbound.peer_addr()
+        && !forwarding::is_trusted(peer.ip(), cidrs)
+    {
+        peer_addr_override = None;
+        local_addr_override = None;
+        UNTRUSTED_PROXY_HEADERS.fetch_add(1, Ordering::SeqCst);
+        warn!(
+            conn = conn_id,
+            %peer,
+            claimed_ip = %claimed.ip(),
+            "PROXY protocol header from untrusted peer"
+        );
+        if options.proxy_protocol_in == ProxyProtocolIn::Strict {
+            cleanup_conn(conn_id, DisconnectReason::ProxyProtocol);
+            return;
+        }
+    }
+    if let Some(addr) = peer_addr_override {
+        update_conn_info(conn_id, |info| info.peer_ip = addr.ip().to_string());
+        logging::record_field(conn_id, "peer_ip", &addr.ip().to_string());
+    }
+
+    // Terminate TLS or WebSocket framing behind the PROXY header, if the
+    // listener speaks either.
+    let inbound = match tokio::time::timeout(TRANSPORT_HANDSHAKE_TIMEOUT, transport.accept(inbound)).await {
+        Ok(Ok(inbound)) => inbound,
+        Ok(Err(e)) => {
+            warn!(conn = conn_id, "Transport handshake failed: {}", e);
+            cleanup_conn(conn_id, DisconnectReason::ProtocolError);
+            return;
+        }
+        Err(_) => {
+            warn!(conn = conn_id, "Transport handshake timed out");
+            cleanup_conn(conn_id, DisconnectReason::ProtocolError);
+            return;
+        }
+    };
+    let sni = inbound.server_name().map(str::to_string);
+    let transport = inbound.kind();
+    let local_addr = local_addr_override.or_else(|| inbound.local_addr().ok());
+    update_conn_info(conn_id, |info| {
+        info.sni = sni.clone();
+        info.transport = Some(transport);
+    });
+
+    // From here on the client is read through a buffer; pipelined bytes
+    // beyond the login packet are forwarded after it.
+    let mut inbound = ConnReader::new(inbound);
+
+    // Keep the first bytes of the session for protocol-error reports and the corpus
+    let sample_limit = protocol_errors::capture_limit();
+    let mut sample = Vec::new();
+
+    // The handshake and the login start or status request that follows must
+    // arrive by this deadline, so silent or dribbling clients don't hold a
+    // task and its state forever.
+    let handshake_deadline = tokio::time::Instant::now()
+        + Duration::from_millis(options.handshake_timeout_ms.unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_MS));
+
     // Parse handshake & determine next action based on state
-    let hs = match protocol::parse_handshake(&mut inbound).await {
-        Ok(h) => h,
+    let mut sampled = SampledReader::new(&mut inbound, &mut sample, sample_limit);
+    let handshake = protocol::parse_handshake(&mut sampled);
+    let Ok(handshake) = tokio::time::timeout_at(handshake_deadline, handshake).await else {
+        handshake_timed_out(conn_id);
+        return;
+    };
+    let mut hs = match handshake {
+        Ok(h) => {
+            HANDSHAKES.fetch_add(1, Ordering::SeqCst);
+            lifecycle::record(
+                conn_id,
+                LifecycleKind::HandshakeParsed {
+                    host: h.host.clone(),
+                    port: h.port,
+                    protocol: h.protocol_version,
+                    next_state: h.next_state,
+                },
+            );
+            h
+        }
+        // Scanners and probes are routine; only broken clients are worth an error.
+        Err(e) if protocol::is_not_minecraft(&e) => {
+            debug!(conn = conn_id, "Dropping non-Minecraft traffic: {}", e);
+            protocol_errors::report(conn_id, ProtocolErrorKind::NotMinecraft, &e, &sample);
+            cleanup_conn(conn_id, DisconnectReason::ProtocolError);
+            return;
+        }
         Err(e) => {
             error!(conn = conn_id, "Handshake failed: {}", e);
-            cleanup_conn(conn_id);
+            protocol_errors::report(conn_id, ProtocolErrorKind::Handshake, &e, &sample);
+            cleanup_conn(conn_id, DisconnectReason::ProtocolError);
             return;
         }
     };
 
+    if let Some(config) = &options.bungee_forwarding {
+        let peer = peer_addr_override
+            .or_else(|| inbound.get_ref().peer_addr().ok())
+            .map(|addr| addr.ip());
+        if let Some(ip) = forwarding::apply(&mut hs, peer, config) {
+            let port = peer_addr_override
+                .or_else(|| inbound.get_ref().peer_addr().ok())
+                .map_or(0, |addr| addr.port());
+            peer_addr_override = Some(SocketAddr::new(ip, port));
+            update_conn_info(conn_id, |info| info.peer_ip = ip.to_string());
+            logging::record_field(conn_id, "peer_ip", &ip.to_string());
+        }
+    }
+
+    route_metrics::count_host(&hs.host);
+    logging::record_field(conn_id, "host", &hs.host);
+    update_conn_info(conn_id, |info| {
+        info.host = Some(hs.host.clone());
+        info.uuid = hs.forwarded.as_ref().map(|f| f.uuid.clone());
+        info.port = Some(hs.port);
+        info.protocol = Some(hs.protocol_version);
+    });
+
     // Check if this is a status request (MOTD) or login request
     if hs.next_state == 1 {
         // Status request - handle MOTD
-        handle_status_request(conn_id, &mut inbound, &hs, peer_addr_override).await;
-        cleanup_conn(conn_id);
+        STATUS_REQUESTS.fetch_add(1, Ordering::SeqCst);
+        let request = read_status_request(&mut inbound);
+        let Ok(request) = tokio::time::timeout_at(handshake_deadline, request).await else {
+            handshake_timed_out(conn_id);
+            return;
+        };
+        let rejected = match request {
+            Ok(()) => handle_status_request(conn_id, &mut inbound, &hs, peer_addr_override, local_addr).await,
+            Err(e) => {
+                error!(conn = conn_id, "Failed to read status request: {}", e);
+                false
+            }
+        };
+        cleanup_conn(conn_id, DisconnectReason::StatusDone);
+        if rejected {
+            let _ = tarpit::try_hold(inbound);
+        }
         return;
-    } else if hs.next_state != 2 {
+    } else if hs.next_state != 2 && hs.next_state != protocol::TRANSFER_INTENT {
         // Unknown state
         error!(conn = conn_id, "Unknown next_state: {}", hs.next_state);
-        cleanup_conn(conn_id);
+        protocol_errors::report(
+            conn_id,
+            ProtocolErrorKind::UnknownState,
+            &format_args!("unknown next_state {}", hs.next_state),
+            &sample,
+        );
+        cleanup_conn(conn_id, DisconnectReason::ProtocolError);
         return;
     }
 
-    // Continue with login flow (state 2)
-    let (login_packet, username) = match read_login_packet(&mut inbound).await {
+    // Continue with login flow (state 2, or 3 for a transferred client)
+    let mut sampled = SampledReader::new(&mut inbound, &mut sample, sample_limit);
+    let login = read_login_packet(&mut sampled);
+    let Ok(login) = tokio::time::timeout_at(handshake_deadline, login).await else {
+        handshake_timed_out(conn_id);
+        return;
+    };
+    let (login_packet, username) = match login {
         Ok(res) => res,
         Err(e) => {
             error!(conn = conn_id, "Login failed: {}", e);
-            cleanup_conn(conn_id);
+            protocol_errors::report(conn_id, ProtocolErrorKind::Login, &e, &sample);
+            cleanup_conn(conn_id, DisconnectReason::ProtocolError);
             return;
         }
     };
+    let login_at = Instant::now();
+    LOGINS.fetch_add(1, Ordering::SeqCst);
+    let (mut inbound, pipelined) = inbound.into_parts();
+    register_login_socket(conn_id, &inbound, hs.protocol_version);
 
     // Route
     let peer_ip = peer_addr_override
@@ -202,11 +547,95 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
                 .peer_addr()
                 .map_or_else(|_| "0.0.0.0".to_string(), |addr| addr.ip().to_string())
         });
+    update_conn_info(conn_id, |info| {
+        info.peer_ip = peer_ip.clone();
+        info.username = Some(username.clone());
+        info.login_at_ms = Some(events::now_ms());
+    });
+    logging::record_field(conn_id, "username", &username);
+
+    let placeholders = messages::Placeholders {
+        host: Some(&hs.host),
+        username: Some(&username),
+        conn_id: Some(conn_id),
+        ..Default::default()
+    };
+
+    if let Some(message) = pause::rejection(conn_id) {
+        info!(conn = conn_id, "Listener paused, refusing login");
+        let message = messages::fill(&message, &placeholders);
+        let _ = write_disconnect(&mut inbound, &message, hs.protocol_version).await;
+        cleanup_conn(conn_id, DisconnectReason::Rejected);
+        return;
+    }
+
+    if overflow::reject_login() {
+        info!(conn = conn_id, "Connection cap reached, refusing login");
+        let _ = write_disconnect(
+            &mut inbound,
+            &messages::builtin_with(messages::PROXY_FULL, &placeholders),
+            hs.protocol_version,
+        )
+        .await;
+        cleanup_conn(conn_id, DisconnectReason::Full);
+        return;
+    }
+
+    if !login_throttle::admit(&username, &peer_ip) {
+        info!(conn = conn_id, %username, %peer_ip, "Too many login attempts, refusing login");
+        let _ = write_disconnect(
+            &mut inbound,
+            &messages::builtin_with(messages::LOGIN_THROTTLED, &placeholders),
+            hs.protocol_version,
+        )
+        .await;
+        cleanup_conn(conn_id, DisconnectReason::LoginThrottled);
+        return;
+    }
+
+    match vhosts::check_login(&hs.host, hs.protocol_version) {
+        Some(vhosts::Refusal::Maintenance) => {
+            info!(conn = conn_id, "Virtual host under maintenance, refusing login");
+            let _ = write_disconnect(
+                &mut inbound,
+                &messages::builtin_with(messages::MAINTENANCE, &placeholders),
+                hs.protocol_version,
+            )
+            .await;
+            cleanup_conn(conn_id, DisconnectReason::Rejected);
+            return;
+        }
+        Some(vhosts::Refusal::Protocol(min, max)) => {
+            info!(
+                conn = conn_id,
+                protocol = hs.protocol_version,
+                "Client protocol outside the virtual host's range, refusing login"
+            );
+            let _ = write_disconnect(
+                &mut inbound,
+                &messages::unsupported_version(hs.protocol_version, min, max),
+                hs.protocol_version,
+            )
+            .await;
+            cleanup_conn(conn_id, DisconnectReason::UnsupportedVersion);
+            return;
+        }
+        None => {}
+    }
+
+    // A player this proxy transferred goes to the backend it was sent to.
+    let returning = if hs.next_state == protocol::TRANSFER_INTENT {
+        transfer::take_return(&peer_ip, &username)
+    } else {
+        None
+    };
 
     // Check cache first for routing
-    if let Some(cached_entry) = ROUTER_MOTD_CACHE
-        .get(&peer_ip, Some(&hs.host), &CacheGranularity::IpHost)
-        .or_else(|| ROUTER_MOTD_CACHE.get(&peer_ip, None, &CacheGranularity::Ip))
+    let mut cached_route = None;
+    if returning.is_none()
+        && let Some(cached_entry) = ROUTER_MOTD_CACHE
+            .lookup_decision(CacheScope::Route, &peer_ip, &hs.host)
+            .await
     {
         info!(
             conn = conn_id,
@@ -216,210 +645,1104 @@ pub async fn handle_conn(conn_id: ProxyConnection, mut inbound: TcpStream) {
         if cached_entry.is_rejection {
             let disconnect_msg = cached_entry
                 .reject_reason
-                .unwrap_or_else(|| "Connection blocked by cache".to_string());
-            let _ = write_disconnect(&mut inbound, &disconnect_msg).await;
-            cleanup_conn(conn_id);
+                .unwrap_or_else(|| messages::builtin(messages::BLOCKED));
+            route_audit::publish(RouteAudit {
+                conn_id,
+                timestamp_ms: events::now_ms(),
+                peer_ip: peer_ip.clone(),
+                host: hs.host.clone(),
+                username: username.clone(),
+                source: "cache",
+                backend: None,
+                proxy: None,
+                reject_reason: Some(disconnect_msg.clone()),
+                metadata: None,
+            });
+            lifecycle::record(
+                conn_id,
+                LifecycleKind::Routed {
+                    source: "cache",
+                    rejected: true,
+                },
+            );
+            ROUTER_MOTD_CACHE.count_rejection();
+            // Counted out before the tarpit may take the socket over.
+            cleanup_conn(conn_id, DisconnectReason::Rejected);
+            if let Err(mut inbound) = tarpit::try_hold(inbound) {
+                let _ = write_disconnect(&mut inbound, &disconnect_msg, hs.protocol_version).await;
+            }
             return;
         }
 
-        // Use cached route data
-        if let Ok(cached_route) = serde_json::from_value::<RouteDecision>(cached_entry.data) {
-            // Apply cached route decision (same logic as below)
-            if let Some(disconnect_msg) = cached_route.disconnect {
-                let _ = write_disconnect(&mut inbound, &disconnect_msg).await;
-                cleanup_conn(conn_id);
-                return;
-            }
-
-            // Continue with cached route processing - extract remaining logic here
-            info!(conn = conn_id, "Applying cached route decision");
-            // For now, just continue to main logic to avoid duplication
-        }
+        // A cached decision stands in for the router's
+        cached_route = serde_json::from_value::<RouteDecision>(cached_entry.data).ok();
     }
 
-    // Asynchronously get the routing decision.
-    let route_decision = match get_route_info(conn_id, &hs, &username, &peer_ip).await {
+    // Schedules, static routes and vhosts are decided in Rust; everything else asks the router,
+    // falling back to a `routerFirst` vhost's backend, then to `fallbackRoute`.
+    transfer::begin_routing(conn_id);
+    let (route_decision, source) = if let Some(decision) = returning {
+        (Ok(decision), "transfer")
+    } else if let Some(decision) = cached_route {
+        (Ok(decision), "cache")
+    } else if let Some(decision) = schedule::route(&hs.host) {
+        (Ok(decision), "schedule")
+    } else if let Some(decision) = static_routes::route(&hs.host) {
+        (Ok(decision), "static")
+    } else if let Some(decision) = vhosts::route(&hs.host) {
+        (Ok(decision), "vhost")
+    } else if let Some(decision) = &listener_options.default_route {
+        (Ok(decision.clone()), "listener")
+    } else {
+        let routing_started = Instant::now();
+        let result = get_route_info(conn_id, &hs, &username, &peer_ip, local_addr, sni.as_deref(), transport).await;
+        ROUTING_HISTOGRAM.record(routing_started.elapsed());
+        match result {
+            Ok(decision) => (Ok(decision), "callback"),
+            // The router is overloaded or gone; degrade as configured.
+            Err(failure) => {
+                if let Some(decision) = vhosts::fallback(&hs.host) {
+                    warn!(conn = conn_id, "No route decision, using the vhost backend");
+                    (Ok(decision), "vhost")
+                } else if let Some(decision) = handler::fallback_route() {
+                    warn!(conn = conn_id, "No route decision, using the fallback route");
+                    (Ok(decision), "fallback")
+                } else {
+                    (Err(failure), "callback")
+                }
+            }
+        }
+    };
+    let route_decision = match route_decision {
         Ok(decision) => decision,
-        Err(_) => {
+        Err(failure) => {
             // Error already logged, just clean up.
-            let _ = write_disconnect(&mut inbound, "Internal routing error.").await;
-            cleanup_conn(conn_id);
+            let key = match failure {
+                NoRoute::TimedOut => messages::ROUTING_TIMEOUT,
+                NoRoute::Failed => messages::ROUTING_ERROR,
+            };
+            let message = handler::fallback_disconnect_message()
+                .unwrap_or_else(|| messages::builtin_with(key, &placeholders));
+            route_audit::publish(RouteAudit {
+                conn_id,
+                timestamp_ms: events::now_ms(),
+                peer_ip: peer_ip.clone(),
+                host: hs.host.clone(),
+                username: username.clone(),
+                source,
+                backend: None,
+                proxy: None,
+                reject_reason: Some(message.clone()),
+                metadata: None,
+            });
+            let _ = write_disconnect(&mut inbound, &message, hs.protocol_version).await;
+            cleanup_conn(conn_id, DisconnectReason::RoutingFailed);
             return;
         }
     };
+    // A transfer requested while routing replaces the decision.
+    let (route_decision, source) = match transfer::end_routing(conn_id) {
+        Some(decision) => (decision, "transfer"),
+        None => (route_decision, source),
+    };
+
+    route_audit::publish(RouteAudit {
+        conn_id,
+        timestamp_ms: events::now_ms(),
+        peer_ip: peer_ip.clone(),
+        host: hs.host.clone(),
+        username: username.clone(),
+        source,
+        backend: match &route_decision.pool {
+            Some(pool) => Some(format!("pool:{}", pool)),
+            None => route_decision.remote_host.as_ref().map(|h| {
+                format!("{}:{}", h, route_decision.remote_port.unwrap_or(hs.port))
+            }),
+        },
+        proxy: route_decision.proxy.as_ref().map(|proxy| proxy.to_string()),
+        reject_reason: messages::rejection(
+            &route_decision.disconnect,
+            &route_decision.disconnect_template,
+        ),
+        metadata: route_decision.metadata.clone(),
+    });
+    lifecycle::record(
+        conn_id,
+        LifecycleKind::Routed {
+            source,
+            rejected: messages::rejection(
+                &route_decision.disconnect,
+                &route_decision.disconnect_template,
+            )
+            .is_some(),
+        },
+    );
+    if route_decision.metadata.is_some() {
+        update_conn_info(conn_id, |info| info.metadata = route_decision.metadata.clone());
+    }
+    if let Some(tags) = &route_decision.tags {
+        update_conn_info(conn_id, |info| {
+            for tag in tags {
+                info.tags.insert(tag.clone(), serde_json::Value::Bool(true));
+            }
+        });
+    }
 
     // Custom reject
-    if let Some(disconnect_msg) = &route_decision.disconnect {
+    if let Some(disconnect_msg) =
+        messages::rejection(&route_decision.disconnect, &route_decision.disconnect_template)
+    {
         // Cache rejection if cache config is provided
-        if let Some(cache_config) = &route_decision.cache {
+        if source == "cache" {
+            ROUTER_MOTD_CACHE.count_rejection();
+        } else if let Some(cache_config) = &route_decision.cache {
             let cache_data = serde_json::to_value(&route_decision).unwrap_or_default();
-            ROUTER_MOTD_CACHE.set(&peer_ip, Some(&hs.host), cache_data, cache_config);
+            let cache_config = rejection_cache_config(cache_config, &disconnect_msg);
+            ROUTER_MOTD_CACHE.set(CacheScope::Route, &peer_ip, Some(&hs.host), cache_data, &cache_config);
             info!(
                 conn = conn_id,
                 "Cached route rejection for {}@{}@{}", username, peer_ip, hs.host
             );
         }
 
-        let _ = write_disconnect(&mut inbound, disconnect_msg).await;
-        cleanup_conn(conn_id);
+        let _ = write_disconnect(&mut inbound, &disconnect_msg, hs.protocol_version).await;
+        cleanup_conn(conn_id, DisconnectReason::Rejected);
+        return;
+    }
+
+    // Cache successful route result if cache config is provided
+    if source != "cache"
+        && let Some(cache_config) = &route_decision.cache
+    {
+        let cache_data = serde_json::to_value(&route_decision).unwrap_or_default();
+        ROUTER_MOTD_CACHE.set(CacheScope::Route, &peer_ip, Some(&hs.host), cache_data, cache_config);
+        info!(
+            conn = conn_id,
+            "Cached route result for {}@{}@{}", username, peer_ip, hs.host
+        );
+    }
+
+    if let Some((min, max)) = route_decision.allowed_protocol_range
+        && !(min..=max).contains(&hs.protocol_version)
+    {
+        info!(
+            conn = conn_id,
+            protocol = hs.protocol_version,
+            "Client protocol outside the route's allowed range, refusing login"
+        );
+        let _ = write_disconnect(
+            &mut inbound,
+            &messages::unsupported_version(hs.protocol_version, min, max),
+            hs.protocol_version,
+        )
+        .await;
+        cleanup_conn(conn_id, DisconnectReason::UnsupportedVersion);
+        return;
+    }
+
+    if let Some(exceeded) = quota::check_login(&username, route_decision.user_quota_bytes) {
+        info!(conn = conn_id, "Player used up their traffic quota, refusing login");
+        quota::publish(conn_id, &username, &peer_ip, &exceeded);
+        let _ = write_disconnect(
+            &mut inbound,
+            &messages::builtin_with(messages::QUOTA_EXCEEDED, &placeholders),
+            hs.protocol_version,
+        )
+        .await;
+        cleanup_conn(conn_id, DisconnectReason::QuotaExceeded);
         return;
     }
 
-    // Cache successful route result if cache config is provided
-    if let Some(cache_config) = &route_decision.cache {
-        let cache_data = serde_json::to_value(&route_decision).unwrap_or_default();
-        ROUTER_MOTD_CACHE.set(&peer_ip, Some(&hs.host), cache_data, cache_config);
-        info!(
-            conn = conn_id,
-            "Cached route result for {}@{}@{}", username, peer_ip, hs.host
+    if !capacity::admit(conn_id, &route_decision) {
+        info!(conn = conn_id, "Player cap reached, refusing login");
+        let _ = write_disconnect(
+            &mut inbound,
+            &messages::builtin_with(messages::SERVER_FULL, &placeholders),
+            hs.protocol_version,
+        )
+        .await;
+        cleanup_conn(conn_id, DisconnectReason::Full);
+        return;
+    }
+
+    if let Some(limit) = &route_decision.rate_limit {
+        limiter::set_limits(conn_id, limit);
+        debug!(conn = conn_id, ?limit, "Applied route rate limits");
+    }
+
+    // Terminate online-mode authentication here if the route asks for it;
+    // the backend then gets the verified profile through forwarding.
+    let mut forwarded = None;
+    let (mut inbound, pipelined) = if route_decision.authenticate.unwrap_or(false) {
+        // Encryption starts here, so a kick can no longer write in plain.
+        LOGIN_SOCKETS.remove(&conn_id);
+        let ip = peer_ip.parse().unwrap_or(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED));
+        match auth::authenticate(inbound, pipelined, &username, ip, hs.protocol_version).await {
+            Ok(authenticated) => {
+                info!(conn = conn_id, uuid = %authenticated.identity.uuid, "Authenticated {}", username);
+                update_conn_info(conn_id, |info| info.uuid = Some(authenticated.identity.uuid.clone()));
+                forwarded = Some(authenticated.identity);
+                (authenticated.stream, authenticated.pipelined)
+            }
+            Err(e) => {
+                warn!(conn = conn_id, "Authentication of {} failed: {}", username, e);
+                cleanup_conn(conn_id, DisconnectReason::AuthFailed);
+                return;
+            }
+        }
+    } else {
+        (ClientStream::new(inbound), pipelined)
+    };
+
+    // Rewrite host/port if specified
+    let mut hs_for_rewrite = hs.clone();
+    if let Some(identity) = forwarded {
+        hs_for_rewrite.forwarded = Some(identity);
+    }
+    if let Some(new_host) = &route_decision.rewrite_host {
+        hs_for_rewrite.host = new_host.clone();
+    }
+    // The new backend did not send the Transfer, so it sees a plain login.
+    if source == "transfer" {
+        hs_for_rewrite.next_state = 2;
+    }
+
+    // A route's TCP options also replace those the client socket was
+    // accepted with.
+    let tcp_config = options.tcp.unwrap_or_default().merged(route_decision.tcp);
+    if route_decision.tcp.is_some() {
+        tcp_options::apply(inbound.get_ref().tcp(), &tcp_config);
+    }
+
+    // Establish outbound connection, trying each candidate backend in turn
+    let proxy_hops = route_decision
+        .proxy
+        .as_ref()
+        .map(|proxy| proxy.hops())
+        .unwrap_or_default();
+    let proxied = !proxy_hops.is_empty() || route_decision.proxy_pool.is_some();
+    let attempt_timeout = route_decision
+        .connect_timeout_ms
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis);
+    let mut candidates = backend_candidates(&route_decision);
+    // Backends failing their active checks are only a last resort.
+    candidates.sort_by_key(|candidate| health_check::is_unhealthy(&candidate.to_string()));
+
+    // Set for direct connections, whose sessions feed backend health.
+    let mut backend_addr = None;
+    // Proxy picked from `proxyPool`, if any.
+    let mut pool_proxy = None;
+    // Connect time of each hop of a `proxy` chain.
+    let mut proxy_hop_ms = Vec::new();
+    let mut last_err = Error::new(ErrorKind::NotFound, "route has no backend");
+    // A bad `localAddress` fails the route rather than connecting from elsewhere.
+    let local_addr = match outbound::parse_local_address(route_decision.local_address.as_deref()) {
+        Ok(addr) => addr,
+        Err(e) => {
+            candidates.clear();
+            last_err = e;
+            None
+        }
+    };
+    let socket = SocketConfig {
+        local: local_addr,
+        tcp: tcp_config,
+    };
+    let mut connected = None;
+    let connect_started = Instant::now();
+    for candidate in &candidates {
+        let backend = candidate.to_string();
+        // SOCKS5 takes a single target, so a pool hands out its next member.
+        let socks_target = match candidate {
+            Backend::Pool(pool) if proxied => discovery::resolve_pool(pool)
+                .ok()
+                .and_then(|members| members.first().map(|addr| addr.to_string()))
+                .unwrap_or_default(),
+            _ => backend.clone(),
+        };
+        let attempt = async {
+            let stream = if let Some(pool) = &route_decision.proxy_pool {
+                upstream::connect_pool(pool, &peer_ip, &socks_target, socket)
+                    .await
+                    .map(|(proxy, stream)| {
+                        pool_proxy = Some(proxy);
+                        stream
+                    })
+            } else if !proxy_hops.is_empty() {
+                match proxy_hops.iter().map(|hop| Url::parse(hop)).collect::<Result<Vec<_>, _>>() {
+                    Ok(urls) if urls.iter().all(|url| matches!(url.scheme(), "socks5" | "http" | "quic")) => {
+                        upstream::connect_chain(&proxy_hops, &socks_target, socket)
+                            .await
+                            .map(|(stream, hop_ms)| {
+                                proxy_hop_ms = hop_ms;
+                                stream
+                            })
+                    }
+                    Ok(_) => connect_direct(candidate, socket).await.map(|s| {
+                        backend_addr = s.peer_addr().ok();
+                        Box::new(s) as Box<AsyncStream>
+                    }),
+                    Err(e) => Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid proxy URL: {}", e),
+                    )),
+                }
+            } else {
+                connect_direct(candidate, socket).await.map(|s| {
+                    backend_addr = s.peer_addr().ok();
+                    Box::new(s) as Box<AsyncStream>
+                })
+            }?;
+            match candidate {
+                Backend::Tls(host, _) => tls::connect(stream, host, route_decision.tls_server_name.as_deref()).await,
+                _ => Ok(stream),
+            }
+        };
+        let result = match attempt_timeout {
+            Some(limit) => tokio::time::timeout(limit, attempt)
+                .await
+                .unwrap_or_else(|_| Err(Error::new(ErrorKind::TimedOut, "connect timed out"))),
+            None => attempt.await,
+        };
+        match result {
+            Ok(stream) => {
+                connected = Some((candidate, backend, stream));
+                break;
+            }
+            Err(e) => {
+                warn!(conn=conn_id, %backend, "Failed to connect to backend: {}", e);
+                last_err = e;
+            }
+        }
+    }
+    let (candidate, mut outbound) = match connected {
+        Some((candidate, backend, stream)) => {
+            let proxy = pool_proxy.or_else(|| route_decision.proxy.as_ref().map(|proxy| proxy.to_string()));
+            info!(conn=conn_id, %backend, proxy = proxy.as_deref().unwrap_or(""), "Proxying connection");
+            let connect_time = connect_started.elapsed();
+            BACKEND_CONNECT_HISTOGRAM.record(connect_time);
+            lifecycle::record(
+                conn_id,
+                LifecycleKind::BackendConnected {
+                    backend: backend.clone(),
+                    connect_ms: connect_time.as_millis() as u64,
+                },
+            );
+            route_metrics::count_backend(&backend);
+            update_conn_info(conn_id, |info| {
+                info.backend = Some(backend);
+                info.proxy = proxy;
+                info.proxy_hop_ms = std::mem::take(&mut proxy_hop_ms);
+                info.latency_ms = Some(connect_time.as_micros() as f64 / 1000.0);
+                info.backend_connected_at_ms = Some(events::now_ms());
+            });
+            (candidate, stream)
+        }
+        None => {
+            error!(conn = conn_id, "No backend reachable: {}", last_err);
+            let backend = candidates.first().map(|candidate| candidate.to_string());
+            let placeholders = messages::Placeholders {
+                backend: backend.as_deref(),
+                ..placeholders
+            };
+            let _ = write_disconnect(
+                &mut inbound,
+                &messages::builtin_with(messages::BACKEND_DOWN, &placeholders),
+                hs.protocol_version,
+            )
+            .await;
+            cleanup_conn(conn_id, DisconnectReason::BackendUnreachable);
+            return;
+        }
+    };
+
+    // Re-serialize the handshake for the backend connected to.
+    hs_for_rewrite.port = match candidate {
+        Backend::Remote(_, port) | Backend::Tls(_, port) if *port != 0 => *port,
+        _ => route_decision.remote_port.unwrap_or(hs.port),
+    };
+    let handshake_packet = create_handshake_packet(&hs_for_rewrite);
+
+    // If PROXY protocol is enabled, the header goes first.
+    let mut proxy_header = Vec::new();
+    if let Some(version) = route_decision.proxy_protocol {
+        let peer_addr = peer_addr_override.unwrap_or_else(|| inbound.get_ref().peer_addr().unwrap());
+        let source_addr = match route_decision.proxy_protocol_source.as_deref() {
+            Some(source) if options.allow_proxy_protocol_source => {
+                parse_proxy_source(source, peer_addr.port()).unwrap_or_else(|| {
+                    warn!(conn = conn_id, %source, "Invalid proxyProtocolSource, using peer address");
+                    peer_addr
+                })
+            }
+            Some(_) => {
+                warn!(
+                    conn = conn_id,
+                    "Ignoring proxyProtocolSource: allowProxyProtocolSource is off"
+                );
+                peer_addr
+            }
+            None => peer_addr,
+        };
+        let (source_addr, destination_addr) =
+            same_family(source_addr, inbound.get_ref().local_addr().unwrap());
+
+        proxy_header = match version {
+            1 => {
+                let addrs = ppp::v1::Addresses::from((source_addr, destination_addr));
+                format!("{}\r\n", addrs).into_bytes()
+            }
+            2 => ppp::v2::Builder::with_addresses(
+                ppp::v2::Version::Two | ppp::v2::Command::Proxy,
+                ppp::v2::Protocol::Stream,
+                (source_addr, destination_addr),
+            )
+            .build()
+            .unwrap_or_default(),
+            _ => vec![], // Unsupported version
+        };
+    }
+
+    if let Some(config) = &route_decision.mirror {
+        mirror::start(conn_id, config);
+        for bytes in [&handshake_packet, &login_packet, &pipelined] {
+            mirror::feed(conn_id, bytes, true);
+        }
+    }
+
+    // Forward the initial packets that were consumed during parsing, in one
+    // vectored write.
+    let mut initial = [
+        IoSlice::new(&proxy_header),
+        IoSlice::new(&handshake_packet),
+        IoSlice::new(&login_packet),
+        IoSlice::new(&pipelined),
+    ];
+    if let Err(e) = write_all_vectored(&mut outbound, &mut initial).await {
+        error!(conn = conn_id, "Failed to forward login to backend: {}", e);
+        cleanup_conn(conn_id, DisconnectReason::RelayError);
+        return;
+    }
+    lifecycle::record(
+        conn_id,
+        LifecycleKind::LoginForwarded {
+            username: username.clone(),
+        },
+    );
+
+    // From the backend's first bytes on the client may be past the login state.
+    LOGIN_SOCKETS.remove(&conn_id);
+    let relayed = if options.track_login_phase || options.allow_transfer {
+        relay_login_phase(conn_id, &mut inbound, &mut outbound, login_at, hs.protocol_version)
+            .await
+            .map(Some)
+    } else {
+        relay_first_bytes(conn_id, &mut inbound, &mut outbound, login_at)
+            .await
+            .map(|_| None)
+    };
+    let login = match relayed {
+        Ok(login) => login,
+        Err(e) => {
+            error!(conn = conn_id, "Connection proxy failed: {}", e);
+            cleanup_conn(conn_id, DisconnectReason::RelayError);
+            return;
+        }
+    };
+    // Transfers need packet boundaries, so the login phase must have been
+    // followed to its end in plain.
+    let frames = login
+        .filter(|login| {
+            options.allow_transfer
+                && login.state().succeeded
+                && protocol::transfer_supported(hs.protocol_version)
+        })
+        .map(|login| {
+            let mut frames =
+                FrameTracker::new(hs.protocol_version, login.state().compression_threshold);
+            frames.feed(login.remainder());
+            frames
+        });
+
+    // Data proxying, until either side closes or a session limit is hit
+    let idle_timeout = session_limit(route_decision.idle_timeout_ms, options.idle_timeout_ms);
+    let max_duration = session_limit(
+        route_decision.max_session_duration_ms,
+        options.max_session_duration_ms,
+    );
+    let relay = async {
+        match frames {
+            Some(frames) => {
+                let target = Transfer {
+                    host: &hs.host,
+                    port: hs.port,
+                    peer_ip: &peer_ip,
+                    username: &username,
+                    socket,
+                    resume: route_decision
+                        .reconnect_grace_ms
+                        .filter(|&ms| ms > 0)
+                        .map(|ms| (Duration::from_millis(ms), &route_decision)),
+                };
+                relay_transferable(conn_id, &mut inbound, &mut outbound, frames, target).await
+            }
+            None => copy_bidirectional_with_metrics(conn_id, &mut inbound, &mut outbound)
+                .await
+                .map(|_| false),
+        }
+    };
+    let reason = tokio::select! {
+        result = relay => {
+            match result {
+                Ok(false) => DisconnectReason::Closed,
+                Ok(true) => DisconnectReason::Transferred,
+                Err(e) => {
+                    error!(conn = conn_id, "Connection proxy failed: {}", e);
+                    DisconnectReason::RelayError
+                }
+            }
+        }
+        _ = wait_idle(conn_id, idle_timeout) => {
+            info!(conn = conn_id, "Closing idle connection");
+            DisconnectReason::IdleTimeout
+        }
+        _ = wait_until(max_duration.map(|d| login_at + d)) => {
+            info!(conn = conn_id, "Closing connection at its maximum session duration");
+            DisconnectReason::SessionExpired
+        }
+        exceeded = quota::wait_exceeded(
+            conn_id,
+            &username,
+            route_decision.quota_bytes,
+            route_decision.user_quota_bytes,
+        ) => {
+            info!(conn = conn_id, "Closing connection at its traffic quota");
+            quota::publish(conn_id, &username, &peer_ip, &exceeded);
+            DisconnectReason::QuotaExceeded
+        }
+    };
+    if let Some(addr) = backend_addr {
+        health::record_session(addr, reason != DisconnectReason::RelayError);
+    }
+
+    cleanup_conn(conn_id, reason);
+    info!(conn = conn_id, "Connection closed");
+}
+
+/// The route's limit if set, else the global one; 0 disables it.
+fn session_limit(route_ms: Option<u64>, global_ms: Option<u64>) -> Option<Duration> {
+    route_ms
+        .or(global_ms)
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis)
+}
+
+/// Completes once the connection has relayed nothing for `timeout`; never
+/// without one. Activity is read from the byte counters, which every copy
+/// path updates while it runs (the sockmap path once per second); time spent
+/// holding the client for a reconnect counts as active.
+async fn wait_idle(conn_id: ProxyConnection, timeout: Option<Duration>) {
+    let metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    let (Some(timeout), Some(metrics)) = (timeout, metrics) else {
+        return std::future::pending().await;
+    };
+    let relayed = || {
+        metrics.bytes_sent.load(Ordering::SeqCst) + metrics.bytes_recv.load(Ordering::SeqCst)
+    };
+    let period = (timeout / 4).clamp(Duration::from_millis(100), Duration::from_secs(1));
+    let mut ticker = tokio::time::interval(period);
+    let mut last = relayed();
+    let mut last_active = Instant::now();
+    loop {
+        ticker.tick().await;
+        let now = relayed();
+        if now != last || metrics.held.load(Ordering::SeqCst) {
+            last = now;
+            last_active = Instant::now();
+        } else if last_active.elapsed() >= timeout {
+            return;
+        }
+    }
+}
+
+/// Completes at `deadline`; never without one.
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// How a rejection with `config` is cached: as a negative entry turning later
+/// connections away with `message`, under `rejectTtl` when set.
+fn rejection_cache_config(config: &CacheConfig, message: &str) -> CacheConfig {
+    CacheConfig {
+        reject: Some(true),
+        reject_reason: config.reject_reason.clone().or_else(|| Some(message.to_string())),
+        ..config.clone()
+    }
+}
+
+/// Updates the stored session info of a live connection.
+pub fn update_conn_info(conn_id: ProxyConnection, f: impl FnOnce(&mut ConnInfo)) {
+    if let Some(mut info) = CONN_INFO.get_mut(&conn_id) {
+        f(info.value_mut());
+    }
+}
+
+/// Cleanup resources for a connection
+/// Aborts a connection's task and releases its resources. Returns false if
+/// the connection is unknown or already finishing.
+pub fn kick(conn_id: ProxyConnection, reason: DisconnectReason) -> bool {
+    let handle = CONN_MANAGER.remove(&conn_id).map(|(_, handle)| handle);
+    match handle {
+        Some(h) => {
+            h.abort();
+            // The aborted task never reaches its own cleanup, so release its
+            // resources and queue the disconnection event here.
+            cleanup_conn(conn_id, reason);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Disconnects a connection, first sending `message` as a Login Disconnect
+/// if the client is still in the login phase. Returns `None` if the
+/// connection is unknown, else whether the message was sent; connections
+/// already relaying are closed without one.
+pub fn kick_with_message(conn_id: ProxyConnection, message: &str) -> Option<bool> {
+    let login = LOGIN_SOCKETS.remove(&conn_id).map(|(_, login)| login);
+    if !kick(conn_id, DisconnectReason::Kicked) {
+        return None;
+    }
+    let Some(LoginSocket { mut socket, protocol }) = login else {
+        return Some(false);
+    };
+    // The duplicate shares the task's non-blocking mode; a packet this small
+    // fits the empty send buffer of a client that has not been answered yet.
+    let sent = std::io::Write::write_all(&mut socket, &protocol::disconnect_packet(message, protocol));
+    let _ = socket.shutdown(std::net::Shutdown::Both);
+    if let Err(e) = &sent {
+        warn!(conn = conn_id, "Failed to send kick message: {}", e);
+    }
+    Some(sent.is_ok())
+}
+
+/// Client socket of a connection in the login phase, duplicated so a kick
+/// can still write to it after aborting the connection's task.
+pub struct LoginSocket {
+    socket: std::net::TcpStream,
+    protocol: i32,
+}
+
+fn register_login_socket(conn_id: ProxyConnection, transport: &ClientTransport, protocol: i32) {
+    // A kick cannot be written in plain under TLS.
+    let ClientTransport::Tcp(stream) = transport else {
+        return;
+    };
+    #[cfg(unix)]
+    let socket = std::os::fd::AsFd::as_fd(stream).try_clone_to_owned();
+    #[cfg(windows)]
+    let socket = std::os::windows::io::AsSocket::as_socket(stream).try_clone_to_owned();
+    match socket {
+        Ok(socket) => {
+            let socket = std::net::TcpStream::from(socket);
+            LOGIN_SOCKETS.insert(conn_id, LoginSocket { socket, protocol });
+        }
+        Err(e) => debug!(conn = conn_id, "Cannot duplicate client socket: {}", e),
+    }
+}
+
+pub fn cleanup_conn(conn_id: ProxyConnection, reason: DisconnectReason) {
+    let info = CONN_INFO.remove(&conn_id).map(|(_, info)| info).unwrap_or_default();
+    LOGIN_SOCKETS.remove(&conn_id);
+
+    // Add to disconnection event queue (thread-safe alternative)
+    let disconnection_event = DisconnectionEvent {
+        conn_id,
+        tags: info.tags.clone(),
+        metadata: info.metadata.clone(),
+        ttfb_ms: info.ttfb_ms,
+        latency_ms: info.latency_ms,
+        backend: info.backend.clone(),
+        uuid: info.uuid.clone(),
+        compression_threshold: info.compression_threshold,
+        reason,
+    };
+    wakeup::push(&mut DISCONNECTION_EVENT_QUEUE.lock().unwrap(), disconnection_event);
+
+    // The new polling mechanism handles disconnection events.
+    // No need to manually call a callback here.
+
+    CONN_MANAGER.remove(&conn_id);
+    RATE_LIMITERS.remove(&conn_id);
+    mirror::stop(conn_id);
+    capture::stop(conn_id);
+    logging::remove_span(conn_id);
+    capacity::release(conn_id);
+    limits::release(conn_id);
+    transfer::release(conn_id);
+    let metrics = {
+        // Moved into the listener's totals under its lock, so exporter
+        // scrapes never count these bytes twice or not at all.
+        let mut listener_totals = LISTENER_TOTALS.lock().unwrap();
+        let metrics = CONN_METRICS.remove(&conn_id).map(|(_, metrics)| metrics);
+        if let (Some(listener), Some(m)) = (info.listener, &metrics) {
+            let totals = listener_totals.entry(listener).or_default();
+            totals.active = totals.active.saturating_sub(1);
+            totals.bytes_sent += m.bytes_sent.load(Ordering::SeqCst);
+            totals.bytes_recv += m.bytes_recv.load(Ordering::SeqCst);
+        }
+        let sent = metrics.as_ref().map_or(0, |m| m.bytes_sent.load(Ordering::SeqCst));
+        let recv = metrics.as_ref().map_or(0, |m| m.bytes_recv.load(Ordering::SeqCst));
+        route_metrics::release(&info, sent, recv);
+        accounting::release(&info, sent, recv);
+        metrics
+    };
+    ACTIVE_CONN.fetch_sub(1, Ordering::SeqCst);
+
+    let bytes_sent = metrics
+        .as_ref()
+        .map_or(0, |m| m.bytes_sent.load(Ordering::SeqCst));
+    let bytes_recv = metrics
+        .as_ref()
+        .map_or(0, |m| m.bytes_recv.load(Ordering::SeqCst));
+    if info.backend.is_some() {
+        let lifetime_ms = events::now_ms().saturating_sub(info.connected_at_ms);
+        SESSION_HISTOGRAM.record(Duration::from_millis(lifetime_ms));
+    }
+    usage::finish(conn_id, bytes_sent, bytes_recv, &info.tags);
+    lifecycle::record_disconnect(conn_id, info.connected_at_ms, reason, bytes_sent, bytes_recv);
+
+    events::emit(ProxyEvent::Disconnected {
+        conn_id,
+        timestamp_ms: events::now_ms(),
+        reason,
+        info,
+        bytes_sent,
+        bytes_recv,
+    });
+}
+
+/// Relays the backend's first bytes to the client and records the session's
+/// TTFB. Done ahead of the relay so it is measured the same way whichever
+/// copy path follows; not measured if the client speaks first.
+async fn relay_first_bytes(
+    conn_id: ProxyConnection,
+    inbound: &mut ClientStream,
+    outbound: &mut Box<AsyncStream>,
+    login_at: Instant,
+) -> std::io::Result<()> {
+    let mut buf = buffer_pool::take();
+    tokio::select! {
+        biased;
+
+        result = outbound.read(&mut buf) => {
+            let n = result?;
+            if n == 0 {
+                return Ok(());
+            }
+            latency::record_ttfb(conn_id, login_at.elapsed());
+            forward_chunk(conn_id, inbound, &buf[..n], false).await?;
+        }
+        _ = inbound.readable() => {}
+    }
+    Ok(())
+}
+
+/// Relays the login phase in both directions until the backend's Login
+/// Success, recording the TTFB as `relay_first_bytes` does, and the
+/// compression threshold and player UUID the backend assigns. Ends early at
+/// encryption or anything unreadable, leaving the rest to the plain relay.
+/// Returns the tracker for what it learned.
+async fn relay_login_phase(
+    conn_id: ProxyConnection,
+    inbound: &mut ClientStream,
+    outbound: &mut Box<AsyncStream>,
+    login_at: Instant,
+    protocol: i32,
+) -> std::io::Result<LoginTracker> {
+    let mut tracker = LoginTracker::new(protocol);
+    let mut from_backend = buffer_pool::take();
+    let mut from_client = buffer_pool::take();
+    let mut first_bytes = true;
+    while !tracker.is_done() {
+        tokio::select! {
+            result = outbound.read(&mut from_backend) => {
+                let n = result?;
+                if n == 0 {
+                    break;
+                }
+                if first_bytes {
+                    latency::record_ttfb(conn_id, login_at.elapsed());
+                    first_bytes = false;
+                }
+                forward_chunk(conn_id, inbound, &from_backend[..n], false).await?;
+                tracker.feed(&from_backend[..n]);
+            }
+            result = inbound.read(&mut from_client) => {
+                let n = result?;
+                if n == 0 {
+                    break;
+                }
+                forward_chunk(conn_id, outbound, &from_client[..n], true).await?;
+            }
+        }
+    }
+
+    let login = tracker.state();
+    debug!(
+        conn = conn_id,
+        compression_threshold = ?login.compression_threshold,
+        encrypted = login.encrypted,
+        "Login phase tracked"
+    );
+    update_conn_info(conn_id, |info| {
+        info.compression_threshold = login.compression_threshold;
+        if login.uuid.is_some() {
+            info.uuid = login.uuid.clone();
+        }
+    });
+    if login.succeeded {
+        lifecycle::record(
+            conn_id,
+            LifecycleKind::LoginSucceeded {
+                uuid: login.uuid.clone(),
+                compression_threshold: login.compression_threshold,
+            },
         );
     }
+    Ok(tracker)
+}
 
-    // Rewrite host/port if specified
-    let mut hs_for_rewrite = hs.clone();
-    if let Some(new_host) = &route_decision.rewrite_host {
-        hs_for_rewrite.host = new_host.clone();
-    }
-    hs_for_rewrite.port = route_decision.remote_port.unwrap_or(hs.port);
-
-    // Re-serialize the handshake with updated fields.
-    let handshake_packet = create_handshake_packet(&hs_for_rewrite);
+/// Where a transferred client is sent back to, and who it is.
+struct Transfer<'a> {
+    host: &'a str,
+    port: u16,
+    peer_ip: &'a str,
+    username: &'a str,
+    /// Socket options the route connects its backends with.
+    socket: SocketConfig,
+    /// How long to hold the client when its backend drops, and the route it
+    /// reconnects through (`reconnectGraceMs`).
+    resume: Option<(Duration, &'a RouteDecision)>,
+}
 
-    // Establish outbound connection
-    let backend = format!(
-        "{}:{}",
-        route_decision.remote_host.as_deref().unwrap_or(""),
-        route_decision.remote_port.unwrap_or(0)
-    );
-    let proxy_url = route_decision.proxy.as_deref().unwrap_or("");
-
-    let mut outbound: Box<AsyncStream> = match if !proxy_url.is_empty() {
-        let url = Url::parse(proxy_url).expect("Invalid proxy URL");
-        match url.scheme() {
-            "socks5" => {
-                let host = url.host_str().unwrap_or_default();
-                let port = url.port().unwrap_or(1080);
-                let proxy_backend = format!("{}:{}", host, port);
-                let username = url.username();
-                let password = url.password().unwrap_or_default();
-
-                if !username.is_empty() {
-                    Socks5Stream::connect_with_password(
-                        &*proxy_backend,
-                        &*backend,
-                        username,
-                        password,
-                    )
-                    .await
-                    .map(|s| Box::new(s) as Box<AsyncStream>)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                } else {
-                    Socks5Stream::connect(&*proxy_backend, &*backend)
-                        .await
-                        .map(|s| Box::new(s) as Box<AsyncStream>)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+/// Relays like `copy_bidirectional_fallback` while following the backend's
+/// packet framing, and sends the client a Transfer packet at the next packet
+/// boundary once `proxy_transfer_connection` asks for one. Returns whether
+/// the client was transferred.
+async fn relay_transferable(
+    conn_id: ProxyConnection,
+    inbound: &mut ClientStream,
+    outbound: &mut Box<AsyncStream>,
+    mut frames: FrameTracker,
+    target: Transfer<'_>,
+) -> std::io::Result<bool> {
+    let mut requests = transfer::begin_relay(conn_id);
+    let mut pending = None;
+    let mut from_backend = buffer_pool::take();
+    let mut from_client = buffer_pool::take();
+    loop {
+        tokio::select! {
+            result = outbound.read(&mut from_backend) => {
+                let n = match result {
+                    Ok(n) => n,
+                    Err(e) if target.resume.is_none() => return Err(e),
+                    Err(e) => {
+                        debug!(conn = conn_id, "Backend connection failed: {}", e);
+                        0
+                    }
+                };
+                if n == 0 {
+                    // A backend that stops mid-packet leaves the client unable
+                    // to read a Transfer packet.
+                    return match target.resume {
+                        Some((grace, route)) if frames.at_boundary() => {
+                            resume_session(conn_id, inbound, &frames, &target, grace, route).await
+                        }
+                        _ => Ok(false),
+                    };
                 }
+                forward_chunk(conn_id, inbound, &from_backend[..n], false).await?;
+                frames.feed(&from_backend[..n]);
+            }
+            result = inbound.read(&mut from_client) => {
+                let n = result?;
+                if n == 0 {
+                    return Ok(false);
+                }
+                forward_chunk(conn_id, outbound, &from_client[..n], true).await?;
+            }
+            Some(decision) = requests.recv(), if pending.is_none() => {
+                pending = Some(decision);
             }
-            _ => TcpStream::connect(&backend)
-                .await
-                .map(|s| Box::new(s) as Box<AsyncStream>),
         }
-    } else {
-        TcpStream::connect(&backend)
-            .await
-            .map(|s| Box::new(s) as Box<AsyncStream>)
-    } {
-        Ok(stream) => {
-            info!(conn=conn_id, %backend, %proxy_url, "Proxying connection");
-            stream
+
+        if pending.is_some() && frames.at_boundary() {
+            let Some(packet) = frames.transfer_packet(target.host, target.port) else {
+                warn!(conn = conn_id, "No Transfer packet for the client's state, ignoring transfer");
+                pending = None;
+                continue;
+            };
+            if let Some(decision) = pending.take() {
+                transfer::expect_return(target.peer_ip, target.username, decision);
+            }
+            inbound.write_all(&packet).await?;
+            inbound.flush().await?;
+            info!(conn = conn_id, "Transferred client to another backend");
+            return Ok(true);
         }
-        Err(e) => {
-            error!(conn=conn_id, %backend, "Failed to connect to backend: {}", e);
-            let _ = write_disconnect(&mut inbound, "Could not connect to the destination server.")
-                .await;
-            cleanup_conn(conn_id);
-            return;
+    }
+}
+
+/// Holds a client whose backend dropped until one of the route's backends
+/// accepts connections again, dropping what the client sends meanwhile,
+/// then sends it back through a Transfer so that it replays its handshake
+/// and login to them. Returns `Ok(false)` if none came back within `grace`
+/// or the client left first.
+async fn resume_session(
+    conn_id: ProxyConnection,
+    inbound: &mut ClientStream,
+    frames: &FrameTracker,
+    target: &Transfer<'_>,
+    grace: Duration,
+    route: &RouteDecision,
+) -> std::io::Result<bool> {
+    let Some(packet) = frames.transfer_packet(target.host, target.port) else {
+        return Ok(false);
+    };
+    info!(conn = conn_id, grace_ms = grace.as_millis() as u64, "Backend dropped, holding client for a reconnect");
+    let metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    let set_held = |held: bool| {
+        if let Some(metrics) = &metrics {
+            metrics.held.store(held, Ordering::SeqCst);
         }
     };
+    set_held(true);
+    let came_back = hold_client(conn_id, inbound, grace, route, target.socket).await;
+    set_held(false);
+    if !came_back? {
+        return Ok(false);
+    }
+    transfer::expect_return(target.peer_ip, target.username, route.clone());
+    inbound.write_all(&packet).await?;
+    inbound.flush().await?;
+    info!(conn = conn_id, "Backend is back, sent client to reconnect");
+    Ok(true)
+}
 
-    // If PROXY protocol is enabled, send the header first.
-    if let Some(version) = route_decision.proxy_protocol {
-        let source_addr = peer_addr_override.unwrap_or_else(|| inbound.peer_addr().unwrap());
-        let destination_addr = inbound.local_addr().unwrap();
-
-        let proxy_header = match version {
-            1 => {
-                let addrs = ppp::v1::Addresses::from((source_addr, destination_addr));
-                format!("{}\r\n", addrs).into_bytes()
+/// Waits up to `grace` for one of the route's backends to accept connections,
+/// dropping what the client sends meanwhile. Returns whether one did before
+/// the deadline and the client is still there.
+async fn hold_client(
+    conn_id: ProxyConnection,
+    inbound: &mut ClientStream,
+    grace: Duration,
+    route: &RouteDecision,
+    socket: SocketConfig,
+) -> std::io::Result<bool> {
+    let deadline = tokio::time::Instant::now() + grace;
+    let back = async {
+        while !backend_reachable(route, socket).await {
+            tokio::time::sleep(RESUME_PROBE_INTERVAL).await;
+        }
+    };
+    tokio::pin!(back);
+    let mut discard = buffer_pool::take();
+    loop {
+        tokio::select! {
+            _ = &mut back => return Ok(true),
+            _ = tokio::time::sleep_until(deadline) => {
+                info!(conn = conn_id, "No backend came back within the reconnect grace period");
+                return Ok(false);
             }
-            2 => ppp::v2::Builder::with_addresses(
-                ppp::v2::Version::Two | ppp::v2::Command::Proxy,
-                ppp::v2::Protocol::Stream,
-                (source_addr, destination_addr),
-            )
-            .build()
-            .unwrap_or_default(),
-            _ => vec![], // Unsupported version
-        };
-
-        if !proxy_header.is_empty() {
-            if let Err(e) = outbound.write_all(&proxy_header).await {
-                error!(
-                    conn = conn_id,
-                    "Failed to write PROXY protocol header: {}", e
-                );
-                cleanup_conn(conn_id);
-                return;
+            result = inbound.read(&mut discard) => {
+                if result? == 0 {
+                    return Ok(false);
+                }
             }
         }
     }
+}
 
-    // Forward the initial packets that were consumed during parsing.
-    if let Err(e) = outbound.write_all(&handshake_packet).await {
-        error!(
-            conn = conn_id,
-            "Failed to write handshake to backend: {}", e
-        );
-        cleanup_conn(conn_id);
-        return;
+/// Whether one of the route's backends accepts connections from `socket`.
+/// Backends behind an upstream proxy are not checked and count as reachable.
+async fn backend_reachable(route: &RouteDecision, socket: SocketConfig) -> bool {
+    if route.proxy.is_some() || route.proxy_pool.is_some() {
+        return true;
     }
-    if let Err(e) = outbound.write_all(&login_packet).await {
-        error!(conn = conn_id, "Failed to write login to backend: {}", e);
-        cleanup_conn(conn_id);
-        return;
+    for candidate in backend_candidates(route) {
+        let attempt = connect_direct(&candidate, socket);
+        if let Ok(Ok(_)) = tokio::time::timeout(RESUME_PROBE_TIMEOUT, attempt).await {
+            return true;
+        }
     }
+    false
+}
 
-    // Data proxying
-    if let Err(e) = copy_bidirectional_with_metrics(conn_id, &mut inbound, &mut outbound).await {
-        error!(conn = conn_id, "Connection proxy failed: {}", e);
+/// Writes all of `bufs`, in as few writes as the stream allows.
+async fn write_all_vectored<W>(to: &mut W, mut bufs: &mut [IoSlice<'_>]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        let n = to.write_vectored(bufs).await?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer"));
+        }
+        IoSlice::advance_slices(&mut bufs, n);
     }
-
-    cleanup_conn(conn_id);
-    info!(conn = conn_id, "Connection closed");
+    Ok(())
 }
 
-/// Cleanup resources for a connection
-fn cleanup_conn(conn_id: ProxyConnection) {
-    // Add to disconnection event queue (thread-safe alternative)
-    let disconnection_event = DisconnectionEvent { conn_id };
-    DISCONNECTION_EVENT_QUEUE
-        .lock()
-        .unwrap()
-        .push(disconnection_event);
-
-    // The new polling mechanism handles disconnection events.
-    // No need to manually call a callback here.
+/// Writes bytes read from one side to the other under the connection's and
+/// the shared rate limits, counting them as sent (client to backend) or
+/// received.
+async fn forward_chunk<W>(conn_id: ProxyConnection, to: &mut W, bytes: &[u8], sent: bool) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let limiter = RATE_LIMITERS
+        .get(&conn_id)
+        .map(|entry| if sent { entry.0.clone() } else { entry.1.clone() });
+    let shared = limiter::shared_limiters(limiter::listener_of(conn_id));
+    let limiters: Vec<Arc<ConnLimiter>> = limiter
+        .into_iter()
+        .chain(shared.into_iter().map(|(send, recv)| if sent { send } else { recv }))
+        .collect();
+    let chunk_size = limiter::chunk_size(&limiters, LIMITED_CHUNK_SIZE).unwrap_or(bytes.len().max(1));
+    for chunk in bytes.chunks(chunk_size) {
+        limiter::until_all_ready(limiters.clone(), chunk.len()).await;
+        to.write_all(chunk).await?;
+    }
+    to.flush().await?;
+    capture::feed(conn_id, bytes, sent);
+    mirror::feed(conn_id, bytes, sent);
 
-    CONN_MANAGER.lock().unwrap().remove(&conn_id);
-    CONN_METRICS.lock().unwrap().remove(&conn_id);
-    ACTIVE_CONN.fetch_sub(1, Ordering::SeqCst);
+    let n = bytes.len() as u64;
+    let conn_metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    if sent {
+        if let Some(metrics) = conn_metrics {
+            metrics.bytes_sent.fetch_add(n, Ordering::SeqCst);
+        }
+        TOTAL_BYTES_SENT.fetch_add(n, Ordering::SeqCst);
+    } else {
+        if let Some(metrics) = conn_metrics {
+            metrics.bytes_recv.fetch_add(n, Ordering::SeqCst);
+        }
+        TOTAL_BYTES_RECV.fetch_add(n, Ordering::SeqCst);
+    }
+    Ok(())
 }
 
 /// A custom `copy_bidirectional` that updates metrics.
 #[cfg(not(target_os = "linux"))]
 async fn copy_bidirectional_with_metrics(
     conn_id: ProxyConnection,
-    inbound: &mut TcpStream,
+    inbound: &mut ClientStream,
     outbound: &mut Box<AsyncStream>,
 ) -> Result<(u64, u64), std::io::Error> {
     copy_bidirectional_fallback(conn_id, inbound, outbound).await
@@ -428,33 +1751,42 @@ async fn copy_bidirectional_with_metrics(
 #[cfg(target_os = "linux")]
 async fn copy_bidirectional_with_metrics(
     conn_id: ProxyConnection,
-    inbound: &mut TcpStream,
+    inbound: &mut ClientStream,
     outbound: &mut Box<AsyncStream>,
 ) -> Result<(u64, u64), std::io::Error> {
-    use crate::splice;
+    use crate::{sockmap, splice};
     use std::any::Any;
     use tokio::net::TcpStream;
 
-    // Attempt to downcast to TcpStream for zero-copy.
-    let any_mut: &mut (dyn Any) = &mut **outbound;
-    if let Some(outbound_tcp) = any_mut.downcast_mut::<TcpStream>() {
-        // Both are TCP streams, we can use splice
-        let (a_to_b, b_to_a) = splice::copy_bidirectional(conn_id, inbound, outbound_tcp).await?;
-
-        // Update metrics
-        let conn_metrics = CONN_METRICS.lock().unwrap().get(&conn_id).cloned();
-        if let Some(metrics) = conn_metrics {
-            metrics.bytes_sent.fetch_add(a_to_b, Ordering::SeqCst);
-            metrics.bytes_recv.fetch_add(b_to_a, Ordering::SeqCst);
-            TOTAL_BYTES_SENT.fetch_add(a_to_b, Ordering::SeqCst);
-            TOTAL_BYTES_RECV.fetch_add(b_to_a, Ordering::SeqCst);
+    // Attempt to downcast to TcpStream for zero-copy. Rate limits cannot be
+    // enforced in the kernel, so only unlimited connections bypass the
+    // buffered copier; SOCKS5 and HTTP CONNECT streams, encrypted clients
+    // and mirrored or captured sessions always use it.
+    let any_mut: &mut dyn Any = &mut **outbound;
+    if let Some(outbound_tcp) = any_mut.downcast_mut::<TcpStream>()
+        && let Some(inbound_tcp) = inbound.plain_mut()
+        && limiter::is_unlimited(conn_id)
+        && !mirror::is_active(conn_id)
+        && !capture::is_active(conn_id)
+    {
+        // Metrics are updated while either kernel path runs.
+        if OPTIONS.read().unwrap().sockmap
+            && let Some(result) = sockmap::copy_bidirectional(conn_id, inbound_tcp, outbound_tcp).await
+        {
+            return result;
         }
 
-        Ok((a_to_b, b_to_a))
-    } else {
-        // Fallback to standard copy for other stream types
-        return copy_bidirectional_fallback(conn_id, inbound, outbound).await;
+        match splice::copy_bidirectional(conn_id, inbound_tcp, outbound_tcp).await? {
+            splice::Relayed::Done(a_to_b, b_to_a) => return Ok((a_to_b, b_to_a)),
+            splice::Relayed::Limited(a_to_b, b_to_a) => {
+                info!(conn = conn_id, "Rate limit or capture set, leaving the splice path");
+                let (rest_a_to_b, rest_b_to_a) =
+                    copy_bidirectional_fallback(conn_id, inbound, outbound).await?;
+                return Ok((a_to_b + rest_a_to_b, b_to_a + rest_b_to_a));
+            }
+        }
     }
+    copy_bidirectional_fallback(conn_id, inbound, outbound).await
 }
 
 /// Fallback implementation using standard copy
@@ -468,10 +1800,8 @@ where
     B: AsyncRead + AsyncWrite + Unpin + ?Sized,
 {
     let conn_metrics = CONN_METRICS
-        .lock()
-        .unwrap()
         .get(&conn_id)
-        .cloned()
+        .map(|entry| entry.value().clone())
         .ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -480,10 +1810,8 @@ where
         })?;
 
     let (send_limiter, recv_limiter) = RATE_LIMITERS
-        .lock()
-        .unwrap()
         .get(&conn_id)
-        .cloned()
+        .map(|entry| entry.value().clone())
         .ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -491,11 +1819,14 @@ where
             )
         })?;
 
+    // Global and listener caps are looked up per read, so changes apply to
+    // running relays.
+    let listener = limiter::listener_of(conn_id);
+
     let mut a_to_b_copied = 0;
     let mut b_to_a_copied = 0;
-    const CHUNK_SIZE: usize = 4096;
-    let mut a_buf = [0u8; 4096];
-    let mut b_buf = [0u8; 4096];
+    let mut a_buf = buffer_pool::take();
+    let mut b_buf = buffer_pool::take();
     let mut a_closed = false;
     let mut b_closed = false;
 
@@ -511,17 +1842,23 @@ where
                         b.shutdown().await?;
                     }
                 } else {
+                    let limiters: Vec<Arc<ConnLimiter>> = std::iter::once(send_limiter.clone())
+                        .chain(limiter::shared_limiters(listener).into_iter().map(|(send, _)| send))
+                        .collect();
+                    let chunk_size = limiter::chunk_size(&limiters, LIMITED_CHUNK_SIZE).unwrap_or(n);
                     let mut processed = 0;
                     while processed < n {
-                        let end = (processed + CHUNK_SIZE).min(n);
+                        let end = (processed + chunk_size).min(n);
                         let chunk = &a_buf[processed..end];
                         // Rate limiting for sending (a to b)
-                        if let Some(num) = NonZeroU32::new(chunk.len() as u32) {
-                            send_limiter.until_n_ready(num).await.unwrap();
-                        }
+                        limiter::until_all_ready(limiters.clone(), chunk.len()).await;
                         b.write_all(chunk).await?;
                         processed = end;
                     }
+                    // Buffering streams, such as encrypted clients, send on flush.
+                    b.flush().await?;
+                    capture::feed(conn_id, &a_buf[..n], true);
+                    mirror::feed(conn_id, &a_buf[..n], true);
 
                     a_to_b_copied += n as u64;
                     conn_metrics.bytes_sent.fetch_add(n as u64, Ordering::SeqCst);
@@ -536,18 +1873,23 @@ where
                         a.shutdown().await?;
                     }
                 } else {
+                    let limiters: Vec<Arc<ConnLimiter>> = std::iter::once(recv_limiter.clone())
+                        .chain(limiter::shared_limiters(listener).into_iter().map(|(_, recv)| recv))
+                        .collect();
+                    let chunk_size = limiter::chunk_size(&limiters, LIMITED_CHUNK_SIZE).unwrap_or(n);
                     let mut processed = 0;
                     while processed < n {
-                        let end = (processed + CHUNK_SIZE).min(n);
+                        let end = (processed + chunk_size).min(n);
                         let chunk = &b_buf[processed..end];
 
                         // Rate limiting for receiving (b to a)
-                        if let Some(num) = NonZeroU32::new(chunk.len() as u32) {
-                            recv_limiter.until_n_ready(num).await.unwrap();
-                        }
+                        limiter::until_all_ready(limiters.clone(), chunk.len()).await;
                         a.write_all(chunk).await?;
                         processed = end;
                     }
+                    a.flush().await?;
+                    capture::feed(conn_id, &b_buf[..n], false);
+                    mirror::feed(conn_id, &b_buf[..n], false);
                     b_to_a_copied += n as u64;
                     conn_metrics.bytes_recv.fetch_add(n as u64, Ordering::SeqCst);
                     TOTAL_BYTES_RECV.fetch_add(n as u64, Ordering::SeqCst);
@@ -562,63 +1904,192 @@ where
     Ok((a_to_b_copied, b_to_a_copied))
 }
 
-/// Asynchronously requests route information via FFI and waits for the decision.
+/// Asks the route handler for a decision.
 async fn get_route_info(
     conn_id: ProxyConnection,
     hs: &HandshakeData,
     username: &str,
     peer_ip: &str,
-) -> Result<RouteDecision, ()> {
-    // Acquire the lock to ensure only one FFI routing operation happens at a time.
-    let _guard = FFI_ROUTER_LOCK.lock().await;
-
-    let (tx, rx) = oneshot::channel();
-
-    // Store the sender so the FFI callback can use it
-    PENDING_ROUTES.lock().unwrap().insert(conn_id, tx);
-
-    // This part is now synchronous: it just calls the FFI function and returns.
-    // The actual result will arrive on the `rx` channel.
-    request_route_info(conn_id, hs, username, peer_ip);
-
-    // Asynchronously wait for the decision to be submitted.
-    // Add a timeout to prevent waiting forever.
-    match tokio::time::timeout(std::time::Duration::from_secs(10), rx).await {
-        Ok(Ok(decision)) => Ok(decision),
-        Ok(Err(_)) => {
-            error!(
-                conn = conn_id,
-                "Route decision channel closed unexpectedly."
-            );
-            Err(())
-        }
-        Err(_) => {
-            error!(conn = conn_id, "Timed out waiting for route decision.");
-            // Clean up the pending route entry
-            PENDING_ROUTES.lock().unwrap().remove(&conn_id);
-            Err(())
-        }
-    }
-}
-
-/// Fires off the FFI call to JS to request a routing decision.
-/// This function is synchronous and does not wait for a response.
-/// Also adds the request to a queue for polling-based approach.
-fn request_route_info(conn_id: ProxyConnection, hs: &HandshakeData, username: &str, peer_ip: &str) {
-    // Add to polling queue
+    local_addr: Option<SocketAddr>,
+    sni: Option<&str>,
+    transport: TransportKind,
+) -> Result<RouteDecision, NoRoute> {
     let route_request = RouteRequest {
         conn_id,
         peer_ip: peer_ip.to_string(),
+        local_ip: local_addr.map(|addr| addr.ip().to_canonical().to_string()),
+        local_port: local_addr.map(|addr| addr.port()),
         port: hs.port,
         // 协议版本现改为 i32 直传，保持与握手一致
         protocol: hs.protocol_version,
         host: hs.host.clone(),
         username: username.to_string(),
+        uuid: hs.forwarded.as_ref().map(|f| f.uuid.clone()),
+        sni: sni.map(str::to_string),
+        client_type: hs.client_type.clone(),
+        transport,
+        geo: geoip::lookup(peer_ip),
+    };
+    handler::route(route_request).await
+}
+
+/// Parses a router-supplied PROXY header source, `ip` or `ip:port`.
+/// Unspecified and multicast addresses are refused.
+fn parse_proxy_source(source: &str, default_port: u16) -> Option<SocketAddr> {
+    let addr = source
+        .parse::<SocketAddr>()
+        .ok()
+        .or_else(|| {
+            source
+                .parse::<IpAddr>()
+                .ok()
+                .map(|ip| SocketAddr::new(ip, default_port))
+        })?;
+    let ip = addr.ip();
+    (!ip.is_unspecified() && !ip.is_multicast()).then_some(addr)
+}
+
+/// PROXY headers need both addresses in one family; mixed pairs are
+/// expressed as IPv6, with IPv4 addresses mapped.
+fn same_family(source: SocketAddr, destination: SocketAddr) -> (SocketAddr, SocketAddr) {
+    if source.is_ipv4() == destination.is_ipv4() {
+        return (source, destination);
+    }
+    let v6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    };
+    (v6(source), v6(destination))
+}
+
+/// A backend a login may be sent to.
+enum Backend<'a> {
+    Pool(&'a str),
+    Remote(&'a str, u16),
+    /// A `tls://` backend, connected to over TLS (see `tls.rs`).
+    Tls(&'a str, u16),
+}
+
+impl std::fmt::Display for Backend<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Pool(pool) => write!(f, "pool:{}", pool),
+            // Keyed like a plain backend, for health and SOCKS5 targets.
+            Backend::Remote(host, port) | Backend::Tls(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// The backends of a decision in the order they are tried: its pool or
+/// `remoteHost`, then each of `remotes`.
+fn backend_candidates(route_decision: &RouteDecision) -> Vec<Backend<'_>> {
+    let primary = match (&route_decision.pool, &route_decision.remote_host) {
+        (Some(pool), _) => Some(Backend::Pool(pool)),
+        (None, Some(host)) => Some(remote_backend(host, route_decision.remote_port.unwrap_or(0))),
+        (None, None) => None,
+    };
+    primary
+        .into_iter()
+        .chain(
+            route_decision
+                .remotes
+                .iter()
+                .flatten()
+                .map(|remote| remote_backend(&remote.host, remote.port)),
+        )
+        .collect()
+}
+
+/// A backend host, which asks for TLS as `tls://host[:port]` (IPv6 hosts
+/// bracketed), the port defaulting to `port`.
+fn remote_backend(host: &str, port: u16) -> Backend<'_> {
+    let Some(rest) = host.strip_prefix("tls://") else {
+        return Backend::Remote(host, port);
+    };
+    let (host, port) = match rest.rsplit_once(':') {
+        Some((host, explicit)) if !rest.ends_with(']') => match explicit.parse() {
+            Ok(explicit) => (host, explicit),
+            Err(_) => (rest, port),
+        },
+        _ => (rest, port),
     };
-    ROUTE_REQUEST_QUEUE.lock().unwrap().push(route_request);
+    Backend::Tls(host.trim_start_matches('[').trim_end_matches(']'), port)
+}
+
+/// Connects straight to the backend as `socket` says, trying each resolved
+/// address in turn.
+async fn connect_direct(backend: &Backend<'_>, socket: SocketConfig) -> Result<TcpStream, Error> {
+    let addrs = match *backend {
+        Backend::Pool(pool) => discovery::resolve_pool(pool)?,
+        Backend::Remote(host, port) | Backend::Tls(host, port) => discovery::resolve_backend(host, port).await?,
+    };
+    // Healthy addresses race first; unhealthy ones only once they are exhausted.
+    let (unhealthy, healthy): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .filter(|addr| outbound::reachable(addr, socket.local))
+        .partition(|addr| health_check::is_unhealthy(&addr.to_string()));
+    let mut addrs = discovery::interleave_families(healthy);
+    addrs.extend(discovery::interleave_families(unhealthy));
+
+    let delay = OPTIONS
+        .read()
+        .unwrap()
+        .happy_eyeballs_delay_ms
+        .unwrap_or(DEFAULT_HAPPY_EYEBALLS_DELAY_MS);
+    race_connect(addrs, Duration::from_millis(delay), socket).await
+}
+
+/// Connects to the first of `addrs` to answer. Each attempt starts when the
+/// previous one fails or after `delay`, whichever is first; a zero `delay`
+/// tries them one at a time. Losing attempts are dropped.
+async fn race_connect(addrs: Vec<SocketAddr>, delay: Duration, socket: SocketConfig) -> Result<TcpStream, Error> {
+    let mut pending = addrs.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_err = None;
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => {
+                    attempts.spawn(connect_attempt(addr, socket));
+                }
+                None => break,
+            }
+        }
+        tokio::select! {
+            Some(joined) = attempts.join_next() => {
+                let Ok((addr, result)) = joined else { continue };
+                match result {
+                    Ok(stream) => {
+                        health::record_connect(addr, true);
+                        return Ok(stream);
+                    }
+                    Err(e) => {
+                        warn!(%addr, "Backend address unreachable: {}", e);
+                        health::record_connect(addr, false);
+                        last_err = Some(e);
+                        if let Some(next) = pending.next() {
+                            attempts.spawn(connect_attempt(next, socket));
+                        }
+                    }
+                }
+            }
+            _ = tokio::time::sleep(delay), if !delay.is_zero() && !pending.as_slice().is_empty() => {
+                if let Some(next) = pending.next() {
+                    attempts.spawn(connect_attempt(next, socket));
+                }
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        Error::new(ErrorKind::NotFound, "backend resolved to no addresses")
+    }))
 }
 
-/// --- Packet Serialization Helpers ---
+async fn connect_attempt(addr: SocketAddr, socket: SocketConfig) -> (SocketAddr, std::io::Result<TcpStream>) {
+    (addr, outbound::connect(addr, socket).await)
+}
+
+// --- Packet Serialization Helpers ---
 
 async fn read_login_packet<R>(stream: &mut R) -> std::io::Result<(Vec<u8>, String)>
 where
@@ -723,7 +2194,7 @@ fn create_handshake_packet(hs: &HandshakeData) -> Vec<u8> {
     let mut data = Vec::new();
     data.extend(write_varint(0x00)); // packet id
     data.extend(write_varint(hs.protocol_version));
-    data.extend(write_string(&hs.host));
+    data.extend(write_string(&forwarding::encode_host(hs)));
     data.extend(&hs.port.to_be_bytes());
     data.extend(write_varint(hs.next_state));
 
@@ -732,46 +2203,40 @@ fn create_handshake_packet(hs: &HandshakeData) -> Vec<u8> {
     packet
 }
 
-/// Handle status request (MOTD)
+/// Counts a client that missed `handshakeTimeoutMs` and closes it.
+fn handshake_timed_out(conn_id: ProxyConnection) {
+    HANDSHAKE_TIMEOUTS.fetch_add(1, Ordering::SeqCst);
+    debug!(conn = conn_id, "Handshake timed out");
+    cleanup_conn(conn_id, DisconnectReason::HandshakeTimeout);
+}
+
+/// Reads the status request packet (packet ID 0x00 with no data).
+async fn read_status_request<R: AsyncRead + Unpin>(inbound: &mut R) -> std::io::Result<()> {
+    let _packet_len = protocol::read_varint(inbound).await?;
+    match protocol::read_varint(inbound).await? {
+        0 => Ok(()),
+        id => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid status request packet ID: {id}"),
+        )),
+    }
+}
+
+/// Handle status request (MOTD). Returns whether the client was refused
+/// and left unanswered for the tarpit.
 async fn handle_status_request(
     conn_id: ProxyConnection,
-    inbound: &mut TcpStream,
+    inbound: &mut ConnReader<ClientTransport>,
     hs: &HandshakeData,
     peer_addr_override: Option<SocketAddr>,
-) {
-    // First, read the status request packet (should be packet ID 0x00 with no data)
-    match protocol::read_varint(inbound).await {
-        Ok(_packet_len) => {
-            match protocol::read_varint(inbound).await {
-                Ok(packet_id) if packet_id == 0 => {
-                    // Valid status request, proceed with MOTD handling
-                }
-                Ok(id) => {
-                    error!(conn = conn_id, "Invalid status request packet ID: {}", id);
-                    return;
-                }
-                Err(e) => {
-                    error!(
-                        conn = conn_id,
-                        "Failed to read status request packet ID: {}", e
-                    );
-                    return;
-                }
-            }
-        }
-        Err(e) => {
-            error!(
-                conn = conn_id,
-                "Failed to read status request packet length: {}", e
-            );
-            return;
-        }
-    }
+    local_addr: Option<SocketAddr>,
+) -> bool {
 
     let peer_ip = peer_addr_override
         .map(|addr| addr.ip().to_string())
         .unwrap_or_else(|| {
             inbound
+                .get_ref()
                 .peer_addr()
                 .map_or_else(|_| "0.0.0.0".to_string(), |addr| addr.ip().to_string())
         });
@@ -786,111 +2251,215 @@ async fn handle_status_request(
         "MOTD request received"
     );
 
+    let override_status = pause::motd(conn_id, hs.protocol_version)
+        .or_else(|| overflow::motd(hs.protocol_version))
+        .or_else(|| default_motd::render(hs.protocol_version));
+    if let Some(status) = override_status {
+        if let Err(e) = write_status_response(inbound, &status.to_string()).await {
+            error!(conn = conn_id, "Failed to send default status response: {}", e);
+            return false;
+        }
+        answer_ping(conn_id, inbound).await;
+        return false;
+    }
+
+    // A vhost's MOTD stands in for the handler and its cache.
+    let vhost_motd = vhosts::motd(&hs.host);
+
     // Check cache first for MOTD
-    if let Some(cached_entry) = ROUTER_MOTD_CACHE
-        .get(&peer_ip, Some(&hs.host), &CacheGranularity::IpHost)
-        .or_else(|| ROUTER_MOTD_CACHE.get(&peer_ip, None, &CacheGranularity::Ip))
+    if vhost_motd.is_none()
+        && let Some(cached_entry) = ROUTER_MOTD_CACHE
+            .lookup_decision(CacheScope::Motd, &peer_ip, &hs.host)
+            .await
     {
         info!(conn = conn_id, "MOTD cache hit for {}@{}", peer_ip, hs.host);
 
         if cached_entry.is_rejection {
+            ROUTER_MOTD_CACHE.count_rejection();
+            // Left unanswered for the tarpit.
+            if tarpit::enabled() {
+                return true;
+            }
             let disconnect_msg = cached_entry
                 .reject_reason
-                .unwrap_or_else(|| "Connection blocked by cache".to_string());
-            let _ = write_disconnect(inbound, &disconnect_msg).await;
-            return;
+                .unwrap_or_else(|| messages::builtin(messages::BLOCKED));
+            let _ = protocol::write_status_disconnect(inbound, &disconnect_msg, hs.protocol_version).await;
+            return false;
         }
 
         // Use cached MOTD data
         if let Ok(cached_motd) = serde_json::from_value::<MotdDecision>(cached_entry.data) {
-            if let Err(e) = send_status_response(inbound, &cached_motd, hs.protocol_version).await {
+            if let Some(target) = &cached_motd.passthrough
+                && passthrough_status(conn_id, inbound, hs, target).await
+            {
+                return false;
+            }
+            if let Err(e) = send_status_response(inbound, &cached_motd, hs).await {
                 error!(
                     conn = conn_id,
                     "Failed to send cached status response: {}", e
                 );
+                return false;
             }
-            return;
+            answer_ping(conn_id, inbound).await;
+            return false;
         }
     }
 
-    // Get MOTD decision from callback
-    let motd_decision = match get_motd_info(conn_id, hs, &peer_ip).await {
-        Ok(decision) => decision,
-        Err(_) => {
-            // Error already logged, send default MOTD or disconnect
-            error!(conn = conn_id, "Failed to get MOTD decision, using default");
-            MotdDecision {
-                version: Some(crate::types::MotdVersion {
-                    name: "Geofront".to_string(),
-                    protocol: hs.protocol_version,
-                }),
-                players: Some(crate::types::MotdPlayers {
-                    max: 20,
-                    online: Some(0),
-                    sample: vec![],
-                }),
-                description: Some(serde_json::json!({
-                    "text": "Geofront Proxy - Connection Error"
-                })),
-                favicon: None,
-                disconnect: None,
-                cache: None,
+    // Get MOTD decision from the vhost or callback
+    let motd_decision = match vhost_motd {
+        Some(decision) => decision,
+        None => match get_motd_info(conn_id, hs, &peer_ip, local_addr).await {
+            Ok(decision) => decision,
+            Err(_) => {
+                // Error already logged, send the fallback or default MOTD
+                error!(conn = conn_id, "Failed to get MOTD decision, using fallback");
+                handler::fallback_motd().unwrap_or_else(|| MotdDecision {
+                    version: Some(crate::types::MotdVersion {
+                        name: "Geofront".to_string(),
+                        protocol: hs.protocol_version,
+                    }),
+                    players: Some(crate::types::MotdPlayers {
+                        max: 20,
+                        online: Some(0),
+                        sample: vec![],
+                        auto: None,
+                    }),
+                    description: Some(serde_json::json!({
+                        "text": "Geofront Proxy - Connection Error"
+                    })),
+                    favicon: None,
+                    disconnect: None,
+                    disconnect_template: None,
+                    cache: None,
+                    passthrough: None,
+                })
             }
-        }
+        },
     };
 
     // Check if we should disconnect
-    if let Some(disconnect_msg) = &motd_decision.disconnect {
+    if let Some(disconnect_msg) =
+        messages::rejection(&motd_decision.disconnect, &motd_decision.disconnect_template)
+    {
         // Cache rejection if cache config is provided
         if let Some(cache_config) = &motd_decision.cache {
             let cache_data = serde_json::to_value(&motd_decision).unwrap_or_default();
-            ROUTER_MOTD_CACHE.set(&peer_ip, Some(&hs.host), cache_data, cache_config);
+            let cache_config = rejection_cache_config(cache_config, &disconnect_msg);
+            ROUTER_MOTD_CACHE.set(CacheScope::Motd, &peer_ip, Some(&hs.host), cache_data, &cache_config);
             info!(
                 conn = conn_id,
                 "Cached MOTD rejection for {}@{}", peer_ip, hs.host
             );
         }
 
-        let _ = write_disconnect(inbound, disconnect_msg).await;
-        return;
+        let _ = protocol::write_status_disconnect(inbound, &disconnect_msg, hs.protocol_version).await;
+        return false;
     }
 
     // Cache successful MOTD result if cache config is provided
     if let Some(cache_config) = &motd_decision.cache {
         let cache_data = serde_json::to_value(&motd_decision).unwrap_or_default();
-        ROUTER_MOTD_CACHE.set(&peer_ip, Some(&hs.host), cache_data, cache_config);
+        ROUTER_MOTD_CACHE.set(CacheScope::Motd, &peer_ip, Some(&hs.host), cache_data, cache_config);
         info!(
             conn = conn_id,
             "Cached MOTD result for {}@{}", peer_ip, hs.host
         );
     }
 
+    if let Some(target) = &motd_decision.passthrough
+        && passthrough_status(conn_id, inbound, hs, target).await
+    {
+        return false;
+    }
+
     // Build and send status response
-    if let Err(e) = send_status_response(inbound, &motd_decision, hs.protocol_version).await {
+    if let Err(e) = send_status_response(inbound, &motd_decision, hs).await {
         error!(conn = conn_id, "Failed to send status response: {}", e);
-        return;
+        return false;
     }
 
-    // Handle ping request (if client sends one)
-    if let Ok(_packet_len) = protocol::read_varint(inbound).await {
-        if let Ok(packet_id) = protocol::read_varint(inbound).await {
-            if packet_id == 1 {
-                // Ping packet - read the payload and echo it back
-                if let Ok(payload) = inbound.read_u64().await {
-                    let response = create_ping_response(payload);
-                    let _ = inbound.write_all(&response).await;
-                }
-            }
+    answer_ping(conn_id, inbound).await;
+    false
+}
+
+/// Echoes the ping that follows the status response, if the client sends
+/// one, recording how long it took to arrive as the connection's latency.
+async fn answer_ping(conn_id: ProxyConnection, inbound: &mut ConnReader<ClientTransport>) {
+    let sent_at = Instant::now();
+    if let Ok(_packet_len) = protocol::read_varint(inbound).await
+        && let Ok(packet_id) = protocol::read_varint(inbound).await
+        && packet_id == 1
+    {
+        let rtt = sent_at.elapsed();
+        update_conn_info(conn_id, |info| info.latency_ms = Some(rtt.as_micros() as f64 / 1000.0));
+        // Ping packet - read the payload and echo it back
+        if let Ok(payload) = inbound.read_u64().await {
+            let response = create_ping_response(payload);
+            let _ = inbound.write_all(&response).await;
+        }
+    }
+}
+
+/// Answers the status request with the backend's own response: replays the
+/// handshake and status request to `target`, then relays its response and
+/// the ping exchange. Returns false if the backend could not be reached.
+async fn passthrough_status(
+    conn_id: ProxyConnection,
+    inbound: &mut ConnReader<ClientTransport>,
+    hs: &HandshakeData,
+    target: &StatusPassthrough,
+) -> bool {
+    let connect = TcpStream::connect((target.host.as_str(), target.port));
+    let mut backend = match tokio::time::timeout(STATUS_PASSTHROUGH_TIMEOUT, connect).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            warn!(conn = conn_id, host = %target.host, port = target.port, "Status passthrough failed: {}", e);
+            return false;
+        }
+        Err(_) => {
+            warn!(conn = conn_id, host = %target.host, port = target.port, "Status passthrough timed out");
+            return false;
         }
+    };
+
+    let mut request = create_handshake_packet(&HandshakeData {
+        port: target.port,
+        ..hs.clone()
+    });
+    request.extend([0x01, 0x00]); // Status request: length 1, packet id 0
+    if let Err(e) = backend.write_all(&request).await {
+        warn!(conn = conn_id, "Failed to send status request to backend: {}", e);
+        return false;
+    }
+
+    // Both sides close after the pong; the timeout covers those that don't.
+    if let Ok(Err(e)) = tokio::time::timeout(
+        STATUS_PASSTHROUGH_TIMEOUT,
+        copy_bidirectional_fallback(conn_id, inbound, &mut backend),
+    )
+    .await
+    {
+        warn!(conn = conn_id, "Status passthrough relay failed: {}", e);
     }
+    true
 }
 
 /// Send status response packet with MOTD data
-async fn send_status_response(
-    stream: &mut TcpStream,
+async fn send_status_response<S>(
+    stream: &mut S,
     motd_decision: &MotdDecision,
-    protocol_version: i32,
-) -> std::io::Result<()> {
+    hs: &HandshakeData,
+) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let protocol_version = hs.protocol_version;
+    let mut players = motd_decision.players.clone();
+    if let Some(players) = &mut players {
+        motd_players::fill(players, &hs.host);
+    }
+
     // Build JSON response
     let mut response_json = serde_json::json!({
         "version": {
@@ -902,13 +2471,13 @@ async fn send_status_response(
                 .unwrap_or(protocol_version)
         },
         "players": {
-            "max": motd_decision.players.as_ref()
+            "max": players.as_ref()
                 .map(|p| p.max)
                 .unwrap_or(20),
-            "online": motd_decision.players.as_ref()
+            "online": players.as_ref()
                 .and_then(|p| p.online)
                 .unwrap_or(0),
-            "sample": motd_decision.players.as_ref()
+            "sample": players.as_ref()
                 .map(|p| &p.sample)
                 .unwrap_or(&vec![])
         },
@@ -928,11 +2497,17 @@ async fn send_status_response(
     let json_str = serde_json::to_string(&response_json).unwrap_or_else(|_| {
         r#"{"version":{"name":"Geofront","protocol":47},"players":{"max":20,"online":0,"sample":[]},"description":{"text":"Geofront Proxy - JSON Error"}}"#.to_string()
     });
+    write_status_response(stream, &json_str).await
+}
 
-    // Build status response packet
+/// Sends a status response packet carrying `json_str`.
+async fn write_status_response<S>(stream: &mut S, json_str: &str) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
     let mut payload = Vec::new();
     payload.extend(write_varint(0x00)); // Status Response packet ID
-    payload.extend(write_string(&json_str));
+    payload.extend(write_string(json_str));
 
     let mut packet = write_varint(payload.len() as i32);
     packet.extend(payload);
@@ -951,52 +2526,64 @@ fn create_ping_response(payload: u64) -> Vec<u8> {
     packet
 }
 
-/// Asynchronously requests MOTD information via FFI and waits for the decision.
+/// Asks the MOTD handler for a decision.
 async fn get_motd_info(
     conn_id: ProxyConnection,
     hs: &HandshakeData,
     peer_ip: &str,
+    local_addr: Option<SocketAddr>,
 ) -> Result<MotdDecision, ()> {
-    // Acquire the lock to ensure only one FFI MOTD operation happens at a time.
-    let _guard = FFI_MOTD_LOCK.lock().await;
-
-    let (tx, rx) = oneshot::channel();
-
-    // Store the sender so the FFI callback can use it
-    PENDING_MOTDS.lock().unwrap().insert(conn_id, tx);
-
-    // This part is now synchronous: it just calls the FFI function and returns.
-    // The actual result will arrive on the `rx` channel.
-    request_motd_info(conn_id, hs, peer_ip);
-
-    // Asynchronously wait for the decision to be submitted.
-    // Add a timeout to prevent waiting forever.
-    match tokio::time::timeout(std::time::Duration::from_secs(10), rx).await {
-        Ok(Ok(decision)) => Ok(decision),
-        Ok(Err(_)) => {
-            error!(conn = conn_id, "MOTD decision channel closed unexpectedly.");
-            Err(())
-        }
-        Err(_) => {
-            error!(conn = conn_id, "Timed out waiting for MOTD decision.");
-            // Clean up the pending MOTD entry
-            PENDING_MOTDS.lock().unwrap().remove(&conn_id);
-            Err(())
-        }
-    }
-}
-
-/// Fires off the FFI call to JS to request an MOTD decision.
-/// This function is synchronous and does not wait for a response.
-/// Also adds the request to a queue for polling-based approach.
-fn request_motd_info(conn_id: ProxyConnection, hs: &HandshakeData, peer_ip: &str) {
-    // Add to polling queue
     let motd_request = MotdRequest {
         conn_id,
         peer_ip: peer_ip.to_string(),
+        local_ip: local_addr.map(|addr| addr.ip().to_canonical().to_string()),
+        local_port: local_addr.map(|addr| addr.port()),
         port: hs.port,
         protocol: hs.protocol_version,
         host: hs.host.clone(),
+        geo: geoip::lookup(peer_ip),
     };
-    MOTD_REQUEST_QUEUE.lock().unwrap().push(motd_request);
+    handler::motd(motd_request).await.ok_or(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_backend_reachable_once_it_comes_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let route = RouteDecision {
+            remote_host: Some(addr.ip().to_string()),
+            remote_port: Some(addr.port()),
+            ..Default::default()
+        };
+        let socket = SocketConfig::default();
+        assert!(backend_reachable(&route, socket).await);
+
+        drop(listener);
+        assert!(!backend_reachable(&route, socket).await);
+
+        let _listener = TcpListener::bind(addr).await.unwrap();
+        assert!(backend_reachable(&route, socket).await);
+    }
+
+    #[tokio::test]
+    async fn test_held_connection_is_not_idle() {
+        let conn_id = u64::MAX - 2;
+        let metrics = Arc::new(ConnMetrics::default());
+        metrics.held.store(true, Ordering::SeqCst);
+        CONN_METRICS.insert(conn_id, metrics.clone());
+        let timeout = Duration::from_millis(200);
+
+        let idle = tokio::time::timeout(Duration::from_millis(600), wait_idle(conn_id, Some(timeout))).await;
+        assert!(idle.is_err(), "a held connection timed out as idle");
+
+        metrics.held.store(false, Ordering::SeqCst);
+        let idle = tokio::time::timeout(Duration::from_secs(2), wait_idle(conn_id, Some(timeout))).await;
+        CONN_METRICS.remove(&conn_id);
+        assert!(idle.is_ok(), "an unheld connection never timed out as idle");
+    }
 }
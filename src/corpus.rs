@@ -0,0 +1,107 @@
+//! geofront/src/corpus.rs
+//! A corpus of client traffic the parser rejected (`protocolCorpus`), so
+//! regressions seen in production can be replayed in tests. The raw
+//! handshake and login start bytes of each failed parse are saved as
+//! `<kind>-<hash>.bin` in the corpus directory, at most once per content
+//! and up to `maxFiles` files. Traffic that is not Minecraft at all and bad
+//! PROXY headers are left out, as scanners would fill the corpus.
+//!
+//! Copy a file into `tests/corpus` to replay it with `cargo test`: files
+//! named `ok-*` must parse, others must be rejected cleanly (see
+//! `protocol::replay`).
+
+use crate::{
+    state::OPTIONS,
+    types::{ProtocolCorpusConfig, ProtocolErrorKind},
+};
+use std::{fs, io::ErrorKind, path::Path};
+use tracing::{debug, info, warn};
+
+/// Bytes kept per sample, when the corpus is enabled.
+pub fn sample_limit() -> usize {
+    OPTIONS
+        .read()
+        .unwrap()
+        .protocol_corpus
+        .as_ref()
+        .map_or(0, |config| config.max_bytes)
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn kind_name(kind: ProtocolErrorKind) -> Option<&'static str> {
+    match kind {
+        ProtocolErrorKind::Handshake => Some("handshake"),
+        ProtocolErrorKind::UnknownState => Some("unknown_state"),
+        ProtocolErrorKind::Login => Some("login"),
+        ProtocolErrorKind::ProxyProtocol | ProtocolErrorKind::NotMinecraft => None,
+    }
+}
+
+/// Saves the bytes of a failed parse, if the corpus is enabled and does not
+/// have them yet.
+pub fn save(kind: ProtocolErrorKind, sample: &[u8]) {
+    let Some(config) = OPTIONS.read().unwrap().protocol_corpus.clone() else {
+        return;
+    };
+    let Some(name) = kind_name(kind) else {
+        return;
+    };
+    if sample.is_empty() {
+        return;
+    }
+    if let Err(e) = save_to(&config, name, &sample[..sample.len().min(config.max_bytes)]) {
+        warn!(dir = %config.dir, "Failed to save corpus sample: {}", e);
+    }
+}
+
+fn save_to(config: &ProtocolCorpusConfig, name: &str, sample: &[u8]) -> std::io::Result<()> {
+    let dir = Path::new(&config.dir);
+    let path = dir.join(format!("{}-{:016x}.bin", name, hash(sample)));
+    if path.exists() {
+        return Ok(());
+    }
+    let files = match fs::read_dir(dir) {
+        Ok(entries) => entries.count(),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            fs::create_dir_all(dir)?;
+            0
+        }
+        Err(e) => return Err(e),
+    };
+    if files >= config.max_files {
+        debug!(dir = %config.dir, "Corpus full, not saving sample");
+        return Ok(());
+    }
+    fs::write(&path, sample)?;
+    info!(path = %path.display(), "Saved failed parse to corpus");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_dedups_and_bounds() {
+        let dir = std::env::temp_dir().join(format!("geofront-corpus-{}", std::process::id()));
+        let config = ProtocolCorpusConfig {
+            dir: dir.to_str().unwrap().to_string(),
+            max_files: 2,
+            max_bytes: 4096,
+        };
+        save_to(&config, "handshake", b"\x10\x00bad").unwrap();
+        save_to(&config, "handshake", b"\x10\x00bad").unwrap();
+        save_to(&config, "login", b"\x05\x01").unwrap();
+        save_to(&config, "login", b"\x05\x02").unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, 2);
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+    }
+}
@@ -0,0 +1,80 @@
+//! geofront/src/default_motd.rs
+//! Status responses served from `defaultMotd` without asking the MOTD
+//! handler. The template is a complete status response; `{online}` (players
+//! routed through the proxy), `{max}` (`capacity.maxPlayers`, 20 when unset)
+//! and `{protocol}` (the client's) are filled in wherever they appear in its
+//! strings, and a string holding nothing but a placeholder becomes the
+//! number itself, so `"online": "{online}"` stays a valid status response.
+
+use crate::state::{ADMITTED, OPTIONS};
+use serde_json::Value;
+
+/// `{max}` without a `capacity.maxPlayers`.
+const DEFAULT_MAX_PLAYERS: u64 = 20;
+
+/// The template of `defaultMotd` rendered for a client, or `None` if unset.
+pub fn render(protocol: i32) -> Option<Value> {
+    let template = OPTIONS.read().unwrap().default_motd.clone()?;
+    Some(render_template(template, protocol))
+}
+
+/// Fills the placeholders of a status response template for a client; also
+/// used for the MOTD of paused listeners (see `pause.rs`).
+pub fn render_template(mut status: Value, protocol: i32) -> Value {
+    let max = OPTIONS
+        .read()
+        .unwrap()
+        .capacity
+        .as_ref()
+        .and_then(|capacity| capacity.max_players)
+        .unwrap_or(DEFAULT_MAX_PLAYERS);
+    let online = ADMITTED.lock().unwrap().len() as i64;
+    fill(
+        &mut status,
+        &[("{online}", online), ("{max}", max as i64), ("{protocol}", protocol as i64)],
+    );
+    status
+}
+
+fn fill(value: &mut Value, vars: &[(&str, i64)]) {
+    match value {
+        Value::String(text) => {
+            if let Some((_, n)) = vars.iter().find(|(name, _)| text == name) {
+                *value = Value::from(*n);
+                return;
+            }
+            for (name, n) in vars {
+                if text.contains(name) {
+                    *text = text.replace(name, &n.to_string());
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| fill(item, vars)),
+        Value::Object(fields) => fields.values_mut().for_each(|field| fill(field, vars)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fill_placeholders() {
+        let mut status = json!({
+            "version": { "name": "Lobby {protocol}", "protocol": "{protocol}" },
+            "players": { "max": "{max}", "online": "{online}", "sample": [] },
+            "description": { "text": "{online}/{max} online" }
+        });
+        fill(&mut status, &[("{online}", 3), ("{max}", 20), ("{protocol}", 767)]);
+        assert_eq!(
+            status,
+            json!({
+                "version": { "name": "Lobby 767", "protocol": 767 },
+                "players": { "max": 20, "online": 3, "sample": [] },
+                "description": { "text": "3/20 online" }
+            })
+        );
+    }
+}
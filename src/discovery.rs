@@ -0,0 +1,308 @@
+//! geofront/src/discovery.rs
+//! Dynamic backend pools: hostnames are periodically re-resolved and the
+//! resulting address set is balanced round-robin. Named pools are filled by
+//! an external registry (see `service_discovery.rs`).
+
+use crate::{
+    events, health,
+    state::{BACKEND_POOLS, DNS_REFRESHER, LISTENER_STATE, NAMED_POOLS, OPTIONS},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Pools not used for this long are dropped instead of being refreshed.
+const POOL_IDLE_MS: u64 = 10 * 60 * 1000;
+
+/// The resolved members of one `host:port` backend.
+pub struct BackendPool {
+    pub host: String,
+    pub port: u16,
+    members: RwLock<Vec<SocketAddr>>,
+    cursor: AtomicUsize,
+    last_used_ms: AtomicU64,
+    resolved_at_ms: AtomicU64,
+}
+
+impl BackendPool {
+    fn new(host: &str, port: u16, members: Vec<SocketAddr>) -> Self {
+        let now = events::now_ms();
+        Self {
+            host: host.to_string(),
+            port,
+            members: RwLock::new(members),
+            cursor: AtomicUsize::new(0),
+            last_used_ms: AtomicU64::new(now),
+            resolved_at_ms: AtomicU64::new(now),
+        }
+    }
+
+    pub fn members(&self) -> Vec<SocketAddr> {
+        self.members.read().unwrap().clone()
+    }
+
+    /// All members, rotated so that successive calls start at the next one.
+    /// Callers try them in order, which gives round-robin plus failover.
+    pub fn rotation(&self) -> Vec<SocketAddr> {
+        self.last_used_ms.store(events::now_ms(), Ordering::Relaxed);
+        let mut members = self.members();
+        if !members.is_empty() {
+            let start = self.cursor.fetch_add(1, Ordering::Relaxed) % members.len();
+            members.rotate_left(start);
+        }
+        health::order(members)
+    }
+
+    /// Replaces the member set, returning the (added, removed) addresses.
+    /// Added members start slow (see `health.rs`) unless the pool was empty.
+    fn update(&self, fresh: Vec<SocketAddr>) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+        let mut members = self.members.write().unwrap();
+        let added: Vec<SocketAddr> = fresh
+            .iter()
+            .filter(|a| !members.contains(a))
+            .copied()
+            .collect();
+        if !members.is_empty() {
+            added.iter().copied().for_each(health::warm_up);
+        }
+        let removed = members
+            .iter()
+            .filter(|a| !fresh.contains(a))
+            .copied()
+            .collect();
+        *members = fresh;
+        self.resolved_at_ms
+            .store(events::now_ms(), Ordering::Relaxed);
+        (added, removed)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolSnapshot {
+    pub members: Vec<String>,
+    /// Members whose last connection attempt failed.
+    pub unhealthy: Vec<String>,
+    /// Members still ramping up after recovering or joining.
+    pub warming: Vec<String>,
+    pub resolved_at_ms: u64,
+    pub last_used_ms: u64,
+}
+
+fn pool_key(host: &str, port: u16) -> String {
+    format!("{}:{}", host, port)
+}
+
+async fn lookup(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    addrs.sort();
+    addrs.dedup();
+    Ok(addrs)
+}
+
+/// Resolves a backend to the ordered list of addresses to try.
+///
+/// IP literals are returned as-is. Hostnames go through a pool when DNS
+/// refreshing is enabled, and through a plain lookup otherwise.
+pub async fn resolve_backend(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    if OPTIONS.read().unwrap().dns_refresh_ms.is_none() {
+        return lookup(host, port).await;
+    }
+
+    let key = pool_key(host, port);
+    if let Some(pool) = BACKEND_POOLS.get(&key).map(|p| p.clone()) {
+        let rotation = pool.rotation();
+        if !rotation.is_empty() {
+            return Ok(rotation);
+        }
+    }
+
+    let members = lookup(host, port).await?;
+    if members.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} resolved to no addresses", host),
+        ));
+    }
+    info!(backend = %key, ?members, "Created DNS backend pool");
+    let pool = BACKEND_POOLS
+        .entry(key)
+        .or_insert_with(|| Arc::new(BackendPool::new(host, port, members)))
+        .clone();
+    Ok(pool.rotation())
+}
+
+/// Returns the ordered addresses to try for a named pool.
+pub fn resolve_pool(name: &str) -> Result<Vec<SocketAddr>> {
+    let rotation = NAMED_POOLS
+        .get(name)
+        .map(|pool| pool.rotation())
+        .unwrap_or_default();
+    if rotation.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("backend pool {} has no members", name),
+        ));
+    }
+    Ok(rotation)
+}
+
+/// Replaces the members of a named pool, creating it if needed.
+pub fn update_named_pool(name: &str, mut members: Vec<SocketAddr>) {
+    members.sort();
+    members.dedup();
+    let pool = NAMED_POOLS
+        .entry(name.to_string())
+        .or_insert_with(|| Arc::new(BackendPool::new(name, 0, Vec::new())))
+        .clone();
+    let (added, removed) = pool.update(members);
+    if !added.is_empty() || !removed.is_empty() {
+        info!(pool = %name, ?added, ?removed, "Backend pool membership changed");
+    }
+}
+
+/// Starts, restarts or stops the background refresher according to `interval_ms`.
+pub fn configure(interval_ms: Option<u64>) {
+    let mut refresher = DNS_REFRESHER.lock().unwrap();
+    if let Some(handle) = refresher.take() {
+        handle.abort();
+    }
+    let Some(interval_ms) = interval_ms else {
+        BACKEND_POOLS.clear();
+        return;
+    };
+    let interval = Duration::from_millis(interval_ms.max(1000));
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    *refresher = Some(runtime.spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            refresh_all().await;
+        }
+    }));
+}
+
+/// Re-resolves every pool, dropping pools that have been idle for too long.
+async fn refresh_all() {
+    let now = events::now_ms();
+    BACKEND_POOLS.retain(|_, pool| {
+        now.saturating_sub(pool.last_used_ms.load(Ordering::Relaxed)) < POOL_IDLE_MS
+    });
+
+    let pools: Vec<_> = BACKEND_POOLS.iter().map(|p| p.value().clone()).collect();
+    for pool in pools {
+        match lookup(&pool.host, pool.port).await {
+            // Keep the last known members rather than emptying the pool on a
+            // transient empty answer.
+            Ok(fresh) if !fresh.is_empty() => {
+                let (added, removed) = pool.update(fresh);
+                if !added.is_empty() || !removed.is_empty() {
+                    info!(
+                        backend = %pool_key(&pool.host, pool.port),
+                        ?added,
+                        ?removed,
+                        "DNS backend pool membership changed"
+                    );
+                }
+            }
+            Ok(_) => {
+                warn!(host = %pool.host, "DNS refresh returned no addresses, keeping previous members")
+            }
+            Err(e) => warn!(host = %pool.host, "DNS refresh failed: {}", e),
+        }
+    }
+}
+
+/// Orders addresses for a happy-eyeballs race (RFC 8305): alternating
+/// between IPv6 and IPv4, starting with IPv6 when both are present, and
+/// otherwise keeping the given order within each family.
+pub fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| a.is_ipv6());
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Snapshot of all pools, keyed by `host:port` for DNS pools and by name for
+/// named pools.
+pub fn snapshot() -> HashMap<String, PoolSnapshot> {
+    BACKEND_POOLS
+        .iter()
+        .chain(NAMED_POOLS.iter())
+        .map(|entry| {
+            let pool = entry.value();
+            let members = pool.members();
+            let with = |pick: fn((bool, bool)) -> bool| {
+                members
+                    .iter()
+                    .filter(|a| pick(health::status(a)))
+                    .map(|a| a.to_string())
+                    .collect()
+            };
+            (
+                entry.key().clone(),
+                PoolSnapshot {
+                    unhealthy: with(|(failing, _)| failing),
+                    warming: with(|(_, warming)| warming),
+                    members: members.iter().map(|a| a.to_string()).collect(),
+                    resolved_at_ms: pool.resolved_at_ms.load(Ordering::Relaxed),
+                    last_used_ms: pool.last_used_ms.load(Ordering::Relaxed),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_round_robin() {
+        let a: SocketAddr = "10.0.0.1:25565".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:25565".parse().unwrap();
+        let pool = BackendPool::new("mc.example.com", 25565, vec![a, b]);
+        assert_eq!(pool.rotation(), vec![a, b]);
+        assert_eq!(pool.rotation(), vec![b, a]);
+        assert_eq!(pool.rotation(), vec![a, b]);
+    }
+
+    #[test]
+    fn test_update_reports_changes() {
+        let a: SocketAddr = "10.0.0.1:25565".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:25565".parse().unwrap();
+        let c: SocketAddr = "10.0.0.3:25565".parse().unwrap();
+        let pool = BackendPool::new("mc.example.com", 25565, vec![a, b]);
+        let (added, removed) = pool.update(vec![b, c]);
+        assert_eq!(added, vec![c]);
+        assert_eq!(removed, vec![a]);
+        assert_eq!(pool.members(), vec![b, c]);
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let a: SocketAddr = "10.0.0.1:25565".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:25565".parse().unwrap();
+        let c: SocketAddr = "10.0.0.3:25565".parse().unwrap();
+        let x: SocketAddr = "[2001:db8::1]:25565".parse().unwrap();
+        let y: SocketAddr = "[2001:db8::2]:25565".parse().unwrap();
+        assert_eq!(interleave_families(vec![a, b, c, x, y]), vec![x, a, y, b, c]);
+        assert_eq!(interleave_families(vec![b, a]), vec![b, a]);
+    }
+}
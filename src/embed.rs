@@ -0,0 +1,512 @@
+//! geofront/src/embed.rs
+//! Native API for embedding the proxy in a Rust application: routing and MOTD
+//! decisions come from Rust handlers instead of the FFI polling queues.
+//!
+//! ```no_run
+//! use geofront::{embed::Geofront, types::{RouteDecision, RouteRequest}};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let proxy = Geofront::new();
+//! proxy.set_router(|_request: RouteRequest| async move {
+//!     RouteDecision {
+//!         remote_host: Some("127.0.0.1".to_string()),
+//!         remote_port: Some(25566),
+//!         ..Default::default()
+//!     }
+//! });
+//! proxy.start_listener("0.0.0.0", 25565).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    acceptors,
+    accounting::{self, Usage},
+    bedrock::{self, BedrockSession},
+    audit_db, buffer_pool,
+    cache::{self, BlockedEntry, CacheStats},
+    capture, config_file, conn_query,
+    connection::{cleanup_conn, kick, kick_with_message},
+    discovery, geoip,
+    handler::{self, FfiHandler, MotdHandler, RouteHandler},
+    health_check, latency,
+    limiter::{self, LimitScope},
+    loadgen, logging, login_throttle, metrics_push, pause, prometheus, quic,
+    route_metrics::RouteTotals,
+    service_discovery, sink, snapshot, tls,
+    transfer::{self, TransferOutcome},
+    transport::ListenerTransport,
+    usage, websocket,
+    state::{
+        ACTIVE_CONN, ADMITTED, BACKEND_EVENT_QUEUE, CONN_COUNTER, CONN_INFO, CONN_MANAGER, CONN_METRICS,
+        DISCONNECTION_EVENT_QUEUE, IP_LIMITS, LIFECYCLE_EVENT_QUEUE, LIFECYCLE_HANDLER, LISTENER_COUNTER, LOG_HANDLER,
+        LISTENER_STATE, LISTENER_TOTALS, METRICS_DELTA_BASE, METRICS_EVENT_QUEUE, MOTD_HANDLER,
+        MOTD_REQUEST_QUEUE, OPTIONS, PAUSED_LISTENERS, PENDING_MOTDS, PENDING_ROUTES, PROTOCOL_ERROR_QUEUE, QUOTA_EVENT_QUEUE,
+        RATE_LIMITERS, RETURNING_PLAYERS, ROUTER_MOTD_CACHE, ROUTE_AUDIT_QUEUE, CONFIG_RELOAD_QUEUE, ROUTE_HANDLER, ROUTE_REQUEST_QUEUE, ROUTE_TOTALS,
+        RUNTIME_CONFIG, SHARED_LIMITERS, STATIC_ROUTES, VHOSTS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT, TTFB_SAMPLES, USAGE_REPORTED, USAGE_REPORT_QUEUE,
+    },
+    types::{
+        BackendCheckStatus, BedrockConfig, ConnFilter, ConnectionDetails, DisconnectReason, GeofrontOptions, LifecycleEvent, ListenerOptions, LogRecord,
+        MetricsSnapshot, PauseConfig, PollEvents, QuicTunnelConfig,
+        ProxyConnection, ProxyListener, RateLimitConfig, RouteDecision, RuntimeConfig, StaticRoute, UsageKey, VirtualHost, ConfigReload,
+    },
+};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, atomic::Ordering},
+};
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::info;
+
+/// Handle to the proxy. The proxy state is process-wide, so every handle
+/// (and the FFI) drives the same listeners and connections.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Geofront;
+
+impl Geofront {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Replaces the global options, reconfiguring the features they drive.
+    pub fn set_options(&self, options: GeofrontOptions) {
+        let mut opts_guard = OPTIONS.write().unwrap();
+        if opts_guard.event_sink != options.event_sink {
+            sink::configure(options.event_sink.as_ref());
+        }
+        if opts_guard.audit_db != options.audit_db {
+            audit_db::configure(options.audit_db.as_ref());
+        }
+        if opts_guard.dns_refresh_ms != options.dns_refresh_ms {
+            discovery::configure(options.dns_refresh_ms);
+        }
+        if opts_guard.copy_buffer_size != options.copy_buffer_size {
+            buffer_pool::configure(options.copy_buffer_size);
+        }
+        if opts_guard.geoip_db != options.geoip_db {
+            geoip::configure(options.geoip_db.as_deref());
+        }
+        if opts_guard.health_check != options.health_check {
+            health_check::configure(options.health_check.as_ref());
+        }
+        if opts_guard.pools != options.pools {
+            service_discovery::configure(&options.pools);
+        }
+        if opts_guard.usage_report_interval_ms != options.usage_report_interval_ms {
+            usage::configure(options.usage_report_interval_ms);
+        }
+        if opts_guard.metrics_exporter != options.metrics_exporter {
+            prometheus::configure(opts_guard.metrics_exporter.as_ref(), options.metrics_exporter.as_ref());
+        }
+        if opts_guard.metrics_push_interval_ms != options.metrics_push_interval_ms {
+            metrics_push::configure(options.metrics_push_interval_ms);
+        }
+        if opts_guard.max_pending_routes != options.max_pending_routes {
+            handler::configure(options.max_pending_routes);
+        }
+        if opts_guard.cache_max_entries != options.cache_max_entries {
+            ROUTER_MOTD_CACHE.set_max_entries(options.cache_max_entries);
+        }
+        if opts_guard.shared_cache != options.shared_cache {
+            // The Redis client binds to the runtime it is created in.
+            let rt = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+            let _guard = rt.enter();
+            ROUTER_MOTD_CACHE.configure_shared(options.shared_cache.as_ref());
+        }
+        *opts_guard = options;
+
+        info!("Updated global options");
+    }
+
+    /// Routes logins through `router` instead of the FFI queues.
+    pub fn set_router(&self, router: impl RouteHandler + 'static) {
+        *ROUTE_HANDLER.write().unwrap() = Arc::new(router);
+    }
+
+    /// Answers server list pings through `handler` instead of the FFI queues.
+    pub fn set_motd_handler(&self, handler: impl MotdHandler + 'static) {
+        *MOTD_HANDLER.write().unwrap() = Arc::new(handler);
+    }
+
+    /// Replaces the static routes, which take precedence over the router.
+    pub fn set_routes(&self, routes: Vec<StaticRoute>) {
+        info!(count = routes.len(), "Updated static routes");
+        *STATIC_ROUTES.write().unwrap() = routes;
+    }
+
+    /// Replaces the virtual hosts, consulted before the router and MOTD
+    /// handler.
+    pub fn set_vhosts(&self, vhosts: Vec<VirtualHost>) {
+        info!(count = vhosts.len(), "Updated virtual hosts");
+        *VHOSTS.write().unwrap() = vhosts;
+    }
+
+    /// Applies the options, vhosts and routes of a JSON config file, then
+    /// reloads it whenever it changes if `watch_interval_ms` is set. The
+    /// file is left unwatched if it fails to load.
+    pub fn load_config(&self, path: &str, watch_interval_ms: Option<u64>) -> Result<ConfigReload, String> {
+        let result = config_file::load(path);
+        config_file::watch(path, watch_interval_ms.filter(|_| result.is_ok()));
+        result
+    }
+
+    /// Delivers lifecycle events to `handler` instead of the polling queue.
+    /// They are only recorded while `lifecycle_events` is on.
+    pub fn set_lifecycle_handler(&self, handler: impl Fn(&LifecycleEvent) + Send + Sync + 'static) {
+        *LIFECYCLE_HANDLER.write().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Delivers log records to `handler` instead of the log queue, when
+    /// logging was initialized in queue mode. The handler must not log.
+    pub fn set_log_handler(&self, handler: impl Fn(&LogRecord) + Send + Sync + 'static) {
+        *LOG_HANDLER.write().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Hands routing and MOTD decisions back to the FFI queues.
+    pub fn reset_handlers(&self) {
+        *ROUTE_HANDLER.write().unwrap() = Arc::new(FfiHandler);
+        *MOTD_HANDLER.write().unwrap() = Arc::new(FfiHandler);
+        *LIFECYCLE_HANDLER.write().unwrap() = None;
+        *LOG_HANDLER.write().unwrap() = None;
+    }
+
+    /// Binds `addr:port` and starts accepting connections on the proxy runtime.
+    pub async fn start_listener(&self, addr: &str, port: u16) -> io::Result<ProxyListener> {
+        listen(addr, port, ListenerTransport::Tcp, ListenerOptions::default()).await
+    }
+
+    /// Like `start_listener`, with settings of its own for this listener.
+    pub async fn start_listener_with_options(
+        &self,
+        addr: &str,
+        port: u16,
+        options: ListenerOptions,
+    ) -> io::Result<ProxyListener> {
+        let transport = ListenerTransport::from_options(&options)?;
+        listen(addr, port, transport, options).await
+    }
+
+    /// Like `start_listener`, terminating TLS with the PEM certificate chain
+    /// and key at the given paths. Requires the `tls` feature.
+    pub async fn start_listener_tls(
+        &self,
+        addr: &str,
+        port: u16,
+        cert_path: &str,
+        key_path: &str,
+    ) -> io::Result<ProxyListener> {
+        let acceptor = tls::load_acceptor(cert_path, key_path, None)?;
+        listen(addr, port, ListenerTransport::Tls(acceptor), ListenerOptions::default()).await
+    }
+
+    /// Like `start_listener`, for WebSocket clients sending the Minecraft
+    /// stream in binary frames. Requires the `websocket` feature.
+    pub async fn start_listener_ws(&self, addr: &str, port: u16) -> io::Result<ProxyListener> {
+        websocket::ensure_supported()?;
+        listen(addr, port, ListenerTransport::WebSocket, ListenerOptions::default()).await
+    }
+
+    /// Starts a UDP listener relaying Bedrock clients (see `bedrock.rs`),
+    /// stopped like the others with `stop_listener`.
+    pub async fn start_bedrock_listener(&self, addr: &str, port: u16, config: BedrockConfig) -> io::Result<ProxyListener> {
+        let socket = bedrock::bind(addr, port).await?.into_std()?;
+        let id = LISTENER_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let bind_addr = format!("{}:{}", addr, port);
+        info!(listener = id, listen_str = %bind_addr, "Starting Bedrock listener");
+        let mut st = LISTENER_STATE.lock().unwrap();
+        let socket = {
+            let _guard = st.runtime.enter();
+            UdpSocket::from_std(socket)?
+        };
+        let handle = st.runtime.spawn(bedrock::serve(id, socket, config));
+        st.listeners.insert(id, handle);
+        st.bind_addrs.insert(id, bind_addr);
+        Ok(id)
+    }
+
+    /// Starts a QUIC tunnel listener for edges' `quic://` proxies (see
+    /// `quic.rs`). Requires the `quic` feature.
+    pub async fn start_quic_tunnel(&self, addr: &str, port: u16, config: QuicTunnelConfig) -> io::Result<ProxyListener> {
+        let Some(local) = tokio::net::lookup_host((addr, port)).await?.next() else {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("{} resolves to no address", addr),
+            ));
+        };
+        let id = LISTENER_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let bind_addr = format!("{}:{}", addr, port);
+        let mut st = LISTENER_STATE.lock().unwrap();
+        let endpoint = {
+            let _guard = st.runtime.enter();
+            quic::bind(local, &config)?
+        };
+        info!(listener = id, listen_str = %bind_addr, "Starting QUIC tunnel");
+        let handle = st.runtime.spawn(quic::serve(id, endpoint, config));
+        st.listeners.insert(id, handle);
+        st.bind_addrs.insert(id, bind_addr);
+        Ok(id)
+    }
+
+    /// Client associations of the Bedrock listeners.
+    pub fn bedrock_sessions(&self) -> Vec<BedrockSession> {
+        bedrock::sessions()
+    }
+
+    /// Configures the proxy runtime. Only possible before it is first used
+    /// (by a listener or a background task); returns `false` after that.
+    pub fn init_runtime(&self, config: RuntimeConfig) -> bool {
+        RUNTIME_CONFIG.set(config).is_ok()
+    }
+
+    /// Stops a listener; returns `false` if it is unknown.
+    pub fn stop_listener(&self, listener: ProxyListener) -> bool {
+        limiter::set_shared_limits(LimitScope::Listener(listener), None);
+        pause::resume(listener);
+        let mut st = LISTENER_STATE.lock().unwrap();
+        st.bind_addrs.remove(&listener);
+        match st.listeners.remove(&listener) {
+            Some(h) => {
+                h.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pauses a listener without closing its socket or connections (see
+    /// `pause.rs`); returns `false` if it is unknown.
+    pub fn pause_listener(&self, listener: ProxyListener, config: PauseConfig) -> bool {
+        pause::pause(listener, config)
+    }
+
+    /// Resumes a paused listener; returns `false` if it was not paused.
+    pub fn resume_listener(&self, listener: ProxyListener) -> bool {
+        pause::resume(listener)
+    }
+
+    /// Caps the combined traffic of all connections; `None` lifts the cap.
+    pub fn set_global_rate_limit(&self, limit: Option<&RateLimitConfig>) {
+        limiter::set_shared_limits(LimitScope::Global, limit);
+    }
+
+    /// Caps the combined traffic of a listener's connections; `None` lifts
+    /// the cap. Returns `false` if the listener is unknown.
+    pub fn set_listener_rate_limit(&self, listener: ProxyListener, limit: Option<&RateLimitConfig>) -> bool {
+        if !LISTENER_STATE.lock().unwrap().listeners.contains_key(&listener) {
+            return false;
+        }
+        limiter::set_shared_limits(LimitScope::Listener(listener), limit);
+        true
+    }
+
+    /// Serves the metrics in the Prometheus text format on `addr:port`,
+    /// replacing the running exporter if any.
+    pub fn start_metrics_exporter(&self, addr: &str, port: u16) -> io::Result<()> {
+        prometheus::start(addr, port, OPTIONS.read().unwrap().metrics_exporter.as_ref())
+    }
+
+    /// Stops the Prometheus exporter; returns `false` if none was running.
+    pub fn stop_metrics_exporter(&self) -> bool {
+        prometheus::stop()
+    }
+
+    /// Latest active health check results, keyed by backend `host:port`.
+    pub fn backend_health(&self) -> HashMap<String, BackendCheckStatus> {
+        health_check::snapshot()
+    }
+
+    /// Captures a connection's relayed bytes into a pcap file at `path` of
+    /// at most `max_bytes` (see `capture.rs`); `Ok(false)` if it is unknown.
+    pub fn start_capture(&self, conn_id: ProxyConnection, path: &str, max_bytes: u64) -> io::Result<bool> {
+        capture::start(conn_id, path, max_bytes)
+    }
+
+    /// Stops a capture; returns `false` if the connection had none.
+    pub fn stop_capture(&self, conn_id: ProxyConnection) -> bool {
+        capture::stop(conn_id)
+    }
+
+    /// Disconnects a connection; returns `false` if it is unknown.
+    pub fn disconnect(&self, conn_id: ProxyConnection) -> bool {
+        kick(conn_id, DisconnectReason::Kicked)
+    }
+
+    /// Disconnects a connection with `message` (legacy text or a JSON text
+    /// component), shown to the player if it is still logging in. Returns
+    /// `None` if the connection is unknown, else whether the message was sent.
+    pub fn disconnect_with_message(&self, conn_id: ProxyConnection, message: &str) -> Option<bool> {
+        kick_with_message(conn_id, message)
+    }
+
+    /// Moves a connection to the backend of `decision` without a disconnect
+    /// screen, if it is still being routed or is a transferable relayed
+    /// session (see `transfer.rs`).
+    pub fn transfer_connection(&self, conn_id: ProxyConnection, decision: RouteDecision) -> TransferOutcome {
+        transfer::request(conn_id, decision)
+    }
+
+    /// Drops every cached route and MOTD decision, including those in the
+    /// shared cache.
+    pub fn clear_cache(&self) {
+        ROUTER_MOTD_CACHE.clear_all();
+    }
+
+    /// Drops the cached decisions for `ip`: its IP-wide entry and the entry
+    /// for `host`, or every per-host entry when `host` is `None`.
+    pub fn clear_cached(&self, ip: &str, host: Option<&str>) {
+        ROUTER_MOTD_CACHE.clear_client(ip, host);
+    }
+
+    /// Rejections currently cached, which turn connections away without
+    /// asking the router.
+    pub fn blocked(&self) -> Vec<BlockedEntry> {
+        ROUTER_MOTD_CACHE.blocked()
+    }
+
+    /// Size of the decision cache and its hit, miss and eviction counters.
+    pub fn cache_stats(&self) -> CacheStats {
+        ROUTER_MOTD_CACHE.get_stats()
+    }
+
+    /// Details of a live connection: handshake, backend, timestamps, limits
+    /// and transfer totals. `None` if it is unknown.
+    pub fn connection_info(&self, conn_id: ProxyConnection) -> Option<ConnectionDetails> {
+        snapshot::connection(conn_id)
+    }
+
+    /// Everything known about the live connections matching `filter`.
+    pub fn list_connections(&self, filter: &ConnFilter) -> Vec<ConnectionDetails> {
+        conn_query::list(filter)
+    }
+
+    /// Replaces the rate limits of the connections matching `filter`;
+    /// returns how many were changed.
+    pub fn set_rate_limit_where(&self, filter: &ConnFilter, limit: &RateLimitConfig) -> usize {
+        conn_query::set_rate_limit(filter, limit)
+    }
+
+    /// Closes the connections matching `filter`; returns how many were
+    /// closed.
+    pub fn kick_where(&self, filter: &ConnFilter) -> usize {
+        conn_query::kick_where(filter)
+    }
+
+    /// Takes a snapshot of all metrics.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        snapshot::metrics()
+    }
+
+    /// Bytes and connections by username (`USAGE_BY_USERNAME`) or by IP
+    /// (`USAGE_BY_IP`); `None` for an unknown key type.
+    pub fn usage(&self, key: UsageKey) -> Option<HashMap<String, Usage>> {
+        accounting::usage(key)
+    }
+
+    /// Drains the queued events. Disconnection events are queued whichever
+    /// handlers are installed, so embedders should poll now and then.
+    pub fn poll_events(&self) -> Option<PollEvents> {
+        snapshot::poll_events()
+    }
+
+    /// Replaces the labels logged with a live connection's lines; returns
+    /// `false` if it is unknown.
+    pub fn set_log_fields(&self, conn_id: ProxyConnection, fields: &serde_json::Map<String, serde_json::Value>) -> bool {
+        logging::set_fields(conn_id, fields)
+    }
+
+    /// Takes the log records queued in log queue mode, oldest first.
+    pub fn poll_log_records(&self) -> Vec<LogRecord> {
+        logging::poll_records()
+    }
+
+    /// Stops all listeners and connections and clears the proxy state.
+    pub fn shutdown(&self) {
+        let mut st = LISTENER_STATE.lock().unwrap();
+        st.bind_addrs.clear();
+        for (_, h) in st.listeners.drain() {
+            h.abort();
+        }
+        drop(st);
+        PAUSED_LISTENERS.lock().unwrap().clear();
+
+        let ids: Vec<ProxyConnection> = CONN_MANAGER.iter().map(|entry| *entry.key()).collect();
+        let connections: Vec<_> = ids.into_iter().filter_map(|id| CONN_MANAGER.remove(&id)).collect();
+        for (conn_id, h) in connections {
+            h.abort();
+            cleanup_conn(conn_id, DisconnectReason::Shutdown);
+        }
+        loadgen::stop();
+        prometheus::stop();
+        cache::stop_sweeper();
+        health_check::configure(None);
+
+        // Clear all state
+        CONN_METRICS.clear();
+        CONN_INFO.clear();
+        RATE_LIMITERS.clear();
+        SHARED_LIMITERS.write().unwrap().clear();
+        PENDING_ROUTES.clear();
+        PENDING_MOTDS.clear();
+        ROUTE_REQUEST_QUEUE.lock().unwrap().clear();
+        MOTD_REQUEST_QUEUE.lock().unwrap().clear();
+        DISCONNECTION_EVENT_QUEUE.lock().unwrap().clear();
+        USAGE_REPORT_QUEUE.lock().unwrap().clear();
+        USAGE_REPORTED.clear();
+        ADMITTED.lock().unwrap().clear();
+        IP_LIMITS.lock().unwrap().clear();
+        METRICS_EVENT_QUEUE.lock().unwrap().clear();
+        BACKEND_EVENT_QUEUE.lock().unwrap().clear();
+        PROTOCOL_ERROR_QUEUE.lock().unwrap().clear();
+        QUOTA_EVENT_QUEUE.lock().unwrap().clear();
+        ROUTE_AUDIT_QUEUE.lock().unwrap().clear();
+        CONFIG_RELOAD_QUEUE.lock().unwrap().clear();
+        LIFECYCLE_EVENT_QUEUE.lock().unwrap().clear();
+        TTFB_SAMPLES.lock().unwrap().clear();
+        latency::reset_histograms();
+        RETURNING_PLAYERS.lock().unwrap().clear();
+        LISTENER_TOTALS.lock().unwrap().clear();
+        *ROUTE_TOTALS.lock().unwrap() = RouteTotals::default();
+        accounting::reset();
+        login_throttle::reset();
+        *METRICS_DELTA_BASE.lock().unwrap() = None;
+        snapshot::reset_metrics_cursors();
+
+        // Reset counters
+        CONN_COUNTER.store(0, Ordering::SeqCst);
+        ACTIVE_CONN.store(0, Ordering::SeqCst);
+        TOTAL_BYTES_SENT.store(0, Ordering::SeqCst);
+        TOTAL_BYTES_RECV.store(0, Ordering::SeqCst);
+    }
+}
+
+async fn listen(
+    addr: &str,
+    port: u16,
+    transport: ListenerTransport,
+    options: ListenerOptions,
+) -> io::Result<ProxyListener> {
+    let listeners = acceptors::bind(addr, port, options.acceptors)
+        .await?
+        .into_iter()
+        .map(|listener| listener.into_std())
+        .collect::<io::Result<Vec<_>>>()?;
+    let id = LISTENER_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let bind_addr = format!("{}:{}", addr, port);
+    info!(listener = id, listen_str = %bind_addr, acceptors = listeners.len(), "Starting listener");
+
+    let mut st = LISTENER_STATE.lock().unwrap();
+    let listeners = {
+        let _guard = st.runtime.enter();
+        listeners
+            .into_iter()
+            .map(TcpListener::from_std)
+            .collect::<io::Result<Vec<_>>>()?
+    };
+    let handle = st
+        .runtime
+        .spawn(acceptors::serve(id, listeners, transport, Arc::new(options)));
+    st.listeners.insert(id, handle);
+    st.bind_addrs.insert(id, bind_addr);
+    Ok(id)
+}
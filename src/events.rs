@@ -0,0 +1,105 @@
+//! geofront/src/events.rs
+//! Connection lifecycle and routing-audit events published to external sinks.
+
+use crate::state::{AUDIT_SINK, EVENT_SINK, EVENT_SINK_DROPPED};
+use crate::types::{
+    BackendEvent, ConfigReload, ConnInfo, DisconnectReason, ProtocolErrorEvent, ProxyConnection, ProxyHealthEvent,
+    QuotaEvent, RouteAudit, UsageReport,
+};
+use serde::Serialize;
+use std::{
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ProxyEvent {
+    /// A routing decision was applied to a login attempt.
+    Routed(RouteAudit),
+    /// A connection has been closed and its resources released.
+    #[serde(rename_all = "camelCase")]
+    Disconnected {
+        conn_id: ProxyConnection,
+        timestamp_ms: u64,
+        reason: DisconnectReason,
+        bytes_sent: u64,
+        bytes_recv: u64,
+        #[serde(flatten)]
+        info: ConnInfo,
+    },
+    /// Periodic or final byte delta for billing.
+    Usage(UsageReport),
+    /// A backend was ejected from or readmitted to balancing.
+    Backend(BackendEvent),
+    /// An upstream proxy was marked unhealthy or recovered.
+    UpstreamProxy(ProxyHealthEvent),
+    /// A client sent something that is not valid Minecraft protocol.
+    ProtocolError(ProtocolErrorEvent),
+    /// A connection was closed or refused for a byte quota.
+    QuotaExceeded(QuotaEvent),
+    /// The config file was loaded, or changed and failed to apply.
+    ConfigReloaded(ConfigReload),
+}
+
+impl ProxyEvent {
+    /// Short event name, used as the NATS subject suffix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProxyEvent::Routed(_) => "routed",
+            ProxyEvent::Disconnected { .. } => "disconnected",
+            ProxyEvent::Usage(_) => "usage",
+            ProxyEvent::Backend(_) => "backend",
+            ProxyEvent::UpstreamProxy(_) => "upstream_proxy",
+            ProxyEvent::ProtocolError(_) => "protocol_error",
+            ProxyEvent::QuotaExceeded(_) => "quota_exceeded",
+            ProxyEvent::ConfigReloaded(_) => "config_reloaded",
+        }
+    }
+
+    /// Partitioning key: the connection id, or the backend address.
+    pub fn key(&self) -> String {
+        match self {
+            ProxyEvent::Routed(audit) => audit.conn_id.to_string(),
+            ProxyEvent::Disconnected { conn_id, .. } => conn_id.to_string(),
+            ProxyEvent::Usage(report) => report.conn_id.to_string(),
+            ProxyEvent::Backend(event) => event.backend.clone(),
+            ProxyEvent::UpstreamProxy(event) => event.proxy.clone(),
+            ProxyEvent::ProtocolError(event) => event.conn_id.to_string(),
+            ProxyEvent::QuotaExceeded(event) => event.conn_id.to_string(),
+            ProxyEvent::ConfigReloaded(reload) => reload.path.clone(),
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Hands an event to the configured sinks, if any. Never blocks: when a sink
+/// cannot keep up the event is dropped and counted.
+pub fn emit(event: ProxyEvent) {
+    if matches!(event, ProxyEvent::Disconnected { .. })
+        && let Some(sender) = AUDIT_SINK.read().unwrap().as_ref()
+    {
+        try_send(sender, event.clone());
+    }
+
+    if let Some(sender) = EVENT_SINK.read().unwrap().as_ref() {
+        try_send(sender, event);
+    }
+}
+
+fn try_send(sender: &mpsc::Sender<ProxyEvent>, event: ProxyEvent) {
+    match sender.try_send(event) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) | Err(TrySendError::Closed(_)) => {
+            EVENT_SINK_DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -2,101 +2,327 @@
 //! FFI interface functions.
 
 use crate::{
-    connection::handle_conn,
-    logging,
+    acceptors, accounting, audit_db, bedrock, conn_query,
+    connection::{cleanup_conn, kick},
+    discovery, embed::Geofront, health_check, introspect, limiter, loadgen, logging, proxy_manager, snapshot, tls,
+    transfer::TransferOutcome,
+    transport::ListenerTransport,
+    wakeup, websocket,
     state::{
-        ACTIVE_CONN, CONN_COUNTER, CONN_MANAGER, CONN_METRICS, DISCONNECTION_EVENT_QUEUE,
-        LISTENER_COUNTER, LISTENER_STATE, MOTD_REQUEST_QUEUE, OPTIONS, PENDING_MOTDS,
-        PENDING_ROUTES, RATE_LIMITERS, RELOAD_HANDLE, ROUTE_REQUEST_QUEUE, TOTAL_BYTES_RECV,
-        TOTAL_BYTES_SENT, TOTAL_CONN, ROUTER_MOTD_CACHE,
+        CONN_INFO, CONN_MANAGER, CONN_METRICS, DISCONNECTION_EVENT_QUEUE, EVENTS_BUF, LISTENER_COUNTER,
+        LISTENER_STATE, METRICS_BUF, MOTD_REQUEST_QUEUE, OPTIONS, PENDING_MOTDS, PENDING_ROUTES, RATE_LIMITERS,
+        RELOAD_HANDLE, ROUTE_REQUEST_QUEUE, ROUTER_MOTD_CACHE,
     },
     types::{
-        ConnMetrics, ConnMetricsSnapshot, GeofrontOptions, MetricsSnapshot, MotdDecision,
-        PROXY_ERR_BAD_PARAM, PROXY_ERR_INTERNAL, PROXY_ERR_NOT_FOUND, PROXY_OK, PollEvents,
-        ProxyConnection, ProxyError, ProxyListener, RouteDecision,
+        AuditQuery, BedrockConfig, ConnFilter, DisconnectReason, ConnMetricsSnapshot, GeofrontOptions, ListenerOptions, LoadGenConfig,
+        MotdDecision, PauseConfig, QuicTunnelConfig,
+        PROXY_ERR_BAD_PARAM, PROXY_ERR_INTERNAL, PROXY_ERR_NOT_FOUND, PROXY_ERR_UNSUPPORTED, PROXY_OK,
+        ProxyConnection, ProxyError, ProxyListener, RateLimitConfig, RouteDecision, RuntimeConfig, StaticRoute,
+        UsageKey, VirtualHost, WireFormat,
     },
 };
-use governor::{Quota, RateLimiter};
-use nonzero_ext::nonzero;
 use std::{
+    cell::RefCell,
     ffi::{CStr, CString},
-    num::NonZeroU32,
     os::raw::{c_char, c_uint, c_ushort},
     ptr,
-    sync::{
-        Arc,
-        atomic::{AtomicU64, Ordering},
-    },
+    sync::{Arc, atomic::Ordering},
 };
-use tokio::net::TcpListener;
 use tracing::{error, info};
 use tracing_subscriber::filter::EnvFilter;
 
+thread_local! {
+    // Why the last failed call on this thread failed
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Records why the current call failed, for `proxy_last_error_message`.
+fn set_last_error(message: impl Into<String>) {
+    let message = message.into();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Records `message` as the reason for failing with `code`.
+fn fail(code: ProxyError, message: impl Into<String>) -> ProxyError {
+    set_last_error(message);
+    code
+}
+
+/// Returns why the last failed call on the calling thread failed, in more
+/// detail than its error code, or NULL if none has. Successful calls leave
+/// it unchanged, so it is only meaningful right after a failure.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_last_error_message() -> *const c_char {
+    let Some(message) = LAST_ERROR.with(|last| last.borrow().clone()) else {
+        return ptr::null();
+    };
+    match CString::new(message) {
+        Ok(c_str) => c_str.into_raw(),
+        Err(_) => ptr::null(),
+    }
+}
+
 /// Set global options from a JSON string.
+///
+/// # Safety
+///
+/// `options_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_set_options(options_json: *const c_char) -> ProxyError {
     if options_json.is_null() {
-        return PROXY_ERR_BAD_PARAM;
+        return fail(PROXY_ERR_BAD_PARAM, "options_json is null");
     }
     let json_str = unsafe { CStr::from_ptr(options_json) }.to_string_lossy();
     let options: GeofrontOptions = match serde_json::from_str(&json_str) {
         Ok(opts) => opts,
         Err(e) => {
             error!("Failed to parse options JSON: {}", e);
-            return PROXY_ERR_BAD_PARAM;
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid options JSON: {}", e));
         }
     };
 
-    let mut opts_guard = OPTIONS.write().unwrap();
-    *opts_guard = options;
+    Geofront::new().set_options(options);
+    PROXY_OK
+}
+
+/// Replaces the static routes with a JSON array of `StaticRoute`. Logins to
+/// matching hosts are routed without a `proxy_poll_events` round trip; an
+/// empty array removes them all.
+///
+/// # Safety
+///
+/// `routes_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_set_routes(routes_json: *const c_char) -> ProxyError {
+    if routes_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "routes_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(routes_json) }.to_string_lossy();
+    let routes: Vec<StaticRoute> = match serde_json::from_str(&json_str) {
+        Ok(routes) => routes,
+        Err(e) => {
+            error!("Failed to parse static routes JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid static routes JSON: {}", e));
+        }
+    };
+    Geofront::new().set_routes(routes);
+    PROXY_OK
+}
 
-    info!("Updated global options");
+/// Replaces the virtual hosts with a JSON array of `VirtualHost`. Pings and
+/// logins to matching hosts use their MOTD, backend, protocol range and
+/// maintenance flag before any `proxy_poll_events` round trip; an empty
+/// array removes them all.
+///
+/// # Safety
+///
+/// `vhosts_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_set_vhosts(vhosts_json: *const c_char) -> ProxyError {
+    if vhosts_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "vhosts_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(vhosts_json) }.to_string_lossy();
+    let vhosts: Vec<VirtualHost> = match serde_json::from_str(&json_str) {
+        Ok(vhosts) => vhosts,
+        Err(e) => {
+            error!("Failed to parse virtual hosts JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid virtual hosts JSON: {}", e));
+        }
+    };
+    Geofront::new().set_vhosts(vhosts);
     PROXY_OK
 }
 
+/// Applies a JSON config file holding any of `options`, `vhosts` and
+/// `routes`; nothing is applied if it fails to parse. With a nonzero
+/// `watch_interval_ms` the file is polled at that interval and reloaded on
+/// change, replacing any previous watch; 0 stops watching. Each load is
+/// reported as a `configReloads` entry of `proxy_poll_events`.
+///
+/// # Safety
+///
+/// `path` must be NULL or point to a NUL-terminated string that stays valid for the duration of
+/// the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_load_config(path: *const c_char, watch_interval_ms: u64) -> ProxyError {
+    if path.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "path is null");
+    }
+    let path = unsafe { CStr::from_ptr(path) }.to_string_lossy();
+    let watch = (watch_interval_ms > 0).then_some(watch_interval_ms);
+    match Geofront::new().load_config(&path, watch) {
+        Ok(_) => PROXY_OK,
+        Err(e) => {
+            error!("Failed to load config file: {}", e);
+            fail(PROXY_ERR_BAD_PARAM, e)
+        }
+    }
+}
+
 /// Initialize global logging level
+///
+/// # Safety
+///
+/// `level` must be NULL or point to a NUL-terminated string that stays valid for the duration
+/// of the call.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_init_logging(level: *const c_char) -> ProxyError {
     if level.is_null() {
-        return PROXY_ERR_BAD_PARAM;
+        return fail(PROXY_ERR_BAD_PARAM, "level is null");
     }
-    let lvl = unsafe { CStr::from_ptr(level) }
-        .to_str()
-        .map_err(|_| PROXY_ERR_BAD_PARAM)
-        .unwrap();
+    let Ok(lvl) = unsafe { CStr::from_ptr(level) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "level must be UTF-8");
+    };
     logging::init_logging(lvl);
     PROXY_OK
 }
 
+/// Initializes logging as JSON lines, one object per event, for log
+/// shippers. With `path` null logs go to stdout; otherwise they are appended
+/// to the file at `path`, rotated to `<path>.1` .. `<path>.5` every 64 MiB.
+/// Returns `PROXY_ERR_UNSUPPORTED` if logging was already initialized.
+///
+/// # Safety
+///
+/// `level` and `path` must each be NULL or point to a NUL-terminated string that stays valid
+/// for the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_init_logging_json(level: *const c_char, path: *const c_char) -> ProxyError {
+    if level.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "level is null");
+    }
+    let Ok(lvl) = unsafe { CStr::from_ptr(level) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "level must be UTF-8");
+    };
+    let path = if path.is_null() {
+        None
+    } else {
+        match unsafe { CStr::from_ptr(path) }.to_str() {
+            Ok(path) => Some(path),
+            Err(_) => return fail(PROXY_ERR_BAD_PARAM, "path must be UTF-8"),
+        }
+    };
+    match logging::init_logging_json(lvl, path) {
+        Ok(true) => PROXY_OK,
+        Ok(false) => fail(PROXY_ERR_UNSUPPORTED, "logging is already initialized"),
+        Err(e) => fail(PROXY_ERR_BAD_PARAM, format!("failed to open log file: {}", e)),
+    }
+}
+
+/// Initializes logging into a queue of JSON records drained with
+/// `proxy_poll_log_events`, for hosts feeding their own logging pipeline.
+/// Once `capacity` records are waiting the oldest are dropped.
+/// Returns `PROXY_ERR_UNSUPPORTED` if logging was already initialized.
+///
+/// # Safety
+///
+/// `level` must be NULL or point to a NUL-terminated string that stays valid for the duration
+/// of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_init_logging_queue(level: *const c_char, capacity: usize) -> ProxyError {
+    if level.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "level is null");
+    }
+    let Ok(lvl) = unsafe { CStr::from_ptr(level) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "level must be UTF-8");
+    };
+    if !logging::init_logging_queue(lvl, capacity) {
+        return fail(PROXY_ERR_UNSUPPORTED, "logging is already initialized");
+    }
+    PROXY_OK
+}
+
+/// Takes the queued log records as a JSON array of `{timestampMs, level,
+/// target, connId, message, fields}`, oldest first.
+/// Returns NULL if none are pending.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_poll_log_events() -> *const c_char {
+    let records = Geofront::new().poll_log_records();
+    if records.is_empty() {
+        return ptr::null();
+    }
+    match serde_json::to_string(&records) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Configures the proxy runtime from a JSON `RuntimeConfig` (`workerThreads`,
+/// `maxBlockingThreads`, `threadName`). Must be called before the first
+/// listener or background task starts; returns `PROXY_ERR_UNSUPPORTED`
+/// once the runtime exists.
+///
+/// # Safety
+///
+/// `config_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_init_runtime(config_json: *const c_char) -> ProxyError {
+    if config_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "config_json is null");
+    }
+    let Ok(json) = unsafe { CStr::from_ptr(config_json) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "config_json must be UTF-8");
+    };
+    let config: RuntimeConfig = match serde_json::from_str(json) {
+        Ok(config) => config,
+        Err(e) => return fail(PROXY_ERR_BAD_PARAM, format!("invalid runtime config JSON: {}", e)),
+    };
+    if !Geofront::new().init_runtime(config) {
+        return fail(PROXY_ERR_UNSUPPORTED, "the runtime is already running");
+    }
+    PROXY_OK
+}
+
 /// Set log level at runtime
+///
+/// # Safety
+///
+/// `level` must be NULL or point to a NUL-terminated string that stays valid for the duration
+/// of the call.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_set_log_level(level: *const c_char) -> ProxyError {
     if level.is_null() {
-        return PROXY_ERR_BAD_PARAM;
-    }
-    let lvl = unsafe { CStr::from_ptr(level) }
-        .to_str()
-        .map_err(|_| PROXY_ERR_BAD_PARAM)
-        .unwrap();
-    if let Some(handle) = RELOAD_HANDLE.lock().unwrap().as_ref() {
-        handle
-            .reload(EnvFilter::new(lvl))
-            .map_err(|_| PROXY_ERR_INTERNAL)
-            .unwrap();
-        PROXY_OK
-    } else {
-        PROXY_ERR_INTERNAL
+        return fail(PROXY_ERR_BAD_PARAM, "level is null");
+    }
+    let Ok(lvl) = unsafe { CStr::from_ptr(level) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "level must be UTF-8");
+    };
+    let Some(handle) = RELOAD_HANDLE.lock().unwrap().clone() else {
+        return fail(PROXY_ERR_INTERNAL, "logging is not initialized");
+    };
+    match handle.reload(EnvFilter::new(lvl)) {
+        Ok(()) => PROXY_OK,
+        Err(e) => fail(PROXY_ERR_INTERNAL, format!("failed to reload log level: {}", e)),
     }
 }
 
 /// Submits the routing decision from JS back to Rust.
+///
+/// # Safety
+///
+/// `decision_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_submit_routing_decision(
     conn_id: ProxyConnection,
     decision_json: *const c_char,
 ) -> ProxyError {
     if decision_json.is_null() {
-        return PROXY_ERR_BAD_PARAM;
+        return fail(PROXY_ERR_BAD_PARAM, "decision_json is null");
     }
     let json_str = unsafe { CStr::from_ptr(decision_json) }.to_string_lossy();
 
@@ -114,33 +340,44 @@ pub unsafe extern "C" fn proxy_submit_routing_decision(
         }
     };
 
-    if let Some(sender) = PENDING_ROUTES.lock().unwrap().remove(&conn_id) {
+    if let Some((_, sender)) = PENDING_ROUTES.remove(&conn_id) {
         if sender.send(decision).is_err() {
             error!(
                 conn = conn_id,
                 "Failed to send routing decision: receiver dropped."
             );
-            return PROXY_ERR_INTERNAL;
+            return fail(
+                PROXY_ERR_INTERNAL,
+                format!("connection {} stopped waiting for its route", conn_id),
+            );
         }
     } else {
         error!(
             conn = conn_id,
             "No pending route decision found for this connection."
         );
-        return PROXY_ERR_NOT_FOUND;
+        return fail(
+            PROXY_ERR_NOT_FOUND,
+            format!("no pending route decision for connection {}", conn_id),
+        );
     }
 
     PROXY_OK
 }
 
 /// Submits the MOTD decision from JS back to Rust.
+///
+/// # Safety
+///
+/// `decision_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_submit_motd_decision(
     conn_id: ProxyConnection,
     decision_json: *const c_char,
 ) -> ProxyError {
     if decision_json.is_null() {
-        return PROXY_ERR_BAD_PARAM;
+        return fail(PROXY_ERR_BAD_PARAM, "decision_json is null");
     }
     let json_str = unsafe { CStr::from_ptr(decision_json) }.to_string_lossy();
 
@@ -158,25 +395,37 @@ pub unsafe extern "C" fn proxy_submit_motd_decision(
         }
     };
 
-    if let Some(sender) = PENDING_MOTDS.lock().unwrap().remove(&conn_id) {
+    if let Some((_, sender)) = PENDING_MOTDS.remove(&conn_id) {
         if sender.send(decision).is_err() {
             error!(
                 conn = conn_id,
                 "Failed to send MOTD decision: receiver dropped."
             );
-            return PROXY_ERR_INTERNAL;
+            return fail(
+                PROXY_ERR_INTERNAL,
+                format!("connection {} stopped waiting for its MOTD", conn_id),
+            );
         }
     } else {
         error!(
             conn = conn_id,
             "No pending MOTD decision found for this connection."
         );
-        return PROXY_ERR_NOT_FOUND;
+        return fail(
+            PROXY_ERR_NOT_FOUND,
+            format!("no pending MOTD decision for connection {}", conn_id),
+        );
     }
 
     PROXY_OK
 }
 
+/// Starts a TCP listener on `bind_addr:bind_port`, storing its id in `out_listener`.
+///
+/// # Safety
+///
+/// `bind_addr` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call. `out_listener` must be NULL or valid for writing a `ProxyListener`.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_start_listener(
     bind_addr: *const c_char,
@@ -185,15 +434,236 @@ pub unsafe extern "C" fn proxy_start_listener(
 ) -> ProxyError {
     logging::init_logging("info");
     if bind_addr.is_null() || out_listener.is_null() {
-        return PROXY_ERR_BAD_PARAM;
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr or out_listener is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    let id = spawn_listener(addr, bind_port, ListenerTransport::Tcp, ListenerOptions::default());
+    unsafe { ptr::write(out_listener, id) };
+    PROXY_OK
+}
+
+/// Start a listener terminating TLS with the PEM certificate chain and key
+/// at the given paths. Fails with `PROXY_ERR_BAD_PARAM` if they cannot be
+/// loaded, or without the `tls` feature.
+///
+/// # Safety
+///
+/// `bind_addr`, `cert_path` and `key_path` must each be NULL or point to a NUL-terminated
+/// string that stays valid for the duration of the call. `out_listener` must be NULL or valid
+/// for writing a `ProxyListener`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_listener_tls(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+    cert_path: *const c_char,
+    key_path: *const c_char,
+    out_listener: *mut ProxyListener,
+) -> ProxyError {
+    logging::init_logging("info");
+    if bind_addr.is_null() || cert_path.is_null() || key_path.is_null() || out_listener.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr, cert_path, key_path or out_listener is null");
+    }
+    let paths = unsafe {
+        (
+            CStr::from_ptr(bind_addr).to_str(),
+            CStr::from_ptr(cert_path).to_str(),
+            CStr::from_ptr(key_path).to_str(),
+        )
+    };
+    let (Ok(addr), Ok(cert_path), Ok(key_path)) = paths else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr, cert_path and key_path must be UTF-8");
+    };
+    let acceptor = match tls::load_acceptor(cert_path, key_path, None) {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            error!("Failed to load TLS certificate: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("failed to load TLS certificate: {}", e));
+        }
+    };
+    let id = spawn_listener(addr, bind_port, ListenerTransport::Tls(acceptor), ListenerOptions::default());
+    unsafe { ptr::write(out_listener, id) };
+    PROXY_OK
+}
+
+/// Start a listener for WebSocket clients. Fails with `PROXY_ERR_INTERNAL`
+/// without the `websocket` feature.
+///
+/// # Safety
+///
+/// `bind_addr` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call. `out_listener` must be NULL or valid for writing a `ProxyListener`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_listener_ws(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+    out_listener: *mut ProxyListener,
+) -> ProxyError {
+    logging::init_logging("info");
+    if bind_addr.is_null() || out_listener.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr or out_listener is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    if let Err(e) = websocket::ensure_supported() {
+        error!("Cannot start WebSocket listener: {}", e);
+        return fail(PROXY_ERR_INTERNAL, format!("cannot start WebSocket listener: {}", e));
+    }
+    let id = spawn_listener(addr, bind_port, ListenerTransport::WebSocket, ListenerOptions::default());
+    unsafe { ptr::write(out_listener, id) };
+    PROXY_OK
+}
+
+/// Start a listener with settings of its own: proxy-protocol-in mode,
+/// default route, connection limits and transport (`ListenerOptions` JSON).
+/// Fails with `PROXY_ERR_BAD_PARAM` if the options are invalid or ask for a
+/// transport that cannot be set up.
+///
+/// # Safety
+///
+/// `bind_addr` and `options_json` must each be NULL or point to a NUL-terminated string that
+/// stays valid for the duration of the call. `out_listener` must be NULL or valid for writing a
+/// `ProxyListener`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_listener_with_options(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+    options_json: *const c_char,
+    out_listener: *mut ProxyListener,
+) -> ProxyError {
+    logging::init_logging("info");
+    if bind_addr.is_null() || options_json.is_null() || out_listener.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr, options_json or out_listener is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    let json_str = unsafe { CStr::from_ptr(options_json) }.to_string_lossy();
+    let options: ListenerOptions = match serde_json::from_str(&json_str) {
+        Ok(options) => options,
+        Err(e) => {
+            error!("Failed to parse listener options JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid listener options JSON: {}", e));
+        }
+    };
+    let transport = match ListenerTransport::from_options(&options) {
+        Ok(transport) => transport,
+        Err(e) => {
+            error!("Cannot set up listener transport: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("cannot set up listener transport: {}", e));
+        }
+    };
+    let id = spawn_listener(addr, bind_port, transport, options);
+    unsafe { ptr::write(out_listener, id) };
+    PROXY_OK
+}
+
+/// Start a QUIC tunnel listener for edges' `quic://` proxies
+/// (`QuicTunnelConfig` JSON, see `quic.rs`). Fails with
+/// `PROXY_ERR_UNSUPPORTED` without the `quic` feature.
+///
+/// # Safety
+///
+/// `bind_addr` and `config_json` must each be NULL or point to a NUL-terminated string that
+/// stays valid for the duration of the call. `out_listener` must be NULL or valid for writing a
+/// `ProxyListener`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_quic_tunnel(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+    config_json: *const c_char,
+    out_listener: *mut ProxyListener,
+) -> ProxyError {
+    logging::init_logging("info");
+    if bind_addr.is_null() || config_json.is_null() || out_listener.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr, config_json or out_listener is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    let json_str = unsafe { CStr::from_ptr(config_json) }.to_string_lossy();
+    let config: QuicTunnelConfig = match serde_json::from_str(&json_str) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to parse QUIC tunnel config JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid QUIC tunnel config JSON: {}", e));
+        }
+    };
+    match block_on(Geofront::new().start_quic_tunnel(addr, bind_port, config)) {
+        Ok(id) => {
+            unsafe { ptr::write(out_listener, id) };
+            PROXY_OK
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => fail(PROXY_ERR_UNSUPPORTED, e.to_string()),
+        Err(e) => {
+            error!("Failed to start QUIC tunnel: {}", e);
+            fail(PROXY_ERR_BAD_PARAM, format!("failed to start QUIC tunnel: {}", e))
+        }
+    }
+}
+
+/// Start a UDP listener relaying Bedrock clients (`BedrockConfig` JSON,
+/// see `bedrock.rs`). Stopped with `proxy_stop_listener`.
+///
+/// # Safety
+///
+/// `bind_addr` and `config_json` must each be NULL or point to a NUL-terminated string that
+/// stays valid for the duration of the call. `out_listener` must be NULL or valid for writing a
+/// `ProxyListener`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_bedrock_listener(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+    config_json: *const c_char,
+    out_listener: *mut ProxyListener,
+) -> ProxyError {
+    logging::init_logging("info");
+    if bind_addr.is_null() || config_json.is_null() || out_listener.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr, config_json or out_listener is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    let json_str = unsafe { CStr::from_ptr(config_json) }.to_string_lossy();
+    let config: BedrockConfig = match serde_json::from_str(&json_str) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to parse Bedrock config JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid Bedrock config JSON: {}", e));
+        }
+    };
+    match block_on(Geofront::new().start_bedrock_listener(addr, bind_port, config)) {
+        Ok(id) => {
+            unsafe { ptr::write(out_listener, id) };
+            PROXY_OK
+        }
+        Err(e) => {
+            error!("Failed to start Bedrock listener: {}", e);
+            fail(PROXY_ERR_BAD_PARAM, format!("failed to start Bedrock listener: {}", e))
+        }
     }
-    let addr = unsafe { CStr::from_ptr(bind_addr) }
-        .to_str()
-        .map_err(|_| PROXY_ERR_BAD_PARAM)
-        .unwrap();
+}
+
+/// Runs a `Geofront` call that binds sockets to completion on the proxy
+/// runtime, so its errors reach the caller.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    runtime.block_on(future)
+}
+
+/// Binds and serves a listener on the proxy runtime, returning its id.
+fn spawn_listener(
+    addr: &str,
+    bind_port: c_ushort,
+    transport: ListenerTransport,
+    options: ListenerOptions,
+) -> ProxyListener {
     let id = LISTENER_COUNTER.fetch_add(1, Ordering::SeqCst);
     let listen_str = format!("{}:{}", addr, bind_port);
     info!(listener = id, %listen_str, "Starting listener");
+    let host = addr.to_string();
     let handle = LISTENER_STATE
         .lock()
         .unwrap()
@@ -201,7 +671,7 @@ pub unsafe extern "C" fn proxy_start_listener(
         .handle()
         .clone()
         .spawn(async move {
-            let listener = match TcpListener::bind(&listen_str).await {
+            let listeners = match acceptors::bind(&host, bind_port, options.acceptors).await {
                 Ok(l) => l,
                 Err(e) => {
                     error!("Failed to bind listener {}: {}", id, e);
@@ -209,70 +679,195 @@ pub unsafe extern "C" fn proxy_start_listener(
                 }
             };
             info!("Bound {}", listen_str);
-            loop {
-                match listener.accept().await {
-                    Ok((inb, _)) => {
-                        let conn_id = CONN_COUNTER.fetch_add(1, Ordering::SeqCst);
-                        TOTAL_CONN.fetch_add(1, Ordering::SeqCst);
-                        ACTIVE_CONN.fetch_add(1, Ordering::SeqCst);
-                        let cm = Arc::new(ConnMetrics {
-                            bytes_sent: AtomicU64::new(0),
-                            bytes_recv: AtomicU64::new(0),
-                        });
-                        CONN_METRICS.lock().unwrap().insert(conn_id, cm);
-                        let unlimited =
-                            Arc::new(RateLimiter::direct(Quota::per_second(nonzero!(u32::MAX))));
-                        RATE_LIMITERS
-                            .lock()
-                            .unwrap()
-                            .insert(conn_id, (unlimited.clone(), unlimited));
-                        let h = tokio::spawn(handle_conn(conn_id, inb));
-                        CONN_MANAGER.lock().unwrap().insert(conn_id, h);
-                    }
-                    Err(e) => {
-                        error!("Accept error: {}", e);
-                        break;
-                    }
-                }
-            }
+            acceptors::serve(id, listeners, transport, Arc::new(options)).await;
         });
-    unsafe { ptr::write(out_listener, id) };
-    LISTENER_STATE.lock().unwrap().listeners.insert(id, handle);
-    PROXY_OK
+    let mut st = LISTENER_STATE.lock().unwrap();
+    st.listeners.insert(id, handle);
+    st.bind_addrs.insert(id, format!("{}:{}", addr, bind_port));
+    id
 }
 
 /// Stop a listener
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_stop_listener(listener: ProxyListener) -> ProxyError {
-    let mut st = LISTENER_STATE.lock().unwrap();
-    if let Some(h) = st.listeners.remove(&listener) {
-        h.abort();
+    if Geofront::new().stop_listener(listener) {
         PROXY_OK
     } else {
-        PROXY_ERR_NOT_FOUND
+        fail(PROXY_ERR_NOT_FOUND, format!("unknown listener {}", listener))
     }
 }
 
-/// Disconnect a connection
+/// Pauses a listener, keeping its socket bound and its connections open.
+/// `config_json` is a `PauseConfig` (`motd`, `message`) or NULL to stop
+/// accepting until `proxy_resume_listener`.
+///
+/// # Safety
+///
+/// `config_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn proxy_disconnect(conn_id: ProxyConnection) -> ProxyError {
-    if let Some(h) = CONN_MANAGER.lock().unwrap().remove(&conn_id) {
-        h.abort();
+pub unsafe extern "C" fn proxy_pause_listener(listener: ProxyListener, config_json: *const c_char) -> ProxyError {
+    let config = if config_json.is_null() {
+        PauseConfig::default()
+    } else {
+        let Ok(json) = unsafe { CStr::from_ptr(config_json) }.to_str() else {
+            return fail(PROXY_ERR_BAD_PARAM, "config_json must be UTF-8");
+        };
+        match serde_json::from_str(json) {
+            Ok(config) => config,
+            Err(e) => return fail(PROXY_ERR_BAD_PARAM, format!("invalid pause config JSON: {}", e)),
+        }
+    };
+    if Geofront::new().pause_listener(listener, config) {
+        PROXY_OK
+    } else {
+        fail(PROXY_ERR_NOT_FOUND, format!("unknown listener {}", listener))
+    }
+}
 
-        // Call disconnection callback if registered
-        // The new polling mechanism handles disconnection events.
-        // No need to manually call a callback here.
+/// Resumes a paused listener.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_resume_listener(listener: ProxyListener) -> ProxyError {
+    if Geofront::new().resume_listener(listener) {
+        PROXY_OK
+    } else {
+        fail(PROXY_ERR_NOT_FOUND, format!("listener {} is not paused", listener))
+    }
+}
+
+/// Capture a connection's relayed bytes into a new pcap file at `path`,
+/// stopping before the file exceeds `max_bytes` or when the connection
+/// closes (see `capture.rs`).
+///
+/// # Safety
+///
+/// `path` must be NULL or point to a NUL-terminated string that stays valid for the duration of
+/// the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_capture(conn_id: ProxyConnection, path: *const c_char, max_bytes: u64) -> ProxyError {
+    if path.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "path is null");
+    }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "path must be UTF-8");
+    };
+    match Geofront::new().start_capture(conn_id, path, max_bytes) {
+        Ok(true) => PROXY_OK,
+        Ok(false) => fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id)),
+        Err(e) => {
+            error!("Failed to start capture: {}", e);
+            fail(PROXY_ERR_BAD_PARAM, format!("failed to create {}: {}", path, e))
+        }
+    }
+}
+
+/// Stop capturing a connection
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_stop_capture(conn_id: ProxyConnection) -> ProxyError {
+    if Geofront::new().stop_capture(conn_id) {
+        PROXY_OK
+    } else {
+        fail(PROXY_ERR_NOT_FOUND, format!("connection {} is not captured", conn_id))
+    }
+}
 
-        RATE_LIMITERS.lock().unwrap().remove(&conn_id);
-        CONN_METRICS.lock().unwrap().remove(&conn_id);
-        ACTIVE_CONN.fetch_sub(1, Ordering::SeqCst);
+/// Disconnect a connection
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_disconnect(conn_id: ProxyConnection) -> ProxyError {
+    if kick(conn_id, DisconnectReason::Kicked) {
         PROXY_OK
     } else {
-        PROXY_ERR_NOT_FOUND
+        fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id))
+    }
+}
+
+/// Disconnect a connection, showing `message` (legacy text or a JSON text
+/// component) to the player if it is still in the login phase; connections
+/// already relaying are closed without it.
+///
+/// # Safety
+///
+/// `message` must be NULL or point to a NUL-terminated string that stays valid for the duration
+/// of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_disconnect_with_message(
+    conn_id: ProxyConnection,
+    message: *const c_char,
+) -> ProxyError {
+    if message.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "message is null");
+    }
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    match Geofront::new().disconnect_with_message(conn_id, &message) {
+        Some(sent) => {
+            info!(conn = conn_id, sent, "Disconnected connection with message");
+            PROXY_OK
+        }
+        None => fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id)),
+    }
+}
+
+/// Move a connection to the backend of a routing decision (JSON) without a
+/// disconnect screen. Fails with `PROXY_ERR_UNSUPPORTED` once the connection
+/// is past its routing and is not a transferable relayed session.
+///
+/// # Safety
+///
+/// `new_backend_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_transfer_connection(
+    conn_id: ProxyConnection,
+    new_backend_json: *const c_char,
+) -> ProxyError {
+    if new_backend_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "new_backend_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(new_backend_json) }.to_string_lossy();
+    let decision: RouteDecision = match serde_json::from_str(&json_str) {
+        Ok(decision) => decision,
+        Err(e) => {
+            error!("Failed to parse transfer JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid transfer JSON: {}", e));
+        }
+    };
+    match Geofront::new().transfer_connection(conn_id, decision) {
+        TransferOutcome::Accepted => {
+            info!(conn = conn_id, "Transfer requested");
+            PROXY_OK
+        }
+        TransferOutcome::NotTransferable => fail(
+            PROXY_ERR_UNSUPPORTED,
+            format!("connection {} cannot be transferred anymore", conn_id),
+        ),
+        TransferOutcome::NotFound => fail(
+            PROXY_ERR_NOT_FOUND,
+            format!("unknown connection {}", conn_id),
+        ),
     }
 }
 
 /// Set burst-capable rate limits
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_set_rate_limit(
     conn_id: ProxyConnection,
@@ -281,20 +876,13 @@ pub unsafe extern "C" fn proxy_set_rate_limit(
     recv_avg_bytes_per_sec: u64,
     recv_burst_bytes_per_sec: u64,
 ) -> ProxyError {
-    let mut rl = RATE_LIMITERS.lock().unwrap();
-    if let Some((send_l, recv_l)) = rl.get_mut(&conn_id) {
-        let send_avg = NonZeroU32::new(send_avg_bytes_per_sec as u32).unwrap_or(nonzero!(u32::MAX));
-        let send_burst = NonZeroU32::new(send_burst_bytes_per_sec as u32).unwrap_or(send_avg);
-        let recv_avg = NonZeroU32::new(recv_avg_bytes_per_sec as u32).unwrap_or(nonzero!(u32::MAX));
-        let recv_burst = NonZeroU32::new(recv_burst_bytes_per_sec as u32).unwrap_or(recv_avg);
-
-        *send_l = Arc::new(RateLimiter::direct(
-            Quota::per_second(send_avg).allow_burst(send_burst),
-        ));
-        *recv_l = Arc::new(RateLimiter::direct(
-            Quota::per_second(recv_avg).allow_burst(recv_burst),
-        ));
-
+    let limit = RateLimitConfig {
+        send_avg: Some(send_avg_bytes_per_sec),
+        send_burst: Some(send_burst_bytes_per_sec),
+        recv_avg: Some(recv_avg_bytes_per_sec),
+        recv_burst: Some(recv_burst_bytes_per_sec),
+    };
+    if limiter::set_limits(conn_id, &limit) {
         info!(
             conn = conn_id,
             send_avg = send_avg_bytes_per_sec,
@@ -305,99 +893,252 @@ pub unsafe extern "C" fn proxy_set_rate_limit(
         );
         PROXY_OK
     } else {
-        PROXY_ERR_NOT_FOUND
+        fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id))
     }
 }
 
-/// Shutdown all listeners and connections
+/// Sets the rate limits of every connection matching a JSON `ConnFilter`
+/// (NULL for all); returns how many were changed, or 0 with the last error
+/// set if the filter is invalid.
+///
+/// # Safety
+///
+/// `filter_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn proxy_shutdown() -> ProxyError {
-    for h in LISTENER_STATE
-        .lock()
-        .unwrap()
-        .listeners
-        .drain()
-        .map(|(_, h)| h)
-    {
-        h.abort();
-    }
+pub unsafe extern "C" fn proxy_set_rate_limit_where(
+    filter_json: *const c_char,
+    send_avg_bytes_per_sec: u64,
+    send_burst_bytes_per_sec: u64,
+    recv_avg_bytes_per_sec: u64,
+    recv_burst_bytes_per_sec: u64,
+) -> c_uint {
+    let filter = match unsafe { conn_filter(filter_json) } {
+        Ok(filter) => filter,
+        Err(e) => {
+            set_last_error(e);
+            return 0;
+        }
+    };
+    let limit = RateLimitConfig {
+        send_avg: Some(send_avg_bytes_per_sec),
+        send_burst: Some(send_burst_bytes_per_sec),
+        recv_avg: Some(recv_avg_bytes_per_sec),
+        recv_burst: Some(recv_burst_bytes_per_sec),
+    };
+    let changed = Geofront::new().set_rate_limit_where(&filter, &limit);
+    info!(changed, "Updated rate limits of matching connections");
+    changed as c_uint
+}
 
-    for (_, h) in CONN_MANAGER.lock().unwrap().connections.drain() {
-        h.abort();
+/// Parses a `ConnFilter`; NULL matches every connection.
+unsafe fn conn_filter(filter_json: *const c_char) -> Result<ConnFilter, String> {
+    if filter_json.is_null() {
+        return Ok(ConnFilter::default());
     }
-
-    // Call disconnection callback for each connection
-    // The new polling mechanism handles disconnection events.
-    // No need to manually call callbacks here.
-
-    // Clear all state
-    CONN_METRICS.lock().unwrap().clear();
-    RATE_LIMITERS.lock().unwrap().clear();
-    PENDING_ROUTES.lock().unwrap().clear();
-    PENDING_MOTDS.lock().unwrap().clear();
-    ROUTE_REQUEST_QUEUE.lock().unwrap().clear();
-    MOTD_REQUEST_QUEUE.lock().unwrap().clear();
-    DISCONNECTION_EVENT_QUEUE.lock().unwrap().clear();
-
-    // Reset counters
-    CONN_COUNTER.store(0, Ordering::SeqCst);
-    ACTIVE_CONN.store(0, Ordering::SeqCst);
-    TOTAL_BYTES_SENT.store(0, Ordering::SeqCst);
-    TOTAL_BYTES_RECV.store(0, Ordering::SeqCst);
-
-    PROXY_OK
+    let json_str = unsafe { CStr::from_ptr(filter_json) }.to_string_lossy();
+    serde_json::from_str(&json_str).map_err(|e| format!("invalid connection filter JSON: {}", e))
 }
 
-/// Disconnect all active connections and returns the number of connections kicked.
+/// Caps the combined traffic of all connections; averages of 0 lift the cap.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn proxy_kick_all() -> c_uint {
-    let mut conn_manager = CONN_MANAGER.lock().unwrap();
-    let mut rate_limiters = RATE_LIMITERS.lock().unwrap();
-    let mut conn_metrics = CONN_METRICS.lock().unwrap();
+pub extern "C" fn proxy_set_global_rate_limit(
+    send_avg_bytes_per_sec: u64,
+    send_burst_bytes_per_sec: u64,
+    recv_avg_bytes_per_sec: u64,
+    recv_burst_bytes_per_sec: u64,
+) -> ProxyError {
+    let limit = RateLimitConfig {
+        send_avg: Some(send_avg_bytes_per_sec),
+        send_burst: Some(send_burst_bytes_per_sec),
+        recv_avg: Some(recv_avg_bytes_per_sec),
+        recv_burst: Some(recv_burst_bytes_per_sec),
+    };
+    Geofront::new().set_global_rate_limit(Some(&limit));
+    info!(
+        send_avg = send_avg_bytes_per_sec,
+        recv_avg = recv_avg_bytes_per_sec,
+        "Updated global rate limits"
+    );
+    PROXY_OK
+}
 
-    let kicked_count = conn_manager.connections.len();
+/// Caps the combined traffic of a listener's connections; averages of 0 lift
+/// the cap.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_set_listener_rate_limit(
+    listener: ProxyListener,
+    send_avg_bytes_per_sec: u64,
+    send_burst_bytes_per_sec: u64,
+    recv_avg_bytes_per_sec: u64,
+    recv_burst_bytes_per_sec: u64,
+) -> ProxyError {
+    let limit = RateLimitConfig {
+        send_avg: Some(send_avg_bytes_per_sec),
+        send_burst: Some(send_burst_bytes_per_sec),
+        recv_avg: Some(recv_avg_bytes_per_sec),
+        recv_burst: Some(recv_burst_bytes_per_sec),
+    };
+    if !Geofront::new().set_listener_rate_limit(listener, Some(&limit)) {
+        return fail(PROXY_ERR_NOT_FOUND, format!("unknown listener {}", listener));
+    }
+    info!(
+        listener,
+        send_avg = send_avg_bytes_per_sec,
+        recv_avg = recv_avg_bytes_per_sec,
+        "Updated listener rate limits"
+    );
+    PROXY_OK
+}
 
-    for (conn_id, handle) in conn_manager.connections.drain() {
-        handle.abort();
-        rate_limiters.remove(&conn_id);
-        conn_metrics.remove(&conn_id);
-        ACTIVE_CONN.fetch_sub(1, Ordering::SeqCst);
+/// Returns the configured limits and live capacity of a connection's send and
+/// recv limiters as JSON, or NULL if the connection is unknown.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_rate_limit_state(conn_id: ProxyConnection) -> *const c_char {
+    let Some((send, recv)) = RATE_LIMITERS.get(&conn_id).map(|entry| entry.value().clone()) else {
+        return ptr::null();
+    };
+    let state = serde_json::json!({
+        "send": send.snapshot(),
+        "recv": recv.snapshot(),
+    });
+    match CString::new(state.to_string()) {
+        Ok(c_str) => c_str.into_raw(),
+        Err(_) => ptr::null(),
     }
+}
+
+/// Shutdown all listeners and connections
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_shutdown() -> ProxyError {
+    Geofront::new().shutdown();
+    PROXY_OK
+}
+
+/// Disconnect all active connections and returns the number of connections kicked.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_kick_all() -> c_uint {
+    let ids: Vec<ProxyConnection> = CONN_MANAGER.iter().map(|entry| *entry.key()).collect();
+    let connections: Vec<_> = ids.into_iter().filter_map(|id| CONN_MANAGER.remove(&id)).collect();
+    let kicked_count = connections.len();
 
-    // Call disconnection callback for each kicked connection
-    // The new polling mechanism handles disconnection events.
-    // No need to manually call callbacks here.
+    for (conn_id, handle) in connections {
+        handle.abort();
+        cleanup_conn(conn_id, DisconnectReason::Kicked);
+    }
 
     kicked_count as c_uint
 }
 
+/// Disconnects every connection matching a JSON `ConnFilter`, queueing a
+/// disconnection event for each, and returns the number kicked. Returns 0
+/// with the last error set if the filter is NULL, invalid or sets no
+/// criterion; use `proxy_kick_all` to kick everyone.
+///
+/// # Safety
+///
+/// `filter_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_kick_where(filter_json: *const c_char) -> c_uint {
+    if filter_json.is_null() {
+        set_last_error("filter_json is null");
+        return 0;
+    }
+    let filter = match unsafe { conn_filter(filter_json) } {
+        Ok(filter) => filter,
+        Err(e) => {
+            set_last_error(e);
+            return 0;
+        }
+    };
+    if conn_query::is_unfiltered(&filter) {
+        set_last_error("filter matches every connection; use proxy_kick_all to kick everyone");
+        return 0;
+    }
+    let kicked = Geofront::new().kick_where(&filter);
+    info!(kicked, "Kicked matching connections");
+    kicked as c_uint
+}
+
 /// Takes a snapshot of all metrics and returns it as a JSON string.
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_get_metrics() -> *const c_char {
-    let conn_metrics_guard = CONN_METRICS.lock().unwrap();
-    let connections = conn_metrics_guard
-        .iter()
-        .map(|(id, metrics)| {
-            (
-                *id,
-                ConnMetricsSnapshot {
-                    bytes_sent: metrics.bytes_sent.load(Ordering::SeqCst),
-                    bytes_recv: metrics.bytes_recv.load(Ordering::SeqCst),
-                },
-            )
-        })
-        .collect();
+    match serde_json::to_string(&snapshot::metrics()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
 
-    let snapshot = MetricsSnapshot {
-        total_conn: TOTAL_CONN.load(Ordering::SeqCst),
-        active_conn: ACTIVE_CONN.load(Ordering::SeqCst),
-        total_bytes_sent: TOTAL_BYTES_SENT.load(Ordering::SeqCst),
-        total_bytes_recv: TOTAL_BYTES_RECV.load(Ordering::SeqCst),
-        connections,
+/// Returns as a JSON string the metrics of the connections that changed
+/// since `cursor` (`MetricsDelta`), along with the cursor for the next call.
+/// Pass 0 on the first call; an unknown or spent cursor gets every live
+/// connection with `full` set.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_get_metrics_delta(cursor: u64) -> *const c_char {
+    match serde_json::to_string(&snapshot::metrics_delta_since(cursor)) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns as a JSON string the bytes and connections keyed by username
+/// (`key_type` 0) or by peer IP (1), including live connections.
+/// Returns NULL for another `key_type`.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_get_usage(key_type: UsageKey) -> *const c_char {
+    let Some(usage) = accounting::usage(key_type) else {
+        set_last_error(format!("unknown usage key type {}", key_type));
+        return ptr::null();
     };
+    match serde_json::to_string(&usage) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
 
-    match serde_json::to_string(&snapshot) {
+/// Returns as a JSON string the client associations of Bedrock listeners.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_get_bedrock_sessions() -> *const c_char {
+    match serde_json::to_string(&bedrock::sessions()) {
         Ok(json_str) => match CString::new(json_str) {
             Ok(c_str) => c_str.into_raw(),
             Err(_) => ptr::null(),
@@ -406,16 +1147,54 @@ pub unsafe extern "C" fn proxy_get_metrics() -> *const c_char {
     }
 }
 
+/// Encodes a metrics snapshot, or with `delta` only what changed since the
+/// previous delta (`MetricsDelta`), as `format` into a buffer owned by Rust.
+/// Stores the length in `out_len` and returns a pointer to the bytes, valid
+/// until the next call; NULL on failure. The buffer must not be freed.
+///
+/// # Safety
+///
+/// `out_len` must be NULL or valid for writing a `usize`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_get_metrics_buf(
+    format: WireFormat,
+    delta: bool,
+    out_len: *mut usize,
+) -> *const u8 {
+    if out_len.is_null() {
+        set_last_error("out_len is null");
+        return ptr::null();
+    }
+    let out_len = unsafe { &mut *out_len };
+    if delta {
+        METRICS_BUF.encode(&snapshot::metrics_delta(), format, out_len)
+    } else {
+        METRICS_BUF.encode(&snapshot::metrics(), format, out_len)
+    }
+}
+
 /// Takes a snapshot of a single connection's metrics and returns it as a JSON string.
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_get_connection_metrics(conn_id: ProxyConnection) -> *const c_char {
-    let conn_metrics_guard = CONN_METRICS.lock().unwrap();
-    if let Some(metrics) = conn_metrics_guard.get(&conn_id) {
+    let metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    if let Some(metrics) = metrics {
+        let info_ref = CONN_INFO.get(&conn_id);
+        let info = info_ref.as_deref();
         let snapshot = ConnMetricsSnapshot {
             bytes_sent: metrics.bytes_sent.load(Ordering::SeqCst),
             bytes_recv: metrics.bytes_recv.load(Ordering::SeqCst),
+            tags: info.map(|info| info.tags.clone()).unwrap_or_default(),
+            metadata: info.and_then(|info| info.metadata.clone()),
+            ttfb_ms: info.and_then(|info| info.ttfb_ms),
+            proxy_hop_ms: info.map(|info| info.proxy_hop_ms.clone()).unwrap_or_default(),
+            latency_ms: info.and_then(|info| info.latency_ms),
         };
+        drop(info_ref);
         match serde_json::to_string(&snapshot) {
             Ok(json_str) => match CString::new(json_str) {
                 Ok(c_str) => c_str.into_raw(),
@@ -428,7 +1207,96 @@ pub unsafe extern "C" fn proxy_get_connection_metrics(conn_id: ProxyConnection)
     }
 }
 
+/// Returns everything known about a live connection as JSON: peer, handshake,
+/// username, backend and proxy, timestamps, rate limits and transfer totals.
+/// Returns NULL if the connection is unknown.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_connection_info(conn_id: ProxyConnection) -> *const c_char {
+    let Some(details) = Geofront::new().connection_info(conn_id) else {
+        set_last_error("out_len is null");
+        return ptr::null();
+    };
+    match serde_json::to_string(&details) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns the connections matching a JSON `ConnFilter` (NULL for all) as a
+/// JSON array of the objects `proxy_get_connection_info` returns, oldest
+/// first. Returns NULL if the filter is invalid.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// `filter_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_list_connections(filter_json: *const c_char) -> *const c_char {
+    let filter = match unsafe { conn_filter(filter_json) } {
+        Ok(filter) => filter,
+        Err(e) => {
+            set_last_error(e);
+            return ptr::null();
+        }
+    };
+    match serde_json::to_string(&Geofront::new().list_connections(&filter)) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Serves the metrics in the Prometheus text format at `http://addr:port/metrics`,
+/// replacing the running exporter if any. Addresses other than loopback are
+/// refused unless the `metricsExporter` option sets a token or a TLS client CA.
+///
+/// # Safety
+///
+/// `bind_addr` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_start_metrics_exporter(
+    bind_addr: *const c_char,
+    bind_port: c_ushort,
+) -> ProxyError {
+    if bind_addr.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr is null");
+    }
+    let Ok(addr) = unsafe { CStr::from_ptr(bind_addr) }.to_str() else {
+        return fail(PROXY_ERR_BAD_PARAM, "bind_addr must be UTF-8");
+    };
+    match Geofront::new().start_metrics_exporter(addr, bind_port) {
+        Ok(()) => PROXY_OK,
+        Err(e) => {
+            error!("Failed to start metrics exporter on {}:{}: {}", addr, bind_port, e);
+            fail(PROXY_ERR_INTERNAL, format!("failed to start metrics exporter: {}", e))
+        }
+    }
+}
+
+/// Stops the Prometheus exporter.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_stop_metrics_exporter() -> ProxyError {
+    if Geofront::new().stop_metrics_exporter() {
+        PROXY_OK
+    } else {
+        fail(PROXY_ERR_NOT_FOUND, "metrics exporter is not running")
+    }
+}
+
 /// Frees a string that was allocated by Rust and passed to another language.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by this library that has not been freed yet; it must
+/// not be used afterwards.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_free_string(s: *mut c_char) {
     if !s.is_null() {
@@ -438,9 +1306,31 @@ pub unsafe extern "C" fn proxy_free_string(s: *mut c_char) {
     }
 }
 
+/// Returns a handle that becomes ready whenever an event queue goes from
+/// empty to non-empty, for hosts waiting in their own event loop instead of
+/// polling on a timer: an eventfd on Linux (read 8 bytes to re-arm), a pipe
+/// read end on other Unix systems (read it empty), an auto-reset event
+/// HANDLE on Windows. Poll until no events are left after each wakeup.
+/// Returns -1 if the handle could not be created. It stays open for the
+/// life of the process and must not be closed.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_event_fd() -> isize {
+    match wakeup::handle() {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("Failed to create the event handle: {}", e);
+            -1
+        }
+    }
+}
+
 /// Alternative thread-safe approach: Poll for pending route requests
 /// Returns NULL if no pending requests, otherwise returns JSON with request info
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_poll_route_request() -> *const c_char {
     let mut queue = ROUTE_REQUEST_QUEUE.lock().unwrap();
@@ -461,6 +1351,10 @@ pub unsafe extern "C" fn proxy_poll_route_request() -> *const c_char {
 /// Alternative thread-safe approach: Poll for pending MOTD requests
 /// Returns NULL if no pending requests, otherwise returns JSON with request info
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_poll_motd_request() -> *const c_char {
     let mut queue = MOTD_REQUEST_QUEUE.lock().unwrap();
@@ -481,6 +1375,10 @@ pub unsafe extern "C" fn proxy_poll_motd_request() -> *const c_char {
 /// Alternative thread-safe approach: Poll for disconnection events
 /// Returns NULL if no pending events, otherwise returns JSON with disconnection info
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_poll_disconnection_event() -> *const c_char {
     let mut queue = DISCONNECTION_EVENT_QUEUE.lock().unwrap();
@@ -498,30 +1396,43 @@ pub unsafe extern "C" fn proxy_poll_disconnection_event() -> *const c_char {
     }
 }
 
-/// Batch polling for all event types (route requests, MOTD requests, disconnection events)
+/// Batch polling for all event types (route requests, MOTD requests, disconnection events,
+/// usage reports, metrics events, backend events, protocol errors)
 /// Returns NULL if no pending events, otherwise returns JSON with all events
 /// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_poll_events() -> *const c_char {
-    let mut route_queue = ROUTE_REQUEST_QUEUE.lock().unwrap();
-    let mut motd_queue = MOTD_REQUEST_QUEUE.lock().unwrap();
-    let mut disconnection_queue = DISCONNECTION_EVENT_QUEUE.lock().unwrap();
-
-    let route_requests = route_queue.drain(..).collect::<Vec<_>>();
-    let motd_requests = motd_queue.drain(..).collect::<Vec<_>>();
-    let disconnection_events = disconnection_queue.drain(..).collect::<Vec<_>>();
-
-    // If no events at all, return null
-    if route_requests.is_empty() && motd_requests.is_empty() && disconnection_events.is_empty() {
+    let Some(events) = snapshot::poll_events() else {
         return ptr::null();
+    };
+    match serde_json::to_string(&events) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
     }
+}
 
-    let events = PollEvents {
-        route_requests,
-        motd_requests,
-        disconnection_events,
+/// Batch polling for the events a host must answer or account for promptly:
+/// up to `max_events` (0 for no limit) route requests, MOTD requests and
+/// disconnection events, oldest first and in that order, as one JSON
+/// document shaped like `proxy_poll_events`. Other events stay queued.
+/// Returns NULL if none are pending.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_poll_all(max_events: usize) -> *const c_char {
+    let Some(events) = snapshot::poll_all(max_events) else {
+        return ptr::null();
     };
-
     match serde_json::to_string(&events) {
         Ok(json_str) => match CString::new(json_str) {
             Ok(c_str) => c_str.into_raw(),
@@ -531,7 +1442,32 @@ pub unsafe extern "C" fn proxy_poll_events() -> *const c_char {
     }
 }
 
+/// Same as `proxy_poll_events`, encoded as `format` into a buffer owned by
+/// Rust. Stores the length in `out_len` and returns a pointer to the bytes,
+/// valid until the next call; NULL if there are no events or on failure.
+/// The buffer must not be freed.
+///
+/// # Safety
+///
+/// `out_len` must be NULL or valid for writing a `usize`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_poll_events_buf(format: WireFormat, out_len: *mut usize) -> *const u8 {
+    if out_len.is_null() {
+        return ptr::null();
+    }
+    let out_len = unsafe { &mut *out_len };
+    *out_len = 0;
+    match snapshot::poll_events() {
+        Some(events) => EVENTS_BUF.encode(&events, format, out_len),
+        None => ptr::null(),
+    }
+}
+
 /// Clean up expired cache entries
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_cleanup_cache() -> ProxyError {
     ROUTER_MOTD_CACHE.cleanup_expired();
@@ -540,6 +1476,10 @@ pub unsafe extern "C" fn proxy_cleanup_cache() -> ProxyError {
 }
 
 /// Get cache statistics
+///
+/// # Safety
+///
+/// Takes no pointer arguments; safe to call from any thread.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn proxy_get_cache_stats() -> *const c_char {
     let stats = ROUTER_MOTD_CACHE.get_stats();
@@ -556,3 +1496,300 @@ pub unsafe extern "C" fn proxy_get_cache_stats() -> *const c_char {
         Err(_) => ptr::null(),
     }
 }
+
+/// Drops every cached route and MOTD decision, including those in the shared
+/// cache.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_cache_clear_all() -> ProxyError {
+    Geofront::new().clear_cache();
+    PROXY_OK
+}
+
+/// Drops the cached decisions for `ip`: its IP-wide entry and the entry for
+/// `host`, or every per-host entry of the IP when `host` is NULL.
+///
+/// # Safety
+///
+/// `ip` and `host` must each be NULL or point to a NUL-terminated string that stays valid for
+/// the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_cache_clear(ip: *const c_char, host: *const c_char) -> ProxyError {
+    if ip.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "ip is null");
+    }
+    let ip = unsafe { CStr::from_ptr(ip) }.to_string_lossy();
+    let host = (!host.is_null()).then(|| unsafe { CStr::from_ptr(host) }.to_string_lossy());
+    Geofront::new().clear_cached(&ip, host.as_deref());
+    PROXY_OK
+}
+
+/// Returns the cache size and hit, miss and eviction counters as JSON.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_cache_stats() -> *const c_char {
+    match serde_json::to_string(&Geofront::new().cache_stats()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns the cached rejections as a JSON array of `{ scope, ip, host?,
+/// rejectReason?, expiresInMs }`.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_cache_blocked() -> *const c_char {
+    match serde_json::to_string(&Geofront::new().blocked()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Queries the SQLite connection history with a JSON filter (`AuditQuery`) and
+/// returns matching records as a JSON array, newest first.
+/// Returns NULL if the audit database is disabled or the query fails.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+///
+/// # Safety
+///
+/// `filter_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_query_audit(filter_json: *const c_char) -> *const c_char {
+    let filter: AuditQuery = if filter_json.is_null() {
+        AuditQuery::default()
+    } else {
+        let json_str = unsafe { CStr::from_ptr(filter_json) }.to_string_lossy();
+        match serde_json::from_str(&json_str) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Failed to parse audit query JSON: {}", e);
+                set_last_error(format!("invalid audit query JSON: {}", e));
+                return ptr::null();
+            }
+        }
+    };
+    let Some(config) = OPTIONS.read().unwrap().audit_db.clone() else {
+        set_last_error("audit database is disabled");
+        return ptr::null();
+    };
+
+    match audit_db::query(&config, &filter) {
+        Ok(records) => match serde_json::to_string(&records) {
+            Ok(json_str) => match CString::new(json_str) {
+                Ok(c_str) => c_str.into_raw(),
+                Err(_) => ptr::null(),
+            },
+            Err(_) => ptr::null(),
+        },
+        Err(e) => {
+            error!("Audit query failed: {}", e);
+            set_last_error(format!("audit query failed: {}", e));
+            ptr::null()
+        }
+    }
+}
+
+/// Returns the effective configuration as JSON: the options as parsed by the
+/// proxy (with credentials masked) and the running listeners.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_options() -> *const c_char {
+    match serde_json::to_string(&introspect::effective_config()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns the DNS-resolved backend pools as a JSON object keyed by `host:port`.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_backend_pools() -> *const c_char {
+    match serde_json::to_string(&discovery::snapshot()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns the active health check results as a JSON object keyed by backend
+/// `host:port`. The caller is responsible for freeing the returned string
+/// using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_backend_health() -> *const c_char {
+    match serde_json::to_string(&health_check::snapshot()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Returns the health of the configured upstream proxies as a JSON object
+/// keyed by pool name. The caller is responsible for freeing the returned
+/// string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_get_proxy_pools() -> *const c_char {
+    match serde_json::to_string(&proxy_manager::snapshot()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Merges a JSON object of tags into a live connection's metadata. Keys set to
+/// `null` are removed. Tags are echoed in disconnection events, connection
+/// info and metrics.
+///
+/// # Safety
+///
+/// `tags_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_set_connection_tag(
+    conn_id: ProxyConnection,
+    tags_json: *const c_char,
+) -> ProxyError {
+    if tags_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "tags_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(tags_json) }.to_string_lossy();
+    let tags: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(&json_str) {
+        Ok(tags) => tags,
+        Err(e) => {
+            error!("Failed to parse connection tags JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid connection tags JSON: {}", e));
+        }
+    };
+
+    let Some(mut info) = CONN_INFO.get_mut(&conn_id) else {
+        return fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id));
+    };
+    for (key, value) in tags {
+        if value.is_null() {
+            info.tags.remove(&key);
+        } else {
+            info.tags.insert(key, value);
+        }
+    }
+    PROXY_OK
+}
+
+/// Sets the labels logged with every line about a live connection, as a JSON
+/// object replacing any set before; `{}` clears them. They appear in the
+/// connection's `labels` span field, and as fields of their own in log
+/// queue records.
+///
+/// # Safety
+///
+/// `fields_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_set_log_fields(conn_id: ProxyConnection, fields_json: *const c_char) -> ProxyError {
+    if fields_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "fields_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(fields_json) }.to_string_lossy();
+    let fields: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(&json_str) {
+        Ok(fields) => fields,
+        Err(e) => return fail(PROXY_ERR_BAD_PARAM, format!("invalid log fields JSON: {}", e)),
+    };
+    if !Geofront::new().set_log_fields(conn_id, &fields) {
+        return fail(PROXY_ERR_NOT_FOUND, format!("unknown connection {}", conn_id));
+    }
+    PROXY_OK
+}
+
+/// Starts a load-generation run (`loadgen` feature) against the target in
+/// `config_json`. Only one run may be in progress at a time.
+///
+/// # Safety
+///
+/// `config_json` must be NULL or point to a NUL-terminated string that stays valid for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn proxy_loadgen_start(config_json: *const c_char) -> ProxyError {
+    if config_json.is_null() {
+        return fail(PROXY_ERR_BAD_PARAM, "config_json is null");
+    }
+    let json_str = unsafe { CStr::from_ptr(config_json) }.to_string_lossy();
+    let config: LoadGenConfig = match serde_json::from_str(&json_str) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to parse load generator config JSON: {}", e);
+            return fail(PROXY_ERR_BAD_PARAM, format!("invalid load generator config JSON: {}", e));
+        }
+    };
+    match loadgen::start(config) {
+        Ok(()) => PROXY_OK,
+        Err(e) => {
+            error!("Failed to start load generator: {}", e);
+            fail(PROXY_ERR_INTERNAL, format!("failed to start load generator: {}", e))
+        }
+    }
+}
+
+/// Returns the state of the load generator and the report of its last run.
+/// The caller is responsible for freeing the returned string using `proxy_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_loadgen_status() -> *const c_char {
+    match serde_json::to_string(&loadgen::status()) {
+        Ok(json_str) => match CString::new(json_str) {
+            Ok(c_str) => c_str.into_raw(),
+            Err(_) => ptr::null(),
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Aborts the load-generation run in progress.
+#[unsafe(no_mangle)]
+pub extern "C" fn proxy_loadgen_stop() -> ProxyError {
+    if loadgen::stop() {
+        PROXY_OK
+    } else {
+        fail(PROXY_ERR_NOT_FOUND, "no load generation run in progress")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bedrock_bind_failure_is_reported() {
+        let taken = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let mut listener: ProxyListener = 0;
+        let code = unsafe {
+            proxy_start_bedrock_listener(
+                c"127.0.0.1".as_ptr(),
+                port,
+                c"{\"defaultBackend\": \"127.0.0.1:19132\"}".as_ptr(),
+                &mut listener,
+            )
+        };
+        assert_eq!(code, PROXY_ERR_BAD_PARAM);
+        assert_eq!(listener, 0);
+    }
+
+    #[test]
+    fn test_kick_where_rejects_empty_filter() {
+        assert_eq!(unsafe { proxy_kick_where(c"{}".as_ptr()) }, 0);
+        let message = LAST_ERROR.with(|last| last.borrow().clone()).unwrap();
+        assert!(message.contains("proxy_kick_all"), "unexpected error: {}", message);
+    }
+}
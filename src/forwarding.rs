@@ -0,0 +1,160 @@
+//! geofront/src/forwarding.rs
+//! BungeeCord-style forwarded handshakes: an upstream proxy appends
+//! `\0<ip>\0<uuid>[\0<properties>]` to the handshake host.
+
+use crate::types::{BungeeForwardingConfig, ForwardedIdentity, HandshakeData};
+use ipnet::IpNet;
+use std::net::IpAddr;
+use tracing::warn;
+
+/// Splits off the forwarding payload of `hs.host`. When `peer` is trusted the
+/// identity is kept on the handshake and its IP returned as the effective
+/// peer address; otherwise the payload is dropped so it cannot be spoofed
+/// through to the backends.
+pub fn apply(
+    hs: &mut HandshakeData,
+    peer: Option<IpAddr>,
+    config: &BungeeForwardingConfig,
+) -> Option<IpAddr> {
+    let (host, identity) = split(&hs.host)?;
+    hs.host = host.to_string();
+    if !peer.is_some_and(|ip| is_trusted(ip, &config.trusted_cidrs)) {
+        warn!(
+            peer = ?peer,
+            forwarded_ip = %identity.ip,
+            "Ignoring forwarded handshake from untrusted peer"
+        );
+        return None;
+    }
+    let ip = identity.ip;
+    hs.forwarded = Some(identity);
+    Some(ip)
+}
+
+/// Host as sent to the backend, with the client marker and the accepted
+/// payload re-appended.
+pub fn encode_host(hs: &HandshakeData) -> String {
+    let mut host = hs.host.clone();
+    if let Some(client_type) = &hs.client_type {
+        host.push('\0');
+        host.push_str(client_type);
+        host.push('\0');
+    }
+    if let Some(identity) = &hs.forwarded {
+        host.push_str(&format!("\0{}\0{}", identity.ip, identity.uuid));
+        if let Some(properties) = &identity.properties {
+            host.push('\0');
+            host.push_str(properties);
+        }
+    }
+    host
+}
+
+/// `None` for plain hosts and Forge markers (`host\0FML2\0`).
+fn split(host: &str) -> Option<(&str, ForwardedIdentity)> {
+    let mut parts = host.splitn(4, '\0');
+    let real_host = parts.next()?;
+    let ip = parts.next()?.parse().ok()?;
+    let uuid = parts.next()?;
+    if uuid.len() != 32 || !uuid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let properties = parts.next().filter(|p| !p.is_empty()).map(str::to_string);
+    Some((
+        real_host,
+        ForwardedIdentity {
+            ip,
+            uuid: uuid.to_string(),
+            properties,
+        },
+    ))
+}
+
+/// Whether `peer` is in one of `cidrs`, IPv4-mapped addresses included.
+pub fn is_trusted(peer: IpAddr, cidrs: &[IpNet]) -> bool {
+    let peer = peer.to_canonical();
+    cidrs.iter().any(|net| net.contains(&peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "069a79f444e94726a5befca90e38aaf5";
+
+    fn handshake(host: &str) -> HandshakeData {
+        HandshakeData {
+            protocol_version: 767,
+            host: host.to_string(),
+            port: 25565,
+            next_state: 2,
+            forwarded: None,
+            client_type: None,
+        }
+    }
+
+    fn joined(parts: &[&str]) -> String {
+        parts.join("\0")
+    }
+
+    fn config(cidrs: &[&str]) -> BungeeForwardingConfig {
+        BungeeForwardingConfig {
+            trusted_cidrs: cidrs.iter().map(|c| c.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        assert!(split("mc.example.com").is_none());
+        assert!(split("mc.example.com\0FML2\0").is_none());
+        assert!(split(&joined(&["mc.example.com", "203.0.113.7", "not-a-uuid"])).is_none());
+
+        let raw = joined(&["mc.example.com", "203.0.113.7", UUID]);
+        let (host, identity) = split(&raw).unwrap();
+        assert_eq!(host, "mc.example.com");
+        assert_eq!(identity.ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(identity.properties, None);
+
+        let raw = joined(&["h", "::1", UUID, "[{\"name\":\"textures\"}]"]);
+        let (_, identity) = split(&raw).unwrap();
+        assert_eq!(
+            identity.properties.as_deref(),
+            Some("[{\"name\":\"textures\"}]")
+        );
+    }
+
+    #[test]
+    fn test_apply_trusted() {
+        let raw = joined(&["mc.example.com", "203.0.113.7", UUID, "[]"]);
+        let mut hs = handshake(&raw);
+        let peer = "::ffff:10.0.0.5".parse().ok();
+        let ip = apply(&mut hs, peer, &config(&["10.0.0.0/8"]));
+        assert_eq!(ip, "203.0.113.7".parse().ok());
+        assert_eq!(hs.host, "mc.example.com");
+        assert_eq!(hs.forwarded.as_ref().unwrap().uuid, UUID);
+        assert_eq!(encode_host(&hs), raw);
+    }
+
+    #[test]
+    fn test_apply_untrusted() {
+        let mut hs = handshake(&joined(&["mc.example.com", "203.0.113.7", UUID]));
+        let peer = "198.51.100.1".parse().ok();
+        assert_eq!(apply(&mut hs, peer, &config(&["10.0.0.0/8"])), None);
+        assert_eq!(hs.host, "mc.example.com");
+        assert_eq!(encode_host(&hs), "mc.example.com");
+    }
+
+    #[test]
+    fn test_encode_host_with_client_type() {
+        let raw = joined(&["mc.example.com", "FML", "", "203.0.113.7", UUID]);
+        let (host, client_type) = crate::protocol::split_client_type(&raw);
+        let mut hs = handshake(&host);
+        hs.client_type = client_type;
+        apply(&mut hs, "10.0.0.5".parse().ok(), &config(&["10.0.0.0/8"]));
+        hs.host = "backend.internal".to_string();
+        assert_eq!(
+            encode_host(&hs),
+            joined(&["backend.internal", "FML", "", "203.0.113.7", UUID])
+        );
+    }
+}
@@ -12,6 +12,7 @@ import {
 	CString,
 	dlopen,
 	FFIType,
+	toArrayBuffer,
 	type ConvertFns,
 	type Pointer
 } from 'bun:ffi'
@@ -27,41 +28,150 @@ export interface ProxyConfig {
 	readonly host: string
 	readonly port: number
 	readonly proxyProtocol?: 'none' | 'optional' | 'strict'
+	// 以 TLS 接入（PEM 证书链与私钥路径），需以 `tls` feature 编译；客户端 SNI 见 RouteContext.sni
+	readonly tls?: {
+		readonly certPath: string
+		readonly keyPath: string
+		// 设置后要求客户端出示由该 CA（PEM）签发的证书（双向 TLS），用于只接受边缘节点的源站
+		readonly clientCaPath?: string
+	}
+	// 接受 WebSocket 连接（如 Eaglercraft 等网页客户端），以二进制帧承载 Minecraft 数据流，需以 `websocket` feature 编译
+	readonly websocket?: boolean
+	// 未匹配计划路由与静态路由时直接使用的路由结果，不再调用路由回调
+	readonly defaultRoute?: RouteResult
+	// 该监听器的连接数上限，以及覆盖全局设置的单 IP 限制
+	readonly maxConnections?: number
+	readonly maxConnectionsPerIp?: number
+	readonly handshakesPerIpPerSecond?: number
+	// 以 SO_REUSEPORT 绑定的接受套接字数，由内核在各自的 accept 循环间分摊新连接（仅 Linux，默认 1）
+	readonly acceptors?: number
 }
 
 export interface RouteContext {
 	readonly ip: string
+	// 客户端实际连接的地址与端口（入站 PROXY Protocol 头中的目标地址，否则为监听 socket 地址），
+	// 可用于按 anycast / 多条 DNS 记录对应的入口 IP 路由
+	readonly localIp?: string
+	readonly localPort?: number
 	readonly host: string
 	readonly username: string
 	readonly protocol: number
+	// 上游 BungeeCord/Velocity 转发的玩家 UUID（无连字符），需开启 bungeeForwarding
+	readonly uuid?: string
+	// TLS 监听器上客户端的 SNI 主机名，可与握手中的 host 一同作为路由依据
+	readonly sni?: string
+	// Forge 客户端附加在握手 host 后的标记（'FML' | 'FML2' | 'FML3' | 'FORGE'），原版客户端为空；
+	// host 中已去除该标记，转发给后端时（包括 rewrite 之后）会重新附加
+	readonly clientType?: string
+	// 客户端的接入方式
+	readonly transport: 'tcp' | 'tls' | 'websocket'
+	// 客户端 IP 的国家代码（ISO 3166-1）与自治系统，需配置 geoipDb
+	readonly country?: string
+	readonly asn?: number
+	readonly asOrg?: string
 }
 
 export interface RouteResult {
-	readonly target: {
+	// host 写作 tls://host[:port] 时经 TLS 连接后端（按 backendTls 选项校验证书并出示客户端证书）
+	readonly target?: {
 		readonly host: string
 		readonly port: number
 	}
-	// 上游 SOCKS5/HTTP 代理配置（仅负责上游连接）
+	// 备用后端：target（或 pool）不可达时按顺序依次尝试
+	readonly fallbacks?: readonly {
+		readonly host: string
+		readonly port: number
+	}[]
+	// 每次连接后端尝试的超时时间
+	readonly connectTimeoutMs?: number
+	// 连接到 pools 选项中的命名后端池（由 Consul/etcd 维护），优先于 target
+	readonly pool?: string
+	// 容量满时的优先级（默认 0），capacity.evict 开启时可挤掉更低优先级的玩家
+	readonly priority?: number
+	// 计入 capacity.tenantMaxPlayers 的租户
+	readonly tenant?: string
+	// 上游 SOCKS5/HTTP 代理配置（仅负责上游连接）；url 为数组时按顺序逐级隧道（代理链）
+	// quic://token@origin:port 经 QUIC 隧道交由另一 geofront 实例（listenQuicTunnel）连接后端，只能作为第一跳
 	readonly proxy?: {
-		readonly url: string
+		readonly url: string | readonly string[]
 	}
+	// 改用 proxyPools 选项中的命名上游代理池（按轮换策略选取，连接失败时依次切换），优先于 proxy
+	readonly proxyPool?: string
+	// 连接后端（或上游代理）时绑定的本机 IP，用于多出口主机；仅尝试与其同协议族的地址
+	readonly localAddress?: string
 	// 向后端写入 HAProxy PROXY Protocol 版本；与监听器的 inbound proxyProtocol 配置语义不同
 	readonly proxyProtocol?: 1 | 2
+	// 写入 PROXY Protocol 头的源地址（"ip" 或 "ip:port"），覆盖真实连接地址，
+	// 例如上游 CDN 头中恢复的客户端 IP；需开启 allowProxyProtocolSource
+	readonly proxyProtocolSource?: string
 	readonly rewrite?: {
 		readonly host: string
 	}
+	// 随连接保存的任意数据，在断开事件、指标、审计记录与事件导出中原样回传，无需自行维护连接映射
+	readonly metadata?: Record<string, unknown>
+	// 连接标签（如 ["vip", "eu"]），以 true 值写入连接的 tags，可用 listConnections 等按标签筛选
+	readonly tags?: ReadonlyArray<string>
 	readonly cache?: {
 		readonly granularity: 'ip' | 'ip+host'
 		readonly ttl: number
 		readonly reject?: boolean
 		readonly rejectReason?: string
-	}
+		// 拒绝决策（断开或 reject: true）的缓存时长，通常长于 ttl；未设置时使用 ttl
+		readonly rejectTtl?: number
+	}
+	// 连接后端前即生效的限速（覆盖全局限速），避免开头的流量未被限速
+	readonly rateLimit?: RateLimit
+	// 覆盖全局 idleTimeoutMs / maxSessionDurationMs（0 表示不限制）
+	readonly idleTimeoutMs?: number
+	readonly maxSessionDurationMs?: number
+	// 本连接双向流量达到 quotaBytes 时断开；userQuotaBytes 按用户名累计（含 accounting 保留的已关闭连接），
+	// 已超出的玩家登录时即以 quotaExceeded 消息拒绝
+	readonly quotaBytes?: number
+	readonly userQuotaBytes?: number
+	// 允许的客户端协议版本范围 [min, max]（含两端），范围外的客户端由代理直接以 unsupportedVersion 消息拒绝
+	readonly allowedProtocolRange?: readonly [number, number]
+	// 按字段覆盖全局 tcp 选项，作用于本连接的客户端与后端（上游代理）socket
+	readonly tcp?: TcpOptions
+	// tls:// 后端的 SNI，覆盖 backendTls.serverName
+	readonly tlsServerName?: string
+	// 把会话字节流复制到另一目标用于调试与反作弊分析（尽力而为，目标跟不上时丢弃并计入指标）；
+	// direction 为 client（客户端发出）、server（后端发出）或 both（默认，每个方向各用一条连接）
+	readonly mirror?: {
+		readonly host: string
+		readonly port: number
+		readonly direction?: 'client' | 'server' | 'both'
+	}
+	// 后端断开而客户端仍在时，最多保持客户端这么多毫秒，等路由中任一后端恢复可连后
+	// 以 Transfer 数据包让客户端重连并重放登录；需开启 allowTransfer 且客户端为 1.20.5+，应小于客户端 30 秒超时
+	readonly reconnectGraceMs?: number
+	// 由 Geofront 完成正版验证（加密握手 + Mojang hasJoined 校验），
+	// 再以 BungeeCord 转发方式把玩家资料交给离线模式的后端；需要以 `auth` feature 编译
+	readonly authenticate?: boolean
+}
+
+// TCP socket 选项；未设置的字段保持系统默认值
+export interface TcpOptions {
+	readonly noDelay?: boolean
+	// 设置任一 keepalive 字段即开启 keepalive；keepaliveProbes 在 Windows 上无效
+	readonly keepaliveTimeMs?: number
+	readonly keepaliveIntervalMs?: number
+	readonly keepaliveProbes?: number
+	// SO_SNDBUF / SO_RCVBUF（字节）
+	readonly sendBufferSize?: number
+	readonly recvBufferSize?: number
 }
 
 export interface MotdContext {
 	readonly ip: string
+	// 同 RouteContext
+	readonly localIp?: string
+	readonly localPort?: number
 	readonly host: string
 	readonly protocol: number
+	// 同 RouteContext，需配置 geoipDb
+	readonly country?: string
+	readonly asn?: number
+	readonly asOrg?: string
 }
 
 export interface RateLimit {
@@ -78,6 +188,219 @@ export interface RateLimit {
 export interface ConnectionMetrics {
 	readonly bytesSent: number
 	readonly bytesReceived: number
+	// 从收到登录请求到后端返回首个字节的毫秒数
+	readonly ttfbMs?: number
+	// 代理链每一跳建立隧道的毫秒数（第一跳含连接代理本身）
+	readonly proxyHopMs?: readonly number[]
+	// 状态请求：从发出状态响应到收到 ping 的毫秒数；登录：连接后端耗时
+	readonly latencyMs?: number
+}
+
+export interface AuditQuery {
+	readonly username?: string
+	readonly peerIp?: string
+	readonly host?: string
+	readonly backend?: string
+	readonly sinceMs?: number
+	readonly untilMs?: number
+	readonly limit?: number
+}
+
+export interface AuditRecord {
+	readonly connId: number
+	readonly peerIp: string
+	readonly username: string | null
+	readonly host: string | null
+	readonly protocol: number | null
+	readonly backend: string | null
+	readonly proxy: string | null
+	readonly connectedAtMs: number
+	readonly closedAtMs: number
+	readonly bytesSent: number
+	readonly bytesRecv: number
+	readonly reason: string
+	readonly metadata: Record<string, unknown> | null
+}
+
+// 单个方向的限速器状态；未限速时 avgBytesPerSec/burstBytes/availableBytes 为 null
+export interface LimiterState {
+	readonly avgBytesPerSec: number | null
+	readonly burstBytes: number | null
+	// 当前无需等待即可通过的字节数
+	readonly availableBytes: number | null
+	// 是否正有数据被限速器阻塞
+	readonly throttled: boolean
+	readonly throttledMs: number
+	readonly throttleEvents: number
+}
+
+export interface RateLimitState {
+	readonly send: LimiterState
+	readonly recv: LimiterState
+}
+
+// 单个连接的详细信息；时间戳均为毫秒，尚未到达的阶段为 null 或缺省
+export interface ConnectionDetails {
+	readonly connId: number
+	readonly peerIp: string
+	readonly host: string | null
+	readonly port: number | null
+	readonly protocol: number | null
+	readonly username: string | null
+	readonly uuid?: string
+	readonly backend: string | null
+	readonly proxy: string | null
+	readonly listener?: number
+	readonly transport?: 'tcp' | 'tls' | 'websocket'
+	readonly sni?: string
+	readonly compressionThreshold?: number
+	readonly connectedAtMs: number
+	readonly loginAtMs?: number
+	readonly backendConnectedAtMs?: number
+	readonly ttfbMs?: number
+	readonly proxyHopMs?: readonly number[]
+	readonly latencyMs?: number
+	readonly tags?: Record<string, unknown>
+	readonly metadata?: Record<string, unknown>
+	readonly bytesSent: number
+	readonly bytesRecv: number
+	// 连接自身的限速器（不含全局与监听器限速）
+	readonly sendLimit: LimiterState | null
+	readonly recvLimit: LimiterState | null
+}
+
+// 连接筛选条件，未设置的条件匹配所有连接：tags 须全部具备（值不为 false/null），
+// host 为精确主机名或 "*.后缀" 通配，backend 为 "host:port"，username 不区分大小写，ip 为单个 IP 或 CIDR
+export interface ConnectionFilter {
+	readonly tags?: ReadonlyArray<string>
+	readonly host?: string
+	readonly backend?: string
+	readonly username?: string
+	readonly ip?: string
+}
+
+export interface BackendPool {
+	readonly members: string[]
+	// 最近一次连接失败的成员（排在轮询末尾，定期试探）
+	readonly unhealthy: string[]
+	// 处于慢启动爬坡期的成员
+	readonly warming: string[]
+	readonly resolvedAtMs: number
+	readonly lastUsedMs: number
+}
+
+// 主动健康检查结果
+export interface BackendHealthStatus {
+	readonly healthy: boolean
+	readonly consecutiveFailures: number
+	readonly consecutiveSuccesses: number
+	readonly lastCheckedMs: number | null
+	// 最近一次检查通过时的响应耗时
+	readonly latencyMs: number | null
+	readonly lastError: string | null
+}
+
+export interface EffectiveConfig {
+	readonly options: Record<string, unknown>
+	// 监听器 ID -> 绑定地址
+	readonly listeners: Record<number, string>
+	// setGlobalRateLimit 设置的新连接默认限速
+	readonly globalRateLimit: RateLimit
+	// setRoutes 设置的静态路由（凭据已打码）
+	readonly routes: ReadonlyArray<Record<string, unknown>>
+	// setVhosts 设置的虚拟主机
+	readonly vhosts: ReadonlyArray<Record<string, unknown>>
+}
+
+// 静态路由：host 为精确主机名、"*.后缀" 通配或 "*"（任意主机）；
+// 命中时直接在 Rust 内路由，不再调用路由回调。精确匹配优先，其次为更长的通配后缀
+export interface StaticRoute {
+	readonly host: string
+	readonly route: RouteResult
+}
+
+// 虚拟主机：host 匹配规则同 StaticRoute，在调用回调之前生效。
+// motd 直接应答 ping（version.protocol 必填），backend 直接路由登录；未设置的项仍交给 MOTD/路由回调。
+// routerFirst 为 true 时先询问路由回调，回调失败或超时才使用 backend（优先于 fallbackRoute）
+// protocolRange 为允许的 [最小, 最大] 客户端协议号，超出时以 unsupportedVersion 消息拒绝登录；
+// maintenance 时以 maintenance 消息拒绝登录（ping 照常应答）
+export interface VirtualHost {
+	readonly host: string
+	readonly motd?: Omit<MotdResult, 'cache' | 'version'> & {
+		readonly version: { readonly name: string; readonly protocol: number }
+	}
+	readonly backend?: { readonly host: string; readonly port: number }
+	readonly routerFirst?: boolean
+	readonly protocolRange?: readonly [number, number]
+	readonly maintenance?: boolean
+}
+
+export interface UpstreamProxyStatus {
+	// 密码已打码
+	readonly url: string
+	// 连续失败后冷却（见 proxyHealth 选项），期间排在池末尾
+	readonly healthy: boolean
+	readonly consecutiveFailures: number
+	readonly successes: number
+	readonly failures: number
+	readonly lastError: string | null
+}
+
+// 压测（需以 loadgen feature 编译）
+export interface LoadGenConfig {
+	// host:port
+	target: string
+	// 握手包中的主机名，默认取 target
+	host?: string
+	clients?: number
+	protocol?: number
+	usernamePrefix?: string
+	// 在此时间内均匀启动各客户端
+	rampUpMs?: number
+	durationMs?: number
+	connectTimeoutMs?: number
+	// 每个客户端登录后持续发送的填充包速率，0 表示只保持连接
+	payloadBytesPerSec?: number
+	payloadChunkBytes?: number
+}
+
+export interface LatencySummary {
+	readonly samples: number
+	readonly min: number
+	readonly avg: number
+	readonly p50: number
+	readonly p90: number
+	readonly p99: number
+	readonly max: number
+}
+
+export interface HistogramMetrics {
+	// 各桶上界（毫秒）
+	readonly boundsMs: number[]
+	// 各桶计数（非累计），最后一项为超出所有上界的次数
+	readonly counts: number[]
+	readonly count: number
+	readonly sumMs: number
+}
+
+export interface LoadGenReport {
+	readonly clients: number
+	readonly connected: number
+	// 发送登录后收到服务端任意数据的客户端数
+	readonly loggedIn: number
+	readonly failed: number
+	readonly bytesSent: number
+	readonly bytesRecv: number
+	readonly elapsedMs: number
+	readonly connectMs: LatencySummary
+	readonly firstByteMs: LatencySummary
+	readonly errors: Record<string, number>
+}
+
+export interface LoadGenStatus {
+	readonly running: boolean
+	readonly report: LoadGenReport | null
+	readonly error: string | null
 }
 
 export interface GlobalMetrics {
@@ -89,6 +412,63 @@ export interface GlobalMetrics {
 		readonly totalBytesSent: number
 		readonly totalBytesReceived: number
 	}
+	// 按标签键、标签值聚合的活动连接
+	readonly tagGroups: Record<
+		string,
+		Record<
+			string,
+			{
+				readonly connections: number
+				readonly bytesSent: number
+				readonly bytesReceived: number
+			}
+		>
+	>
+	// 启动以来按类型统计的协议错误次数
+	readonly protocolErrors: Partial<Record<ProtocolErrorKind, number>>
+	// 最近会话的首字节时间（毫秒）
+	readonly ttfb: LatencySummary
+	// 启动以来的后端连接、路由决策与会话时长分布
+	readonly histograms: {
+		readonly backendConnect: HistogramMetrics
+		readonly routingDecision: HistogramMetrics
+		readonly sessionDuration: HistogramMetrics
+	}
+	// 按监听器 ID 统计的连接与流量
+	readonly perListener: Record<number, ListenerMetrics>
+	// 启动以来按握手主机名（小写）和实际连接的后端统计的连接与流量，可用于按域名计费
+	readonly perHost: Record<string, RouteMetrics>
+	readonly perBackend: Record<string, RouteMetrics>
+	// 已使用过的上游代理（含代理链各跳与代理池），键为打码后的 URL
+	readonly upstreamProxies: Record<string, UpstreamProxyMetrics>
+}
+
+export interface UpstreamProxyMetrics {
+	readonly healthy: boolean
+	readonly successes: number
+	readonly failures: number
+	// 失败占全部连接尝试的比例（0~1）
+	readonly failureRate: number
+	readonly lastError: string | null
+	// 最近连接经该代理建立隧道的耗时（毫秒）
+	readonly connectMs: LatencySummary
+}
+
+export interface ListenerMetrics {
+	readonly accepted: number
+	readonly active: number
+	readonly bytesSent: number
+	readonly bytesReceived: number
+	// 各 SO_REUSEPORT 接受套接字接受的连接数，用于检查负载是否均衡
+	readonly acceptorAccepts: number[]
+}
+
+export interface RouteMetrics {
+	// 启动以来的连接数
+	readonly connections: number
+	readonly active: number
+	readonly bytesSent: number
+	readonly bytesReceived: number
 }
 
 // ===== 连接信息接口 =====
@@ -99,8 +479,263 @@ export interface ConnectionInfo {
 	readonly host: string
 	readonly protocol: number
 	readonly startAt: Date
+	// 通过 Connection.setTags 附加的元数据（断开事件中回传）
+	readonly tags?: Readonly<Record<string, unknown>>
+	// 路由结果中的 metadata
+	readonly metadata?: Readonly<Record<string, unknown>>
+	// 从收到登录请求到后端返回首个字节的毫秒数
+	readonly ttfbMs?: number
+	// 状态请求的 ping 往返毫秒数，或登录连接后端的耗时
+	readonly latencyMs?: number
+	// 实际连接的后端（"host:port" 或 "pool:名称"），启用备用后端时可据此判断
+	readonly backend?: string
+	// 玩家 UUID（可信转发握手、正版验证或开启 trackLoginPhase 时后端的 Login Success）
+	readonly uuid?: string
+	// 后端设置的压缩阈值（需开启 trackLoginPhase）
+	readonly compressionThreshold?: number
+	// 断开原因
+	readonly reason?: DisconnectReason
+}
+
+// 连接断开原因
+export type DisconnectReason =
+	| 'closed'
+	| 'status_done'
+	| 'protocol_error'
+	| 'proxy_protocol'
+	| 'rejected'
+	| 'routing_failed'
+	| 'backend_unreachable'
+	| 'relay_error'
+	| 'kicked'
+	| 'full'
+	| 'evicted'
+	| 'shutdown'
+	| 'auth_failed'
+	| 'idle_timeout'
+	| 'session_expired'
+	| 'transferred'
+	| 'handshake_timeout'
+	| 'quota_exceeded'
+	| 'unsupported_version'
+	| 'login_throttled'
+
+// ===== 缓存统计 =====
+// hits/misses 为路由与 MOTD 查询缓存决策的命中/未命中次数；
+// evictions 为超出 cacheMaxEntries 被淘汰（最久未访问）的条目数，expirations 为因过期被移除的条目数
+// 某用户名或 IP 的累计流量（含仍在线的连接）
+export interface Usage {
+	readonly bytesSent: number
+	readonly bytesRecv: number
+	// 已关闭与仍在线的连接数
+	readonly connections: number
+	readonly active: number
+	readonly lastSeenMs: number
+}
+
+// ===== QUIC 隧道 =====
+// 源站实例接受边缘实例 quic:// 代理的 QUIC 连接，每个玩家一条流，按流中目标连接后端
+export interface QuicTunnelConfig {
+	readonly host: string
+	readonly port: number
+	readonly certPath: string
+	readonly keyPath: string
+	// 边缘实例须在 URL 用户名中给出的令牌
+	readonly token?: string
+	// 每个边缘连接同时承载的玩家数上限，默认 1024
+	readonly maxStreams?: number
+}
+
+// ===== Bedrock =====
+// UDP 监听器，按客户端地址转发 RakNet 数据报到后端（如 Geyser）；
+// RakNet 握手不含主机名，只能按客户端所连的服务器地址（routes 的键为 ip:port 或 ip）选择后端
+export interface BedrockListenerConfig {
+	readonly host: string
+	readonly port: number
+	readonly routes?: Record<string, string>
+	// 未匹配路由的客户端及 ping 所用的后端 host:port
+	readonly defaultBackend: string
+	// 无流量超过该时长的关联被关闭，默认 30 秒
+	readonly idleTimeoutMs?: number
+	// 同时保持的关联数上限，默认 10000
+	readonly maxSessions?: number
+}
+
+export interface BedrockSession {
+	readonly listener: number
+	readonly client: string
+	readonly backend: string
+	readonly packetsToBackend: number
+	readonly bytesToBackend: number
+	readonly packetsToClient: number
+	readonly bytesToClient: number
+	readonly createdAtMs: number
+	readonly lastSeenMs: number
 }
 
+export interface CacheStats {
+	totalEntries: number
+	expiredEntries: number
+	maxEntries?: number
+	hits: number
+	misses: number
+	evictions: number
+	expirations: number
+	// 直接由缓存的拒绝决策断开、未询问路由器的连接数
+	rejections: number
+}
+
+// 缓存中生效的拒绝决策；scope 区分登录路由与 MOTD，host 仅 ip+host 粒度的条目有
+export interface BlockedEntry {
+	scope: 'route' | 'motd'
+	ip: string
+	host?: string
+	rejectReason?: string
+	expiresInMs: number
+}
+
+// ===== 用量报告 =====
+// 按 usageReportIntervalMs 周期及连接关闭时产生；seq 全局单调递增，出现空缺表示有报告丢失
+export interface UsageReport {
+	seq: number
+	connId: number
+	timestampMs: number
+	// 自上一份报告以来的增量
+	bytesSent: number
+	bytesRecv: number
+	// 累计值，可用于丢失报告后的对账
+	totalSent: number
+	totalRecv: number
+	final: boolean
+	tags?: Record<string, unknown>
+}
+
+// ===== 定时指标事件 =====
+// 按 metricsPushIntervalMs 周期推送的全局计数
+export interface MetricsEvent {
+	timestampMs: number
+	totalConn: number
+	activeConn: number
+	totalBytesSent: number
+	totalBytesRecv: number
+}
+
+// ===== 后端剔除事件 =====
+export interface BackendEvent {
+	backend: string
+	kind: 'ejected' | 'readmitted'
+	timestampMs: number
+	reason: string | null
+	ejectedForMs: number | null
+}
+
+// ===== 上游代理健康事件 =====
+export interface UpstreamProxyEvent {
+	// 密码已打码
+	proxy: string
+	kind: 'unhealthy' | 'recovered'
+	timestampMs: number
+	reason: string | null
+	cooldownMs: number | null
+}
+
+// ===== 协议错误事件 =====
+// 握手/登录解析失败时产生，可用于识别扫描器或联动封禁
+export type ProtocolErrorKind =
+	| 'proxy_protocol'
+	| 'handshake'
+	// 明显不是 Minecraft 的流量（HTTP 扫描、TLS 探测等），在握手阶段即被丢弃，仅以 debug 级别记录
+	| 'not_minecraft'
+	| 'unknown_state'
+	| 'login'
+
+export interface ProtocolErrorEvent {
+	connId: number
+	timestampMs: number
+	peerIp: string
+	listener: number | null
+	kind: ProtocolErrorKind
+	error: string
+	// 客户端发送的前若干字节（十六进制），长度由 protocolErrorSampleBytes 控制
+	sampleHex: string
+}
+
+// ===== 流量配额事件 =====
+// 连接因路由结果中的 quotaBytes / userQuotaBytes 被断开或拒绝登录
+export interface QuotaEvent {
+	connId: number
+	timestampMs: number
+	username: string
+	peerIp: string
+	scope: 'connection' | 'user'
+	quotaBytes: number
+	usedBytes: number
+}
+
+// ===== 路由审计记录 =====
+// 每次登录的路由决定（需在 routeAudit 中开启 queue）；source 为 callback / cache / schedule /
+// static / vhost / listener / transfer / fallback，被拒绝时 rejectReason 为断开原因
+export interface RouteAudit {
+	connId: number
+	timestampMs: number
+	peerIp: string
+	host: string
+	username: string
+	source: string
+	backend: string | null
+	proxy: string | null
+	rejectReason: string | null
+	metadata?: Record<string, unknown>
+}
+
+// ===== 配置文件加载 =====
+// loadConfig 每次加载（含监视到文件变更后的重新加载）的结果：变更的选项名，以及按 host 汇总的虚拟主机/静态路由增删改；
+// error 表示文件变更后未能应用（配置保持不变）
+export interface HostDiff {
+	added: string[]
+	removed: string[]
+	changed: string[]
+}
+
+export interface ConfigReload {
+	timestampMs: number
+	path: string
+	optionsChanged: string[]
+	vhosts: HostDiff
+	routes: HostDiff
+	error?: string
+}
+
+// 连接各阶段事件（需开启 lifecycleEvents），elapsedMs 为距建立连接的毫秒数
+export type LifecycleEvent = {
+	connId: number
+	timestampMs: number
+	elapsedMs: number
+} & (
+	| { type: 'connected'; peerIp: string; listener: number }
+	| {
+			type: 'handshake_parsed'
+			host: string
+			port: number
+			protocol: number
+			nextState: number
+	  }
+	| { type: 'routed'; source: string; rejected: boolean }
+	| { type: 'backend_connected'; backend: string; connectMs: number }
+	| { type: 'login_forwarded'; username: string }
+	| {
+			type: 'login_succeeded'
+			uuid?: string
+			compressionThreshold?: number
+	  }
+	| {
+			type: 'disconnected'
+			reason: DisconnectReason
+			bytesSent: number
+			bytesRecv: number
+	  }
+)
+
 // ===== 核心函数类型 =====
 export type RouterFn = (
 	context: RouteContext
@@ -114,8 +749,12 @@ export type ConnectionClosedHandler = (
 
 // ===== 特殊错误类型 =====
 export class DisconnectError extends Error {
-	constructor(public readonly reason: string) {
-		super(`Connection rejected: ${reason}`)
+	constructor(
+		public readonly reason: string,
+		// 消息模板键（见 messages 选项），优先于 reason
+		public readonly template?: string
+	) {
+		super(`Connection rejected: ${template ?? reason}`)
 	}
 }
 
@@ -123,28 +762,59 @@ export class DisconnectError extends Error {
 interface RouteRequest {
 	connId: number
 	peerIp: string
+	localIp?: string
+	localPort?: number
 	port: number
 	protocol: number
 	host: string
 	username: string
+	uuid?: string
+	sni?: string
+	clientType?: string
+	transport: 'tcp' | 'tls' | 'websocket'
+	country?: string
+	asn?: number
+	asOrg?: string
 }
 
 interface MotdRequest {
 	connId: number
 	peerIp: string
+	localIp?: string
+	localPort?: number
 	port: number
 	protocol: number
 	host: string
+	country?: string
+	asn?: number
+	asOrg?: string
 }
 
 interface DisconnectionEvent {
 	connId: number
+	tags?: Record<string, unknown>
+	metadata?: Record<string, unknown>
+	ttfbMs?: number
+	latencyMs?: number
+	backend?: string
+	uuid?: string
+	compressionThreshold?: number
+	reason: DisconnectReason
 }
 
 interface PollEvents {
 	routeRequests: RouteRequest[]
 	motdRequests: MotdRequest[]
 	disconnectionEvents: DisconnectionEvent[]
+	usageReports: UsageReport[]
+	metricsEvents: MetricsEvent[]
+	backendEvents: BackendEvent[]
+	proxyHealthEvents: UpstreamProxyEvent[]
+	protocolErrors: ProtocolErrorEvent[]
+	lifecycleEvents: LifecycleEvent[]
+	quotaEvents: QuotaEvent[]
+	routeAudits: RouteAudit[]
+	configReloads: ConfigReload[]
 }
 
 // 内部旧格式兼容
@@ -157,11 +827,304 @@ const limitSchema = z
 	})
 	.partial()
 
+const eventSinkSchema = z.discriminatedUnion('kind', [
+	// 需要以 `nats` feature 编译
+	z.object({
+		kind: z.literal('nats'),
+		url: z.string(),
+		subject: z.string()
+	}),
+	// 需要以 `kafka` feature 编译
+	z.object({
+		kind: z.literal('kafka'),
+		brokers: z.array(z.string()).min(1),
+		topic: z.string(),
+		partition: z.number().int().min(0).optional()
+	})
+])
+
+const serviceDiscoverySchema = z.discriminatedUnion('kind', [
+	z.object({
+		kind: z.literal('consul'),
+		address: z.string(),
+		service: z.string(),
+		tag: z.string().optional(),
+		datacenter: z.string().optional(),
+		token: z.string().optional()
+	}),
+	z.object({
+		kind: z.literal('etcd'),
+		endpoint: z.string(),
+		prefix: z.string()
+	})
+])
+
+// 时间段路由：窗口内使用 open，窗口外使用 closed（closed.disconnect 中的 {opensAt} 会替换为下次开放时间）
+const scheduleSchema = z.object({
+	// 精确主机名或 *.suffix 通配
+	host: z.string(),
+	// IANA 时区名（如 Asia/Shanghai），默认 UTC
+	timezone: z.string().optional(),
+	windows: z.array(
+		z.object({
+			days: z
+				.array(z.enum(['mon', 'tue', 'wed', 'thu', 'fri', 'sat', 'sun']))
+				.optional(),
+			// HH:MM，结束早于开始表示跨越午夜
+			start: z.string().regex(/^\d{2}:\d{2}$/),
+			end: z.string().regex(/^\d{2}:\d{2}$/)
+		})
+	),
+	open: z.custom<RouteResult>(),
+	closed: z.custom<RouteResult | { disconnect: string }>()
+})
+
 const geofrontOptionsSchema = z.object({
 	proxyProtocolIn: z
 		.enum(['optional', 'strict', 'none'])
 		.default('none')
-		.optional()
+		.optional(),
+	// 仅信任来自这些网段的 PROXY Protocol 头；其余连接携带的头会被剥离且不采用其中地址（strict 模式下直接断开），
+	// 并记入 geofront_proxy_protocol_untrusted_total。未设置时信任所有连接
+	proxyProtocolTrustedCidrs: z.array(z.string()).min(1).optional(),
+	eventSink: eventSinkSchema.optional(),
+	// 需要以 `redis` feature 编译；多个节点共享路由/MOTD 缓存
+	sharedCache: z
+		.object({
+			url: z.string(),
+			keyPrefix: z.string().optional(),
+			localTtlMs: z.number().int().min(0).optional()
+		})
+		.optional(),
+	// 需要以 `sqlite` feature 编译；记录已结束连接的历史
+	auditDb: z
+		.object({
+			path: z.string(),
+			retentionDays: z.number().int().min(1).optional(),
+			maxRows: z.number().int().min(1).optional(),
+			includeStatus: z.boolean().optional()
+		})
+		.optional(),
+	// 按间隔重新解析以域名指定的后端，并在所有解析结果间轮询
+	dnsRefreshMs: z.number().int().min(1000).optional(),
+	// 后端解析出多个地址（如同时有 A 与 AAAA 记录）时，按 RFC 8305 交替 IPv6/IPv4 并发尝试连接的间隔，默认 250ms，0 为逐个尝试
+	happyEyeballsDelayMs: z.number().int().min(0).optional(),
+	// 需要以 `consul`/`etcd` feature 编译；按名称维护的后端池，可在路由结果中以 pool 引用
+	pools: z.record(z.string(), serviceDiscoverySchema).optional(),
+	// 命名上游代理池（socks5:// 或 http:// URL），由路由结果的 proxyPool 引用
+	proxyPools: z
+		.record(
+			z.string(),
+			z.object({
+				proxies: z.array(z.string()).min(1),
+				// roundRobin：轮询；sticky：同一玩家 IP 固定使用同一代理；failover：按配置顺序
+				rotation: z.enum(['roundRobin', 'sticky', 'failover']).optional()
+			})
+		)
+		.optional(),
+	// 上游代理健康判定：连续失败 failureThreshold 次（默认 3，0 为不判定）后冷却 cooldownMs（默认 30000），通过 onUpstreamProxyEvent 通知
+	proxyHealth: z
+		.object({
+			failureThreshold: z.number().int().min(0).optional(),
+			cooldownMs: z.number().int().min(1000).optional()
+		})
+		.optional(),
+	// 实验性（仅 Linux，需要 CAP_BPF/CAP_NET_ADMIN）：未限速的连接通过 BPF sockmap 在内核中转发
+	sockmap: z.boolean().optional(),
+	// 允许路由结果通过 proxyProtocolSource 指定 PROXY Protocol 源地址（安全敏感，默认关闭）
+	allowProxyProtocolSource: z.boolean().optional(),
+	// 断开消息模板（可覆盖内置的 serverFull、proxyFull、maintenance、banned、backendDown、routingError、routingTimeout、
+	// quotaExceeded、unsupportedVersion、loginThrottled 等），值为支持 &/§ 颜色代码的文本或 JSON 文本组件；
+	// {host}、{username}、{conn} 会被替换（backendDown 另有 {backend}），unsupportedVersion 中的 {protocol}、{min}、{max} 也会被替换
+	messages: z
+		.record(
+			z.string(),
+			z.union([z.string(), z.record(z.string(), z.unknown()), z.array(z.unknown())])
+		)
+		.optional(),
+	// 按此间隔（以及连接关闭时）通过 onUsageReport 上报每个连接的流量增量，用于计费
+	usageReportIntervalMs: z.number().int().min(100).optional(),
+	// 按此间隔通过 onMetrics 推送全局指标，无需自行定时调用 getMetrics
+	metricsPushIntervalMs: z.number().int().min(100).optional(),
+	// 在 Rust 中按时间段决定路由，命中时不再调用路由回调
+	schedules: z.array(scheduleSchema).optional(),
+	// 慢启动：恢复或新加入后端池的成员在此时间窗口内逐步提升分配比例
+	slowStartMs: z.number().int().min(1000).optional(),
+	// 异常后端剔除：连接失败/会话中断过多的后端暂时移出后端池，通过 onBackendEvent 通知
+	outlierDetection: z
+		.object({
+			consecutiveFailures: z.number().int().min(1).optional(),
+			failureRate: z.number().gt(0).max(1).optional(),
+			minRequests: z.number().int().min(1).optional(),
+			intervalMs: z.number().int().min(1000).optional(),
+			baseEjectionMs: z.number().int().min(1000).optional(),
+			maxEjectionPercent: z.number().int().min(0).max(100).optional()
+		})
+		.optional(),
+	// 主动健康检查：定期对后端发送状态查询（或仅 TCP 连接），连续失败的后端在路由时排到最后
+	healthCheck: z
+		.object({
+			backends: z.array(z.string()).min(1),
+			kind: z.enum(['status', 'tcp']).optional(),
+			intervalMs: z.number().int().min(1000).optional(),
+			timeoutMs: z.number().int().min(100).optional(),
+			unhealthyThreshold: z.number().int().min(1).optional(),
+			healthyThreshold: z.number().int().min(1).optional()
+		})
+		.optional(),
+	// 解析上游 BungeeCord/Velocity 转发的握手（host 中以 \0 分隔的真实 IP 与 UUID）；
+	// 仅信任来自 trustedCidrs 的连接，其余连接的转发内容会被丢弃
+	bungeeForwarding: z
+		.object({
+			trustedCidrs: z.array(z.string()).min(1)
+		})
+		.optional(),
+	// 协议错误事件中保留的原始字节数（默认 64）
+	protocolErrorSampleBytes: z.number().int().min(0).max(4096).optional(),
+	// 把解析失败的握手与登录原始字节保存到 dir（按内容去重，最多 maxFiles 个文件，每个最多 maxBytes 字节），
+	// 复制到 tests/corpus 后可由 cargo test 回放；非 Minecraft 流量与 PROXY 头错误不保存
+	protocolCorpus: z
+		.object({
+			dir: z.string(),
+			maxFiles: z.number().int().min(1).optional(),
+			maxBytes: z.number().int().min(1).optional()
+		})
+		.optional(),
+	// 记录每次登录的路由决定（来源、后端、代理、拒绝原因）：path 为追加写入的 JSON Lines 文件，
+	// queue 为 true 时同时通过 onRouteAudit 回调
+	routeAudit: z
+		.object({
+			path: z.string().optional(),
+			queue: z.boolean().optional()
+		})
+		.optional(),
+	// 记录连接各阶段事件（建立、握手、路由、连接后端、转发登录、断开），通过 onLifecycleEvent 回调
+	lifecycleEvents: z.boolean().optional(),
+	// 解析后端登录阶段的数据包，记录压缩阈值与 Login Success 中的玩家 UUID（遇到加密或压缩包后停止解析）
+	trackLoginPhase: z.boolean().optional(),
+	// MaxMind GeoLite2/GeoIP2 数据库路径，为路由与 MOTD 请求附加客户端的国家与 ASN，需以 `geoip` feature 编译
+	geoipDb: z.string().optional(),
+	// 以逐包方式转发 1.20.5+ 的会话，使 transferConnection 可通过 Transfer 数据包转移玩家；
+	// 隐含 trackLoginPhase，且不再使用内核零拷贝转发
+	allowTransfer: z.boolean().optional(),
+	// 转发缓冲区大小（字节，默认 4096）；缓冲区在连接间复用，调大可提升吞吐，但每个连接占用更多内存
+	copyBufferSize: z.number().int().min(1024).max(1048576).optional(),
+	// 每个客户端 IP 的连接上限与每秒新连接（握手）上限，超出的连接在解析前直接断开；
+	// 按 TCP 对端地址计数，位于 PROXY Protocol 负载均衡之后时应谨慎设置
+	maxConnectionsPerIp: z.number().int().min(1).optional(),
+	handshakesPerIpPerSecond: z.number().int().min(1).optional(),
+	// 内存中缓存的路由/MOTD 决策条数上限，超出时淘汰最久未访问的条目（默认不限）
+	cacheMaxEntries: z.number().int().min(1).optional(),
+	// 客户端须在该时长内发送握手及随后的登录/状态请求，否则断开并计数（默认 5000）
+	handshakeTimeoutMs: z.number().int().min(100).optional(),
+	// 同时等待路由回调结果的登录数上限（默认 1024），超出的登录排队等待空位
+	maxPendingRoutes: z.number().int().min(1).optional(),
+	// 等待路由/MOTD 回调结果的超时（默认 10000）
+	decisionTimeoutMs: z.number().int().min(100).optional(),
+	// 回调超时或出错时的降级策略（不会被缓存）：fallbackRoute 代替断开，否则以 disconnectMessage 断开；fallbackMotd 代替内置的错误 MOTD
+	decisionFallback: z
+		.object({
+			disconnectMessage: z.string().optional(),
+			fallbackRoute: z.custom<RouteResult>().optional(),
+			fallbackMotd: z
+				.object({
+					version: z.object({ name: z.string(), protocol: z.number().int() }).optional(),
+					players: z
+						.object({ max: z.number().int(), online: z.number().int().optional() })
+						.optional(),
+					// 字符串或 JSON 文本组件
+					description: z.any().optional(),
+					favicon: z.string().optional()
+				})
+				.optional()
+		})
+		.optional(),
+	// 由 Rust 直接应答所有状态请求的完整状态 JSON（设置后不再调用 MOTD 回调），
+	// 字符串中的 {online}、{max}（capacity.maxPlayers，默认 20）、{protocol} 会被替换，仅含占位符的字符串替换为数字
+	defaultMotd: z.record(z.string(), z.any()).optional(),
+	// 按用户名/IP 统计流量（getUsage）的保留时长：最后一个连接关闭后超过该时长即遗忘，默认 24 小时
+	accounting: z
+		.object({ retentionMs: z.number().int().min(0).optional() })
+		.optional(),
+	// 同一用户名 / IP 在 periodMs 内最多尝试登录 attempts 次（逐步恢复），超出时直接以 loginThrottled 消息拒绝，
+	// 不经过路由回调；byUsername / byIp 默认均开启
+	loginThrottle: z
+		.object({
+			attempts: z.number().int().min(0),
+			periodMs: z.number().int().min(1),
+			byUsername: z.boolean().optional(),
+			byIp: z.boolean().optional()
+		})
+		.optional(),
+	// 蜜罐/tarpit 模式：被单 IP 限制拒绝或命中缓存拒绝的连接不立即关闭，而是在 durationMs 内每秒仅读取并丢弃
+	// bytesPerSec 字节，拖慢扫描器与机器人；这些连接不计入活跃连接，同时最多保持 maxSockets 个
+	tarpit: z
+		.object({
+			durationMs: z.number().int().min(1000).optional(),
+			bytesPerSec: z.number().int().min(1).optional(),
+			maxSockets: z.number().int().min(1).optional()
+		})
+		.optional(),
+	// tls:// 后端的连接方式：caPath 为信任的后端证书（PEM），certPath/keyPath 为客户端证书（须同时设置），
+	// serverName 为 SNI 及证书校验名，默认为后端主机；需以 `tls` feature 编译
+	backendTls: z
+		.object({
+			caPath: z.string(),
+			certPath: z.string().optional(),
+			keyPath: z.string().optional(),
+			serverName: z.string().optional()
+		})
+		.optional(),
+	// 内置 Prometheus 端点：listen（host:port）设置后随选项启动、迁移或停止；token 要求抓取方携带
+	// Authorization: Bearer <token>；tls 以 HTTPS 提供服务，clientCaPath 要求客户端证书（需 `tls` feature）。
+	// 未设置 token 或 clientCaPath 时只能绑定回环地址
+	metricsExporter: z
+		.object({
+			listen: z.string().optional(),
+			token: z.string().min(1).optional(),
+			tls: z
+				.object({
+					certPath: z.string(),
+					keyPath: z.string(),
+					clientCaPath: z.string().optional()
+				})
+				.optional()
+		})
+		.optional(),
+	// quic:// 上游代理的连接方式：caPath 为信任的源站证书（PEM），serverName 默认为 URL 主机，keepAliveMs 默认 10 秒
+	quic: z
+		.object({
+			caPath: z.string(),
+			serverName: z.string().optional(),
+			keepAliveMs: z.number().int().min(1000).optional()
+		})
+		.optional(),
+	// 客户端、后端与上游代理连接的 TCP 选项（TCP_NODELAY、keepalive、收发缓冲区大小），可在路由结果中按字段覆盖
+	tcp: z
+		.object({
+			noDelay: z.boolean().optional(),
+			keepaliveTimeMs: z.number().int().min(1000).optional(),
+			keepaliveIntervalMs: z.number().int().min(1000).optional(),
+			keepaliveProbes: z.number().int().min(1).optional(),
+			sendBufferSize: z.number().int().min(1).optional(),
+			recvBufferSize: z.number().int().min(1).optional()
+		})
+		.optional(),
+	// 连接转发阶段无任何流量超过该时长，或自登录起超过最长会话时长时断开（可在路由结果中覆盖）
+	idleTimeoutMs: z.number().int().min(1000).optional(),
+	maxSessionDurationMs: z.number().int().min(1000).optional(),
+	// 已转发玩家数上限（全局/按租户），满员时拒绝登录或按优先级踢出最老的低优先级玩家
+	capacity: z
+		.object({
+			maxPlayers: z.number().int().min(0).optional(),
+			tenantMaxPlayers: z.record(z.string(), z.number().int().min(0)).optional(),
+			evict: z.boolean().optional()
+		})
+		.optional(),
+	// 同时打开的连接数上限（含状态请求）；超出时 ping 返回 overflowMotd（占位符同 defaultMotd，未设置时为内置的“代理已满”），
+	// 登录在调用路由回调前以 proxyFull 消息拒绝，并计入 geofront_overflow_rejected_total
+	maxConnections: z.number().int().min(1).optional(),
+	overflowMotd: z.record(z.string(), z.any()).optional()
 })
 
 export type GeofrontOptions = z.infer<typeof geofrontOptionsSchema>
@@ -184,8 +1147,44 @@ export const FFISymbols = {
 		args: [FFIType.cstring, FFIType.u16, FFIType.ptr],
 		returns: FFIType.i32
 	},
+	proxy_start_listener_ws: {
+		args: [FFIType.cstring, FFIType.u16, FFIType.ptr],
+		returns: FFIType.i32
+	},
+	proxy_start_listener_with_options: {
+		args: [FFIType.cstring, FFIType.u16, FFIType.cstring, FFIType.ptr],
+		returns: FFIType.i32
+	},
+	proxy_start_listener_tls: {
+		args: [
+			FFIType.cstring,
+			FFIType.u16,
+			FFIType.cstring, // certPath
+			FFIType.cstring, // keyPath
+			FFIType.ptr
+		],
+		returns: FFIType.i32
+	},
 	proxy_stop_listener: { args: [FFIType.u64], returns: FFIType.i32 },
+	proxy_pause_listener: {
+		args: [FFIType.u64, FFIType.ptr],
+		returns: FFIType.i32
+	},
+	proxy_resume_listener: { args: [FFIType.u64], returns: FFIType.i32 },
 	proxy_disconnect: { args: [FFIType.u64], returns: FFIType.i32 },
+	proxy_start_capture: {
+		args: [FFIType.u64, FFIType.cstring, FFIType.u64],
+		returns: FFIType.i32
+	},
+	proxy_stop_capture: { args: [FFIType.u64], returns: FFIType.i32 },
+	proxy_disconnect_with_message: {
+		args: [FFIType.u64, FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_transfer_connection: {
+		args: [FFIType.u64, FFIType.cstring],
+		returns: FFIType.i32
+	},
 	proxy_set_rate_limit: {
 		args: [
 			FFIType.u64, // connId
@@ -196,16 +1195,53 @@ export const FFISymbols = {
 		],
 		returns: FFIType.i32
 	},
+	proxy_set_global_rate_limit: {
+		args: [FFIType.u64, FFIType.u64, FFIType.u64, FFIType.u64],
+		returns: FFIType.i32
+	},
+	proxy_set_listener_rate_limit: {
+		args: [
+			FFIType.u64, // listener
+			FFIType.u64,
+			FFIType.u64,
+			FFIType.u64,
+			FFIType.u64
+		],
+		returns: FFIType.i32
+	},
 	proxy_shutdown: { args: [], returns: FFIType.i32 },
 	proxy_kick_all: { args: [], returns: FFIType.u32 },
+	proxy_kick_where: { args: [FFIType.cstring], returns: FFIType.u32 },
 	proxy_get_metrics: {
 		args: [],
 		returns: FFIType.pointer
 	},
+	proxy_get_metrics_delta: {
+		args: [FFIType.u64],
+		returns: FFIType.pointer
+	},
 	proxy_get_connection_metrics: {
 		args: [FFIType.u64],
 		returns: FFIType.pointer
 	},
+	proxy_list_connections: {
+		args: [FFIType.cstring],
+		returns: FFIType.pointer
+	},
+	proxy_set_rate_limit_where: {
+		args: [
+			FFIType.cstring,
+			FFIType.u64,
+			FFIType.u64,
+			FFIType.u64,
+			FFIType.u64
+		],
+		returns: FFIType.u32
+	},
+	proxy_get_connection_info: {
+		args: [FFIType.u64],
+		returns: FFIType.pointer
+	},
 	proxy_free_string: {
 		args: [FFIType.ptr],
 		returns: FFIType.void
@@ -221,12 +1257,148 @@ export const FFISymbols = {
 	proxy_get_cache_stats: {
 		args: [],
 		returns: FFIType.pointer
+	},
+	proxy_get_usage: {
+		args: [FFIType.u32],
+		returns: FFIType.pointer
+	},
+	proxy_start_quic_tunnel: {
+		args: [FFIType.cstring, FFIType.u16, FFIType.cstring, FFIType.ptr],
+		returns: FFIType.i32
+	},
+	proxy_start_bedrock_listener: {
+		args: [FFIType.cstring, FFIType.u16, FFIType.cstring, FFIType.ptr],
+		returns: FFIType.i32
+	},
+	proxy_get_bedrock_sessions: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_cache_clear_all: {
+		args: [],
+		returns: FFIType.i32
+	},
+	proxy_cache_clear: {
+		args: [FFIType.cstring, FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_cache_stats: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_cache_blocked: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_query_audit: {
+		args: [FFIType.cstring],
+		returns: FFIType.pointer
+	},
+	proxy_get_backend_pools: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_set_connection_tag: {
+		args: [FFIType.u64, FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_set_log_fields: {
+		args: [FFIType.u64, FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_get_rate_limit_state: {
+		args: [FFIType.u64],
+		returns: FFIType.pointer
+	},
+	proxy_get_proxy_pools: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_get_backend_health: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_get_options: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_set_routes: {
+		args: [FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_set_vhosts: {
+		args: [FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_load_config: {
+		args: [FFIType.cstring, FFIType.u64],
+		returns: FFIType.i32
+	},
+	proxy_init_runtime: {
+		args: [FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_loadgen_start: {
+		args: [FFIType.cstring],
+		returns: FFIType.i32
+	},
+	proxy_loadgen_status: {
+		args: [],
+		returns: FFIType.pointer
+	},
+	proxy_loadgen_stop: {
+		args: [],
+		returns: FFIType.i32
+	},
+	proxy_start_metrics_exporter: {
+		args: [FFIType.cstring, FFIType.u16],
+		returns: FFIType.i32
+	},
+	proxy_stop_metrics_exporter: {
+		args: [],
+		returns: FFIType.i32
+	},
+	proxy_get_metrics_buf: {
+		args: [FFIType.u32, FFIType.bool, FFIType.ptr],
+		returns: FFIType.pointer
+	},
+	proxy_poll_events_buf: {
+		args: [FFIType.u32, FFIType.ptr],
+		returns: FFIType.pointer
+	},
+	proxy_last_error_message: {
+		args: [],
+		returns: FFIType.pointer
 	}
 }
 
+// *_buf 调用的编码格式
+const WIRE_JSON = 0
+
+const utf8Decoder = new TextDecoder()
+
+// 解析 *_buf 调用返回的 JSON；字节由 Rust 持有，下次调用前有效，无需释放
+function readWireJson(ptr: Pointer, outLen: BigUint64Array): any {
+	const bytes = toArrayBuffer(ptr, 0, Number(outLen[0]))
+	return JSON.parse(utf8Decoder.decode(bytes))
+}
+
 // FFI 符号实例
 let symbols: ConvertFns<typeof FFISymbols>
 
+// 构造 FFI 调用失败的错误，附带 Rust 侧记录的详细原因
+function ffiError(action: string, code: number): Error {
+	const ptr = symbols.proxy_last_error_message() as Pointer | null
+	if (!ptr) {
+		return new Error(`${action}: code ${code}`)
+	}
+	try {
+		return new Error(`${action}: ${new CString(ptr)} (code ${code})`)
+	} finally {
+		symbols.proxy_free_string(ptr)
+	}
+}
+
 // ===== 连接类 =====
 export class Connection {
 	readonly id: number
@@ -235,6 +1407,7 @@ export class Connection {
 	readonly host: string
 	readonly protocol: number
 	readonly startAt: Date
+	readonly metadata?: Readonly<Record<string, unknown>>
 
 	private proxy: GeofrontProxy
 	private lastKnownMetrics: ConnectionMetrics = {
@@ -249,6 +1422,7 @@ export class Connection {
 		this.host = info.host
 		this.protocol = info.protocol
 		this.startAt = info.startAt
+		this.metadata = info.metadata
 		this.proxy = proxy
 	}
 
@@ -276,8 +1450,44 @@ export class Connection {
 		)
 	}
 
+	// 限速器的配置与实时可用额度，用于判断玩家卡顿是否由代理限速造成
+	getRateLimitState(): RateLimitState | null {
+		return this.proxy.getRateLimitState(this.id)
+	}
+
+	// 抓包：把转发的字节流（转发前、已解密）以合成 TCP 头写入 pcap 文件，文件达到 maxBytes 前或连接关闭时自动停止
+	startCapture(path: string, maxBytes: number): void {
+		this.proxy.startCapture(this.id, path, maxBytes)
+	}
+
+	stopCapture(): boolean {
+		return this.proxy.stopCapture(this.id)
+	}
+
+	// 连接的详细信息（握手、后端、各阶段时间戳、限速与流量），连接已关闭时为 null
+	getDetails(): ConnectionDetails | null {
+		return this.proxy.getConnectionDetails(this.id)
+	}
+
+	// 不断开地将玩家转移到另一后端，返回是否已受理（见 GeofrontProxy.transferConnection）
+	transfer(route: RouteResult): boolean {
+		return this.proxy.transferConnection(this.id, route)
+	}
+
+	// 带 reason 时，若玩家仍在登录阶段会看到该断开原因（支持 & 颜色代码或 JSON 文本组件）；
+	// 已进入转发阶段的连接直接关闭
 	disconnect(reason?: string): void {
-		this.proxy.disconnect(this.id)
+		this.proxy.disconnect(this.id, reason)
+	}
+
+	// 合并标签；值为 null 的键会被删除
+	setTags(tags: Record<string, unknown>): boolean {
+		return this.proxy.setConnectionTags(this.id, tags)
+	}
+
+	// 替换该连接日志行附带的标签；传 {} 清除
+	setLogFields(fields: Record<string, unknown>): boolean {
+		return this.proxy.setLogFields(this.id, fields)
 	}
 
 	isActive(): boolean {
@@ -324,6 +1534,48 @@ export class Listener {
 	isListening(): boolean {
 		return this.proxy.getListeners().some(l => l.id === this.id)
 	}
+
+	// 维护模式：保留端口与已有连接。不传参数时暂停接受新连接；
+	// 传入 motd 或 message 时继续接受，状态请求返回该 MOTD（占位符同 defaultMotd），登录以 message（默认 maintenance 模板）拒绝
+	pause(options?: { motd?: Record<string, any>; message?: string }): void {
+		this.proxy.pauseListener(this.id, options)
+	}
+
+	resume(): void {
+		this.proxy.resumeListener(this.id)
+	}
+
+	// 该监听器所有连接合计的带宽上限，传 null 取消
+	setBandwidthLimit(limit: RateLimit | null): void {
+		this.proxy.setListenerBandwidthLimit(this.id, limit)
+	}
+}
+
+// 转换按主机名/后端聚合的统计
+function toRouteMetrics(raw: Record<string, any> | undefined): Record<string, RouteMetrics> {
+	return Object.fromEntries(
+		Object.entries(raw || {}).map(([key, bucket]) => [
+			key,
+			{
+				connections: bucket.connections,
+				active: bucket.active,
+				bytesSent: bucket.bytes_sent,
+				bytesReceived: bucket.bytes_recv
+			}
+		])
+	)
+}
+
+// 转换为 FFI 限速参数（upload 对应 send），0 表示不限速
+function rateLimitArgs(limit: RateLimit | null): [bigint, bigint, bigint, bigint] {
+	const sendAvg = limit?.upload?.average ?? 0
+	const recvAvg = limit?.download?.average ?? 0
+	return [
+		BigInt(sendAvg),
+		BigInt(limit?.upload?.burst ?? sendAvg),
+		BigInt(recvAvg),
+		BigInt(limit?.download?.burst ?? recvAvg)
+	]
 }
 
 // ===== 事件处理器配置 =====
@@ -332,6 +1584,19 @@ export interface EventHandlers {
 	onConnectionClosed?: ConnectionClosedHandler
 	onListenerStarted?: (listener: Listener) => void
 	onListenerStopped?: (listener: Listener) => void
+	onUsageReport?: (report: UsageReport) => void
+	onMetrics?: (metrics: MetricsEvent) => void
+	onBackendEvent?: (event: BackendEvent) => void
+	// 上游代理被判定为不健康或恢复
+	onUpstreamProxyEvent?: (event: UpstreamProxyEvent) => void
+	onProtocolError?: (event: ProtocolErrorEvent) => void
+	// 连接各阶段事件，需在选项中开启 lifecycleEvents
+	onLifecycleEvent?: (event: LifecycleEvent) => void
+	onQuotaExceeded?: (event: QuotaEvent) => void
+	// 路由审计记录，需在选项 routeAudit 中开启 queue
+	onRouteAudit?: (audit: RouteAudit) => void
+	// 配置文件加载或重新加载（见 loadConfig）
+	onConfigReload?: (reload: ConfigReload) => void
 	onError?: (error: Error) => void
 }
 
@@ -340,6 +1605,8 @@ export class GeofrontProxy {
 	private connections = new Map<number, Connection>()
 	private listeners = new Map<number, Listener>()
 	private connectionMetricsCache = new Map<number, ConnectionMetrics>()
+	// *_buf 调用写入的字节数
+	private wireLen = new BigUint64Array(1)
 
 	private routerCallback?: RouterFn
 	private motdCallback?: MotdFn
@@ -400,7 +1667,22 @@ export class GeofrontProxy {
 		}
 	}
 
-	// ===== 配置方法 =====
+	// ===== 配置方法 =====
+	// 配置 Tokio 运行时（工作线程数、阻塞线程上限、线程名前缀），须在启动第一个监听器前调用
+	initRuntime(options: {
+		workerThreads?: number
+		maxBlockingThreads?: number
+		threadName?: string
+	}): this {
+		const code = symbols.proxy_init_runtime(
+			Buffer.from(JSON.stringify(options) + '\0')
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to initialize runtime', code)
+		}
+		return this
+	}
+
 	setRouter(router: RouterFn): this {
 		this.routerCallback = router
 		return this
@@ -411,6 +1693,44 @@ export class GeofrontProxy {
 		return this
 	}
 
+	// 替换全部静态路由；传入空数组即清除
+	setRoutes(routes: ReadonlyArray<StaticRoute>): this {
+		const json = JSON.stringify(
+			routes.map(({ host, route }) => ({
+				host,
+				route: this.convertRouteResult(route)
+			}))
+		)
+		const code = symbols.proxy_set_routes(Buffer.from(json + '\0'))
+		if (code !== 0) {
+			throw ffiError('Failed to set routes', code)
+		}
+		return this
+	}
+
+	// 替换全部虚拟主机；传入空数组即清除
+	setVhosts(vhosts: ReadonlyArray<VirtualHost>): this {
+		const json = JSON.stringify(vhosts)
+		const code = symbols.proxy_set_vhosts(Buffer.from(json + '\0'))
+		if (code !== 0) {
+			throw ffiError('Failed to set virtual hosts', code)
+		}
+		return this
+	}
+
+	// 从 JSON 文件加载 options / vhosts / routes（缺省的部分保持不变，解析失败则不做任何更改）；
+	// watchIntervalMs 大于 0 时按该间隔检查文件，变更后自动重新加载，结果通过 onConfigReload 通知
+	loadConfig(path: string, watchIntervalMs: number = 0): this {
+		const code = symbols.proxy_load_config(
+			Buffer.from(path + '\0'),
+			BigInt(watchIntervalMs)
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to load config', code)
+		}
+		return this
+	}
+
 	setGlobalRateLimit(limit: RateLimit): this {
 		this.globalLimit = limit
 
@@ -422,6 +1742,23 @@ export class GeofrontProxy {
 		return this
 	}
 
+	// 所有连接合计的带宽上限（与按连接生效的 setGlobalRateLimit 不同），传 null 取消
+	setBandwidthLimit(limit: RateLimit | null): this {
+		const [sendAvg, sendBurst, recvAvg, recvBurst] = rateLimitArgs(limit)
+		symbols.proxy_set_global_rate_limit(sendAvg, sendBurst, recvAvg, recvBurst)
+		return this
+	}
+
+	setListenerBandwidthLimit(listenerId: number, limit: RateLimit | null): void {
+		const code = symbols.proxy_set_listener_rate_limit(
+			BigInt(listenerId),
+			...rateLimitArgs(limit)
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to set listener bandwidth limit', code)
+		}
+	}
+
 	setEventHandlers(handlers: EventHandlers): this {
 		this.eventHandlers = handlers
 		return this
@@ -429,21 +1766,30 @@ export class GeofrontProxy {
 
 	// ===== 监听器管理 =====
 	async listen(config: ProxyConfig): Promise<Listener> {
-		// 转换 proxyProtocol 格式
-		const options: GeofrontOptions = {
-			proxyProtocolIn: config.proxyProtocol ?? 'none'
+		// 监听器级设置，未设置的项沿用全局选项
+		const listenerOptions = {
+			proxyProtocolIn: config.proxyProtocol,
+			defaultRoute: config.defaultRoute
+				? this.convertRouteResult(config.defaultRoute)
+				: undefined,
+			maxConnections: config.maxConnections,
+			maxConnectionsPerIp: config.maxConnectionsPerIp,
+			handshakesPerIpPerSecond: config.handshakesPerIpPerSecond,
+			tls: config.tls,
+			websocket: config.websocket ?? false,
+			acceptors: config.acceptors
 		}
-		this.setOptions(options)
 
 		const buf = new ArrayBuffer(8)
-		const code = symbols.proxy_start_listener(
+		const code = symbols.proxy_start_listener_with_options(
 			Buffer.from(config.host + '\0'),
 			config.port,
+			Buffer.from(JSON.stringify(listenerOptions) + '\0'),
 			buf as any
 		)
 
 		if (code !== 0) {
-			throw new Error(`Failed to start listener: code ${code}`)
+			throw ffiError('Failed to start listener', code)
 		}
 
 		const listenerId = Number(new DataView(buf).getBigUint64(0, true))
@@ -459,6 +1805,53 @@ export class GeofrontProxy {
 		return listener
 	}
 
+	// 启动 QUIC 隧道监听器（需以 `quic` feature 编译），返回其 ID，以 stopListener 停止
+	listenQuicTunnel(config: QuicTunnelConfig): number {
+		const { host, port, ...tunnelConfig } = config
+		const buf = new ArrayBuffer(8)
+		const code = symbols.proxy_start_quic_tunnel(
+			Buffer.from(host + '\0'),
+			port,
+			Buffer.from(JSON.stringify(tunnelConfig) + '\0'),
+			buf as any
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to start QUIC tunnel', code)
+		}
+		return Number(new DataView(buf).getBigUint64(0, true))
+	}
+
+	// 启动 Bedrock 监听器，返回其 ID，以 stopListener 停止
+	listenBedrock(config: BedrockListenerConfig): number {
+		const { host, port, ...bedrockConfig } = config
+		const buf = new ArrayBuffer(8)
+		const code = symbols.proxy_start_bedrock_listener(
+			Buffer.from(host + '\0'),
+			port,
+			Buffer.from(JSON.stringify(bedrockConfig) + '\0'),
+			buf as any
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to start Bedrock listener', code)
+		}
+		return Number(new DataView(buf).getBigUint64(0, true))
+	}
+
+	getBedrockSessions(): BedrockSession[] {
+		let sessionsPtr: Pointer | null = null
+		try {
+			sessionsPtr = symbols.proxy_get_bedrock_sessions() as Pointer
+			if (sessionsPtr === 0) {
+				return []
+			}
+			return JSON.parse(new CString(sessionsPtr).toString())
+		} finally {
+			if (sessionsPtr) {
+				symbols.proxy_free_string(sessionsPtr)
+			}
+		}
+	}
+
 	getListeners(): ReadonlyArray<Listener> {
 		return Array.from(this.listeners.values())
 	}
@@ -522,23 +1915,23 @@ export class GeofrontProxy {
 			if (metricsPtr === 0) {
 				return {
 					connections: { total: 0, active: 0 },
-					traffic: { totalBytesSent: 0, totalBytesReceived: 0 }
+					traffic: { totalBytesSent: 0, totalBytesReceived: 0 },
+					tagGroups: {},
+					protocolErrors: {},
+					ttfb: { samples: 0, min: 0, avg: 0, p50: 0, p90: 0, p99: 0, max: 0 },
+					histograms: {
+						backendConnect: { boundsMs: [], counts: [], count: 0, sumMs: 0 },
+						routingDecision: { boundsMs: [], counts: [], count: 0, sumMs: 0 },
+						sessionDuration: { boundsMs: [], counts: [], count: 0, sumMs: 0 }
+					},
+					perListener: {},
+					perHost: {},
+					perBackend: {},
+					upstreamProxies: {}
 				}
 			}
 			const metricsJson = new CString(metricsPtr)
-			const rawMetrics = JSON.parse(metricsJson.toString())
-
-			// 转换为新格式
-			return {
-				connections: {
-					total: rawMetrics.total_conn,
-					active: rawMetrics.active_conn
-				},
-				traffic: {
-					totalBytesSent: rawMetrics.total_bytes_sent,
-					totalBytesReceived: rawMetrics.total_bytes_recv
-				}
-			}
+			return this.toGlobalMetrics(JSON.parse(metricsJson.toString()))
 		} finally {
 			if (metricsPtr) {
 				symbols.proxy_free_string(metricsPtr)
@@ -546,6 +1939,71 @@ export class GeofrontProxy {
 		}
 	}
 
+	// 转换为新格式
+	private toGlobalMetrics(rawMetrics: any): GlobalMetrics {
+		return {
+			connections: {
+				total: rawMetrics.total_conn,
+				active: rawMetrics.active_conn
+			},
+			traffic: {
+				totalBytesSent: rawMetrics.total_bytes_sent,
+				totalBytesReceived: rawMetrics.total_bytes_recv
+			},
+			tagGroups: Object.fromEntries(
+				Object.entries(rawMetrics.tag_groups || {}).map(
+					([key, values]: [string, any]) => [
+						key,
+						Object.fromEntries(
+							Object.entries(values).map(([value, group]: [string, any]) => [
+								value,
+								{
+									connections: group.connections,
+									bytesSent: group.bytes_sent,
+									bytesReceived: group.bytes_recv
+								}
+							])
+						)
+					]
+				)
+			),
+			protocolErrors: rawMetrics.protocol_errors || {},
+			ttfb: rawMetrics.ttfb,
+			histograms: rawMetrics.histograms,
+			perListener: Object.fromEntries(
+				Object.entries(rawMetrics.per_listener || {}).map(
+					([id, listener]: [string, any]) => [
+						Number(id),
+						{
+							accepted: listener.accepted,
+							active: listener.active,
+							bytesSent: listener.bytes_sent,
+							bytesReceived: listener.bytes_recv,
+							acceptorAccepts: listener.acceptor_accepts ?? []
+						}
+					]
+				)
+			),
+			perHost: toRouteMetrics(rawMetrics.per_host),
+			perBackend: toRouteMetrics(rawMetrics.per_backend),
+			upstreamProxies: Object.fromEntries(
+				Object.entries(rawMetrics.upstream_proxies || {}).map(
+					([url, proxy]: [string, any]) => [
+						url,
+						{
+							healthy: proxy.healthy,
+							successes: proxy.successes,
+							failures: proxy.failures,
+							failureRate: proxy.failure_rate,
+							lastError: proxy.last_error,
+							connectMs: proxy.connect_ms
+						}
+					]
+				)
+			)
+		}
+	}
+
 	getConnectionCount(): number {
 		return this.connections.size
 	}
@@ -593,19 +2051,52 @@ export class GeofrontProxy {
 		symbols.proxy_cleanup_cache()
 	}
 
-	getCacheStats(): { totalEntries: number; expiredEntries: number } {
+	// 清空全部路由/MOTD 缓存（含 Redis 共享层）
+	clearCache(): void {
+		symbols.proxy_cache_clear_all()
+	}
+
+	// 清除某个 IP 的缓存：IP 粒度条目及指定 host 的条目；未指定 host 时清除该 IP 的全部条目
+	clearCachedDecision(ip: string, host?: string): void {
+		symbols.proxy_cache_clear(
+			Buffer.from(ip + '\0'),
+			host === undefined ? null : Buffer.from(host + '\0')
+		)
+	}
+
+	// 当前缓存中生效的拒绝决策
+	getBlockedEntries(): BlockedEntry[] {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_cache_blocked() as Pointer
+			if (resultPtr === 0) {
+				return []
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	getCacheStats(): CacheStats {
 		let statsPtr: Pointer | null = null
 		try {
-			statsPtr = symbols.proxy_get_cache_stats() as Pointer
+			statsPtr = symbols.proxy_cache_stats() as Pointer
 			if (statsPtr === 0) {
-				return { totalEntries: 0, expiredEntries: 0 }
+				return {
+					totalEntries: 0,
+					expiredEntries: 0,
+					hits: 0,
+					misses: 0,
+					evictions: 0,
+					expirations: 0,
+					rejections: 0
+				}
 			}
 			const statsJson = new CString(statsPtr)
-			const stats = JSON.parse(statsJson.toString())
-			return {
-				totalEntries: stats.total_entries || 0,
-				expiredEntries: stats.expired_entries || 0
-			}
+			return JSON.parse(statsJson.toString())
 		} finally {
 			if (statsPtr) {
 				symbols.proxy_free_string(statsPtr)
@@ -613,6 +2104,159 @@ export class GeofrontProxy {
 		}
 	}
 
+	// 按用户名或 IP 汇总的流量，供宿主实现配额
+	getUsage(by: 'username' | 'ip'): Record<string, Usage> {
+		let usagePtr: Pointer | null = null
+		try {
+			usagePtr = symbols.proxy_get_usage(by === 'username' ? 0 : 1) as Pointer
+			if (usagePtr === 0) {
+				return {}
+			}
+			return JSON.parse(new CString(usagePtr).toString())
+		} finally {
+			if (usagePtr) {
+				symbols.proxy_free_string(usagePtr)
+			}
+		}
+	}
+
+	// ===== 审计历史 =====
+	queryAudit(filter: AuditQuery = {}): AuditRecord[] {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_query_audit(
+				Buffer.from(JSON.stringify(filter) + '\0')
+			) as Pointer
+			if (resultPtr === 0) {
+				return []
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// ===== 后端池 =====
+	getBackendPools(): Record<string, BackendPool> {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_backend_pools() as Pointer
+			if (resultPtr === 0) {
+				return {}
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// 返回 Rust 侧实际生效的配置（含默认值，凭据已打码）、运行中的监听器与默认限速，便于排查
+	getEffectiveConfig(): EffectiveConfig {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_options() as Pointer
+			if (resultPtr === 0) {
+				throw new Error('Failed to read effective config')
+			}
+			const raw = JSON.parse(new CString(resultPtr).toString())
+			return {
+				options: raw.options,
+				listeners: Object.fromEntries(
+					Object.entries(raw.listeners as Record<string, string>).map(
+						([id, addr]) => [Number(id), addr]
+					)
+				),
+				globalRateLimit: { ...this.globalLimit },
+				routes: raw.routes ?? [],
+				vhosts: raw.vhosts ?? []
+			}
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	getProxyPools(): Record<string, UpstreamProxyStatus[]> {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_proxy_pools() as Pointer
+			if (resultPtr === 0) {
+				return {}
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// 主动健康检查结果，键为 healthCheck.backends 中的 "host:port"
+	getBackendHealth(): Record<string, BackendHealthStatus> {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_backend_health() as Pointer
+			if (resultPtr === 0) {
+				return {}
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// ===== 压测 =====
+	// 在后台启动一轮压测，同一时间只能有一轮
+	startLoadGen(config: LoadGenConfig): boolean {
+		return (
+			symbols.proxy_loadgen_start(
+				Buffer.from(JSON.stringify(config) + '\0')
+			) === 0
+		)
+	}
+
+	getLoadGenStatus(): LoadGenStatus {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_loadgen_status() as Pointer
+			if (resultPtr === 0) {
+				throw new Error('Failed to read load generator status')
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	stopLoadGen(): boolean {
+		return symbols.proxy_loadgen_stop() === 0
+	}
+
+	// ===== Prometheus =====
+	// 在 http://host:port/metrics 以 Prometheus 文本格式暴露指标，已运行时会替换
+	startMetricsExporter(host: string, port: number): void {
+		const code = symbols.proxy_start_metrics_exporter(
+			Buffer.from(host + '\0'),
+			port
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to start metrics exporter', code)
+		}
+	}
+
+	stopMetricsExporter(): boolean {
+		return symbols.proxy_stop_metrics_exporter() === 0
+	}
+
 	// ===== 内部方法 =====
 	getCachedConnectionMetrics(connectionId: number): ConnectionMetrics {
 		return (
@@ -632,8 +2276,57 @@ export class GeofrontProxy {
 		this.listeners.delete(listenerId)
 	}
 
-	disconnect(connectionId: number): void {
-		symbols.proxy_disconnect(BigInt(connectionId))
+	pauseListener(
+		listenerId: number,
+		options?: { motd?: Record<string, any>; message?: string }
+	): void {
+		const code = symbols.proxy_pause_listener(
+			BigInt(listenerId),
+			options ? Buffer.from(JSON.stringify(options) + '\0') : null
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to pause listener', code)
+		}
+	}
+
+	resumeListener(listenerId: number): void {
+		symbols.proxy_resume_listener(BigInt(listenerId))
+	}
+
+	disconnect(connectionId: number, reason?: string): void {
+		if (reason === undefined) {
+			symbols.proxy_disconnect(BigInt(connectionId))
+		} else {
+			symbols.proxy_disconnect_with_message(
+				BigInt(connectionId),
+				Buffer.from(reason + '\0')
+			)
+		}
+	}
+
+	startCapture(connectionId: number, path: string, maxBytes: number): void {
+		const code = symbols.proxy_start_capture(
+			BigInt(connectionId),
+			Buffer.from(path + '\0'),
+			BigInt(maxBytes)
+		)
+		if (code !== 0) {
+			throw ffiError('Failed to start capture', code)
+		}
+	}
+
+	stopCapture(connectionId: number): boolean {
+		return symbols.proxy_stop_capture(BigInt(connectionId)) === 0
+	}
+
+	// 仍在等待路由的连接直接改用新后端；已在转发的 1.20.5+ 连接（需开启 allowTransfer）
+	// 会收到 Transfer 数据包并重连至本代理，随后被路由到新后端
+	transferConnection(connectionId: number, route: RouteResult): boolean {
+		const code = symbols.proxy_transfer_connection(
+			BigInt(connectionId),
+			Buffer.from(JSON.stringify(this.convertRouteResult(route)) + '\0')
+		)
+		return code === 0
 	}
 
 	setRateLimit(
@@ -652,24 +2345,162 @@ export class GeofrontProxy {
 		)
 	}
 
+	getRateLimitState(connectionId: number): RateLimitState | null {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_rate_limit_state(
+				BigInt(connectionId)
+			) as Pointer
+			if (resultPtr === 0) {
+				return null
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	getConnectionDetails(connectionId: number): ConnectionDetails | null {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_get_connection_info(
+				BigInt(connectionId)
+			) as Pointer
+			if (resultPtr === 0) {
+				return null
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// 按条件列出当前连接（最早建立的在前）
+	listConnections(filter: ConnectionFilter = {}): ConnectionDetails[] {
+		let resultPtr: Pointer | null = null
+		try {
+			resultPtr = symbols.proxy_list_connections(
+				Buffer.from(JSON.stringify(filter) + '\0')
+			) as Pointer
+			if (resultPtr === 0) {
+				throw ffiError('Failed to list connections', -1)
+			}
+			return JSON.parse(new CString(resultPtr).toString())
+		} finally {
+			if (resultPtr) {
+				symbols.proxy_free_string(resultPtr)
+			}
+		}
+	}
+
+	// 为所有符合条件的连接设置限速，返回更改的连接数
+	setRateLimitWhere(filter: ConnectionFilter, limit: RateLimit): number {
+		return symbols.proxy_set_rate_limit_where(
+			Buffer.from(JSON.stringify(filter) + '\0'),
+			BigInt(limit.upload?.average ?? 0),
+			BigInt(limit.upload?.burst ?? limit.upload?.average ?? 0),
+			BigInt(limit.download?.average ?? 0),
+			BigInt(limit.download?.burst ?? limit.download?.average ?? 0)
+		) as number
+	}
+
+	// 断开所有符合条件的连接（如排空某个后端以便维护），返回断开的连接数；
+	// 每个连接都会产生断开事件。指定 reason 时向仍在登录阶段的客户端发送该消息。
+	// 空过滤条件会被拒绝并返回 0，断开所有连接请用 disconnectAll
+	disconnectWhere(filter: ConnectionFilter, reason?: string): number {
+		const unfiltered =
+			!filter.tags?.length &&
+			filter.host === undefined &&
+			filter.backend === undefined &&
+			filter.username === undefined &&
+			filter.ip === undefined
+		if (unfiltered) {
+			return 0
+		}
+		if (reason === undefined) {
+			return symbols.proxy_kick_where(
+				Buffer.from(JSON.stringify(filter) + '\0')
+			) as number
+		}
+		let count = 0
+		for (const details of this.listConnections(filter)) {
+			const connection = this.connections.get(details.connId)
+			if (connection) {
+				connection.disconnect(reason)
+				count++
+			}
+		}
+		return count
+	}
+
+	setConnectionTags(connectionId: number, tags: Record<string, unknown>): boolean {
+		return (
+			symbols.proxy_set_connection_tag(
+				BigInt(connectionId),
+				Buffer.from(JSON.stringify(tags) + '\0')
+			) === 0
+		)
+	}
+
+	setLogFields(connectionId: number, fields: Record<string, unknown>): boolean {
+		return (
+			symbols.proxy_set_log_fields(
+				BigInt(connectionId),
+				Buffer.from(JSON.stringify(fields) + '\0')
+			) === 0
+		)
+	}
+
 	setOptions(options: GeofrontOptions): number {
 		const validatedOptions = geofrontOptionsSchema.parse(options)
-		const jsonOptions = JSON.stringify(validatedOptions)
+		const jsonOptions = JSON.stringify({
+			...validatedOptions,
+			schedules: validatedOptions.schedules?.map(schedule => ({
+				...schedule,
+				open: this.convertRouteResult(schedule.open),
+				closed: this.convertRouteResult(schedule.closed as RouteResult)
+			})),
+			decisionFallback: validatedOptions.decisionFallback && {
+				...validatedOptions.decisionFallback,
+				fallbackRoute: validatedOptions.decisionFallback.fallbackRoute
+					? this.convertRouteResult(validatedOptions.decisionFallback.fallbackRoute)
+					: undefined
+			}
+		})
 		return symbols.proxy_set_options(Buffer.from(jsonOptions + '\0')) as number
 	}
 
 	updateMetrics(): void {
 		try {
-			this.metrics = this.getMetrics()
+			// 只取上次以来变化的连接，连接较多时开销更低
+			const ptr = symbols.proxy_get_metrics_buf(
+				WIRE_JSON,
+				true,
+				this.wireLen
+			) as Pointer
+			if (!ptr) return
+			const delta = readWireJson(ptr, this.wireLen)
+			this.metrics = this.toGlobalMetrics(delta)
 			// 更新连接 metrics 缓存
-			this.connectionMetricsCache.clear()
-			const rawMetrics = this.getRawMetrics()
+			if (delta.full) {
+				this.connectionMetricsCache.clear()
+			}
+			for (const connId of delta.closed) {
+				this.connectionMetricsCache.delete(connId)
+			}
 			for (const [connId, connMetrics] of Object.entries(
-				rawMetrics.connections || {}
+				delta.connections || {}
 			)) {
 				this.connectionMetricsCache.set(Number(connId), {
 					bytesSent: (connMetrics as any).bytes_sent || 0,
-					bytesReceived: (connMetrics as any).bytes_recv || 0
+					bytesReceived: (connMetrics as any).bytes_recv || 0,
+					ttfbMs: (connMetrics as any).ttfb_ms ?? undefined,
+					proxyHopMs: (connMetrics as any).proxy_hop_ms ?? undefined,
+					latencyMs: (connMetrics as any).latency_ms ?? undefined
 				})
 			}
 		} catch (error) {
@@ -681,22 +2512,6 @@ export class GeofrontProxy {
 		}
 	}
 
-	private getRawMetrics(): any {
-		let metricsPtr: Pointer | null = null
-		try {
-			metricsPtr = symbols.proxy_get_metrics() as Pointer
-			if (metricsPtr === 0) {
-				return { connections: {} }
-			}
-			const metricsJson = new CString(metricsPtr)
-			return JSON.parse(metricsJson.toString())
-		} finally {
-			if (metricsPtr) {
-				symbols.proxy_free_string(metricsPtr)
-			}
-		}
-	}
-
 	private enablePolling(intervalMs: number = 10): void {
 		if (this.pollingEnabled) {
 			return
@@ -748,14 +2563,14 @@ export class GeofrontProxy {
 	}
 
 	private pollBatchEvents(): void {
-		let eventsPtr: Pointer | null = null
 		try {
-			eventsPtr = symbols.proxy_poll_events() as Pointer
-			if (eventsPtr === 0) return
+			const eventsPtr = symbols.proxy_poll_events_buf(
+				WIRE_JSON,
+				this.wireLen
+			) as Pointer
+			if (!eventsPtr) return
 
-			const eventsJson = new CString(eventsPtr).toString()
-			if (!eventsJson) return
-			const events: PollEvents = JSON.parse(eventsJson)
+			const events: PollEvents = readWireJson(eventsPtr, this.wireLen)
 
 			// Process route requests
 			for (const request of events.routeRequests) {
@@ -771,16 +2586,69 @@ export class GeofrontProxy {
 			for (const event of events.disconnectionEvents) {
 				this.handleDisconnectionEvent(event)
 			}
+
+			// Process usage reports
+			if (this.eventHandlers.onUsageReport) {
+				for (const report of events.usageReports) {
+					this.eventHandlers.onUsageReport(report)
+				}
+			}
+
+			// Process pushed metrics events
+			if (this.eventHandlers.onMetrics) {
+				for (const metrics of events.metricsEvents) {
+					this.eventHandlers.onMetrics(metrics)
+				}
+			}
+
+			// Process backend ejection/readmission events
+			if (this.eventHandlers.onBackendEvent) {
+				for (const event of events.backendEvents) {
+					this.eventHandlers.onBackendEvent(event)
+				}
+			}
+
+			if (this.eventHandlers.onUpstreamProxyEvent) {
+				for (const event of events.proxyHealthEvents ?? []) {
+					this.eventHandlers.onUpstreamProxyEvent(event)
+				}
+			}
+
+			if (this.eventHandlers.onProtocolError) {
+				for (const event of events.protocolErrors) {
+					this.eventHandlers.onProtocolError(event)
+				}
+			}
+
+			if (this.eventHandlers.onLifecycleEvent) {
+				for (const event of events.lifecycleEvents ?? []) {
+					this.eventHandlers.onLifecycleEvent(event)
+				}
+			}
+
+			if (this.eventHandlers.onQuotaExceeded) {
+				for (const event of events.quotaEvents ?? []) {
+					this.eventHandlers.onQuotaExceeded(event)
+				}
+			}
+
+			if (this.eventHandlers.onRouteAudit) {
+				for (const audit of events.routeAudits ?? []) {
+					this.eventHandlers.onRouteAudit(audit)
+				}
+			}
+
+			if (this.eventHandlers.onConfigReload) {
+				for (const reload of events.configReloads ?? []) {
+					this.eventHandlers.onConfigReload(reload)
+				}
+			}
 		} catch (e) {
 			if (this.eventHandlers.onError) {
 				this.eventHandlers.onError(
 					new Error(`Error polling batch events: ${e}`)
 				)
 			}
-		} finally {
-			if (eventsPtr) {
-				symbols.proxy_free_string(eventsPtr)
-			}
 		}
 	}
 
@@ -799,9 +2667,18 @@ export class GeofrontProxy {
 
 			const context: RouteContext = {
 				ip: request.peerIp,
+				localIp: request.localIp,
+				localPort: request.localPort,
 				host: request.host,
 				username: request.username,
-				protocol: request.protocol
+				protocol: request.protocol,
+				uuid: request.uuid,
+				sni: request.sni,
+				clientType: request.clientType,
+				transport: request.transport,
+				country: request.country,
+				asn: request.asn,
+				asOrg: request.asOrg
 			}
 
 			const result = await this.routerCallback(context)
@@ -814,14 +2691,18 @@ export class GeofrontProxy {
 					ip: request.peerIp,
 					host: request.host,
 					protocol: request.protocol,
-					startAt: new Date()
+					startAt: new Date(),
+					metadata: (result as RouteResult).metadata
 				}
 
 				const connection = new Connection(this, connectionInfo)
 				this.connections.set(request.connId, connection)
 
-				// 应用全局速率限制
-				if (Object.keys(this.globalLimit).length > 0) {
+				// 应用全局速率限制（路由结果自带限速时由 Rust 侧直接安装）
+				if (
+					Object.keys(this.globalLimit).length > 0 &&
+					!(result as RouteResult).rateLimit
+				) {
 					connection.setRateLimit(this.globalLimit)
 				}
 
@@ -840,12 +2721,15 @@ export class GeofrontProxy {
 			)
 		} catch (e) {
 			let errorMessage = 'Internal router error'
+			let template: string | undefined
 			if (e instanceof DisconnectError) {
 				errorMessage = e.reason
+				template = e.template
 			}
 
 			const errResult = JSON.stringify({
-				disconnect: errorMessage
+				disconnect: errorMessage,
+				disconnectTemplate: template
 			})
 			symbols.proxy_submit_routing_decision(
 				BigInt(request.connId),
@@ -858,8 +2742,13 @@ export class GeofrontProxy {
 		try {
 			const context: MotdContext = {
 				ip: request.peerIp,
+				localIp: request.localIp,
+				localPort: request.localPort,
 				host: request.host,
-				protocol: request.protocol
+				protocol: request.protocol,
+				country: request.country,
+				asn: request.asn,
+				asOrg: request.asOrg
 			}
 
 			let result: MotdResult
@@ -893,9 +2782,11 @@ export class GeofrontProxy {
 								result.cache.granularity === 'ip+host' ? 'ipHost' : 'ip',
 							ttl: result.cache.ttl,
 							reject: result.cache.reject,
-							rejectReason: result.cache.rejectReason
+							rejectReason: result.cache.rejectReason,
+							rejectTtl: result.cache.rejectTtl
 					  }
-					: undefined
+					: undefined,
+				passthrough: result.passthrough
 			}
 
 			const jsonResult = JSON.stringify(finalResult)
@@ -923,7 +2814,15 @@ export class GeofrontProxy {
 				ip: connection.ip,
 				host: connection.host,
 				protocol: connection.protocol,
-				startAt: connection.startAt
+				startAt: connection.startAt,
+				tags: event.tags ?? {},
+				metadata: event.metadata ?? connection.metadata,
+				ttfbMs: event.ttfbMs,
+				latencyMs: event.latencyMs,
+				backend: event.backend,
+				uuid: event.uuid,
+				compressionThreshold: event.compressionThreshold,
+				reason: event.reason
 			}
 
 			this.connections.delete(event.connId)
@@ -935,23 +2834,52 @@ export class GeofrontProxy {
 	}
 
 	private convertRouteResult(result: RouteResult): any {
-		if ('target' in result) {
+		if ('target' in result || 'pool' in result || 'fallbacks' in result) {
 			// 兼容旧格式：允许用户仍使用 proxy: { url, protocol } 写法
 			const legacyProxyProtocol: 1 | 2 | undefined = (result as any)?.proxy
 				?.protocol
 			return {
-				remoteHost: result.target.host,
-				remotePort: result.target.port,
+				remoteHost: result.target?.host,
+				remotePort: result.target?.port,
+				remotes: result.fallbacks,
+				connectTimeoutMs: result.connectTimeoutMs,
+				pool: result.pool,
+				priority: result.priority,
+				tenant: result.tenant,
 				proxy: result.proxy?.url,
+				proxyPool: result.proxyPool,
+				localAddress: result.localAddress,
 				proxyProtocol: result.proxyProtocol ?? legacyProxyProtocol,
+				proxyProtocolSource: result.proxyProtocolSource,
 				rewriteHost: result.rewrite?.host,
+				metadata: result.metadata,
+				tags: result.tags,
+				authenticate: result.authenticate,
+				rateLimit: result.rateLimit
+					? {
+							sendAvg: result.rateLimit.upload?.average,
+							sendBurst: result.rateLimit.upload?.burst,
+							recvAvg: result.rateLimit.download?.average,
+							recvBurst: result.rateLimit.download?.burst
+					  }
+					: undefined,
+				idleTimeoutMs: result.idleTimeoutMs,
+				maxSessionDurationMs: result.maxSessionDurationMs,
+				quotaBytes: result.quotaBytes,
+				userQuotaBytes: result.userQuotaBytes,
+				tcp: result.tcp,
+				tlsServerName: result.tlsServerName,
+				mirror: result.mirror,
+				reconnectGraceMs: result.reconnectGraceMs,
+				allowedProtocolRange: result.allowedProtocolRange,
 				cache: result.cache
 					? {
 							granularity:
 								result.cache.granularity === 'ip+host' ? 'ipHost' : 'ip',
 							ttl: result.cache.ttl,
 							reject: result.cache.reject,
-							rejectReason: result.cache.rejectReason
+							rejectReason: result.cache.rejectReason,
+							rejectTtl: result.cache.rejectTtl
 					  }
 					: undefined
 			}
@@ -997,8 +2925,16 @@ export namespace Geofront {
 		return new GeofrontProxy()
 	}
 
-	export function disconnect(reason: string): never {
-		throw new DisconnectError(reason)
+	// reason 可为带 &/§ 颜色代码的文本或 JSON 文本组件对象
+	export function disconnect(reason: string | Record<string, unknown>): never {
+		throw new DisconnectError(
+			typeof reason === 'string' ? reason : JSON.stringify(reason)
+		)
+	}
+
+	// 使用命名消息模板断开（如 'maintenance'、'banned'），支持 &/§ 颜色代码
+	export function disconnectWithTemplate(template: string): never {
+		throw new DisconnectError(template, template)
 	}
 
 	export function rateLimit(
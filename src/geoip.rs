@@ -0,0 +1,107 @@
+//! geofront/src/geoip.rs
+//! Country and autonomous system of client IPs from the MaxMind database at
+//! `geoipDb`, added to route and MOTD requests. Any GeoLite2/GeoIP2 database
+//! works; fields it does not carry (the ASN in a Country database, the
+//! country in an ASN database) are left out.
+//!
+//! Requires the `geoip` feature.
+
+use crate::{state::GEOIP_DB, types::GeoInfo};
+use std::{net::IpAddr, sync::Arc};
+use tracing::{error, info};
+
+#[cfg(not(feature = "geoip"))]
+pub use disabled::GeoIpDb;
+
+#[cfg(feature = "geoip")]
+pub use maxmind::GeoIpDb;
+
+/// Opens the database at `path`, replacing the current one, or closes it
+/// when `path` is `None`.
+pub fn configure(path: Option<&str>) {
+    let db = path.and_then(|path| match GeoIpDb::open(path) {
+        Ok(db) => {
+            info!(path, "Opened GeoIP database");
+            Some(Arc::new(db))
+        }
+        Err(e) => {
+            error!(path, "Failed to open GeoIP database: {}", e);
+            None
+        }
+    });
+    *GEOIP_DB.write().unwrap() = db;
+}
+
+/// Looks up `ip`; empty without a database, or if it is not found.
+pub fn lookup(ip: &str) -> GeoInfo {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return GeoInfo::default();
+    };
+    let db = GEOIP_DB.read().unwrap().clone();
+    db.map(|db| db.lookup(ip)).unwrap_or_default()
+}
+
+#[cfg(not(feature = "geoip"))]
+mod disabled {
+    use crate::types::GeoInfo;
+    use std::net::IpAddr;
+
+    /// Never constructed without the `geoip` feature.
+    pub enum GeoIpDb {}
+
+    impl GeoIpDb {
+        pub fn open(_path: &str) -> Result<Self, String> {
+            Err("geofront was built without the `geoip` feature".to_string())
+        }
+
+        pub fn lookup(&self, _ip: IpAddr) -> GeoInfo {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(feature = "geoip")]
+mod maxmind {
+    use crate::types::GeoInfo;
+    use maxminddb::Reader;
+    use serde::Deserialize;
+    use std::net::IpAddr;
+
+    pub struct GeoIpDb(Reader<Vec<u8>>);
+
+    /// The fields read from any database type.
+    #[derive(Deserialize)]
+    struct Record<'a> {
+        #[serde(borrow)]
+        country: Option<Country<'a>>,
+        autonomous_system_number: Option<u32>,
+        autonomous_system_organization: Option<&'a str>,
+    }
+
+    #[derive(Deserialize)]
+    struct Country<'a> {
+        iso_code: Option<&'a str>,
+    }
+
+    impl GeoIpDb {
+        pub fn open(path: &str) -> Result<Self, String> {
+            Reader::open_readfile(path)
+                .map(GeoIpDb)
+                .map_err(|e| e.to_string())
+        }
+
+        pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+            match self.0.lookup::<Record>(ip) {
+                Ok(record) => GeoInfo {
+                    country: record
+                        .country
+                        .and_then(|country| country.iso_code)
+                        .map(str::to_string),
+                    asn: record.autonomous_system_number,
+                    as_org: record.autonomous_system_organization.map(str::to_string),
+                },
+                Err(_) => GeoInfo::default(),
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! geofront/src/handler.rs
+//! Routing and MOTD decision handlers. By default decisions are requested
+//! from the host through the FFI polling queues; a Rust application can
+//! install its own handlers instead (see `embed.rs`).
+
+use crate::{
+    state::{
+        FFI_MOTD_LOCK, FFI_ROUTE_PERMITS, MOTD_HANDLER, MOTD_REQUEST_QUEUE, OPTIONS, PENDING_MOTDS,
+        PENDING_ROUTES, ROUTE_HANDLER, ROUTE_REQUEST_QUEUE,
+    },
+    types::{MotdDecision, MotdRequest, ProxyConnection, RouteDecision, RouteRequest},
+    wakeup,
+};
+use dashmap::DashMap;
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use tokio::sync::{Semaphore, oneshot};
+use tracing::{error, warn};
+
+/// How long a connection waits for a handler's decision by default.
+pub const DEFAULT_DECISION_TIMEOUT_MS: u64 = 10_000;
+/// Route requests the host may have outstanding at once by default.
+pub const DEFAULT_MAX_PENDING_ROUTES: usize = 1024;
+
+/// Sets how many route requests the host may have outstanding at once;
+/// further logins wait for a slot, within the decision timeout. Requests
+/// already waiting keep the slots they were given.
+pub fn configure(max_pending_routes: Option<usize>) {
+    let permits = max_pending_routes
+        .unwrap_or(DEFAULT_MAX_PENDING_ROUTES)
+        .max(1);
+    *FFI_ROUTE_PERMITS.write().unwrap() = Arc::new(Semaphore::new(permits));
+}
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Decides where a login is sent.
+pub trait RouteHandler: Send + Sync {
+    /// Returns the decision for `request`, or `None` if none could be made.
+    fn route(&self, request: RouteRequest) -> BoxFuture<Option<RouteDecision>>;
+}
+
+/// Decides the status response of a server list ping.
+pub trait MotdHandler: Send + Sync {
+    /// Returns the decision for `request`, or `None` if none could be made.
+    fn motd(&self, request: MotdRequest) -> BoxFuture<Option<MotdDecision>>;
+}
+
+impl<F, Fut> RouteHandler for F
+where
+    F: Fn(RouteRequest) -> Fut + Send + Sync,
+    Fut: Future<Output = RouteDecision> + Send + 'static,
+{
+    fn route(&self, request: RouteRequest) -> BoxFuture<Option<RouteDecision>> {
+        let decision = self(request);
+        Box::pin(async move { Some(decision.await) })
+    }
+}
+
+impl<F, Fut> MotdHandler for F
+where
+    F: Fn(MotdRequest) -> Fut + Send + Sync,
+    Fut: Future<Output = MotdDecision> + Send + 'static,
+{
+    fn motd(&self, request: MotdRequest) -> BoxFuture<Option<MotdDecision>> {
+        let decision = self(request);
+        Box::pin(async move { Some(decision.await) })
+    }
+}
+
+fn decision_timeout() -> Duration {
+    let timeout_ms = OPTIONS.read().unwrap().decision_timeout_ms;
+    Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_DECISION_TIMEOUT_MS))
+}
+
+/// The route of `decisionFallback`, for logins the router left without one.
+pub fn fallback_route() -> Option<RouteDecision> {
+    let options = OPTIONS.read().unwrap();
+    let mut decision = options.decision_fallback.as_ref()?.fallback_route.clone()?;
+    decision.cache = None;
+    Some(decision)
+}
+
+/// The status response of `decisionFallback`, for pings the MOTD handler
+/// left without one.
+pub fn fallback_motd() -> Option<MotdDecision> {
+    let options = OPTIONS.read().unwrap();
+    let mut decision = options.decision_fallback.as_ref()?.fallback_motd.clone()?;
+    decision.cache = None;
+    Some(decision)
+}
+
+/// The disconnect message of `decisionFallback` for logins left without a
+/// route.
+pub fn fallback_disconnect_message() -> Option<String> {
+    OPTIONS
+        .read()
+        .unwrap()
+        .decision_fallback
+        .as_ref()?
+        .disconnect_message
+        .clone()
+}
+
+/// Why a login was left without a route decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoRoute {
+    /// The handler did not answer within `decisionTimeoutMs`.
+    TimedOut,
+    /// The handler gave up, e.g. when overloaded.
+    Failed,
+}
+
+/// Asks the installed route handler for a decision.
+pub async fn route(request: RouteRequest) -> Result<RouteDecision, NoRoute> {
+    let conn_id = request.conn_id;
+    let handler = ROUTE_HANDLER.read().unwrap().clone();
+    match tokio::time::timeout(decision_timeout(), handler.route(request)).await {
+        Ok(decision) => decision.ok_or(NoRoute::Failed),
+        Err(_) => {
+            error!(conn = conn_id, "Timed out waiting for route decision.");
+            Err(NoRoute::TimedOut)
+        }
+    }
+}
+
+/// Asks the installed MOTD handler for a decision.
+pub async fn motd(request: MotdRequest) -> Option<MotdDecision> {
+    let conn_id = request.conn_id;
+    let handler = MOTD_HANDLER.read().unwrap().clone();
+    match tokio::time::timeout(decision_timeout(), handler.motd(request)).await {
+        Ok(decision) => decision,
+        Err(_) => {
+            error!(conn = conn_id, "Timed out waiting for MOTD decision.");
+            None
+        }
+    }
+}
+
+/// Requests decisions from the host: queues the request for
+/// `proxy_poll_events` and waits for `proxy_submit_*_decision`.
+pub struct FfiHandler;
+
+impl RouteHandler for FfiHandler {
+    fn route(&self, request: RouteRequest) -> BoxFuture<Option<RouteDecision>> {
+        Box::pin(async move {
+            // Any number of requests up to `maxPendingRoutes` are outstanding
+            // at once, each answered by its connection ID.
+            let conn_id = request.conn_id;
+            let permits = FFI_ROUTE_PERMITS.read().unwrap().clone();
+            let _permit = match permits.try_acquire() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    warn!(conn = conn_id, "Too many pending route requests; waiting for a slot");
+                    permits.acquire().await.ok()?
+                }
+            };
+            let (tx, rx) = oneshot::channel();
+            PENDING_ROUTES.insert(conn_id, tx);
+            let _pending = Pending(&PENDING_ROUTES, conn_id);
+            wakeup::push(&mut ROUTE_REQUEST_QUEUE.lock().unwrap(), request);
+            match rx.await {
+                Ok(decision) => Some(decision),
+                Err(_) => {
+                    error!(conn = conn_id, "Route decision channel closed unexpectedly.");
+                    None
+                }
+            }
+        })
+    }
+}
+
+impl MotdHandler for FfiHandler {
+    fn motd(&self, request: MotdRequest) -> BoxFuture<Option<MotdDecision>> {
+        Box::pin(async move {
+            // Only one FFI MOTD operation happens at a time.
+            let _guard = FFI_MOTD_LOCK.lock().await;
+            let conn_id = request.conn_id;
+            let (tx, rx) = oneshot::channel();
+            PENDING_MOTDS.insert(conn_id, tx);
+            let _pending = Pending(&PENDING_MOTDS, conn_id);
+            wakeup::push(&mut MOTD_REQUEST_QUEUE.lock().unwrap(), request);
+            match rx.await {
+                Ok(decision) => Some(decision),
+                Err(_) => {
+                    error!(conn = conn_id, "MOTD decision channel closed unexpectedly.");
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// Drops the pending entry of a request that was given up on, e.g. on timeout.
+struct Pending<'a, T>(&'a DashMap<ProxyConnection, T>, ProxyConnection);
+
+impl<T> Drop for Pending<'_, T> {
+    fn drop(&mut self) {
+        self.0.remove(&self.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(conn_id: ProxyConnection) -> RouteRequest {
+        RouteRequest {
+            conn_id,
+            peer_ip: "127.0.0.1".to_string(),
+            local_ip: None,
+            local_port: None,
+            port: 25565,
+            protocol: 767,
+            host: "mc.example.com".to_string(),
+            username: "Steve".to_string(),
+            uuid: None,
+            sni: None,
+            client_type: None,
+            transport: crate::types::TransportKind::Tcp,
+            geo: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closure_handler() {
+        let router = |request: RouteRequest| async move {
+            RouteDecision {
+                remote_host: Some(request.host),
+                ..Default::default()
+            }
+        };
+        let decision = RouteHandler::route(&router, request(1)).await.unwrap();
+        assert_eq!(decision.remote_host.as_deref(), Some("mc.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_ffi_routes_are_concurrent() {
+        let (first, second) = (900_001, 900_002);
+        let pending = [first, second].map(|conn_id| tokio::spawn(FfiHandler.route(request(conn_id))));
+        let both_pending = async {
+            while ![first, second]
+                .iter()
+                .all(|conn_id| PENDING_ROUTES.contains_key(conn_id))
+            {
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), both_pending)
+            .await
+            .expect("requests were serialized");
+        // Answered out of order: the second request does not wait on the first.
+        for conn_id in [second, first] {
+            let (_, tx) = PENDING_ROUTES.remove(&conn_id).unwrap();
+            tx.send(RouteDecision {
+                remote_host: Some(conn_id.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        for (conn_id, decision) in [first, second].into_iter().zip(pending) {
+            let decision = decision.await.unwrap().unwrap();
+            assert_eq!(decision.remote_host, Some(conn_id.to_string()));
+        }
+    }
+}
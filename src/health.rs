@@ -0,0 +1,393 @@
+//! geofront/src/health.rs
+//! Passive backend health derived from connection outcomes: slow-start of
+//! recovered or newly added pool members, and outlier ejection of backends
+//! that keep failing.
+
+use crate::{
+    events::{self, ProxyEvent},
+    state::{BACKEND_EVENT_QUEUE, BACKEND_HEALTH, OPTIONS},
+    types::{BackendEvent, BackendEventKind, OutlierDetectionConfig},
+    wakeup,
+};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// How often a failing backend is offered a trial connection.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// Repeated ejections lengthen the ejection time up to this factor.
+const MAX_EJECTION_FACTOR: u32 = 10;
+
+#[derive(Default)]
+pub struct BackendHealth {
+    failing_since: Option<Instant>,
+    last_probe: Option<Instant>,
+    warming_since: Option<Instant>,
+    /// Accumulated share of first picks while warming up.
+    credit: f64,
+    // Outlier detection
+    window_start: Option<Instant>,
+    window_total: u32,
+    window_failures: u32,
+    consecutive_failures: u32,
+    ejected_until: Option<Instant>,
+    ejections: u32,
+}
+
+impl BackendHealth {
+    /// Counts an outcome; returns the ejection reason if a threshold is crossed.
+    fn observe(
+        &mut self,
+        ok: bool,
+        config: &OutlierDetectionConfig,
+        now: Instant,
+    ) -> Option<String> {
+        let interval = Duration::from_millis(config.interval_ms);
+        if self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= interval)
+        {
+            self.window_start = Some(now);
+            self.window_total = 0;
+            self.window_failures = 0;
+        }
+        self.window_total += 1;
+        if ok {
+            self.consecutive_failures = 0;
+            return None;
+        }
+        self.window_failures += 1;
+        self.consecutive_failures += 1;
+        if self.ejected_until.is_some() {
+            return None;
+        }
+
+        if self.consecutive_failures >= config.consecutive_failures {
+            return Some(format!(
+                "{} consecutive failures",
+                self.consecutive_failures
+            ));
+        }
+        let rate = self.window_failures as f64 / self.window_total as f64;
+        match config.failure_rate {
+            Some(limit) if self.window_total >= config.min_requests && rate >= limit => {
+                Some(format!(
+                    "failure rate {:.0}% over {} attempts",
+                    rate * 100.0,
+                    self.window_total
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn eject(&mut self, config: &OutlierDetectionConfig, now: Instant) -> Duration {
+        self.ejections += 1;
+        let duration = Duration::from_millis(config.base_ejection_ms)
+            * self.ejections.min(MAX_EJECTION_FACTOR);
+        self.ejected_until = Some(now + duration);
+        self.consecutive_failures = 0;
+        self.window_start = None;
+        duration
+    }
+}
+
+fn slow_start() -> Option<Duration> {
+    OPTIONS
+        .read()
+        .unwrap()
+        .slow_start_ms
+        .map(Duration::from_millis)
+}
+
+/// Records the outcome of a connection attempt to `addr`.
+pub fn record_connect(addr: SocketAddr, ok: bool) {
+    record(addr, ok, true);
+}
+
+/// Records how a relayed session to `addr` ended: `ok` unless it failed with
+/// an I/O error.
+pub fn record_session(addr: SocketAddr, ok: bool) {
+    record(addr, ok, false);
+}
+
+fn record(addr: SocketAddr, ok: bool, connect: bool) {
+    let now = Instant::now();
+    let outlier = OPTIONS.read().unwrap().outlier_detection.clone();
+    let ejection = {
+        // Successes only need tracking when they feed a failure rate.
+        let mut health = match BACKEND_HEALTH.get_mut(&addr) {
+            Some(health) => health,
+            None if ok && outlier.is_none() => return,
+            None => BACKEND_HEALTH.entry(addr).or_default(),
+        };
+
+        if connect && ok && health.failing_since.take().is_some() {
+            info!(%addr, "Backend recovered");
+            if slow_start().is_some() {
+                health.warming_since = Some(now);
+                health.credit = 0.0;
+            }
+        } else if connect && !ok {
+            if health.failing_since.is_none() {
+                warn!(%addr, "Backend marked unhealthy");
+                health.failing_since = Some(now);
+                health.warming_since = None;
+            }
+            health.last_probe = Some(now);
+        }
+
+        outlier.as_ref().and_then(|config| {
+            let reason = health.observe(ok, config, now)?;
+            Some((reason, health.eject(config, now)))
+        })
+    };
+
+    if let Some((reason, duration)) = ejection {
+        warn!(%addr, %reason, ejected_for = ?duration, "Ejecting outlier backend");
+        publish(BackendEvent {
+            backend: addr.to_string(),
+            kind: BackendEventKind::Ejected,
+            timestamp_ms: events::now_ms(),
+            reason: Some(reason),
+            ejected_for_ms: Some(duration.as_millis() as u64),
+        });
+    }
+}
+
+fn publish(event: BackendEvent) {
+    wakeup::push(&mut BACKEND_EVENT_QUEUE.lock().unwrap(), event.clone());
+    events::emit(ProxyEvent::Backend(event));
+}
+
+/// Starts the slow-start ramp of a member that just joined a pool.
+pub fn warm_up(addr: SocketAddr) {
+    if slow_start().is_some() {
+        let mut health = BACKEND_HEALTH.entry(addr).or_default();
+        health.warming_since = Some(Instant::now());
+        health.credit = 0.0;
+    }
+}
+
+/// Reorders a pool rotation: ejected members are left out, warming members
+/// get the first pick only in proportion to their ramp progress, and failing
+/// members go last except for a periodic probe.
+pub fn order(rotation: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let max_ejection_percent = OPTIONS
+        .read()
+        .unwrap()
+        .outlier_detection
+        .as_ref()
+        .map_or(100, |config| config.max_ejection_percent);
+    let (order, readmitted) =
+        order_at(rotation, slow_start(), max_ejection_percent, Instant::now());
+    for addr in readmitted {
+        info!(%addr, "Readmitting ejected backend");
+        publish(BackendEvent {
+            backend: addr.to_string(),
+            kind: BackendEventKind::Readmitted,
+            timestamp_ms: events::now_ms(),
+            reason: None,
+            ejected_for_ms: None,
+        });
+    }
+    order
+}
+
+/// Returns the ordered members and those whose ejection just ended.
+fn order_at(
+    rotation: Vec<SocketAddr>,
+    slow_start: Option<Duration>,
+    max_ejection_percent: u32,
+    now: Instant,
+) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    let total = rotation.len();
+    let mut ready = Vec::with_capacity(total);
+    let mut deferred = Vec::new();
+    let mut failing = Vec::new();
+    let mut ejected = Vec::new();
+    let mut readmitted = Vec::new();
+    for addr in rotation {
+        let Some(mut health) = BACKEND_HEALTH.get_mut(&addr) else {
+            ready.push(addr);
+            continue;
+        };
+        if let Some(until) = health.ejected_until {
+            if now < until {
+                ejected.push(addr);
+                continue;
+            }
+            health.ejected_until = None;
+            if slow_start.is_some() {
+                health.warming_since = Some(now);
+                health.credit = 0.0;
+            }
+            readmitted.push(addr);
+        }
+        if health.failing_since.is_some() {
+            if health
+                .last_probe
+                .is_none_or(|at| now.duration_since(at) >= PROBE_INTERVAL)
+            {
+                health.last_probe = Some(now);
+                ready.push(addr);
+            } else {
+                failing.push(addr);
+            }
+            continue;
+        }
+        let (Some(since), Some(window)) = (health.warming_since, slow_start) else {
+            ready.push(addr);
+            continue;
+        };
+        let progress = now.duration_since(since).as_secs_f64() / window.as_secs_f64();
+        if progress >= 1.0 {
+            health.warming_since = None;
+            ready.push(addr);
+        } else if ready.is_empty() {
+            // Only the first pick matters for load; ramp its share.
+            health.credit += progress;
+            if health.credit >= 1.0 {
+                health.credit -= 1.0;
+                ready.push(addr);
+            } else {
+                deferred.push(addr);
+            }
+        } else {
+            deferred.push(addr);
+        }
+    }
+    ready.extend(deferred);
+    ready.extend(failing);
+    // Ejected members beyond the allowed share stay as a last resort, as do
+    // all of them if nothing else is left.
+    let allowed = total * max_ejection_percent.min(100) as usize / 100;
+    let kept = if ready.is_empty() {
+        0
+    } else {
+        ejected.len().min(allowed)
+    };
+    ready.extend(ejected.into_iter().skip(kept));
+    (ready, readmitted)
+}
+
+/// Whether `addr` is currently considered failing, and whether it is warming up.
+pub fn status(addr: &SocketAddr) -> (bool, bool) {
+    BACKEND_HEALTH.get(addr).map_or((false, false), |h| {
+        (
+            h.failing_since.is_some() || h.ejected_until.is_some(),
+            h.warming_since.is_some(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outlier_config() -> OutlierDetectionConfig {
+        OutlierDetectionConfig {
+            consecutive_failures: 3,
+            failure_rate: Some(0.5),
+            min_requests: 4,
+            interval_ms: 60_000,
+            base_ejection_ms: 1_000,
+            max_ejection_percent: 50,
+        }
+    }
+
+    #[test]
+    fn test_slow_start_ramps_first_picks() {
+        let warm: SocketAddr = "10.9.0.1:25565".parse().unwrap();
+        let other: SocketAddr = "10.9.0.2:25565".parse().unwrap();
+        let now = Instant::now();
+        BACKEND_HEALTH.insert(
+            warm,
+            BackendHealth {
+                warming_since: Some(now - Duration::from_secs(25)),
+                ..Default::default()
+            },
+        );
+        let window = Some(Duration::from_secs(100));
+        let first = |at| order_at(vec![warm, other], window, 100, at).0[0];
+        // 25% into the ramp: first pick one time in four.
+        let firsts = (0..8).filter(|_| first(now) == warm).count();
+        assert_eq!(firsts, 2);
+        // Never dropped, only deferred.
+        assert_eq!(order_at(vec![warm, other], window, 100, now).0.len(), 2);
+        // Past the window it is a regular member again.
+        assert_eq!(first(now + Duration::from_secs(80)), warm);
+        assert_eq!(first(now), warm);
+    }
+
+    #[test]
+    fn test_failing_members_go_last_between_probes() {
+        let bad: SocketAddr = "10.9.1.1:25565".parse().unwrap();
+        let good: SocketAddr = "10.9.1.2:25565".parse().unwrap();
+        let now = Instant::now();
+        BACKEND_HEALTH.insert(
+            bad,
+            BackendHealth {
+                failing_since: Some(now),
+                last_probe: Some(now),
+                ..Default::default()
+            },
+        );
+        let order = |at| order_at(vec![bad, good], None, 100, at).0;
+        assert_eq!(order(now), vec![good, bad]);
+        // A probe is due after the interval, then not again right away.
+        let later = now + PROBE_INTERVAL;
+        assert_eq!(order(later), vec![bad, good]);
+        assert_eq!(order(later), vec![good, bad]);
+    }
+
+    #[test]
+    fn test_outlier_thresholds() {
+        let config = outlier_config();
+        let now = Instant::now();
+
+        let mut health = BackendHealth::default();
+        assert_eq!(health.observe(false, &config, now), None);
+        assert_eq!(health.observe(false, &config, now), None);
+        assert!(health.observe(false, &config, now).is_some());
+        assert_eq!(health.eject(&config, now), Duration::from_secs(1));
+        // A second ejection lasts longer.
+        health.ejected_until = None;
+        assert_eq!(health.eject(&config, now), Duration::from_secs(2));
+
+        // Alternating outcomes never hit the consecutive limit but reach the rate.
+        let mut health = BackendHealth::default();
+        assert_eq!(health.observe(true, &config, now), None);
+        assert_eq!(health.observe(false, &config, now), None);
+        assert_eq!(health.observe(true, &config, now), None);
+        assert!(health.observe(false, &config, now).is_some());
+    }
+
+    #[test]
+    fn test_ejection_and_readmission() {
+        let a: SocketAddr = "10.9.2.1:25565".parse().unwrap();
+        let b: SocketAddr = "10.9.2.2:25565".parse().unwrap();
+        let c: SocketAddr = "10.9.2.3:25565".parse().unwrap();
+        let now = Instant::now();
+        for addr in [a, b] {
+            BACKEND_HEALTH.insert(
+                addr,
+                BackendHealth {
+                    ejected_until: Some(now + Duration::from_secs(5)),
+                    ..Default::default()
+                },
+            );
+        }
+        // With a 50% cap on three members only one of the two is left out.
+        let (order, readmitted) = order_at(vec![a, b, c], None, 50, now);
+        assert_eq!(order, vec![c, b]);
+        assert!(readmitted.is_empty());
+
+        let later = now + Duration::from_secs(5);
+        let (order, readmitted) = order_at(vec![a, b, c], None, 50, later);
+        assert_eq!(order, vec![a, b, c]);
+        assert_eq!(readmitted, vec![a, b]);
+    }
+}
@@ -0,0 +1,202 @@
+//! geofront/src/health_check.rs
+//! Active backend health checks: configured backends are probed on an
+//! interval with a status ping (or a bare TCP connect), and backends that
+//! keep failing are tried last when routing. Complements the passive health
+//! in `health.rs`, which only learns from real connections.
+
+use crate::{
+    events,
+    protocol::{self, write_string, write_varint},
+    state::{BACKEND_CHECKS, HEALTH_CHECKER, LISTENER_STATE},
+    types::{BackendCheckStatus, HealthCheckConfig, HealthCheckKind},
+};
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinSet,
+};
+use tracing::{info, warn};
+
+/// Protocol version sent in probe handshakes; servers answer status pings
+/// whatever the version.
+const PROBE_PROTOCOL: i32 = 767;
+
+/// Starts, restarts or stops the checks according to `config`. Backends no
+/// longer configured are forgotten.
+pub fn configure(config: Option<&HealthCheckConfig>) {
+    let mut checker = HEALTH_CHECKER.lock().unwrap();
+    if let Some(handle) = checker.take() {
+        handle.abort();
+    }
+    let Some(config) = config.cloned() else {
+        BACKEND_CHECKS.clear();
+        return;
+    };
+    BACKEND_CHECKS.retain(|backend, _| config.backends.contains(backend));
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    let config = Arc::new(config);
+    *checker = Some(runtime.spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(config.interval_ms.max(1000)));
+        loop {
+            ticker.tick().await;
+            let mut probes = JoinSet::new();
+            for backend in config.backends.clone() {
+                let config = config.clone();
+                probes.spawn(async move {
+                    let result = probe(&backend, &config).await;
+                    record(&backend, &config, result);
+                });
+            }
+            while probes.join_next().await.is_some() {}
+        }
+    }));
+}
+
+/// Probes `backend` once, returning the time it took to answer.
+async fn probe(backend: &str, config: &HealthCheckConfig) -> Result<Duration> {
+    let started = Instant::now();
+    let check = async {
+        let mut stream = TcpStream::connect(backend).await?;
+        if config.kind == HealthCheckKind::Status {
+            status_ping(&mut stream, backend).await?;
+        }
+        Ok(started.elapsed())
+    };
+    tokio::time::timeout(Duration::from_millis(config.timeout_ms), check)
+        .await
+        .unwrap_or_else(|_| Err(Error::new(ErrorKind::TimedOut, "probe timed out")))
+}
+
+/// Sends a status request and waits for the status response packet.
+async fn status_ping(stream: &mut TcpStream, backend: &str) -> Result<()> {
+    let (host, port) = backend
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+        .unwrap_or((backend, 25565));
+
+    let mut data = Vec::new();
+    write_varint(&mut data, 0x00);
+    write_varint(&mut data, PROBE_PROTOCOL);
+    write_string(&mut data, host.trim_start_matches('[').trim_end_matches(']'));
+    data.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut data, 1);
+    let mut packets = Vec::new();
+    write_varint(&mut packets, data.len() as i32);
+    packets.extend_from_slice(&data);
+    // Status request: length 1, packet id 0
+    packets.extend_from_slice(&[0x01, 0x00]);
+    stream.write_all(&packets).await?;
+
+    let len = protocol::read_varint(stream).await?;
+    if len <= 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "empty status response"));
+    }
+    if stream.read_u8().await? != 0x00 {
+        return Err(Error::new(ErrorKind::InvalidData, "unexpected status response packet"));
+    }
+    Ok(())
+}
+
+fn record(backend: &str, config: &HealthCheckConfig, result: Result<Duration>) {
+    let mut status = BACKEND_CHECKS.entry(backend.to_string()).or_default();
+    status.last_checked_ms = Some(events::now_ms());
+    match result {
+        Ok(latency) => {
+            status.latency_ms = Some(latency.as_secs_f64() * 1000.0);
+            status.last_error = None;
+            status.consecutive_failures = 0;
+            status.consecutive_successes += 1;
+            if !status.healthy && status.consecutive_successes >= config.healthy_threshold {
+                info!(%backend, "Backend passed its health checks");
+                status.healthy = true;
+            }
+        }
+        Err(e) => {
+            status.latency_ms = None;
+            status.last_error = Some(e.to_string());
+            status.consecutive_successes = 0;
+            status.consecutive_failures += 1;
+            if status.healthy && status.consecutive_failures >= config.unhealthy_threshold {
+                warn!(%backend, "Backend failed its health checks: {}", e);
+                status.healthy = false;
+            }
+        }
+    }
+}
+
+/// Whether the checks currently mark `backend` (`host:port`) unhealthy.
+/// Backends that are not checked are never unhealthy.
+pub fn is_unhealthy(backend: &str) -> bool {
+    BACKEND_CHECKS
+        .get(backend)
+        .is_some_and(|status| !status.healthy)
+}
+
+/// Check results of all configured backends, keyed by `host:port`.
+pub fn snapshot() -> HashMap<String, BackendCheckStatus> {
+    BACKEND_CHECKS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HealthCheckConfig {
+        HealthCheckConfig {
+            backends: vec![],
+            kind: HealthCheckKind::Tcp,
+            interval_ms: 1_000,
+            timeout_ms: 1_000,
+            unhealthy_threshold: 2,
+            healthy_threshold: 2,
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let backend = "10.8.0.1:25565";
+        let config = config();
+        let failed = || Err(Error::new(ErrorKind::ConnectionRefused, "refused"));
+        record(backend, &config, failed());
+        assert!(!is_unhealthy(backend));
+        record(backend, &config, failed());
+        assert!(is_unhealthy(backend));
+        record(backend, &config, Ok(Duration::from_millis(5)));
+        assert!(is_unhealthy(backend));
+        record(backend, &config, Ok(Duration::from_millis(5)));
+        assert!(!is_unhealthy(backend));
+        assert!(!is_unhealthy("10.8.0.2:25565"));
+    }
+
+    #[tokio::test]
+    async fn test_status_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let mut response = Vec::new();
+            write_string(&mut response, "{}");
+            let mut packet = Vec::new();
+            write_varint(&mut packet, response.len() as i32 + 1);
+            packet.push(0x00);
+            packet.extend_from_slice(&response);
+            socket.write_all(&packet).await.unwrap();
+        });
+        let config = HealthCheckConfig {
+            kind: HealthCheckKind::Status,
+            ..config()
+        };
+        assert!(probe(&addr, &config).await.is_ok());
+    }
+}
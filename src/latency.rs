@@ -0,0 +1,197 @@
+//! geofront/src/latency.rs
+//! Latency percentiles, and the time-to-first-byte of proxied sessions:
+//! from receiving the login start to the first byte the backend sends back.
+//! Backend connect times, routing decisions and session durations are also
+//! counted into fixed-bucket histograms, which cover every observation since
+//! start rather than a recent window, so tail latencies stay visible.
+
+use crate::{
+    state::{BACKEND_CONNECT_HISTOGRAM, ROUTING_HISTOGRAM, SESSION_HISTOGRAM, TTFB_SAMPLES},
+    types::ProxyConnection,
+};
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Sessions kept for the aggregate TTFB percentiles.
+const TTFB_WINDOW: usize = 4096;
+
+/// Bucket bounds of connect and routing times, in milliseconds.
+pub const LATENCY_BOUNDS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+/// Bucket bounds of session durations, in milliseconds: 10 s to 1 day.
+pub const SESSION_BOUNDS_MS: [u64; 9] = [
+    10_000, 60_000, 300_000, 900_000, 1_800_000, 3_600_000, 7_200_000, 21_600_000, 86_400_000,
+];
+
+/// Observations counted into buckets with atomics, so recording never locks.
+pub struct Histogram<const N: usize> {
+    bounds_ms: [u64; N],
+    /// Observations at or below each bound and above the previous one.
+    buckets: [AtomicU64; N],
+    /// Observations above the last bound.
+    overflow: AtomicU64,
+    sum_us: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(bounds_ms: [u64; N]) -> Self {
+        Self {
+            bounds_ms,
+            buckets: [const { AtomicU64::new(0) }; N],
+            overflow: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        let bucket = self.bounds_ms.iter().position(|&bound| micros <= bound * 1000);
+        match bucket {
+            Some(i) => self.buckets[i].fetch_add(1, Ordering::Relaxed),
+            None => self.overflow.fetch_add(1, Ordering::Relaxed),
+        };
+        self.sum_us.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        counts.push(self.overflow.load(Ordering::Relaxed));
+        HistogramSnapshot {
+            bounds_ms: self.bounds_ms.to_vec(),
+            count: counts.iter().sum(),
+            counts,
+            sum_ms: self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+
+    pub fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.overflow.store(0, Ordering::Relaxed);
+        self.sum_us.store(0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramSnapshot {
+    /// Upper bound of each bucket, in milliseconds.
+    pub bounds_ms: Vec<u64>,
+    /// Observations per bucket (not cumulative), plus a last one for those
+    /// above every bound.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: f64,
+}
+
+/// The histograms in `MetricsSnapshot`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Histograms {
+    /// Time to connect to the backend of a login, through any proxies.
+    pub backend_connect: HistogramSnapshot,
+    /// Time the router took to answer a route request.
+    pub routing_decision: HistogramSnapshot,
+    /// Lifetime of connections that reached a backend.
+    pub session_duration: HistogramSnapshot,
+}
+
+pub fn reset_histograms() {
+    BACKEND_CONNECT_HISTOGRAM.reset();
+    ROUTING_HISTOGRAM.reset();
+    SESSION_HISTOGRAM.reset();
+}
+
+pub fn histograms() -> Histograms {
+    Histograms {
+        backend_connect: BACKEND_CONNECT_HISTOGRAM.snapshot(),
+        routing_decision: ROUTING_HISTOGRAM.snapshot(),
+        session_duration: SESSION_HISTOGRAM.snapshot(),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub min: f64,
+    pub avg: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl LatencySummary {
+    /// Summarizes samples given in microseconds; the result is in milliseconds.
+    pub fn from_micros(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let ms = |us: u64| us as f64 / 1000.0;
+        let at = |q: f64| ms(samples[((samples.len() - 1) as f64 * q).round() as usize]);
+        Self {
+            samples: samples.len(),
+            min: ms(samples[0]),
+            avg: ms(samples.iter().sum::<u64>()) / samples.len() as f64,
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max: ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Records the TTFB of a session on its connection info and in the window.
+pub fn record_ttfb(conn_id: ProxyConnection, ttfb: Duration) {
+    let micros = ttfb.as_micros() as u64;
+    crate::connection::update_conn_info(conn_id, |info| {
+        info.ttfb_ms = Some(micros as f64 / 1000.0);
+    });
+    let mut samples = TTFB_SAMPLES.lock().unwrap();
+    if samples.len() >= TTFB_WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(micros);
+}
+
+/// Percentiles over the most recent sessions.
+pub fn ttfb_summary() -> LatencySummary {
+    LatencySummary::from_micros(TTFB_SAMPLES.lock().unwrap().iter().copied().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_summary() {
+        let summary = LatencySummary::from_micros((1..=100).map(|ms| ms * 1000).collect());
+        assert_eq!(summary.samples, 100);
+        assert_eq!((summary.min, summary.max), (1.0, 100.0));
+        assert_eq!(summary.p50, 51.0);
+        assert_eq!(summary.p90, 90.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.avg, 50.5);
+        assert_eq!(
+            LatencySummary::from_micros(Vec::new()),
+            LatencySummary::default()
+        );
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::new([10, 100]);
+        histogram.record(Duration::from_millis(10));
+        histogram.record(Duration::from_micros(10_001));
+        histogram.record(Duration::from_millis(500));
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.counts, [1, 1, 1]);
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.sum_ms, 520.001);
+    }
+}
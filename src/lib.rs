@@ -2,11 +2,73 @@
 //! Minimal Minecraft proxy backend core with logging, routing, zero-copy forwarding, rate limiting, upstream proxy support, and metrics
 
 // Module declarations
+pub mod acceptors;
+pub mod accounting;
+pub mod audit_db;
+pub mod auth;
+pub mod bedrock;
+pub mod buffer_pool;
 pub mod cache;
+pub mod capacity;
+pub mod capture;
+pub mod config_file;
+pub mod conn_query;
 pub mod connection;
+pub mod corpus;
+pub mod default_motd;
+pub mod discovery;
+pub mod embed;
+pub mod events;
 pub mod ffi;
+pub mod forwarding;
+pub mod geoip;
+pub mod handler;
+pub mod health;
+pub mod health_check;
+pub mod introspect;
+pub mod latency;
+pub mod lifecycle;
+pub mod limiter;
+pub mod limits;
+pub mod loadgen;
+pub mod login_phase;
+pub mod login_throttle;
 pub mod logging;
+pub mod messages;
+pub mod metrics_push;
+pub mod mirror;
+pub mod motd_players;
+#[cfg(feature = "napi")]
+pub mod node;
+pub mod outbound;
+pub mod overflow;
+pub mod pause;
+pub mod prometheus;
 pub mod protocol;
+pub mod protocol_errors;
+pub mod proxy_manager;
+pub mod quic;
+pub mod quota;
+pub mod route_audit;
+pub mod route_metrics;
+pub mod schedule;
+pub mod service_discovery;
+#[cfg(feature = "redis")]
+pub mod shared_cache;
+pub mod sink;
+pub mod snapshot;
+pub mod sockmap;
 pub mod state;
+pub mod static_routes;
 pub mod splice;
+pub mod tarpit;
+pub mod tcp_options;
+pub mod tls;
+pub mod transfer;
+pub mod transport;
 pub mod types;
+pub mod upstream;
+pub mod usage;
+pub mod vhosts;
+pub mod wakeup;
+pub mod websocket;
@@ -0,0 +1,72 @@
+//! geofront/src/lifecycle.rs
+//! Per-phase connection events: accept, handshake, routing, backend connect,
+//! login forwarding and disconnect, each stamped with the time since accept so
+//! hosts can see where a connection spent its time and why it ended.
+//!
+//! Recorded only while `lifecycleEvents` is on. Events go to the handler set
+//! through `Geofront::set_lifecycle_handler` if any, else to the polling queue.
+
+use crate::{
+    events,
+    state::{CONN_INFO, LIFECYCLE_EVENT_QUEUE, LIFECYCLE_HANDLER, OPTIONS},
+    types::{DisconnectReason, LifecycleEvent, LifecycleKind, ProxyConnection},
+    wakeup,
+};
+use std::sync::Arc;
+
+/// Receives every lifecycle event instead of the polling queue. Called on
+/// the connection's task, so it must not block.
+pub type LifecycleHandler = Arc<dyn Fn(&LifecycleEvent) + Send + Sync>;
+
+/// Events kept for polling at most; the oldest are dropped beyond this.
+const MAX_PENDING_EVENTS: usize = 65_536;
+
+/// Records `kind` for a connection that is still registered.
+pub fn record(conn_id: ProxyConnection, kind: LifecycleKind) {
+    if !OPTIONS.read().unwrap().lifecycle_events {
+        return;
+    }
+    let connected_at_ms = CONN_INFO
+        .get(&conn_id)
+        .map(|info| info.connected_at_ms);
+    publish(conn_id, connected_at_ms, kind);
+}
+
+/// Records the disconnect of a connection already removed from `CONN_INFO`.
+pub fn record_disconnect(
+    conn_id: ProxyConnection,
+    connected_at_ms: u64,
+    reason: DisconnectReason,
+    bytes_sent: u64,
+    bytes_recv: u64,
+) {
+    if !OPTIONS.read().unwrap().lifecycle_events {
+        return;
+    }
+    publish(
+        conn_id,
+        Some(connected_at_ms),
+        LifecycleKind::Disconnected {
+            reason,
+            bytes_sent,
+            bytes_recv,
+        },
+    );
+}
+
+fn publish(conn_id: ProxyConnection, connected_at_ms: Option<u64>, kind: LifecycleKind) {
+    let timestamp_ms = events::now_ms();
+    let event = LifecycleEvent {
+        conn_id,
+        timestamp_ms,
+        elapsed_ms: connected_at_ms.map_or(0, |at| timestamp_ms.saturating_sub(at)),
+        kind,
+    };
+    let handler = LIFECYCLE_HANDLER.read().unwrap().clone();
+    match handler {
+        Some(handler) => handler(&event),
+        None => {
+            wakeup::push_bounded(&mut LIFECYCLE_EVENT_QUEUE.lock().unwrap(), event, MAX_PENDING_EVENTS);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! geofront/src/limiter.rs
+//! Per-connection byte rate limiters that remember what they last observed,
+//! so their live state can be reported without consuming capacity.
+
+use crate::{
+    state::{CONN_INFO, RATE_LIMITERS, SHARED_LIMITERS, RateLimiterPair},
+    types::{ProxyConnection, ProxyListener, RateLimitConfig},
+};
+use governor::{
+    InsufficientCapacity, Quota, RateLimiter,
+    clock::DefaultClock,
+    middleware::StateInformationMiddleware,
+    state::{InMemoryState, direct::NotKeyed},
+};
+use nonzero_ext::nonzero;
+use serde::Serialize;
+use std::{
+    num::NonZeroU32,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+type DirectRateLimiter =
+    RateLimiter<NotKeyed, InMemoryState, DefaultClock, StateInformationMiddleware>;
+
+pub struct ConnLimiter {
+    limiter: DirectRateLimiter,
+    /// Configured (average, burst) bytes per second; `None` when unlimited.
+    quota: Option<(u32, u32)>,
+    created: Instant,
+    /// Burst capacity left after the last admitted chunk.
+    remaining: AtomicU32,
+    observed_at_us: AtomicU64,
+    waiting: AtomicBool,
+    throttled_us: AtomicU64,
+    throttle_events: AtomicU64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LimiterSnapshot {
+    /// `None` when the direction is unlimited.
+    pub avg_bytes_per_sec: Option<u32>,
+    pub burst_bytes: Option<u32>,
+    /// Bytes that could pass right now without waiting.
+    pub available_bytes: Option<u64>,
+    /// Whether a chunk is currently held back by the limiter.
+    pub throttled: bool,
+    /// Total time chunks spent waiting, and how many had to wait.
+    pub throttled_ms: u64,
+    pub throttle_events: u64,
+}
+
+impl ConnLimiter {
+    pub fn unlimited() -> Self {
+        Self::with_quota(Quota::per_second(nonzero!(u32::MAX)), None)
+    }
+
+    pub fn new(avg: NonZeroU32, burst: NonZeroU32) -> Self {
+        Self::with_quota(
+            Quota::per_second(avg).allow_burst(burst),
+            Some((avg.get(), burst.get())),
+        )
+    }
+
+    fn with_quota(quota: Quota, configured: Option<(u32, u32)>) -> Self {
+        Self {
+            limiter: RateLimiter::direct(quota).with_middleware(),
+            quota: configured,
+            created: Instant::now(),
+            remaining: AtomicU32::new(configured.map_or(u32::MAX, |(_, burst)| burst)),
+            observed_at_us: AtomicU64::new(0),
+            waiting: AtomicBool::new(false),
+            throttled_us: AtomicU64::new(0),
+            throttle_events: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether a quota applies.
+    pub fn is_limited(&self) -> bool {
+        self.quota.is_some()
+    }
+
+    /// Waits until `n` bytes may pass.
+    pub async fn until_n_ready(&self, n: NonZeroU32) -> Result<(), InsufficientCapacity> {
+        if self.quota.is_none() {
+            return Ok(());
+        }
+        let snapshot = match self.limiter.check_n(n)? {
+            Ok(snapshot) => snapshot,
+            Err(_) => {
+                let start = Instant::now();
+                self.throttle_events.fetch_add(1, Ordering::Relaxed);
+                self.waiting.store(true, Ordering::Relaxed);
+                let result = self.limiter.until_n_ready(n).await;
+                self.waiting.store(false, Ordering::Relaxed);
+                self.throttled_us
+                    .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+                result?
+            }
+        };
+        self.record(snapshot.remaining_burst_capacity());
+        Ok(())
+    }
+
+    /// Non-waiting check for `n` bytes; `Ok(false)` when they must wait.
+    pub fn check_n(&self, n: NonZeroU32) -> Result<bool, InsufficientCapacity> {
+        if self.quota.is_none() {
+            return Ok(true);
+        }
+        match self.limiter.check_n(n)? {
+            Ok(snapshot) => {
+                self.record(snapshot.remaining_burst_capacity());
+                Ok(true)
+            }
+            Err(_) => {
+                self.throttle_events.fetch_add(1, Ordering::Relaxed);
+                Ok(false)
+            }
+        }
+    }
+
+    fn record(&self, remaining: u32) {
+        self.remaining.store(remaining, Ordering::Relaxed);
+        self.observed_at_us
+            .store(self.created.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> LimiterSnapshot {
+        let available_bytes = self.quota.map(|(avg, burst)| {
+            // Capacity refills at `avg` bytes per second since the last chunk.
+            let elapsed_us = (self.created.elapsed().as_micros() as u64)
+                .saturating_sub(self.observed_at_us.load(Ordering::Relaxed));
+            let refilled = elapsed_us.saturating_mul(avg as u64) / 1_000_000;
+            (self.remaining.load(Ordering::Relaxed) as u64)
+                .saturating_add(refilled)
+                .min(burst as u64)
+        });
+        LimiterSnapshot {
+            avg_bytes_per_sec: self.quota.map(|(avg, _)| avg),
+            burst_bytes: self.quota.map(|(_, burst)| burst),
+            available_bytes,
+            throttled: self.waiting.load(Ordering::Relaxed),
+            throttled_ms: self.throttled_us.load(Ordering::Relaxed) / 1000,
+            throttle_events: self.throttle_events.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Traffic a shared limiter applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitScope {
+    /// All connections.
+    Global,
+    /// Connections accepted by one listener.
+    Listener(ProxyListener),
+}
+
+/// (send, recv) limiters for `limit`; 0 leaves a direction unlimited and a
+/// burst of 0 defaults to the average.
+fn limiter_pair(limit: &RateLimitConfig) -> RateLimiterPair {
+    let nonzero = |n: Option<u64>| NonZeroU32::new(n.unwrap_or(0).min(u32::MAX as u64) as u32);
+    let direction = |avg: Option<u64>, burst: Option<u64>| match nonzero(avg) {
+        Some(avg) => {
+            let burst = nonzero(burst).unwrap_or(avg);
+            Arc::new(ConnLimiter::new(avg, burst))
+        }
+        None => Arc::new(ConnLimiter::unlimited()),
+    };
+    (
+        direction(limit.send_avg, limit.send_burst),
+        direction(limit.recv_avg, limit.recv_burst),
+    )
+}
+
+/// Installs fresh send and recv limiters on a connection. Returns false if
+/// the connection is unknown.
+pub fn set_limits(conn_id: ProxyConnection, limit: &RateLimitConfig) -> bool {
+    let Some(mut pair) = RATE_LIMITERS.get_mut(&conn_id) else {
+        return false;
+    };
+    *pair = limiter_pair(limit);
+    true
+}
+
+/// Caps the combined traffic of `scope`, or lifts the cap with `None` or a
+/// limit without averages.
+pub fn set_shared_limits(scope: LimitScope, limit: Option<&RateLimitConfig>) {
+    let mut shared = SHARED_LIMITERS.write().unwrap();
+    match limit.filter(|l| l.send_avg.unwrap_or(0) > 0 || l.recv_avg.unwrap_or(0) > 0) {
+        Some(limit) => {
+            shared.insert(scope, limiter_pair(limit));
+        }
+        None => {
+            shared.remove(&scope);
+        }
+    }
+}
+
+/// The shared (send, recv) limiters a connection of `listener` is subject to.
+pub fn shared_limiters(listener: Option<ProxyListener>) -> Vec<RateLimiterPair> {
+    let shared = SHARED_LIMITERS.read().unwrap();
+    if shared.is_empty() {
+        return Vec::new();
+    }
+    std::iter::once(LimitScope::Global)
+        .chain(listener.map(LimitScope::Listener))
+        .filter_map(|scope| shared.get(&scope).cloned())
+        .collect()
+}
+
+/// The largest chunk to wait for at once under `limiters`: `max`, capped to
+/// the smallest burst so every limiter charges the chunk in full, or `None`
+/// when none of them is limited.
+pub fn chunk_size(limiters: &[Arc<ConnLimiter>], max: usize) -> Option<usize> {
+    limiters
+        .iter()
+        .filter_map(|limiter| limiter.quota.map(|(_, burst)| burst as usize))
+        .min()
+        .map(|burst| burst.min(max))
+}
+
+/// Waits until `n` bytes may pass every limiter in `limiters`. Takes them
+/// owned, so callers' futures hold no borrows across the wait.
+pub async fn until_all_ready(limiters: Vec<Arc<ConnLimiter>>, n: usize) {
+    let Some(n) = NonZeroU32::new(n.min(u32::MAX as usize) as u32) else {
+        return;
+    };
+    for limiter in limiters {
+        // Cannot fail for chunks split to `chunk_size`.
+        let _ = limiter.until_n_ready(n).await;
+    }
+}
+
+/// Whether the connection still has the shared unlimited limiter pair the
+/// accept loop installs, and no global or listener cap applies to it;
+/// `set_limits` always installs two distinct limiters. Only such connections
+/// may bypass the userspace copier.
+pub fn is_unlimited(conn_id: ProxyConnection) -> bool {
+    let own = RATE_LIMITERS
+        .get(&conn_id)
+        .is_some_and(|entry| Arc::ptr_eq(&entry.0, &entry.1));
+    own && shared_limiters(listener_of(conn_id)).is_empty()
+}
+
+/// Listener that accepted the connection.
+pub fn listener_of(conn_id: ProxyConnection) -> Option<ProxyListener> {
+    CONN_INFO.get(&conn_id).and_then(|info| info.listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_tracks_capacity() {
+        let limiter = ConnLimiter::new(nonzero!(1000u32), nonzero!(4000u32));
+        assert_eq!(limiter.snapshot().available_bytes, Some(4000));
+
+        limiter.until_n_ready(nonzero!(3000u32)).await.unwrap();
+        let snapshot = limiter.snapshot();
+        assert!((1000..1100).contains(&snapshot.available_bytes.unwrap()));
+        assert_eq!(snapshot.throttle_events, 0);
+
+        // Needs ~1s of refill: waits and is counted as throttled.
+        limiter.until_n_ready(nonzero!(2000u32)).await.unwrap();
+        let snapshot = limiter.snapshot();
+        assert_eq!(snapshot.throttle_events, 1);
+        assert!(snapshot.throttled_ms >= 500);
+        assert!(!snapshot.throttled);
+
+        let unlimited = ConnLimiter::unlimited().snapshot();
+        assert_eq!(unlimited.avg_bytes_per_sec, None);
+        assert_eq!(unlimited.available_bytes, None);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_below_burst_hold_the_rate() {
+        let limiters = vec![
+            Arc::new(ConnLimiter::new(nonzero!(10_000u32), nonzero!(1000u32))),
+            Arc::new(ConnLimiter::unlimited()),
+        ];
+        let chunk = chunk_size(&limiters, 4096).unwrap();
+        assert_eq!(chunk, 1000);
+        assert_eq!(chunk_size(&limiters[1..], 4096), None);
+
+        // 5000 bytes: one burst, then 4000 bytes at 10 kB/s.
+        let start = Instant::now();
+        for _ in 0..5 {
+            until_all_ready(limiters.clone(), chunk).await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed.as_millis() >= 350, "5000 bytes passed in {:?}", elapsed);
+        assert!(elapsed.as_millis() < 1000, "5000 bytes passed in {:?}", elapsed);
+    }
+}
@@ -0,0 +1,145 @@
+//! geofront/src/limits.rs
+//! Per-IP caps on open connections and on new connections (each carrying
+//! one handshake) per second, enforced as connections are accepted so a
+//! flood is dropped before any parsing or routing round trip.
+//!
+//! Clients are counted by their TCP peer address, before any PROXY header.
+//! A listener's own caps replace the global ones for its connections.
+
+use crate::{
+    state::{IP_LIMITS, OPTIONS},
+    types::{ListenerOptions, ProxyConnection},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+const WINDOW: Duration = Duration::from_secs(1);
+/// Windows are pruned once this many IPs are tracked.
+const PRUNE_THRESHOLD: usize = 4096;
+
+#[derive(Default)]
+pub struct IpLimits {
+    /// Open connections by client IP.
+    connections: HashMap<IpAddr, usize>,
+    /// Client IP of each counted connection.
+    conn_ips: HashMap<ProxyConnection, IpAddr>,
+    /// Start of the current window and the handshakes in it, by client IP.
+    handshakes: HashMap<IpAddr, (Instant, u32)>,
+}
+
+impl IpLimits {
+    fn admit(
+        &mut self,
+        conn_id: ProxyConnection,
+        ip: IpAddr,
+        max_connections: Option<usize>,
+        handshakes_per_sec: Option<u32>,
+        now: Instant,
+    ) -> bool {
+        let open = self.connections.get(&ip).copied().unwrap_or(0);
+        if max_connections.is_some_and(|max| open >= max) {
+            return false;
+        }
+        if let Some(max) = handshakes_per_sec {
+            if self.handshakes.len() >= PRUNE_THRESHOLD {
+                self.handshakes
+                    .retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+            }
+            let (start, count) = self.handshakes.entry(ip).or_insert((now, 0));
+            if now.duration_since(*start) >= WINDOW {
+                *start = now;
+                *count = 0;
+            }
+            if *count >= max {
+                return false;
+            }
+            *count += 1;
+        }
+        self.connections.insert(ip, open + 1);
+        self.conn_ips.insert(conn_id, ip);
+        true
+    }
+
+    fn release(&mut self, conn_id: ProxyConnection) {
+        let Some(ip) = self.conn_ips.remove(&conn_id) else {
+            return;
+        };
+        if let Some(open) = self.connections.get_mut(&ip) {
+            *open -= 1;
+            if *open == 0 {
+                self.connections.remove(&ip);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.connections.clear();
+        self.conn_ips.clear();
+        self.handshakes.clear();
+    }
+}
+
+/// Counts a newly accepted connection from `ip` on a listener with
+/// `listener` options. Returns false when it exceeds `maxConnectionsPerIp`
+/// or `handshakesPerIpPerSecond` and must be dropped.
+pub fn admit(conn_id: ProxyConnection, ip: IpAddr, listener: &ListenerOptions) -> bool {
+    let (max_connections, handshakes_per_sec) = {
+        let options = OPTIONS.read().unwrap();
+        (
+            listener.max_connections_per_ip.or(options.max_connections_per_ip),
+            listener
+                .handshakes_per_ip_per_second
+                .or(options.handshakes_per_ip_per_second),
+        )
+    };
+    IP_LIMITS.lock().unwrap().admit(
+        conn_id,
+        ip,
+        max_connections,
+        handshakes_per_sec,
+        Instant::now(),
+    )
+}
+
+/// Stops counting a closed connection.
+pub fn release(conn_id: ProxyConnection) {
+    IP_LIMITS.lock().unwrap().release(conn_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_connections() {
+        let mut limits = IpLimits::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+        assert!(limits.admit(1, ip, Some(2), None, now));
+        assert!(limits.admit(2, ip, Some(2), None, now));
+        assert!(!limits.admit(3, ip, Some(2), None, now));
+        assert!(limits.admit(4, other, Some(2), None, now));
+
+        limits.release(1);
+        assert!(limits.admit(5, ip, Some(2), None, now));
+        limits.release(2);
+        limits.release(5);
+        limits.release(3);
+        assert!(!limits.connections.contains_key(&ip));
+    }
+
+    #[test]
+    fn test_handshake_rate() {
+        let mut limits = IpLimits::default();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        assert!(limits.admit(1, ip, None, Some(2), now));
+        assert!(limits.admit(2, ip, None, Some(2), now));
+        assert!(!limits.admit(3, ip, None, Some(2), now + Duration::from_millis(500)));
+        assert!(limits.admit(4, ip, None, Some(2), now + WINDOW));
+    }
+}
@@ -0,0 +1,312 @@
+//! geofront/src/loadgen.rs
+//! Simulated Minecraft clients for benchmarking a proxy configuration or
+//! backend capacity (`loadgen` feature). Each client connects, pipelines a
+//! handshake and login start, optionally streams filler packets, and stays
+//! connected for the configured duration.
+
+use crate::{
+    latency::LatencySummary,
+    state::{LISTENER_STATE, LOADGEN_RESULT, LOADGEN_TASK},
+    types::LoadGenConfig,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Outcome of a load-generation run.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadGenReport {
+    pub clients: u32,
+    /// TCP connections established.
+    pub connected: u64,
+    /// Clients that received any bytes after sending their login.
+    pub logged_in: u64,
+    pub failed: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub elapsed_ms: u64,
+    pub connect_ms: LatencySummary,
+    /// From sending the login to the first byte back from the server.
+    pub first_byte_ms: LatencySummary,
+    /// Failures by cause.
+    pub errors: HashMap<String, u64>,
+}
+
+/// State of the run started through FFI.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadGenStatus {
+    pub running: bool,
+    pub report: Option<LoadGenReport>,
+    pub error: Option<String>,
+}
+
+/// Starts a run in the background; only one may be in progress.
+pub fn start(config: LoadGenConfig) -> Result<(), String> {
+    let mut task = LOADGEN_TASK.lock().unwrap();
+    if task.as_ref().is_some_and(|h| !h.is_finished()) {
+        return Err("a load-generation run is already in progress".to_string());
+    }
+    *LOADGEN_RESULT.lock().unwrap() = None;
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    *task = Some(runtime.spawn(async move {
+        let result = run(config).await;
+        *LOADGEN_RESULT.lock().unwrap() = Some(result);
+    }));
+    Ok(())
+}
+
+/// Aborts the run in progress, if any.
+pub fn stop() -> bool {
+    match LOADGEN_TASK.lock().unwrap().take() {
+        Some(handle) => {
+            let running = !handle.is_finished();
+            handle.abort();
+            running
+        }
+        None => false,
+    }
+}
+
+pub fn status() -> LoadGenStatus {
+    let running = LOADGEN_TASK
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|h| !h.is_finished());
+    let (report, error) = match LOADGEN_RESULT.lock().unwrap().clone() {
+        Some(Ok(report)) => (Some(report), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    LoadGenStatus {
+        running,
+        report,
+        error,
+    }
+}
+
+#[cfg(not(feature = "loadgen"))]
+pub async fn run(_config: LoadGenConfig) -> Result<LoadGenReport, String> {
+    Err("geofront was built without the `loadgen` feature".to_string())
+}
+
+#[cfg(feature = "loadgen")]
+pub use imp::run;
+
+#[cfg(feature = "loadgen")]
+mod imp {
+    use super::*;
+    use crate::protocol::{write_string, write_varint};
+    use std::{
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicU64, Ordering},
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, tcp::OwnedReadHalf, tcp::OwnedWriteHalf},
+        task::JoinSet,
+        time::{Instant, sleep, sleep_until, timeout},
+    };
+
+    /// Packet id of the filler stream; unused by vanilla servers.
+    const FILLER_PACKET_ID: i32 = 0x7f;
+
+    #[derive(Default)]
+    struct Stats {
+        connected: AtomicU64,
+        logged_in: AtomicU64,
+        failed: AtomicU64,
+        bytes_sent: AtomicU64,
+        bytes_recv: AtomicU64,
+        connect_us: Mutex<Vec<u64>>,
+        first_byte_us: Mutex<Vec<u64>>,
+        errors: Mutex<HashMap<String, u64>>,
+    }
+
+    impl Stats {
+        fn fail(&self, cause: impl Into<String>) {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            *self.errors.lock().unwrap().entry(cause.into()).or_insert(0) += 1;
+        }
+    }
+
+    pub async fn run(config: LoadGenConfig) -> Result<LoadGenReport, String> {
+        if config.clients == 0 {
+            return Err("clients must be at least 1".to_string());
+        }
+        let config = Arc::new(config);
+        let stats = Arc::new(Stats::default());
+        let started = Instant::now();
+
+        let mut clients = JoinSet::new();
+        for index in 0..config.clients {
+            let delay = config.ramp_up_ms * index as u64 / config.clients as u64;
+            let (config, stats) = (config.clone(), stats.clone());
+            clients.spawn(async move {
+                sleep(Duration::from_millis(delay)).await;
+                client(index, &config, &stats).await;
+            });
+        }
+        while clients.join_next().await.is_some() {}
+
+        let take = |samples: &Mutex<Vec<u64>>| std::mem::take(&mut *samples.lock().unwrap());
+        Ok(LoadGenReport {
+            clients: config.clients,
+            connected: stats.connected.load(Ordering::Relaxed),
+            logged_in: stats.logged_in.load(Ordering::Relaxed),
+            failed: stats.failed.load(Ordering::Relaxed),
+            bytes_sent: stats.bytes_sent.load(Ordering::Relaxed),
+            bytes_recv: stats.bytes_recv.load(Ordering::Relaxed),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            connect_ms: LatencySummary::from_micros(take(&stats.connect_us)),
+            first_byte_ms: LatencySummary::from_micros(take(&stats.first_byte_us)),
+            errors: std::mem::take(&mut *stats.errors.lock().unwrap()),
+        })
+    }
+
+    async fn client(index: u32, config: &LoadGenConfig, stats: &Stats) {
+        let started = Instant::now();
+        let ends_at = started + Duration::from_millis(config.duration_ms);
+        let connect_timeout = Duration::from_millis(config.connect_timeout_ms);
+        let stream = match timeout(connect_timeout, TcpStream::connect(&config.target)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => return stats.fail(format!("connect: {}", e.kind())),
+            Err(_) => return stats.fail("connect: timed out"),
+        };
+        stats.connected.fetch_add(1, Ordering::Relaxed);
+        stats
+            .connect_us
+            .lock()
+            .unwrap()
+            .push(started.elapsed().as_micros() as u64);
+        let _ = stream.set_nodelay(true);
+        let (reader, mut writer) = stream.into_split();
+
+        // Like real clients, send handshake and login start in one flush.
+        let mut hello = handshake(config);
+        hello.extend(login_start(config, index));
+        if let Err(e) = writer.write_all(&hello).await {
+            return stats.fail(format!("write: {}", e.kind()));
+        }
+        stats
+            .bytes_sent
+            .fetch_add(hello.len() as u64, Ordering::Relaxed);
+        let login_sent = Instant::now();
+
+        tokio::select! {
+            _ = sleep_until(ends_at) => {}
+            _ = receive(reader, login_sent, stats) => {}
+            _ = send_filler(writer, config, stats) => {}
+        }
+    }
+
+    /// Counts bytes from the server until it closes the connection.
+    async fn receive(mut reader: OwnedReadHalf, login_sent: Instant, stats: &Stats) {
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut first = true;
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => return stats.fail("closed by server"),
+                Ok(n) => {
+                    if first {
+                        first = false;
+                        stats.logged_in.fetch_add(1, Ordering::Relaxed);
+                        stats
+                            .first_byte_us
+                            .lock()
+                            .unwrap()
+                            .push(login_sent.elapsed().as_micros() as u64);
+                    }
+                    stats.bytes_recv.fetch_add(n as u64, Ordering::Relaxed);
+                }
+                Err(e) => return stats.fail(format!("read: {}", e.kind())),
+            }
+        }
+    }
+
+    /// Streams filler packets at the configured rate; never returns when
+    /// the rate is zero.
+    async fn send_filler(mut writer: OwnedWriteHalf, config: &LoadGenConfig, stats: &Stats) {
+        if config.payload_bytes_per_sec == 0 {
+            return std::future::pending().await;
+        }
+        let chunk = config.payload_chunk_bytes.max(1);
+        let packet = frame(FILLER_PACKET_ID, &vec![0u8; chunk]);
+        let period = Duration::from_secs_f64(chunk as f64 / config.payload_bytes_per_sec as f64);
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = writer.write_all(&packet).await {
+                return stats.fail(format!("write: {}", e.kind()));
+            }
+            stats
+                .bytes_sent
+                .fetch_add(packet.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn frame(id: i32, body: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(body.len() + 5);
+        write_varint(&mut payload, id);
+        payload.extend_from_slice(body);
+        let mut packet = Vec::with_capacity(payload.len() + 5);
+        write_varint(&mut packet, payload.len() as i32);
+        packet.extend(payload);
+        packet
+    }
+
+    fn handshake(config: &LoadGenConfig) -> Vec<u8> {
+        let (target_host, target_port) = config
+            .target
+            .rsplit_once(':')
+            .map(|(h, p)| (h, p.parse().unwrap_or(25565u16)))
+            .unwrap_or((config.target.as_str(), 25565));
+        let mut body = Vec::new();
+        write_varint(&mut body, config.protocol);
+        write_string(&mut body, config.host.as_deref().unwrap_or(target_host));
+        body.extend(target_port.to_be_bytes());
+        write_varint(&mut body, 2);
+        frame(0x00, &body)
+    }
+
+    /// Login Start in the layout of the configured protocol version.
+    fn login_start(config: &LoadGenConfig, index: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_string(&mut body, &format!("{}{}", config.username_prefix, index));
+        let uuid = (index as u128).to_be_bytes();
+        match config.protocol {
+            // 1.19: no signature data.
+            759 => body.push(0),
+            // 1.19.1/1.19.2: no signature data, no UUID.
+            760 => body.extend([0, 0]),
+            // 1.19.3 - 1.20.1: no UUID.
+            761..=763 => body.push(0),
+            // 1.20.2+: UUID is mandatory.
+            764.. => body.extend(uuid),
+            _ => {}
+        }
+        frame(0x00, &body)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::protocol::{parse_handshake, parse_login_start};
+
+        #[tokio::test]
+        async fn test_hello_parses() {
+            let config: LoadGenConfig =
+                serde_json::from_str(r#"{"target":"mc.example.com:25577"}"#).unwrap();
+            let mut hello = handshake(&config);
+            hello.extend(login_start(&config, 7));
+            let mut reader = &hello[..];
+            let hs = parse_handshake(&mut reader).await.unwrap();
+            assert_eq!((hs.host.as_str(), hs.port), ("mc.example.com", 25577));
+            assert_eq!(parse_login_start(&mut reader).await.unwrap(), "bench7");
+        }
+    }
+}
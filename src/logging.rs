@@ -1,22 +1,368 @@
-//! geofront/src/logging.rs
-//! Logging initialization and runtime updates.
-
-use crate::state::RELOAD_HANDLE;
-use std::sync::Once;
+use crate::{
+    events,
+    state::{CONN_SPANS, LOG_HANDLER, LOG_RECORD_QUEUE, LOG_RECORDS_DROPPED, RELOAD_HANDLE},
+    types::{LogRecord, ProxyConnection},
+};
+use serde_json::Value;
+use std::{
+    fmt::Debug,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, Once, atomic::Ordering},
+};
+use tracing::{
+    Event, Span, Subscriber,
+    field::{self, Field, Visit},
+    info_span,
+    span::{Attributes, Id, Record},
+};
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{filter::EnvFilter, fmt, reload::Layer as ReloadLayer};
+use tracing_subscriber::{
+    filter::EnvFilter,
+    fmt,
+    layer::{Context, Layer},
+    registry::LookupSpan,
+    reload::Layer as ReloadLayer,
+};
+
+/// Embedder callback taking log records instead of the queue. It runs on
+/// the thread that logged and must not log itself.
+pub type LogHandler = Arc<dyn Fn(&LogRecord) + Send + Sync>;
 
 static LOG_INIT: Once = Once::new();
 
-// Initialize logging once
+/// Size at which a JSON log file is rotated.
+const MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Rotated files kept as `<path>.1` (newest) to `<path>.<n>`.
+const ROTATED_FILES: u32 = 5;
+
+/// Where log lines go and how they are formatted.
+enum Output {
+    Text,
+    Json(Option<RotatingFile>),
+    /// Records for the host, keeping up to this many until polled.
+    Queue(usize),
+}
+
 pub fn init_logging(default: &str) {
+    init(default, Output::Text);
+}
+
+/// Initializes logging as JSON lines, written to stdout or appended to the
+/// file at `path`, rotated by size. Returns `Ok(false)` if logging was
+/// already initialized, in which case the format stays as it was.
+pub fn init_logging_json(default: &str, path: Option<&str>) -> io::Result<bool> {
+    let file = path.map(|path| RotatingFile::open(path.into(), MAX_FILE_BYTES)).transpose()?;
+    Ok(init(default, Output::Json(file)))
+}
+
+/// Initializes logging as records queued for `poll_records`, or handed to the
+/// handler set with `Geofront::set_log_handler`. Once `capacity` records
+/// are waiting the oldest are dropped. Returns `false` if logging was
+/// already initialized.
+pub fn init_logging_queue(default: &str, capacity: usize) -> bool {
+    init(default, Output::Queue(capacity.max(1)))
+}
+
+/// Takes the queued log records, oldest first.
+pub fn poll_records() -> Vec<LogRecord> {
+    std::mem::take(&mut *LOG_RECORD_QUEUE.lock().unwrap())
+}
+
+fn init(default: &str, output: Output) -> bool {
+    let mut initialized = false;
     LOG_INIT.call_once(|| {
         let filter = EnvFilter::new(default);
         let (reload_layer, handle) = ReloadLayer::new(filter);
-        let subscriber = tracing_subscriber::registry()
-            .with(reload_layer)
-            .with(fmt::layer());
+        let output = match output {
+            Output::Text => fmt::layer().boxed(),
+            Output::Json(None) => fmt::layer().json().flatten_event(true).boxed(),
+            Output::Json(Some(file)) => fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .boxed(),
+            Output::Queue(capacity) => QueueLayer { capacity }.boxed(),
+        };
+        let subscriber = tracing_subscriber::registry().with(reload_layer).with(output);
         tracing::subscriber::set_global_default(subscriber).unwrap();
         *RELOAD_HANDLE.lock().unwrap() = Some(handle);
+        initialized = true;
     });
+    initialized
+}
+
+/// The span a connection's task runs in, so that every line logged while
+/// handling it carries `conn_id` and `peer_ip`, then `host` and `username`
+/// once known and the host's `labels` (see `set_fields`).
+pub fn conn_span(conn_id: ProxyConnection, peer_ip: IpAddr) -> Span {
+    let span = info_span!(
+        "conn",
+        conn_id,
+        peer_ip = %peer_ip,
+        host = field::Empty,
+        username = field::Empty,
+        labels = field::Empty,
+    );
+    CONN_SPANS.insert(conn_id, span.clone());
+    span
+}
+
+/// Sets a connection's span field, such as `host` once the handshake is read.
+pub fn record_field(conn_id: ProxyConnection, name: &str, value: &str) {
+    if let Some(span) = CONN_SPANS.get(&conn_id) {
+        span.record(name, value);
+    }
+}
+
+/// Replaces the host-defined labels logged with a connection's lines;
+/// returns `false` if the connection is unknown.
+pub fn set_fields(conn_id: ProxyConnection, fields: &serde_json::Map<String, Value>) -> bool {
+    let Some(span) = CONN_SPANS.get(&conn_id) else {
+        return false;
+    };
+    span.record("labels", Value::Object(fields.clone()).to_string().as_str());
+    true
+}
+
+/// Forgets a closed connection's span.
+pub fn remove_span(conn_id: ProxyConnection) {
+    CONN_SPANS.remove(&conn_id);
+}
+
+/// Turns events into `LogRecord`s for the host, with the fields of the
+/// spans they were logged in.
+struct QueueLayer {
+    capacity: usize,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for QueueLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = RecordFields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<RecordFields>()
+        {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut record = record(event);
+        for span in ctx.event_scope(event).into_iter().flatten() {
+            let extensions = span.extensions();
+            let Some(fields) = extensions.get::<RecordFields>() else {
+                continue;
+            };
+            record.conn_id = record.conn_id.or(fields.conn_id);
+            for (name, value) in &fields.others {
+                record.fields.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        let handler = LOG_HANDLER.read().unwrap().clone();
+        match handler {
+            Some(handler) => handler(&record),
+            None => {
+                let mut queue = LOG_RECORD_QUEUE.lock().unwrap();
+                if queue.len() >= self.capacity {
+                    let excess = queue.len() + 1 - self.capacity;
+                    queue.drain(..excess);
+                    LOG_RECORDS_DROPPED.fetch_add(excess as u64, Ordering::SeqCst);
+                }
+                queue.push(record);
+            }
+        }
+    }
+}
+
+fn record(event: &Event<'_>) -> LogRecord {
+    let metadata = event.metadata();
+    let mut fields = RecordFields::default();
+    event.record(&mut fields);
+    LogRecord {
+        timestamp_ms: events::now_ms(),
+        level: metadata.level().as_str().to_ascii_lowercase(),
+        target: metadata.target().to_string(),
+        conn_id: fields.conn_id,
+        message: fields.message,
+        fields: fields.others,
+    }
+}
+
+#[derive(Default)]
+struct RecordFields {
+    conn_id: Option<u64>,
+    message: String,
+    others: serde_json::Map<String, Value>,
+}
+
+impl Visit for RecordFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "conn" | "conn_id" => self.conn_id = Some(value),
+            name => {
+                self.others.insert(name.to_string(), value.into());
+            }
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.others.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.others.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.others.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            // Host-defined labels are logged as fields of their own.
+            "labels" => {
+                if let Ok(Value::Object(labels)) = serde_json::from_str(value) {
+                    self.others.extend(labels);
+                }
+            }
+            name => {
+                self.others.insert(name.to_string(), value.into());
+            }
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name => {
+                self.others.insert(name.to_string(), format!("{:?}", value).into());
+            }
+        }
+    }
+}
+
+/// A log file that is moved aside once it would grow past `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(RotatingFile { path, file, len, max_bytes })
+    }
+
+    fn rotated(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..ROTATED_FILES).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    /// Each event is formatted whole and written at once, so files are
+    /// rotated between lines.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_records() {
+        let layer = QueueLayer { capacity: 2 };
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(attempt = 2, "Retrying");
+            tracing::info!(conn = 7u64, backend = "10.0.0.1:25565", "Connected to {}", "backend");
+            tracing::debug!("Closed");
+        });
+        let records = poll_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].level, "info");
+        assert_eq!(records[0].conn_id, Some(7));
+        assert_eq!(records[0].message, "Connected to backend");
+        assert_eq!(records[0].fields["backend"], "10.0.0.1:25565");
+        assert_eq!(records[1].level, "debug");
+        assert_eq!(records[1].conn_id, None);
+        assert!(LOG_RECORDS_DROPPED.load(Ordering::SeqCst) >= 1);
+        assert!(poll_records().is_empty());
+
+        // Run in the same test, as both use the global queue.
+        let subscriber = tracing_subscriber::registry().with(QueueLayer { capacity: 16 });
+        let conn_id = u64::MAX - 7;
+        tracing::subscriber::with_default(subscriber, || {
+            let span = conn_span(conn_id, "198.51.100.7".parse().unwrap());
+            let _entered = span.enter();
+            record_field(conn_id, "host", "mc.example.com");
+            let labels = serde_json::json!({ "region": "eu" });
+            assert!(set_fields(conn_id, labels.as_object().unwrap()));
+            tracing::info!(target: "geofront::span_test", "Routed");
+        });
+        remove_span(conn_id);
+        assert!(!set_fields(conn_id, &serde_json::Map::new()));
+        let records = poll_records();
+        let record = &records[0];
+        assert_eq!(record.conn_id, Some(conn_id));
+        assert_eq!(record.fields["peer_ip"], "198.51.100.7");
+        assert_eq!(record.fields["host"], "mc.example.com");
+        assert_eq!(record.fields["region"], "eu");
+    }
+
+    #[test]
+    fn test_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("geofront-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("geofront.log");
+        let mut file = RotatingFile::open(path.clone(), 16).unwrap();
+        file.write_all(b"{\"n\":1}\n").unwrap();
+        file.write_all(b"{\"n\":2}\n").unwrap();
+        file.write_all(b"{\"n\":3}\n").unwrap();
+        let current = fs::read(&path).unwrap();
+        let rotated = fs::read(dir.join("geofront.log.1")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(current, b"{\"n\":3}\n");
+        assert_eq!(rotated, b"{\"n\":1}\n{\"n\":2}\n");
+    }
 }
@@ -0,0 +1,231 @@
+//! geofront/src/login_phase.rs
+//! Follows the backend's side of the login phase packet by packet, so the
+//! compression threshold and the player UUID from Login Success are known
+//! before the relay goes byte-blind (`trackLoginPhase`).
+//!
+//! Tracking stops at the first thing that cannot be read in plain: an
+//! Encryption Request (online-mode backend) or a compressed packet.
+
+/// First protocol sending the Login Success UUID as 16 bytes (1.16).
+const BINARY_UUID_PROTOCOL: i32 = 735;
+
+/// What was learned about the login phase.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoginState {
+    pub compression_threshold: Option<i32>,
+    /// Hyphenated UUID from Login Success.
+    pub uuid: Option<String>,
+    /// The backend asked for encryption.
+    pub encrypted: bool,
+    /// Login Success was seen.
+    pub succeeded: bool,
+}
+
+pub struct LoginTracker {
+    protocol: i32,
+    buf: Vec<u8>,
+    state: LoginState,
+    done: bool,
+}
+
+impl LoginTracker {
+    pub fn new(protocol: i32) -> Self {
+        Self {
+            protocol,
+            buf: Vec::new(),
+            state: LoginState::default(),
+            done: false,
+        }
+    }
+
+    /// Whether nothing more can or needs to be learned.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn state(&self) -> &LoginState {
+        &self.state
+    }
+
+    /// Bytes fed after the packet tracking stopped at.
+    pub fn remainder(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consumes bytes the backend sent to the client.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        if self.done {
+            return;
+        }
+        self.buf.extend_from_slice(bytes);
+        let mut pos = 0;
+        while !self.done {
+            let Some((len, header)) = read_varint(&self.buf[pos..]) else {
+                break;
+            };
+            let Ok(len) = usize::try_from(len) else {
+                self.done = true;
+                break;
+            };
+            if self.buf.len() - pos - header < len {
+                break;
+            }
+            let start = pos + header;
+            let packet = self.buf[start..start + len].to_vec();
+            pos = start + len;
+            self.packet(&packet);
+        }
+        self.buf.drain(..pos);
+    }
+
+    fn packet(&mut self, mut packet: &[u8]) {
+        if self.state.compression_threshold.is_some() {
+            match read_varint(packet) {
+                // Uncompressed below the threshold
+                Some((0, n)) => packet = &packet[n..],
+                _ => {
+                    self.done = true;
+                    return;
+                }
+            }
+        }
+        let Some((id, n)) = read_varint(packet) else {
+            self.done = true;
+            return;
+        };
+        let body = &packet[n..];
+        match id {
+            // Encryption Request
+            0x01 => {
+                self.state.encrypted = true;
+                self.done = true;
+            }
+            // Login Success
+            0x02 => {
+                self.state.uuid = self.read_uuid(body);
+                self.state.succeeded = true;
+                self.done = true;
+            }
+            // Set Compression
+            0x03 => match read_varint(body) {
+                Some((threshold, _)) => {
+                    self.state.compression_threshold = (threshold >= 0).then_some(threshold)
+                }
+                None => self.done = true,
+            },
+            // Login Plugin Request, Cookie Request
+            0x04 | 0x05 => {}
+            // Disconnect, or anything unknown
+            _ => self.done = true,
+        }
+    }
+
+    fn read_uuid(&self, body: &[u8]) -> Option<String> {
+        let hex: String = if self.protocol >= BINARY_UUID_PROTOCOL {
+            body.get(..16)?.iter().map(|b| format!("{:02x}", b)).collect()
+        } else {
+            let (len, n) = read_varint(body)?;
+            let raw = body.get(n..n + usize::try_from(len).ok()?)?;
+            std::str::from_utf8(raw).ok()?.replace('-', "").to_ascii_lowercase()
+        };
+        if hex.len() != 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+}
+
+/// Reads a VarInt from the start of `buf`, returning it and its length, or
+/// `None` if `buf` ends first or it is too long.
+pub(crate) fn read_varint(buf: &[u8]) -> Option<(i32, usize)> {
+    let mut result = 0i32;
+    for (i, byte) in buf.iter().take(5).enumerate() {
+        result |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((result, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{write_string, write_varint};
+
+    /// Frames `body` as an uncompressed packet.
+    fn frame(body: &[u8], compressed_format: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        if compressed_format {
+            write_varint(&mut data, 0);
+        }
+        data.extend_from_slice(body);
+        let mut packet = Vec::new();
+        write_varint(&mut packet, data.len() as i32);
+        packet.extend(data);
+        packet
+    }
+
+    const UUID: [u8; 16] = [
+        0x06, 0x9a, 0x79, 0xf4, 0x44, 0xe9, 0x47, 0x26, 0xa5, 0xbe, 0xfc, 0xa9, 0x0e, 0x38, 0xaa,
+        0xf5,
+    ];
+
+    fn login_success(protocol: i32) -> Vec<u8> {
+        let mut body = vec![0x02];
+        if protocol >= BINARY_UUID_PROTOCOL {
+            body.extend_from_slice(&UUID);
+        } else {
+            write_string(&mut body, "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        }
+        write_string(&mut body, "Notch");
+        body
+    }
+
+    #[test]
+    fn test_compression_then_success() {
+        let mut tracker = LoginTracker::new(767);
+        let mut set_compression = vec![0x03];
+        write_varint(&mut set_compression, 256);
+        let mut stream = frame(&set_compression, false);
+        stream.extend(frame(&login_success(767), true));
+
+        // Fed in pieces, as reads split it.
+        for chunk in stream.chunks(3) {
+            tracker.feed(chunk);
+        }
+        assert!(tracker.is_done());
+        assert_eq!(
+            tracker.state(),
+            &LoginState {
+                compression_threshold: Some(256),
+                uuid: Some("069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string()),
+                encrypted: false,
+                succeeded: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_string_uuid_and_encryption() {
+        let mut tracker = LoginTracker::new(340);
+        tracker.feed(&frame(&login_success(340), false));
+        assert_eq!(
+            tracker.state().uuid.as_deref(),
+            Some("069a79f4-44e9-4726-a5be-fca90e38aaf5")
+        );
+
+        let mut tracker = LoginTracker::new(767);
+        tracker.feed(&frame(&[0x01, 0x00], false));
+        assert!(tracker.is_done());
+        assert!(tracker.state().encrypted);
+        assert!(!tracker.state().succeeded);
+    }
+}
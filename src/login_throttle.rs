@@ -0,0 +1,88 @@
+//! geofront/src/login_throttle.rs
+//! Login attempts throttled per username and per client IP
+//! (`loginThrottle`), so reconnect-spam bots are turned away with the
+//! `loginThrottled` message before reaching the router or a backend. Each
+//! key may attempt `attempts` logins per `periodMs`, refilling gradually.
+
+use crate::{
+    state::{LOGIN_THROTTLE, OPTIONS},
+    types::LoginThrottleConfig,
+};
+use governor::{DefaultKeyedRateLimiter, Quota};
+use std::{num::NonZeroU32, sync::Arc, time::Duration};
+
+/// Limiter keys are pruned once this many are tracked.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// The limiter of a config, kept while the config stays the same.
+pub struct Throttle {
+    config: LoginThrottleConfig,
+    limiter: Arc<DefaultKeyedRateLimiter<String>>,
+}
+
+fn limiter_of(config: &LoginThrottleConfig) -> Option<Arc<DefaultKeyedRateLimiter<String>>> {
+    let mut throttle = LOGIN_THROTTLE.lock().unwrap();
+    if let Some(current) = throttle.as_ref()
+        && current.config == *config
+    {
+        return Some(current.limiter.clone());
+    }
+    let attempts = NonZeroU32::new(config.attempts)?;
+    let quota = Quota::with_period(Duration::from_millis(config.period_ms) / attempts.get())?
+        .allow_burst(attempts);
+    let limiter = Arc::new(DefaultKeyedRateLimiter::keyed(quota));
+    *throttle = Some(Throttle {
+        config: config.clone(),
+        limiter: limiter.clone(),
+    });
+    Some(limiter)
+}
+
+/// Counts a login attempt; `false` if the username or the IP is over its
+/// allowance.
+pub fn admit(username: &str, peer_ip: &str) -> bool {
+    let Some(config) = OPTIONS.read().unwrap().login_throttle.clone() else {
+        return true;
+    };
+    let Some(limiter) = limiter_of(&config) else {
+        return true;
+    };
+    if limiter.len() >= PRUNE_THRESHOLD {
+        limiter.retain_recent();
+    }
+    let mut keys = Vec::with_capacity(2);
+    if config.by_username {
+        keys.push(format!("user:{}", username.to_ascii_lowercase()));
+    }
+    if config.by_ip {
+        keys.push(format!("ip:{}", peer_ip));
+    }
+    // Every key is charged, so a spammer rotating names still hits its IP.
+    keys.iter().filter(|key| limiter.check_key(key).is_err()).count() == 0
+}
+
+/// Forgets all attempts.
+pub fn reset() {
+    *LOGIN_THROTTLE.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_per_key() {
+        let config = LoginThrottleConfig {
+            attempts: 2,
+            period_ms: 60_000,
+            by_username: true,
+            by_ip: true,
+        };
+        let limiter = limiter_of(&config).unwrap();
+        assert!(limiter.check_key(&"user:throttletest".to_string()).is_ok());
+        assert!(limiter.check_key(&"user:throttletest".to_string()).is_ok());
+        assert!(limiter.check_key(&"user:throttletest".to_string()).is_err());
+        assert!(limiter.check_key(&"user:other".to_string()).is_ok());
+        assert!(limiter_of(&LoginThrottleConfig { attempts: 0, ..config }).is_none());
+    }
+}
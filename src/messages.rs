@@ -0,0 +1,512 @@
+//! geofront/src/messages.rs
+//! Named disconnect message templates and legacy (`&`/`§`) color code
+//! translation into JSON text components.
+//!
+//! Templates are text with color codes or JSON text components, and may
+//! use `{host}`, `{username}`, `{backend}` and `{conn}` placeholders.
+
+use crate::{state::OPTIONS, types::ProxyConnection};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use tracing::warn;
+
+// Built-in template keys. Hosts may override any of them through the
+// `messages` option and add their own.
+pub const SERVER_FULL: &str = "serverFull";
+pub const MAINTENANCE: &str = "maintenance";
+pub const BANNED: &str = "banned";
+pub const BACKEND_DOWN: &str = "backendDown";
+pub const ROUTING_ERROR: &str = "routingError";
+pub const BLOCKED: &str = "blocked";
+pub const AUTH_FAILED: &str = "authFailed";
+pub const QUOTA_EXCEEDED: &str = "quotaExceeded";
+pub const UNSUPPORTED_VERSION: &str = "unsupportedVersion";
+pub const LOGIN_THROTTLED: &str = "loginThrottled";
+pub const ROUTING_TIMEOUT: &str = "routingTimeout";
+pub const PROXY_FULL: &str = "proxyFull";
+
+/// First protocol version (1.16) that accepts `#rrggbb` colors.
+const HEX_COLOR_PROTOCOL: i32 = 735;
+
+fn default_template(key: &str) -> Option<&'static str> {
+    Some(match key {
+        SERVER_FULL => "&cThe server is full.",
+        MAINTENANCE => "&eThe server is under maintenance. Please try again later.",
+        BANNED => "&cYou are banned from this server.",
+        BACKEND_DOWN => "Could not connect to the destination server.",
+        ROUTING_ERROR => "Internal routing error.",
+        ROUTING_TIMEOUT => "&cThe server took too long to respond. Please try again.",
+        BLOCKED => "Connection blocked by cache",
+        AUTH_FAILED => "Failed to verify username!",
+        QUOTA_EXCEEDED => "&cYou have used up your traffic quota.",
+        LOGIN_THROTTLED => "&cToo many login attempts. Please wait before reconnecting.",
+        PROXY_FULL => "&cThe proxy is full. Please try again later.",
+        UNSUPPORTED_VERSION => "&cYour client version is not supported (protocol {protocol}, accepted {min}-{max}).",
+        _ => return None,
+    })
+}
+
+/// Text of a template, preferring the configured one over the built-in default.
+pub fn template(key: &str) -> Option<String> {
+    if let Some(text) = OPTIONS.read().unwrap().messages.get(key) {
+        return Some(text.clone());
+    }
+    default_template(key).map(str::to_string)
+}
+
+/// Text for a built-in template key; always resolves.
+pub fn builtin(key: &str) -> String {
+    template(key).unwrap_or_else(|| key.to_string())
+}
+
+/// Reads the `messages` option, keeping JSON text components as their JSON.
+pub fn deserialize_templates<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let templates = HashMap::<String, Value>::deserialize(deserializer)?;
+    Ok(templates
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(text) => (key, text),
+            component => (key, component.to_string()),
+        })
+        .collect())
+}
+
+/// Values for the placeholders of a message; those without one are left as
+/// written.
+#[derive(Default)]
+pub struct Placeholders<'a> {
+    pub host: Option<&'a str>,
+    pub username: Option<&'a str>,
+    pub backend: Option<&'a str>,
+    pub conn_id: Option<ProxyConnection>,
+}
+
+/// Fills in the placeholders of `text`, escaping the values when it is a
+/// JSON component so that client-sent hosts and names cannot break it.
+pub fn fill(text: &str, values: &Placeholders) -> String {
+    let json = is_component(text);
+    let conn_id = values.conn_id.map(|id| id.to_string());
+    let mut filled = text.to_string();
+    for (placeholder, value) in [
+        ("{host}", values.host),
+        ("{username}", values.username),
+        ("{backend}", values.backend),
+        ("{conn}", conn_id.as_deref()),
+    ] {
+        let Some(value) = value else {
+            continue;
+        };
+        let value = if json {
+            let quoted = Value::String(value.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        } else {
+            value.to_string()
+        };
+        filled = filled.replace(placeholder, &value);
+    }
+    filled
+}
+
+/// Text for a built-in template key with its placeholders filled in.
+pub fn builtin_with(key: &str, values: &Placeholders) -> String {
+    fill(&builtin(key), values)
+}
+
+/// The `unsupportedVersion` message, with `{protocol}`, `{min}` and `{max}`
+/// filled in.
+pub fn unsupported_version(protocol: i32, min: i32, max: i32) -> String {
+    builtin(UNSUPPORTED_VERSION)
+        .replace("{protocol}", &protocol.to_string())
+        .replace("{min}", &min.to_string())
+        .replace("{max}", &max.to_string())
+}
+
+/// The disconnect message of a decision: a template reference wins over a
+/// literal message. Unknown templates fall back to the literal, then the key.
+pub fn rejection(disconnect: &Option<String>, template_key: &Option<String>) -> Option<String> {
+    if let Some(key) = template_key {
+        if let Some(text) = template(key) {
+            return Some(text);
+        }
+        warn!(template = %key, "Unknown message template");
+        return Some(disconnect.clone().unwrap_or_else(|| key.clone()));
+    }
+    disconnect.clone()
+}
+
+/// Reads a `disconnect` message, which may also be given as a JSON text
+/// component, kept as its JSON.
+pub fn deserialize_message<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => None,
+        Some(Value::String(text)) => Some(text),
+        Some(component) => Some(component.to_string()),
+    })
+}
+
+/// Serializes a disconnect message as a JSON text component. Valid JSON
+/// components are passed through, with hex colors turned into the nearest
+/// named ones for clients older than 1.16; anything else, including JSON
+/// that is no text component, is read as text with color codes.
+pub fn to_component_json(msg: &str, protocol: i32) -> String {
+    match parse_component(msg) {
+        Some(mut component) => {
+            if protocol < HEX_COLOR_PROTOCOL && downgrade_colors(&mut component) {
+                component.to_string()
+            } else {
+                msg.to_string()
+            }
+        }
+        None => legacy_to_component(msg, protocol).to_string(),
+    }
+}
+
+fn is_component(msg: &str) -> bool {
+    parse_component(msg).is_some()
+}
+
+/// The message as a JSON text component, if it is one.
+fn parse_component(msg: &str) -> Option<Value> {
+    let trimmed = msg.trim_start();
+    if !trimmed.starts_with('{') && !trimmed.starts_with('[') {
+        return None;
+    }
+    let component = serde_json::from_str::<Value>(trimmed).ok()?;
+    valid_component(&component).then_some(component)
+}
+
+/// Whether `value` is a text component: a string, a non-empty array of
+/// components, or an object with content whose `extra` and `with` hold
+/// components.
+fn valid_component(value: &Value) -> bool {
+    const CONTENT: [&str; 6] = ["text", "translate", "score", "selector", "keybind", "nbt"];
+    match value {
+        Value::String(_) => true,
+        Value::Array(parts) => !parts.is_empty() && parts.iter().all(valid_component),
+        Value::Object(obj) => {
+            let children = |key: &str| match obj.get(key) {
+                None => true,
+                Some(Value::Array(parts)) => parts.iter().all(valid_component),
+                Some(_) => false,
+            };
+            CONTENT.iter().any(|key| obj.contains_key(*key)) && children("extra") && children("with")
+        }
+        _ => false,
+    }
+}
+
+/// Replaces `#rrggbb` colors in a component tree with the nearest named
+/// color; returns whether any was replaced.
+fn downgrade_colors(value: &mut Value) -> bool {
+    match value {
+        Value::Array(parts) => parts.iter_mut().fold(false, |changed, part| downgrade_colors(part) | changed),
+        Value::Object(obj) => {
+            let mut changed = false;
+            if let Some(Value::String(color)) = obj.get_mut("color")
+                && let Some(rgb) = color.strip_prefix('#').and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            {
+                *color = nearest_named(rgb).to_string();
+                changed = true;
+            }
+            for key in ["extra", "with"] {
+                if let Some(children) = obj.get_mut(key) {
+                    changed |= downgrade_colors(children);
+                }
+            }
+            changed
+        }
+        _ => false,
+    }
+}
+
+// (code, name, rgb)
+const COLORS: [(char, &str, u32); 16] = [
+    ('0', "black", 0x000000),
+    ('1', "dark_blue", 0x0000AA),
+    ('2', "dark_green", 0x00AA00),
+    ('3', "dark_aqua", 0x00AAAA),
+    ('4', "dark_red", 0xAA0000),
+    ('5', "dark_purple", 0xAA00AA),
+    ('6', "gold", 0xFFAA00),
+    ('7', "gray", 0xAAAAAA),
+    ('8', "dark_gray", 0x555555),
+    ('9', "blue", 0x5555FF),
+    ('a', "green", 0x55FF55),
+    ('b', "aqua", 0x55FFFF),
+    ('c', "red", 0xFF5555),
+    ('d', "light_purple", 0xFF55FF),
+    ('e', "yellow", 0xFFFF55),
+    ('f', "white", 0xFFFFFF),
+];
+
+const FORMATS: [(char, &str); 5] = [
+    ('k', "obfuscated"),
+    ('l', "bold"),
+    ('m', "strikethrough"),
+    ('n', "underlined"),
+    ('o', "italic"),
+];
+
+/// Named color closest to `rgb`, for clients without hex color support.
+fn nearest_named(rgb: u32) -> &'static str {
+    let channels = |c: u32| {
+        [
+            (c >> 16) as i32 & 0xff,
+            (c >> 8) as i32 & 0xff,
+            c as i32 & 0xff,
+        ]
+    };
+    let target = channels(rgb);
+    COLORS
+        .iter()
+        .min_by_key(|(_, _, c)| {
+            channels(*c)
+                .iter()
+                .zip(target)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<i32>()
+        })
+        .map(|(_, name, _)| *name)
+        .unwrap_or("white")
+}
+
+#[derive(Clone, Default)]
+struct Style {
+    color: Option<String>,
+    formats: Vec<&'static str>,
+}
+
+fn is_marker(c: char) -> bool {
+    c == '&' || c == '§'
+}
+
+/// Parses a hex color at the start of `chars`, in either `#rrggbb` or the
+/// Bukkit `x&r&r&g&g&b&b` form. Returns the color and characters consumed.
+fn parse_hex(chars: &[char]) -> Option<(u32, usize)> {
+    let hex = |digits: &[char]| -> Option<u32> {
+        let s: String = digits.iter().collect();
+        (s.len() == 6 && s.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| u32::from_str_radix(&s, 16).ok())
+            .flatten()
+    };
+    match chars.first()? {
+        '#' => Some((hex(chars.get(1..7)?)?, 7)),
+        'x' | 'X' => {
+            let tail = chars.get(1..13)?;
+            let digits: Vec<char> = tail
+                .chunks(2)
+                .map(|pair| is_marker(pair[0]).then_some(pair[1]))
+                .collect::<Option<_>>()?;
+            Some((hex(&digits)?, 13))
+        }
+        _ => None,
+    }
+}
+
+/// Converts text with legacy formatting codes into a JSON text component.
+pub fn legacy_to_component(text: &str, protocol: i32) -> Value {
+    let chars: Vec<char> = text.chars().collect();
+    let mut parts: Vec<(String, Style)> = Vec::new();
+    let mut current = String::new();
+    let mut style = Style::default();
+    let mut i = 0;
+
+    let mut flush = |current: &mut String, style: &Style| {
+        if !current.is_empty() {
+            parts.push((std::mem::take(current), style.clone()));
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).map(|n| n.to_ascii_lowercase());
+        if is_marker(c)
+            && let Some(code) = next
+        {
+            if let Some((rgb, consumed)) = parse_hex(&chars[i + 1..]) {
+                flush(&mut current, &style);
+                style = Style {
+                    color: Some(if protocol >= HEX_COLOR_PROTOCOL {
+                        format!("#{:06x}", rgb)
+                    } else {
+                        nearest_named(rgb).to_string()
+                    }),
+                    formats: Vec::new(),
+                };
+                i += 1 + consumed;
+                continue;
+            }
+            if let Some((_, name, _)) = COLORS.iter().find(|(k, _, _)| *k == code) {
+                // As in vanilla, a color code resets formatting.
+                flush(&mut current, &style);
+                style = Style {
+                    color: Some(name.to_string()),
+                    formats: Vec::new(),
+                };
+                i += 2;
+                continue;
+            }
+            if let Some((_, name)) = FORMATS.iter().find(|(k, _)| *k == code) {
+                flush(&mut current, &style);
+                if !style.formats.contains(name) {
+                    style.formats.push(name);
+                }
+                i += 2;
+                continue;
+            }
+            if code == 'r' {
+                flush(&mut current, &style);
+                style = Style::default();
+                i += 2;
+                continue;
+            }
+        }
+        current.push(c);
+        i += 1;
+    }
+    flush(&mut current, &style);
+
+    let component = |(text, style): (String, Style)| {
+        let mut obj = Map::new();
+        obj.insert("text".to_string(), Value::String(text));
+        if let Some(color) = style.color {
+            obj.insert("color".to_string(), Value::String(color));
+        }
+        for format in style.formats {
+            obj.insert(format.to_string(), Value::Bool(true));
+        }
+        Value::Object(obj)
+    };
+
+    match parts.len() {
+        0 => json!({ "text": "" }),
+        1 => component(parts.remove(0)),
+        _ => json!({
+            "text": "",
+            "extra": parts.into_iter().map(component).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            legacy_to_component("Tom & Jerry", 47),
+            json!({ "text": "Tom & Jerry" })
+        );
+    }
+
+    #[test]
+    fn test_colors_and_formats() {
+        assert_eq!(
+            legacy_to_component("&c&lBanned&r: §7appeal at example.com", 47),
+            json!({
+                "text": "",
+                "extra": [
+                    { "text": "Banned", "color": "red", "bold": true },
+                    { "text": ": " },
+                    { "text": "appeal at example.com", "color": "gray" },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_hex_colors_per_protocol() {
+        assert_eq!(
+            legacy_to_component("&#ff5555Full", 763),
+            json!({ "text": "Full", "color": "#ff5555" })
+        );
+        assert_eq!(
+            legacy_to_component("&x&f&f&5&5&5&5Full", 763),
+            json!({ "text": "Full", "color": "#ff5555" })
+        );
+        // Pre-1.16 clients get the nearest named color.
+        assert_eq!(
+            legacy_to_component("&#fe5050Full", 340),
+            json!({ "text": "Full", "color": "red" })
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        assert_eq!(
+            unsupported_version(47, 763, 767),
+            "&cYour client version is not supported (protocol 47, accepted 763-767)."
+        );
+    }
+
+    #[test]
+    fn test_fill_placeholders() {
+        let values = Placeholders {
+            host: Some("mc.example.com"),
+            username: Some("Steve"),
+            conn_id: Some(42),
+            ..Default::default()
+        };
+        assert_eq!(
+            fill("&c{username}: {host} is down ({backend}, #{conn})", &values),
+            "&cSteve: mc.example.com is down ({backend}, #42)"
+        );
+        let quoting = Placeholders {
+            host: Some("evil\"host"),
+            ..Default::default()
+        };
+        let filled = fill(r#"{"text":"No route for {host}"}"#, &quoting);
+        assert_eq!(
+            serde_json::from_str::<Value>(&filled).unwrap(),
+            json!({ "text": "No route for evil\"host" })
+        );
+    }
+
+    #[test]
+    fn test_component_templates() {
+        let templates: HashMap<String, String> = deserialize_templates(json!({
+            "serverFull": "&cFull",
+            "backendDown": { "text": "Down", "color": "red" },
+        }))
+        .unwrap();
+        assert_eq!(templates["serverFull"], "&cFull");
+        assert_eq!(
+            serde_json::from_str::<Value>(&templates["backendDown"]).unwrap(),
+            json!({ "text": "Down", "color": "red" })
+        );
+    }
+
+    #[test]
+    fn test_json_passthrough() {
+        let raw = r#"{"text":"already","color":"gold"}"#;
+        assert_eq!(to_component_json(raw, 763), raw);
+        assert_eq!(
+            to_component_json("{not json", 763),
+            r#"{"text":"{not json"}"#
+        );
+    }
+
+    #[test]
+    fn test_component_validation() {
+        // JSON that is no text component is shown as written.
+        assert_eq!(to_component_json(r#"{"reason":1}"#, 763), r#"{"text":"{\"reason\":1}"}"#);
+        assert_eq!(to_component_json("[]", 763), r#"{"text":"[]"}"#);
+        assert!(is_component(r#"[{"text":"a"},"b"]"#));
+        assert!(!is_component(r#"{"text":"a","extra":"b"}"#));
+
+        let hex = r##"{"text":"Full","extra":[{"text":"!","color":"#ff5555"}]}"##;
+        assert_eq!(to_component_json(hex, 763), hex);
+        assert_eq!(
+            serde_json::from_str::<Value>(&to_component_json(hex, 340)).unwrap(),
+            json!({ "text": "Full", "extra": [{ "text": "!", "color": "red" }] })
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! geofront/src/metrics_push.rs
+//! Periodic metrics events, so hosts can consume global counters from the
+//! polling queue instead of running their own `proxy_get_metrics` timer.
+
+use crate::{
+    events,
+    state::{
+        ACTIVE_CONN, LISTENER_STATE, METRICS_EVENT_QUEUE, METRICS_PUSHER, TOTAL_BYTES_RECV,
+        TOTAL_BYTES_SENT, TOTAL_CONN,
+    },
+    types::MetricsEvent,
+    wakeup,
+};
+use std::{sync::atomic::Ordering, time::Duration};
+
+/// Events kept for polling at most; each supersedes the previous ones, so
+/// only the newest are retained when the host falls behind.
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Starts, restarts or stops the pusher according to `interval_ms`.
+pub fn configure(interval_ms: Option<u64>) {
+    let mut pusher = METRICS_PUSHER.lock().unwrap();
+    if let Some(handle) = pusher.take() {
+        handle.abort();
+    }
+    let Some(interval_ms) = interval_ms else {
+        METRICS_EVENT_QUEUE.lock().unwrap().clear();
+        return;
+    };
+    let interval = Duration::from_millis(interval_ms.max(100));
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    *pusher = Some(runtime.spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            push(snapshot());
+        }
+    }));
+}
+
+fn snapshot() -> MetricsEvent {
+    MetricsEvent {
+        timestamp_ms: events::now_ms(),
+        total_conn: TOTAL_CONN.load(Ordering::SeqCst),
+        active_conn: ACTIVE_CONN.load(Ordering::SeqCst),
+        total_bytes_sent: TOTAL_BYTES_SENT.load(Ordering::SeqCst),
+        total_bytes_recv: TOTAL_BYTES_RECV.load(Ordering::SeqCst),
+    }
+}
+
+fn push(event: MetricsEvent) {
+    wakeup::push_bounded(&mut METRICS_EVENT_QUEUE.lock().unwrap(), event, MAX_PENDING_EVENTS);
+}
@@ -0,0 +1,133 @@
+//! geofront/src/mirror.rs
+//! Copies of a relayed session's bytes sent to a secondary destination
+//! (`RouteDecision.mirror`), for debugging and anti-cheat analysis. Each
+//! mirrored direction gets a TCP connection of its own carrying exactly
+//! what the other side was sent, starting with the forwarded handshake and
+//! login for the client's direction.
+//!
+//! Mirroring is best effort and never slows the session down: chunks are
+//! queued up to a bound and dropped when the destination falls behind or
+//! cannot be reached, counting the bytes lost. Mirrored sessions relay in
+//! userspace, skipping the splice and sockmap paths.
+
+use crate::{
+    outbound::{self, SocketConfig},
+    state::{MIRROR_BYTES, MIRROR_DROPPED_BYTES, MIRRORS},
+    types::{MirrorConfig, MirrorDirection, ProxyConnection},
+};
+use std::sync::atomic::Ordering;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tracing::{debug, warn};
+
+/// Chunks queued per direction before further ones are dropped.
+const QUEUE_CHUNKS: usize = 256;
+
+/// The queues of a mirrored session, one per mirrored direction.
+pub struct Mirror {
+    client: Option<mpsc::Sender<Vec<u8>>>,
+    server: Option<mpsc::Sender<Vec<u8>>>,
+}
+
+/// Starts mirroring a connection as its route asks.
+pub fn start(conn_id: ProxyConnection, config: &MirrorConfig) {
+    let queue = |direction: &'static str| {
+        let (tx, rx) = mpsc::channel(QUEUE_CHUNKS);
+        tokio::spawn(write_mirror(conn_id, config.host.clone(), config.port, direction, rx));
+        tx
+    };
+    let mirror = Mirror {
+        client: matches!(config.direction, MirrorDirection::Client | MirrorDirection::Both).then(|| queue("client")),
+        server: matches!(config.direction, MirrorDirection::Server | MirrorDirection::Both).then(|| queue("server")),
+    };
+    MIRRORS.insert(conn_id, mirror);
+}
+
+/// Stops mirroring a connection; what is queued is still written.
+pub fn stop(conn_id: ProxyConnection) {
+    MIRRORS.remove(&conn_id);
+}
+
+pub fn is_active(conn_id: ProxyConnection) -> bool {
+    MIRRORS.contains_key(&conn_id)
+}
+
+/// Queues bytes relayed client to backend (`sent`) or back, if the
+/// connection mirrors that direction.
+pub fn feed(conn_id: ProxyConnection, bytes: &[u8], sent: bool) {
+    if bytes.is_empty() {
+        return;
+    }
+    let Some(mirror) = MIRRORS.get(&conn_id) else {
+        return;
+    };
+    let queue = if sent { &mirror.client } else { &mirror.server };
+    if let Some(queue) = queue
+        && queue.try_send(bytes.to_vec()).is_err()
+    {
+        MIRROR_DROPPED_BYTES.fetch_add(bytes.len() as u64, Ordering::SeqCst);
+    }
+}
+
+/// Writes one direction's chunks to the destination until the session ends,
+/// dropping them all if it cannot be reached.
+async fn write_mirror(
+    conn_id: ProxyConnection,
+    host: String,
+    port: u16,
+    direction: &'static str,
+    mut chunks: mpsc::Receiver<Vec<u8>>,
+) {
+    let mut stream = match outbound::connect_host(&host, port, SocketConfig::default()).await {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            warn!(conn = conn_id, %host, port, direction, "Failed to connect to mirror: {}", e);
+            None
+        }
+    };
+    while let Some(chunk) = chunks.recv().await {
+        let n = chunk.len() as u64;
+        let written = match &mut stream {
+            Some(writer) => match writer.write_all(&chunk).await {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!(conn = conn_id, direction, "Mirror connection failed: {}", e);
+                    stream = None;
+                    false
+                }
+            },
+            None => false,
+        };
+        if written {
+            MIRROR_BYTES.fetch_add(n, Ordering::SeqCst);
+        } else {
+            MIRROR_DROPPED_BYTES.fetch_add(n, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    #[tokio::test]
+    async fn test_mirrors_one_direction() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = MirrorConfig {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            direction: MirrorDirection::Client,
+        };
+        let conn_id = u64::MAX - 42;
+        start(conn_id, &config);
+        feed(conn_id, b"from client", true);
+        feed(conn_id, b"from server", false);
+        stop(conn_id);
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut mirrored = Vec::new();
+        socket.read_to_end(&mut mirrored).await.unwrap();
+        assert_eq!(mirrored, b"from client");
+        assert!(!is_active(conn_id));
+    }
+}
@@ -60,6 +60,13 @@ export const MotdSchema = z.object({
           return player;
         });
       }),
+    // 由 Rust 按 ping 的主机名统计已登录连接，自动填充 online 与 sample（最近登录的玩家）
+    auto: z
+      .object({
+        sampleSize: z.number().int().min(0).optional(),
+        anonymize: z.boolean().optional(),
+      })
+      .optional(),
   }),
   description: Component,
   favicon: z.string(),
@@ -89,6 +96,12 @@ export const MotdInputSchema = z
             ])
           )
           .optional(),
+        auto: z
+          .object({
+            sampleSize: z.number().int().min(0).optional(),
+            anonymize: z.boolean().optional(),
+          })
+          .optional(),
       })
       .optional(),
     description: Component.optional(),
@@ -113,6 +126,12 @@ export type MotdResult = {
 			readonly name: string
 			readonly id: string
 		} | string>  // 支持字符串（自动生成UUID）或完整对象
+		// 由代理按 ping 的主机名统计已登录连接，自动填充 online 与 sample（覆盖上面两项）；
+		// sampleSize 默认 5，anonymize 时显示为 "Anonymous Player"
+		readonly auto?: {
+			readonly sampleSize?: number
+			readonly anonymize?: boolean
+		}
 	}
 	readonly description: {
 		readonly text: string
@@ -123,6 +142,14 @@ export type MotdResult = {
 		readonly ttl: number
 		readonly reject?: boolean
 		readonly rejectReason?: string
+		// 拒绝决策的缓存时长，通常长于 ttl；未设置时使用 ttl
+		readonly rejectTtl?: number
+	}
+	// 直接转发后端服务器的真实状态响应与 ping（显示实时在线人数）；
+	// 后端不可达时使用上面的字段作为回退 MOTD
+	readonly passthrough?: {
+		readonly host: string
+		readonly port: number
 	}
 }
 
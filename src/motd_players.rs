@@ -0,0 +1,121 @@
+//! geofront/src/motd_players.rs
+//! Player counts and samples of status responses filled in from the
+//! connections geofront is relaying (`MotdPlayers.auto`), so hosts need not
+//! keep their own per-host bookkeeping. `online` becomes the number of
+//! logged-in connections that used the pinged hostname, and `sample` the
+//! usernames of the most recent of them; with `anonymize` each is shown as
+//! "Anonymous Player", as vanilla servers that hide their players do.
+
+use crate::{
+    state::CONN_INFO,
+    types::{AutoPlayers, ConnInfo, MotdPlayerSample, MotdPlayers},
+};
+
+/// Name and id vanilla servers show in place of hidden players.
+const ANONYMOUS_NAME: &str = "Anonymous Player";
+const NIL_UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Fills `online` and `sample` for a ping to `host`, if `auto` is set.
+pub fn fill(players: &mut MotdPlayers, host: &str) {
+    let Some(auto) = players.auto.clone() else {
+        return;
+    };
+    let conns: Vec<ConnInfo> = CONN_INFO.iter().map(|entry| entry.value().clone()).collect();
+    let (online, sample) = collect(conns.iter(), host, &auto);
+    players.online = Some(online);
+    players.sample = sample;
+}
+
+fn collect<'a>(
+    conns: impl Iterator<Item = &'a ConnInfo>,
+    host: &str,
+    auto: &AutoPlayers,
+) -> (i32, Vec<MotdPlayerSample>) {
+    let mut players: Vec<&ConnInfo> = conns
+        .filter(|info| info.username.is_some())
+        .filter(|info| info.host.as_deref().is_some_and(|h| same_host(h, host)))
+        .collect();
+    // Most recent logins first.
+    players.sort_by_key(|info| std::cmp::Reverse(info.login_at_ms.unwrap_or(info.connected_at_ms)));
+    let sample = players
+        .iter()
+        .take(auto.sample_size)
+        .map(|info| {
+            if auto.anonymize {
+                MotdPlayerSample::Full {
+                    name: ANONYMOUS_NAME.to_string(),
+                    id: NIL_UUID.to_string(),
+                }
+            } else {
+                MotdPlayerSample::Full {
+                    name: info.username.clone().unwrap_or_default(),
+                    id: info.uuid.as_deref().map_or_else(|| NIL_UUID.to_string(), dashed),
+                }
+            }
+        })
+        .collect();
+    (players.len() as i32, sample)
+}
+
+fn same_host(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+/// Dashes an undashed UUID; others are kept as they are.
+fn dashed(uuid: &str) -> String {
+    if uuid.len() != 32 || !uuid.is_ascii() {
+        return uuid.to_string();
+    }
+    format!(
+        "{}-{}-{}-{}-{}",
+        &uuid[..8],
+        &uuid[8..12],
+        &uuid[12..16],
+        &uuid[16..20],
+        &uuid[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(host: &str, username: Option<&str>, login_at_ms: u64) -> ConnInfo {
+        ConnInfo {
+            host: Some(host.to_string()),
+            username: username.map(str::to_string),
+            login_at_ms: Some(login_at_ms),
+            ..Default::default()
+        }
+    }
+
+    fn names(sample: &[MotdPlayerSample]) -> Vec<&str> {
+        sample
+            .iter()
+            .map(|player| match player {
+                MotdPlayerSample::Full { name, .. } | MotdPlayerSample::Name(name) => name.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_recent_players() {
+        let conns = [
+            conn("play.example.com", Some("Alice"), 1),
+            conn("PLAY.example.com.", Some("Bob"), 3),
+            conn("play.example.com", Some("Carol"), 2),
+            conn("play.example.com", None, 4),
+            conn("lobby.example.com", Some("Dave"), 5),
+        ];
+        let auto = AutoPlayers { sample_size: 2, anonymize: false };
+        let (online, sample) = collect(conns.iter(), "play.example.com", &auto);
+        assert_eq!(online, 3);
+        assert_eq!(names(&sample), ["Bob", "Carol"]);
+
+        let auto = AutoPlayers { sample_size: 5, anonymize: true };
+        let (online, sample) = collect(conns.iter(), "lobby.example.com", &auto);
+        assert_eq!(online, 1);
+        assert_eq!(names(&sample), [ANONYMOUS_NAME]);
+        assert_eq!(dashed("0123456789abcdef0123456789abcdef"), "01234567-89ab-cdef-0123-456789abcdef");
+    }
+}
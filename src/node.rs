@@ -0,0 +1,272 @@
+//! geofront/src/node.rs
+//! Node-API addon (`napi` feature): the operations of the C ABI in `ffi.rs`
+//! for Node and Bun hosts loading the library as a native module. Options,
+//! decisions, events and metrics cross as JS values instead of JSON C
+//! strings, so the host has nothing to free; listeners start asynchronously
+//! and events are pushed to a callback from the proxy threads instead of
+//! being polled.
+
+use crate::{
+    conn_query,
+    embed::Geofront,
+    logging, snapshot,
+    state::{NODE_EVENT_TASK, PENDING_MOTDS, PENDING_ROUTES},
+    types::{
+        ConnFilter, GeofrontOptions, ListenerOptions, MotdDecision, PauseConfig, ProxyConnection, ProxyListener, RouteDecision,
+        RuntimeConfig, StaticRoute, VirtualHost,
+    },
+    wakeup,
+};
+use napi::{
+    Error, JsFunction, Result, Status,
+    bindgen_prelude::spawn,
+    threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::error;
+
+fn from_js<T: DeserializeOwned>(value: Value, what: &str) -> Result<T> {
+    serde_json::from_value(value)
+        .map_err(|e| Error::new(Status::InvalidArg, format!("invalid {}: {}", what, e)))
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<Value> {
+    serde_json::to_value(value).map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Initializes logging with `level` as the filter, `info` by default.
+#[napi]
+pub fn init_logging(level: Option<String>) {
+    logging::init_logging(level.as_deref().unwrap_or("info"));
+}
+
+/// Initializes logging as JSON lines, to stdout or to the file at `path`
+/// rotated by size; fails if logging was already initialized.
+#[napi]
+pub fn init_logging_json(level: Option<String>, path: Option<String>) -> Result<()> {
+    match logging::init_logging_json(level.as_deref().unwrap_or("info"), path.as_deref()) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::from_reason("logging is already initialized")),
+        Err(e) => Err(Error::from_reason(format!("failed to open log file: {}", e))),
+    }
+}
+
+/// Configures the proxy runtime (`RuntimeConfig`); fails once it is running.
+#[napi]
+pub fn init_runtime(config: Value) -> Result<()> {
+    let config: RuntimeConfig = from_js(config, "runtime config")?;
+    if !Geofront::new().init_runtime(config) {
+        return Err(Error::from_reason("the runtime is already running"));
+    }
+    Ok(())
+}
+
+/// Replaces the global options (`GeofrontOptions`).
+#[napi]
+pub fn set_options(options: Value) -> Result<()> {
+    let options: GeofrontOptions = from_js(options, "options")?;
+    Geofront::new().set_options(options);
+    Ok(())
+}
+
+/// Replaces the static routes (an array of `StaticRoute`).
+#[napi]
+pub fn set_routes(routes: Value) -> Result<()> {
+    let routes: Vec<StaticRoute> = from_js(routes, "static routes")?;
+    Geofront::new().set_routes(routes);
+    Ok(())
+}
+
+/// Replaces the virtual hosts (an array of `VirtualHost`).
+#[napi]
+pub fn set_vhosts(vhosts: Value) -> Result<()> {
+    let vhosts: Vec<VirtualHost> = from_js(vhosts, "virtual hosts")?;
+    Geofront::new().set_vhosts(vhosts);
+    Ok(())
+}
+
+/// Applies a JSON config file, reloading it on change when
+/// `watch_interval_ms` is set; returns what changed.
+#[napi]
+pub fn load_config(path: String, watch_interval_ms: Option<i64>) -> Result<Value> {
+    let watch = watch_interval_ms.filter(|&ms| ms > 0).map(|ms| ms as u64);
+    let reload = Geofront::new()
+        .load_config(&path, watch)
+        .map_err(|e| Error::new(Status::InvalidArg, e))?;
+    to_js(&reload)
+}
+
+/// Binds `host:port` with the given `ListenerOptions`, resolving to the
+/// listener id.
+#[napi]
+pub async fn start_listener(host: String, port: u32, options: Option<Value>) -> Result<i64> {
+    let port = u16::try_from(port)
+        .map_err(|_| Error::new(Status::InvalidArg, format!("invalid port {}", port)))?;
+    let options: ListenerOptions = match options {
+        Some(options) => from_js(options, "listener options")?,
+        None => ListenerOptions::default(),
+    };
+    logging::init_logging("info");
+    match Geofront::new().start_listener_with_options(&host, port, options).await {
+        Ok(listener) => Ok(listener as i64),
+        Err(e) => Err(Error::from_reason(format!(
+            "failed to start listener on {}:{}: {}",
+            host, port, e
+        ))),
+    }
+}
+
+/// Stops a listener; returns `false` if it is unknown.
+#[napi]
+pub fn stop_listener(listener: i64) -> bool {
+    Geofront::new().stop_listener(listener as ProxyListener)
+}
+
+/// Pauses a listener (`PauseConfig`, or nothing to stop accepting); returns
+/// `false` if it is unknown.
+#[napi]
+pub fn pause_listener(listener: i64, config: Option<Value>) -> Result<bool> {
+    let config: PauseConfig = match config {
+        Some(config) => from_js(config, "pause config")?,
+        None => PauseConfig::default(),
+    };
+    Ok(Geofront::new().pause_listener(listener as ProxyListener, config))
+}
+
+/// Resumes a paused listener; returns `false` if it was not paused.
+#[napi]
+pub fn resume_listener(listener: i64) -> bool {
+    Geofront::new().resume_listener(listener as ProxyListener)
+}
+
+/// Answers the route request of a connection.
+#[napi]
+pub fn submit_routing_decision(conn_id: i64, decision: Value) -> Result<()> {
+    let decision: RouteDecision = from_js(decision, "route decision")?;
+    let conn_id = conn_id as ProxyConnection;
+    let Some((_, sender)) = PENDING_ROUTES.remove(&conn_id) else {
+        return Err(Error::from_reason(format!(
+            "no pending route decision for connection {}",
+            conn_id
+        )));
+    };
+    sender.send(decision).map_err(|_| {
+        Error::from_reason(format!("connection {} stopped waiting for its route", conn_id))
+    })
+}
+
+/// Answers the MOTD request of a connection.
+#[napi]
+pub fn submit_motd_decision(conn_id: i64, decision: Value) -> Result<()> {
+    let decision: MotdDecision = from_js(decision, "MOTD decision")?;
+    let conn_id = conn_id as ProxyConnection;
+    let Some((_, sender)) = PENDING_MOTDS.remove(&conn_id) else {
+        return Err(Error::from_reason(format!(
+            "no pending MOTD decision for connection {}",
+            conn_id
+        )));
+    };
+    sender.send(decision).map_err(|_| {
+        Error::from_reason(format!("connection {} stopped waiting for its MOTD", conn_id))
+    })
+}
+
+/// Disconnects a connection, showing `message` if it is still in the login
+/// phase. Returns `false` if it is unknown.
+#[napi]
+pub fn disconnect(conn_id: i64, message: Option<String>) -> bool {
+    let proxy = Geofront::new();
+    match message {
+        Some(message) => proxy
+            .disconnect_with_message(conn_id as ProxyConnection, &message)
+            .is_some(),
+        None => proxy.disconnect(conn_id as ProxyConnection),
+    }
+}
+
+/// Takes a snapshot of all metrics.
+#[napi]
+pub fn get_metrics() -> Result<Value> {
+    to_js(&snapshot::metrics())
+}
+
+/// Details of a live connection, or `null` if it is unknown.
+#[napi]
+pub fn get_connection_info(conn_id: i64) -> Result<Option<Value>> {
+    Geofront::new()
+        .connection_info(conn_id as ProxyConnection)
+        .map(|details| to_js(&details))
+        .transpose()
+}
+
+/// Details of the live connections matching `filter` (a `ConnFilter`), or
+/// of all of them.
+#[napi]
+pub fn list_connections(filter: Option<Value>) -> Result<Value> {
+    let filter: ConnFilter = match filter {
+        Some(filter) => from_js(filter, "connection filter")?,
+        None => ConnFilter::default(),
+    };
+    to_js(&Geofront::new().list_connections(&filter))
+}
+
+/// Disconnects the connections matching `filter` (a `ConnFilter`); returns
+/// how many were kicked. A filter setting no criterion is refused.
+#[napi]
+pub fn kick_where(filter: Value) -> Result<u32> {
+    let filter: ConnFilter = from_js(filter, "connection filter")?;
+    if conn_query::is_unfiltered(&filter) {
+        return Err(Error::from_reason("filter matches every connection"));
+    }
+    Ok(Geofront::new().kick_where(&filter) as u32)
+}
+
+/// Drains the queued events, or returns `null` if there are none.
+#[napi]
+pub fn poll_events() -> Result<Option<Value>> {
+    snapshot::poll_events().map(|events| to_js(&events)).transpose()
+}
+
+/// Calls `callback` with the queued events (`PollEvents`) whenever there
+/// are some, replacing the previous callback. Route and MOTD requests
+/// delivered this way are answered with the `submit*` functions.
+#[napi(ts_args_type = "callback: (events: any) => void")]
+pub fn on_events(callback: JsFunction) -> Result<()> {
+    let callback: ThreadsafeFunction<Value, ErrorStrategy::Fatal> = callback
+        .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<Value>| Ok(vec![ctx.value]))?;
+    let task = spawn(async move {
+        loop {
+            while let Some(events) = snapshot::poll_events() {
+                match serde_json::to_value(&events) {
+                    Ok(events) => {
+                        callback.call(events, ThreadsafeFunctionCallMode::NonBlocking);
+                    }
+                    Err(e) => error!("Failed to serialize events: {}", e),
+                }
+            }
+            wakeup::wait().await;
+        }
+    });
+    if let Some(previous) = NODE_EVENT_TASK.lock().unwrap().replace(task) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+/// Stops delivering events to the `onEvents` callback, letting the host
+/// process exit.
+#[napi]
+pub fn off_events() {
+    if let Some(task) = NODE_EVENT_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+}
+
+/// Stops all listeners, connections and event delivery.
+#[napi]
+pub fn shutdown() {
+    off_events();
+    Geofront::new().shutdown();
+}
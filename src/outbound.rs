@@ -0,0 +1,103 @@
+//! geofront/src/outbound.rs
+//! Sockets to backends and upstream proxies. A route's `localAddress` binds
+//! them to one local address, for multi-homed hosts whose backends or
+//! SOCKS5 proxies only accept a given source; addresses of the other family
+//! are then skipped, as that source cannot reach them. The `tcp` options
+//! are applied to them as well (see `tcp_options.rs`).
+
+use crate::{tcp_options, types::TcpConfig};
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+};
+use tokio::net::{TcpSocket, TcpStream, lookup_host};
+
+/// How a connection's sockets are opened: the local address they are bound
+/// to, if any, and their TCP options.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SocketConfig {
+    pub local: Option<IpAddr>,
+    pub tcp: TcpConfig,
+}
+
+/// Parses a route's `localAddress`, an IP address.
+pub fn parse_local_address(local_address: Option<&str>) -> Result<Option<IpAddr>> {
+    local_address
+        .map(|addr| {
+            addr.parse::<IpAddr>().map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid localAddress {}", addr),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// Whether a socket bound to `local` can reach `addr`.
+pub fn reachable(addr: &SocketAddr, local: Option<IpAddr>) -> bool {
+    local.is_none_or(|local| local.is_ipv4() == addr.is_ipv4())
+}
+
+/// Connects to `addr` as `config` says.
+pub async fn connect(addr: SocketAddr, config: SocketConfig) -> Result<TcpStream> {
+    let stream = if config.local.is_none() && !tcp_options::needs_prepare(&config.tcp) {
+        TcpStream::connect(addr).await?
+    } else {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        tcp_options::prepare(&socket, &config.tcp)?;
+        if let Some(local) = config.local {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+        socket.connect(addr).await?
+    };
+    tcp_options::apply(&stream, &config.tcp);
+    Ok(stream)
+}
+
+/// Resolves `host` and connects to the first of its addresses that answers,
+/// as `config` says.
+pub async fn connect_host(host: &str, port: u16, config: SocketConfig) -> Result<TcpStream> {
+    let mut last_err = None;
+    for addr in lookup_host((host, port)).await? {
+        if !reachable(&addr, config.local) {
+            continue;
+        }
+        match connect(addr, config).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("{} has no address reachable from localAddress", host),
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_connect_binds_local_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let local = parse_local_address(Some("127.0.0.1")).unwrap();
+        let config = SocketConfig {
+            local,
+            ..Default::default()
+        };
+        let stream = connect(addr, config).await.unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), local.unwrap());
+
+        assert!(parse_local_address(Some("eth0")).is_err());
+        assert!(!reachable(&"[::1]:25565".parse().unwrap(), local));
+    }
+}
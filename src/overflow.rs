@@ -0,0 +1,44 @@
+//! geofront/src/overflow.rs
+//! The global connection cap (`maxConnections`). Connections over the cap
+//! are still accepted so they can be told why: status pings are answered
+//! with `overflowMotd` (a status template as in `default_motd.rs`) and
+//! logins are refused with the `proxyFull` message before any routing,
+//! counting `geofront_overflow_rejected_total`.
+
+use crate::{
+    default_motd,
+    state::{ACTIVE_CONN, OPTIONS, OVERFLOW_REJECTED},
+};
+use serde_json::{Value, json};
+use std::sync::atomic::Ordering;
+
+/// Whether more connections are open than `maxConnections` allows; the
+/// connection asking counts as open.
+fn exceeded() -> bool {
+    let max = OPTIONS.read().unwrap().max_connections;
+    max.is_some_and(|max| ACTIVE_CONN.load(Ordering::SeqCst) > max)
+}
+
+/// The status response of a ping over the cap, or `None` if under it.
+pub fn motd(protocol: i32) -> Option<Value> {
+    if !exceeded() {
+        return None;
+    }
+    let template = OPTIONS.read().unwrap().overflow_motd.clone().unwrap_or_else(|| {
+        json!({
+            "version": { "name": "Geofront", "protocol": "{protocol}" },
+            "players": { "max": "{max}", "online": "{online}", "sample": [] },
+            "description": { "text": "The proxy is full. Please try again later.", "color": "red" }
+        })
+    });
+    Some(default_motd::render_template(template, protocol))
+}
+
+/// Whether a login must be refused for the cap, counting it if so.
+pub fn reject_login() -> bool {
+    if !exceeded() {
+        return false;
+    }
+    OVERFLOW_REJECTED.fetch_add(1, Ordering::SeqCst);
+    true
+}
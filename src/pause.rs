@@ -0,0 +1,76 @@
+//! geofront/src/pause.rs
+//! Paused listeners (`proxy_pause_listener`), for maintenance without giving
+//! up the port: the socket stays bound and open connections are untouched.
+//! A plain pause stops accepting, leaving new clients in the kernel backlog
+//! until `proxy_resume_listener`. A pause with a `motd` or a `message` keeps
+//! accepting instead: status requests get the MOTD (with the placeholders of
+//! `defaultMotd`) and logins are refused with the message, the
+//! `maintenance` template by default.
+
+use crate::{
+    default_motd, messages,
+    state::{CONN_INFO, LISTENER_RESUMED, LISTENER_STATE, PAUSED_LISTENERS},
+    types::{PauseConfig, ProxyConnection, ProxyListener},
+};
+use serde_json::Value;
+use std::pin::pin;
+
+/// Pauses a listener, replacing the pause config it may have; returns
+/// `false` if it is unknown.
+pub fn pause(listener: ProxyListener, config: PauseConfig) -> bool {
+    if !LISTENER_STATE.lock().unwrap().listeners.contains_key(&listener) {
+        return false;
+    }
+    PAUSED_LISTENERS.lock().unwrap().insert(listener, config);
+    // A loop waiting for a plain pause to end may accept again now.
+    LISTENER_RESUMED.notify_waiters();
+    true
+}
+
+/// Resumes a listener; returns `false` if it was not paused.
+pub fn resume(listener: ProxyListener) -> bool {
+    let resumed = PAUSED_LISTENERS.lock().unwrap().remove(&listener).is_some();
+    LISTENER_RESUMED.notify_waiters();
+    resumed
+}
+
+/// Waits until the listener may accept, i.e. while it is under a plain
+/// pause.
+pub async fn wait_accepting(listener: ProxyListener) {
+    loop {
+        // Registered before checking, so a resume in between is not missed.
+        let mut resumed = pin!(LISTENER_RESUMED.notified());
+        resumed.as_mut().enable();
+        let holding = PAUSED_LISTENERS
+            .lock()
+            .unwrap()
+            .get(&listener)
+            .is_some_and(|config| config.motd.is_none() && config.message.is_none());
+        if !holding {
+            return;
+        }
+        resumed.await;
+    }
+}
+
+/// The pause of the listener a connection came in on, if any.
+fn config_of(conn_id: ProxyConnection) -> Option<PauseConfig> {
+    let listener = CONN_INFO.get(&conn_id)?.listener?;
+    PAUSED_LISTENERS.lock().unwrap().get(&listener).cloned()
+}
+
+/// The status response for a connection to a paused listener with a `motd`.
+pub fn motd(conn_id: ProxyConnection, protocol: i32) -> Option<Value> {
+    let template = config_of(conn_id)?.motd?;
+    Some(default_motd::render_template(template, protocol))
+}
+
+/// The message refusing a login on a paused listener.
+pub fn rejection(conn_id: ProxyConnection) -> Option<String> {
+    let config = config_of(conn_id)?;
+    Some(
+        config
+            .message
+            .unwrap_or_else(|| messages::builtin(messages::MAINTENANCE)),
+    )
+}
@@ -0,0 +1,573 @@
+//! geofront/src/prometheus.rs
+//! Built-in HTTP endpoint serving the proxy counters in the Prometheus text
+//! format, so scrapers read them at full resolution without going through
+//! the host.
+//!
+//! The endpoint exposes per-host, per-user and per-IP counters, so it only
+//! binds a non-loopback address when scrapers must authenticate: with the
+//! `metricsExporter.token` bearer token or with client certificates signed
+//! by `metricsExporter.tls.clientCaPath`. The token and TLS files are read
+//! per scrape, so option changes apply without a restart.
+
+use crate::{
+    latency,
+    state::{
+        ACTIVE_CONN, CONN_INFO, CONN_METRICS, HANDSHAKE_TIMEOUTS, HANDSHAKES, LISTENER_STATE, LISTENER_TOTALS, LOGINS,
+        METRICS_EXPORTER, OPTIONS, PROTOCOL_ERROR_COUNTS, STATUS_REQUESTS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT,
+        LOG_RECORDS_DROPPED, MIRROR_BYTES, OVERFLOW_REJECTED, MIRROR_DROPPED_BYTES, TARPIT_ACTIVE, TARPIT_TOTAL, TOTAL_CONN, UNTRUSTED_PROXY_HEADERS,
+    },
+    tls::{self, TlsAcceptor},
+    types::{MetricsExporterConfig, ProxyListener, TlsListenerConfig},
+};
+use std::{collections::BTreeMap, fmt::Write, io, sync::atomic::Ordering, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{info, warn};
+
+/// Longest request head read before answering.
+const MAX_REQUEST_BYTES: usize = 4096;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds `addr:port` and serves `/metrics` there, replacing the running
+/// exporter if any. Refuses addresses other than loopback unless `config`
+/// makes scrapers authenticate.
+pub fn start(addr: &str, port: u16, config: Option<&MetricsExporterConfig>) -> io::Result<()> {
+    let config = config.cloned().unwrap_or_default();
+    if let Some(tls) = &config.tls {
+        acceptor(tls)?;
+    }
+    let listener = std::net::TcpListener::bind((addr, port))?;
+    let authenticated =
+        config.token.is_some() || config.tls.as_ref().is_some_and(|tls| tls.client_ca_path.is_some());
+    if !authenticated && !listener.local_addr()?.ip().is_loopback() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "metrics exporter needs metricsExporter.token or a TLS clientCaPath to bind a non-loopback address",
+        ));
+    }
+    listener.set_nonblocking(true)?;
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    let listener = {
+        let _guard = runtime.enter();
+        TcpListener::from_std(listener)?
+    };
+    let mut exporter = METRICS_EXPORTER.lock().unwrap();
+    if let Some(handle) = exporter.take() {
+        handle.abort();
+    }
+    *exporter = Some(runtime.spawn(serve(listener)));
+    info!("Prometheus exporter listening on {}:{}", addr, port);
+    Ok(())
+}
+
+/// Starts, moves or stops the exporter when `metricsExporter.listen`
+/// changes; the token and TLS settings need no restart.
+pub fn configure(old: Option<&MetricsExporterConfig>, new: Option<&MetricsExporterConfig>) {
+    let listen = |config: Option<&MetricsExporterConfig>| config.and_then(|config| config.listen.clone());
+    if listen(old) == listen(new) {
+        return;
+    }
+    let Some(listen) = listen(new) else {
+        stop();
+        return;
+    };
+    let result = listen
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host.trim_start_matches('[').trim_end_matches(']'), port.parse().ok()?)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "listen must be host:port"))
+        .and_then(|(host, port)| start(host, port, new));
+    if let Err(e) = result {
+        warn!("Failed to start metrics exporter on {}: {}", listen, e);
+    }
+}
+
+/// Stops the exporter; returns `false` if none was running.
+pub fn stop() -> bool {
+    match METRICS_EXPORTER.lock().unwrap().take() {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+async fn serve(listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    let config = OPTIONS.read().unwrap().metrics_exporter.clone().unwrap_or_default();
+                    let token = config.token.as_deref();
+                    let result = match &config.tls {
+                        Some(tls) => async {
+                            let acceptor = acceptor(tls)?;
+                            let handshake = acceptor.accept(stream);
+                            let stream = tokio::time::timeout(REQUEST_TIMEOUT, handshake)
+                                .await
+                                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+                            respond(stream, token).await
+                        }
+                        .await,
+                        None => respond(stream, token).await,
+                    };
+                    if let Err(e) = result {
+                        warn!("Metrics scrape failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                warn!("Metrics exporter accept error: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// The acceptor for `tls`, rebuilt when the options change.
+#[cfg(feature = "tls")]
+fn acceptor(tls: &TlsListenerConfig) -> io::Result<TlsAcceptor> {
+    static ACCEPTOR: std::sync::Mutex<Option<(TlsListenerConfig, TlsAcceptor)>> = std::sync::Mutex::new(None);
+    let mut cached = ACCEPTOR.lock().unwrap();
+    if let Some((cached_tls, acceptor)) = &*cached
+        && cached_tls == tls
+    {
+        return Ok(acceptor.clone());
+    }
+    let acceptor = tls::load_acceptor(&tls.cert_path, &tls.key_path, tls.client_ca_path.as_deref())?;
+    *cached = Some((tls.clone(), acceptor.clone()));
+    Ok(acceptor)
+}
+
+/// Without the `tls` feature loading always fails, so there is nothing to cache.
+#[cfg(not(feature = "tls"))]
+fn acceptor(tls: &TlsListenerConfig) -> io::Result<TlsAcceptor> {
+    tls::load_acceptor(&tls.cert_path, &tls.key_path, tls.client_ca_path.as_deref())
+}
+
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, token: Option<&str>) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_BYTES {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+        Ok::<_, io::Error>(())
+    })
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))??;
+
+    let request_line = head.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    let (method, path) = (parts.next(), parts.next());
+    let authorized = token.is_none_or(|token| bearer(&head).is_some_and(|given| same_token(given, token.as_bytes())));
+    let (status, body) = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics" | b"/")) if !authorized => ("401 Unauthorized", String::new()),
+        (Some(b"GET"), Some(b"/metrics" | b"/")) => ("200 OK", render()),
+        (Some(b"GET"), _) => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let challenge = if authorized { "" } else { "WWW-Authenticate: Bearer\r\n" };
+    let response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        challenge,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// The token of the request's `Authorization: Bearer` header.
+fn bearer(head: &[u8]) -> Option<&[u8]> {
+    head.split(|&b| b == b'\n').find_map(|line| {
+        let colon = line.iter().position(|&b| b == b':')?;
+        let (name, value) = (&line[..colon], line[colon + 1..].trim_ascii());
+        if !name.eq_ignore_ascii_case(b"authorization") || value.len() < 7 || !value[..7].eq_ignore_ascii_case(b"bearer ") {
+            return None;
+        }
+        Some(value[7..].trim_ascii())
+    })
+}
+
+/// Compares tokens in time independent of where they differ.
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let single = |value: u64| [(String::new(), value as f64)];
+
+    metric(
+        "geofront_connections_total",
+        "counter",
+        "Connections accepted.",
+        &single(TOTAL_CONN.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_active_connections",
+        "gauge",
+        "Connections currently open.",
+        &single(ACTIVE_CONN.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_bytes_sent_total",
+        "counter",
+        "Bytes relayed from clients to backends.",
+        &single(TOTAL_BYTES_SENT.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_bytes_received_total",
+        "counter",
+        "Bytes relayed from backends to clients.",
+        &single(TOTAL_BYTES_RECV.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_handshakes_total",
+        "counter",
+        "Handshakes parsed.",
+        &single(HANDSHAKES.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_status_requests_total",
+        "counter",
+        "Server list pings.",
+        &single(STATUS_REQUESTS.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_logins_total",
+        "counter",
+        "Login starts read.",
+        &single(LOGINS.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_handshake_timeouts_total",
+        "counter",
+        "Clients closed for missing the handshake timeout.",
+        &single(HANDSHAKE_TIMEOUTS.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_proxy_protocol_untrusted_total",
+        "counter",
+        "PROXY protocol headers from peers outside the trusted CIDRs.",
+        &single(UNTRUSTED_PROXY_HEADERS.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_tarpit_connections",
+        "gauge",
+        "Rejected clients held in the tarpit.",
+        &single(TARPIT_ACTIVE.load(Ordering::SeqCst) as u64),
+    );
+    metric(
+        "geofront_tarpit_total",
+        "counter",
+        "Rejected clients sent to the tarpit.",
+        &single(TARPIT_TOTAL.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_mirror_bytes_total",
+        "counter",
+        "Bytes copied to mirror destinations.",
+        &single(MIRROR_BYTES.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_mirror_dropped_bytes_total",
+        "counter",
+        "Bytes not mirrored because the destination fell behind or was unreachable.",
+        &single(MIRROR_DROPPED_BYTES.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_log_records_dropped_total",
+        "counter",
+        "Log records dropped because the host did not poll the log queue in time.",
+        &single(LOG_RECORDS_DROPPED.load(Ordering::SeqCst)),
+    );
+    metric(
+        "geofront_overflow_rejected_total",
+        "counter",
+        "Logins refused because more connections than maxConnections were open.",
+        &single(OVERFLOW_REJECTED.load(Ordering::SeqCst)),
+    );
+
+    let protocol_errors: Vec<(String, f64)> = PROTOCOL_ERROR_COUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(kind, count)| {
+            let kind = serde_json::to_value(kind)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default();
+            (labels(&[("kind", kind.as_str())]), *count as f64)
+        })
+        .collect();
+    metric(
+        "geofront_protocol_errors_total",
+        "counter",
+        "Unparseable client traffic, by kind.",
+        &protocol_errors,
+    );
+
+    let ttfb = latency::ttfb_summary();
+    metric(
+        "geofront_ttfb_seconds",
+        "gauge",
+        "Time from login start to the first backend byte over recent sessions.",
+        &[("0.5", ttfb.p50), ("0.9", ttfb.p90), ("0.99", ttfb.p99)]
+            .map(|(q, ms)| (labels(&[("quantile", q)]), ms / 1000.0)),
+    );
+
+    let histograms = latency::histograms();
+    for (name, help, histogram) in [
+        (
+            "geofront_backend_connect_seconds",
+            "Time to connect to the backend of a login.",
+            &histograms.backend_connect,
+        ),
+        (
+            "geofront_routing_decision_seconds",
+            "Time the router took to answer a route request.",
+            &histograms.routing_decision,
+        ),
+        (
+            "geofront_session_duration_seconds",
+            "Lifetime of connections that reached a backend.",
+            &histograms.session_duration,
+        ),
+    ] {
+        // Series suffixes ride along with the labels.
+        let mut samples = Vec::new();
+        let mut cumulative = 0;
+        for (i, count) in histogram.counts.iter().enumerate() {
+            cumulative += count;
+            let le = histogram
+                .bounds_ms
+                .get(i)
+                .map_or_else(|| "+Inf".to_string(), |ms| (*ms as f64 / 1000.0).to_string());
+            samples.push((format!("_bucket{}", labels(&[("le", &le)])), cumulative as f64));
+        }
+        samples.push(("_sum".to_string(), histogram.sum_ms / 1000.0));
+        samples.push(("_count".to_string(), histogram.count as f64));
+        metric(name, "histogram", help, &samples);
+    }
+
+    let bind_addrs = LISTENER_STATE.lock().unwrap().bind_addrs.clone();
+    // Closed connections' bytes are folded into `LISTENER_TOTALS` under its
+    // lock, so holding it keeps every byte counted exactly once.
+    let listener_totals = LISTENER_TOTALS.lock().unwrap();
+
+    #[derive(Default)]
+    struct Listener {
+        accepted: u64,
+        active: u64,
+        sent: u64,
+        recv: u64,
+        acceptor_accepts: Vec<u64>,
+    }
+    let mut listeners: BTreeMap<ProxyListener, Listener> = BTreeMap::new();
+    for (id, totals) in listener_totals.iter() {
+        let l = listeners.entry(*id).or_default();
+        l.accepted = totals.accepted;
+        l.sent = totals.bytes_sent;
+        l.recv = totals.bytes_recv;
+        l.acceptor_accepts = totals.acceptor_accepts.clone();
+    }
+    let mut connections = Vec::new();
+    for entry in CONN_METRICS.iter() {
+        let (conn_id, m) = entry.pair();
+        let sent = m.bytes_sent.load(Ordering::SeqCst);
+        let recv = m.bytes_recv.load(Ordering::SeqCst);
+        let info_ref = CONN_INFO.get(conn_id);
+        let info = info_ref.as_deref();
+        let listener = info.and_then(|info| info.listener);
+        if let Some(id) = listener {
+            let l = listeners.entry(id).or_default();
+            l.active += 1;
+            l.sent += sent;
+            l.recv += recv;
+        }
+        let conn_labels = labels(&[
+            ("conn", conn_id.to_string().as_str()),
+            ("listener", listener.map(|id| id.to_string()).unwrap_or_default().as_str()),
+            ("username", info.and_then(|i| i.username.as_deref()).unwrap_or("")),
+            ("host", info.and_then(|i| i.host.as_deref()).unwrap_or("")),
+        ]);
+        connections.push((conn_labels, sent, recv));
+    }
+    drop(listener_totals);
+
+    let listener_labels = |id: &ProxyListener| {
+        labels(&[
+            ("listener", id.to_string().as_str()),
+            ("addr", bind_addrs.get(id).map_or("", String::as_str)),
+        ])
+    };
+    let per_listener = |value: fn(&Listener) -> u64| -> Vec<(String, f64)> {
+        listeners
+            .iter()
+            .map(|(id, l)| (listener_labels(id), value(l) as f64))
+            .collect()
+    };
+    metric(
+        "geofront_listener_connections_total",
+        "counter",
+        "Connections accepted, by listener.",
+        &per_listener(|l| l.accepted),
+    );
+    let per_acceptor: Vec<(String, f64)> = listeners
+        .iter()
+        .flat_map(|(id, l)| {
+            l.acceptor_accepts.iter().enumerate().map(move |(acceptor, accepted)| {
+                let labels = labels(&[
+                    ("listener", id.to_string().as_str()),
+                    ("acceptor", acceptor.to_string().as_str()),
+                ]);
+                (labels, *accepted as f64)
+            })
+        })
+        .collect();
+    metric(
+        "geofront_listener_acceptor_connections_total",
+        "counter",
+        "Connections accepted, by listener and SO_REUSEPORT acceptor socket.",
+        &per_acceptor,
+    );
+    metric(
+        "geofront_listener_active_connections",
+        "gauge",
+        "Connections currently open, by listener.",
+        &per_listener(|l| l.active),
+    );
+    metric(
+        "geofront_listener_bytes_sent_total",
+        "counter",
+        "Bytes relayed from clients to backends, by listener.",
+        &per_listener(|l| l.sent),
+    );
+    metric(
+        "geofront_listener_bytes_received_total",
+        "counter",
+        "Bytes relayed from backends to clients, by listener.",
+        &per_listener(|l| l.recv),
+    );
+
+    connections.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let per_connection = |value: fn(&(String, u64, u64)) -> u64| -> Vec<(String, f64)> {
+        connections
+            .iter()
+            .map(|conn| (conn.0.clone(), value(conn) as f64))
+            .collect()
+    };
+    metric(
+        "geofront_connection_bytes_sent_total",
+        "counter",
+        "Bytes relayed from the client to the backend, by open connection.",
+        &per_connection(|conn| conn.1),
+    );
+    metric(
+        "geofront_connection_bytes_received_total",
+        "counter",
+        "Bytes relayed from the backend to the client, by open connection.",
+        &per_connection(|conn| conn.2),
+    );
+    out
+}
+
+/// Formats a label set, escaping values as the text format requires.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let mut out = String::from("{");
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(name);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            labels(&[("conn", "7"), ("host", "a\"b\\c\nd")]),
+            r#"{conn="7",host="a\"b\\c\nd"}"#
+        );
+    }
+
+    #[test]
+    fn test_bearer() {
+        let head = b"GET /metrics HTTP/1.1\r\nHost: edge\r\nauthorization: bearer s3cret \r\n\r\n";
+        assert_eq!(bearer(head), Some(&b"s3cret"[..]));
+        assert_eq!(bearer(b"GET /metrics HTTP/1.1\r\nAuthorization: Basic eDp5\r\n\r\n"), None);
+        assert!(same_token(b"s3cret", b"s3cret"));
+        assert!(!same_token(b"s3cre", b"s3cret"));
+        assert!(!same_token(b"s3creT", b"s3cret"));
+    }
+
+    #[test]
+    fn test_start_refuses_unauthenticated_public_bind() {
+        let err = start("0.0.0.0", 0, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        let config = MetricsExporterConfig {
+            listen: Some("0.0.0.0:0".to_string()),
+            ..Default::default()
+        };
+        let err = start("0.0.0.0", 0, Some(&config)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_respond_rejects_wrong_token() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n")
+            .await
+            .unwrap();
+        respond(server, Some("s3cret")).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("\r\nWWW-Authenticate: Bearer\r\n"));
+        assert!(!response.contains("geofront_connections_total"));
+    }
+
+    #[test]
+    fn test_render() {
+        let text = render();
+        assert!(text.contains("# TYPE geofront_connections_total counter\ngeofront_connections_total "));
+        assert!(text.contains("# TYPE geofront_active_connections gauge\n"));
+        assert!(text.contains("geofront_ttfb_seconds{quantile=\"0.99\"} "));
+        assert!(text.contains("# TYPE geofront_backend_connect_seconds histogram\n"));
+        assert!(text.contains("geofront_session_duration_seconds_bucket{le=\"+Inf\"} "));
+    }
+}
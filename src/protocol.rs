@@ -1,9 +1,14 @@
 //! proxy_core/src/protocol.rs
 //! Minecraft protocol parsing and serialization utilities
 
-use crate::types::HandshakeData;
-use std::io::{Error, ErrorKind, Result};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::{messages, types::HandshakeData};
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 
 /// Reads a VarInt (max 5 bytes) from the provided stream.
 pub async fn read_varint<R>(stream: &mut R) -> Result<i32>
@@ -28,7 +33,7 @@ where
 }
 
 /// Writes a VarInt to the buffer.
-fn write_varint(buf: &mut Vec<u8>, mut value: i32) {
+pub fn write_varint(buf: &mut Vec<u8>, mut value: i32) {
     loop {
         if (value & !0x7F) == 0 {
             buf.push(value as u8);
@@ -59,57 +64,237 @@ where
 }
 
 /// Writes a length-prefixed UTF-8 string into the buffer.
-fn write_string(buf: &mut Vec<u8>, s: &str) {
+pub fn write_string(buf: &mut Vec<u8>, s: &str) {
     let bytes = s.as_bytes();
     write_varint(buf, bytes.len() as i32);
     buf.extend_from_slice(bytes);
 }
 
 /// Sends a Login Disconnect packet with the given message, then closes the stream.
-pub async fn write_disconnect<S>(stream: &mut S, msg: &str) -> Result<()>
+/// Legacy color codes in `msg` are translated for the client's protocol version.
+pub async fn write_disconnect<S>(stream: &mut S, msg: &str, protocol: i32) -> Result<()>
 where
     S: AsyncWriteExt + Unpin,
 {
+    // Send and shutdown
+    stream.write_all(&disconnect_packet(msg, protocol)).await?;
+    let _ = stream.shutdown().await;
+    Ok(())
+}
+
+/// Builds a Login Disconnect packet carrying `msg`.
+pub fn disconnect_packet(msg: &str, protocol: i32) -> Vec<u8> {
     // Build packet payload: [PacketID VarInt=0] [String reason]
     let mut payload = Vec::new();
     write_varint(&mut payload, 0); // Disconnect packet ID in Login state
-    write_string(&mut payload, msg);
+    write_string(&mut payload, &messages::to_component_json(msg, protocol));
 
     // Prepend length VarInt
     let mut packet = Vec::new();
     write_varint(&mut packet, payload.len() as i32);
     packet.extend(payload);
+    packet
+}
 
-    // Send and shutdown
-    stream.write_all(&packet).await?;
+/// Refuses a status request with `msg` and closes the stream. A status ping
+/// has no disconnect packet, so `msg` is sent as the server description.
+pub async fn write_status_disconnect<S>(stream: &mut S, msg: &str, protocol: i32) -> Result<()>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    stream.write_all(&status_disconnect_packet(msg, protocol)).await?;
     let _ = stream.shutdown().await;
     Ok(())
 }
 
+/// Builds a Status Response describing the server as `msg`, with no players.
+pub fn status_disconnect_packet(msg: &str, protocol: i32) -> Vec<u8> {
+    let description: serde_json::Value =
+        serde_json::from_str(&messages::to_component_json(msg, protocol)).unwrap_or_default();
+    let status = serde_json::json!({
+        "version": { "name": "Geofront", "protocol": protocol },
+        "players": { "max": 0, "online": 0 },
+        "description": description,
+    });
+    // Same framing as the Login Disconnect: Status Response is packet 0 too.
+    let mut payload = Vec::new();
+    write_varint(&mut payload, 0);
+    write_string(&mut payload, &status.to_string());
+
+    let mut packet = Vec::new();
+    write_varint(&mut packet, payload.len() as i32);
+    packet.extend(payload);
+    packet
+}
+
+/// First protocol with the Transfer packet and handshake intent (1.20.5).
+pub const TRANSFER_PROTOCOL: i32 = 766;
+/// Handshake intent of a client arriving through a Transfer packet.
+pub const TRANSFER_INTENT: i32 = 3;
+/// Finish Configuration, clientbound, in the configuration state.
+pub const FINISH_CONFIGURATION_ID: i32 = 0x03;
+
+/// Packet ID of the clientbound Transfer packet in the configuration or play
+/// state, for the protocols it is known for.
+fn transfer_packet_id(protocol: i32, play: bool) -> Option<i32> {
+    match protocol {
+        p if p < TRANSFER_PROTOCOL => None,
+        _ if !play => Some(0x0B),
+        766..=767 => Some(0x73),
+        768 => Some(0x7A),
+        _ => None,
+    }
+}
+
+/// Packet ID of Start Configuration, which takes a client from play back to
+/// the configuration state.
+pub fn start_configuration_id(protocol: i32) -> Option<i32> {
+    match protocol {
+        766..=767 => Some(0x69),
+        768 => Some(0x70),
+        _ => None,
+    }
+}
+
+/// Whether a Transfer packet can be built for `protocol` in either state.
+pub fn transfer_supported(protocol: i32) -> bool {
+    transfer_packet_id(protocol, true).is_some()
+}
+
+/// Builds a Transfer packet sending the client to `host:port`, framed for a
+/// connection in the play (or else configuration) state, with the
+/// compressed packet format once compression is on.
+pub fn transfer_packet(protocol: i32, play: bool, compressed: bool, host: &str, port: u16) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    if compressed {
+        // Data length 0: sent uncompressed
+        write_varint(&mut data, 0);
+    }
+    write_varint(&mut data, transfer_packet_id(protocol, play)?);
+    write_string(&mut data, host);
+    write_varint(&mut data, port as i32);
+
+    let mut packet = Vec::new();
+    write_varint(&mut packet, data.len() as i32);
+    packet.extend(data);
+    Some(packet)
+}
+
 pub async fn parse_handshake<R>(stream: &mut R) -> Result<HandshakeData>
 where
     R: AsyncReadExt + Unpin,
 {
-    let _packet_len = read_varint(stream).await?;
+    let packet_len = read_varint(stream).await?;
+    if !(1..=MAX_HANDSHAKE_LEN).contains(&packet_len) {
+        return Err(not_minecraft("implausible handshake length"));
+    }
     let packet_id = read_varint(stream).await?;
     if packet_id != 0 {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            "Invalid handshake packet ID",
-        ));
+        return Err(not_minecraft("invalid handshake packet ID"));
     }
     let protocol_version = read_varint(stream).await?;
-    let host = read_string(stream).await?;
+    if !plausible_protocol(protocol_version) {
+        return Err(not_minecraft("implausible protocol version"));
+    }
+    let (host, client_type) = split_client_type(&read_string(stream).await?);
+    if !plausible_host(&host) {
+        return Err(not_minecraft("implausible handshake host"));
+    }
     let port = stream.read_u16().await?;
     let next_state = read_varint(stream).await?;
+    if !(1..=TRANSFER_INTENT).contains(&next_state) {
+        return Err(not_minecraft("invalid handshake intent"));
+    }
     Ok(HandshakeData {
         protocol_version,
         host,
         port,
         next_state,
+        forwarded: None,
+        client_type,
     })
 }
 
+/// Longest handshake accepted: a host of 32767 characters, as a forwarding
+/// payload may make it, plus the other fields.
+const MAX_HANDSHAKE_LEN: i32 = 3 * 32767 + 16;
+/// Longest host name accepted in front of any `\0` payload.
+const MAX_HOST_NAME_LEN: usize = 255;
+/// Protocol versions of releases and, from 1.16.4 on, of snapshots
+/// (`0x40000000` plus a sequence number).
+const MAX_RELEASE_PROTOCOL: i32 = 0x7FFF;
+const SNAPSHOT_PROTOCOLS: std::ops::RangeInclusive<i32> = 0x4000_0000..=0x4000_FFFF;
+
+/// A handshake that is not Minecraft traffic at all, such as an HTTP
+/// request or a TLS hello; see `is_not_minecraft`.
+#[derive(Debug)]
+pub struct NotMinecraft(&'static str);
+
+impl fmt::Display for NotMinecraft {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for NotMinecraft {}
+
+fn not_minecraft(reason: &'static str) -> Error {
+    Error::new(ErrorKind::InvalidData, NotMinecraft(reason))
+}
+
+/// Whether `parse_handshake` failed on traffic that is not Minecraft, as
+/// opposed to a Minecraft client sending something broken.
+pub fn is_not_minecraft(error: &Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<NotMinecraft>())
+}
+
+fn plausible_protocol(protocol: i32) -> bool {
+    // -1 is sent by pingers that do not know the server's version.
+    (-1..=MAX_RELEASE_PROTOCOL).contains(&protocol) || SNAPSHOT_PROTOCOLS.contains(&protocol)
+}
+
+/// A host name, an IP literal or empty, ahead of any `\0` payload.
+fn plausible_host(host: &str) -> bool {
+    let name = host.split('\0').next().unwrap_or_default();
+    name.len() <= MAX_HOST_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_' | ':' | '[' | ']'))
+}
+
+/// Markers modded clients append to the handshake host, as `host\0FML2\0`.
+const CLIENT_TYPES: [&str; 4] = ["FML", "FML2", "FML3", "FORGE"];
+
+/// Normalizes a handshake host: splits off a client marker (see
+/// `CLIENT_TYPES`) and drops the trailing dot of a fully qualified name.
+/// Whatever follows the marker, such as a BungeeCord forwarding payload,
+/// stays on the host.
+pub fn split_client_type(raw: &str) -> (String, Option<String>) {
+    let (name, rest) = raw.split_once('\0').map_or((raw, None), |(name, rest)| (name, Some(rest)));
+    let name = name.trim_end_matches('.');
+    let Some(rest) = rest else {
+        return (name.to_string(), None);
+    };
+    let (marker, after) = rest.split_once('\0').unwrap_or((rest, ""));
+    if CLIENT_TYPES.contains(&marker) {
+        (format!("{}{}", name, after), Some(marker.to_string()))
+    } else {
+        (format!("{}\0{}", name, rest), None)
+    }
+}
+
+/// Parses recorded client bytes as the proxy does: the handshake, then the
+/// login start unless it asks for status. For replaying corpus samples.
+pub async fn replay(bytes: &[u8]) -> Result<(HandshakeData, Option<String>)> {
+    let mut reader = ConnReader::new(bytes);
+    let hs = parse_handshake(&mut reader).await?;
+    if hs.next_state == 1 {
+        return Ok((hs, None));
+    }
+    let username = parse_login_start(&mut reader).await?;
+    Ok((hs, Some(username)))
+}
+
 pub async fn parse_login_start<R>(stream: &mut R) -> Result<String>
 where
     R: AsyncReadExt + Unpin,
@@ -124,3 +309,216 @@ where
     }
     read_string(stream).await
 }
+
+/// Reads the client side of a connection in chunks. Anything the client
+/// pipelined behind the packets parsed so far stays buffered and is handed
+/// back by `into_parts`, to be forwarded to the backend untouched.
+pub struct ConnReader<S> {
+    inner: S,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<S> ConnReader<S> {
+    const CHUNK: usize = 4096;
+
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Like `new`, with `buf` already read from `inner` but not consumed.
+    pub fn with_buffered(inner: S, buf: Vec<u8>) -> Self {
+        Self { inner, buf, pos: 0 }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// The stream and the bytes read from it but not consumed yet.
+    pub fn into_parts(mut self) -> (S, Vec<u8>) {
+        self.buf.drain(..self.pos);
+        (self.inner, self.buf)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ConnReader<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if this.pos == this.buf.len() {
+            this.buf.resize(Self::CHUNK, 0);
+            this.pos = 0;
+            let mut chunk = ReadBuf::new(&mut this.buf);
+            let polled = Pin::new(&mut this.inner).poll_read(cx, &mut chunk);
+            let filled = chunk.filled().len();
+            this.buf.truncate(filled);
+            ready!(polled)?;
+        }
+        let n = out.remaining().min(this.buf.len() - this.pos);
+        out.put_slice(&this.buf[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ConnReader<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(id: i32, payload: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_varint(&mut body, id);
+        body.extend_from_slice(payload);
+        let mut packet = Vec::new();
+        write_varint(&mut packet, body.len() as i32);
+        packet.extend(body);
+        packet
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, s.len() as i32);
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_packets_are_kept() {
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, 767);
+        handshake.extend(string("mc.example.com"));
+        handshake.extend(25565u16.to_be_bytes());
+        write_varint(&mut handshake, 2);
+        let mut flush = packet(0, &handshake);
+        flush.extend(packet(0, &string("Steve")));
+        // Sent by the client before the proxy has answered anything.
+        let trailing = packet(3, &[1, 2, 3]);
+        flush.extend(&trailing);
+
+        let mut reader = ConnReader::new(&flush[..]);
+        let hs = parse_handshake(&mut reader).await.unwrap();
+        assert_eq!(hs.host, "mc.example.com");
+        assert_eq!(parse_login_start(&mut reader).await.unwrap(), "Steve");
+        let (_, rest) = reader.into_parts();
+        assert_eq!(rest, trailing);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_is_not_minecraft() {
+        let http = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let err = parse_handshake(&mut &http[..]).await.err().unwrap();
+        assert!(is_not_minecraft(&err));
+        // TLS ClientHello record header.
+        let tls = [0x16, 0x03, 0x01, 0x02, 0x00, 0x01, 0x00, 0x01, 0xfc, 0x03, 0x03];
+        assert!(is_not_minecraft(&parse_handshake(&mut &tls[..]).await.err().unwrap()));
+
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, 767);
+        handshake.extend(string("<script>"));
+        handshake.extend(25565u16.to_be_bytes());
+        write_varint(&mut handshake, 1);
+        let err = parse_handshake(&mut &packet(0, &handshake)[..]).await.err().unwrap();
+        assert!(is_not_minecraft(&err));
+        // A truncated but plausible handshake is a client error.
+        let truncated = &packet(0, &handshake)[..4];
+        assert!(!is_not_minecraft(&parse_handshake(&mut &truncated[..]).await.err().unwrap()));
+    }
+
+    #[test]
+    fn test_split_client_type() {
+        assert_eq!(split_client_type("mc.example.com."), ("mc.example.com".to_string(), None));
+        assert_eq!(
+            split_client_type("mc.example.com\x00FML2\x00"),
+            ("mc.example.com".to_string(), Some("FML2".to_string()))
+        );
+        // A forwarding payload behind the marker is kept for `forwarding.rs`.
+        assert_eq!(
+            split_client_type("mc.example.com\x00FML\x00\x00203.0.113.7\x00uuid"),
+            ("mc.example.com\x00203.0.113.7\x00uuid".to_string(), Some("FML".to_string()))
+        );
+        assert_eq!(
+            split_client_type("mc.example.com\x00203.0.113.7\x00uuid"),
+            ("mc.example.com\x00203.0.113.7\x00uuid".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_transfer_packet() {
+        let mut body = string("mc.example.com");
+        write_varint(&mut body, 25565);
+        assert_eq!(
+            transfer_packet(767, true, false, "mc.example.com", 25565).unwrap(),
+            packet(0x73, &body)
+        );
+
+        let mut compressed = vec![0];
+        compressed.extend(packet(0x0B, &body).split_off(1));
+        let mut framed = Vec::new();
+        write_varint(&mut framed, compressed.len() as i32);
+        framed.extend(compressed);
+        assert_eq!(
+            transfer_packet(767, false, true, "mc.example.com", 25565).unwrap(),
+            framed
+        );
+
+        assert!(transfer_packet(765, false, false, "mc.example.com", 25565).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_status_disconnect_packet() {
+        let packet = status_disconnect_packet("&cClosed", 767);
+        let mut body = &packet[..];
+        let len = read_varint(&mut body).await.unwrap();
+        assert_eq!(len as usize, body.len());
+        assert_eq!(read_varint(&mut body).await.unwrap(), 0);
+        let json_len = read_varint(&mut body).await.unwrap() as usize;
+        let status: serde_json::Value = serde_json::from_slice(&body[..json_len]).unwrap();
+        assert_eq!(status["version"]["protocol"], 767);
+        assert_eq!(status["description"], serde_json::json!({ "text": "Closed", "color": "red" }));
+    }
+
+    /// Replays `tests/corpus`: `ok-*` samples must parse, the others must
+    /// be rejected without panicking.
+    #[tokio::test]
+    async fn test_replay_corpus() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return;
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let bytes = std::fs::read(&path).unwrap();
+            let result = replay(&bytes).await;
+            if name.starts_with("ok-") {
+                if let Err(e) = result {
+                    panic!("{} no longer parses: {}", name, e);
+                }
+            } else {
+                assert!(result.is_err(), "{} parses now; rename it to ok-*", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! geofront/src/protocol_errors.rs
+//! Structured reports of unparseable client traffic, carrying a sample of
+//! the raw bytes so hosts can fingerprint scanners and feed ban lists.
+
+use crate::{
+    corpus,
+    events::{self, ProxyEvent},
+    state::{CONN_INFO, OPTIONS, PROTOCOL_ERROR_COUNTS, PROTOCOL_ERROR_QUEUE},
+    types::{ProtocolErrorEvent, ProtocolErrorKind, ProxyConnection},
+    wakeup,
+};
+use std::{
+    fmt::{Display, Write},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::warn;
+
+const DEFAULT_SAMPLE_BYTES: usize = 64;
+const MAX_SAMPLE_BYTES: usize = 4096;
+/// Events kept for polling at most; the oldest are dropped first.
+const MAX_PENDING_EVENTS: usize = 1024;
+
+/// Configured sample size, clamped to `MAX_SAMPLE_BYTES`.
+pub fn sample_limit() -> usize {
+    OPTIONS
+        .read()
+        .unwrap()
+        .protocol_error_sample_bytes
+        .unwrap_or(DEFAULT_SAMPLE_BYTES)
+        .min(MAX_SAMPLE_BYTES)
+}
+
+/// Bytes of the session to keep for a failed parse: the event sample, or
+/// more for the corpus.
+pub fn capture_limit() -> usize {
+    sample_limit().max(corpus::sample_limit())
+}
+
+/// Counts the error, queues an event for it and saves it to the corpus.
+pub fn report(
+    conn_id: ProxyConnection,
+    kind: ProtocolErrorKind,
+    error: &dyn Display,
+    sample: &[u8],
+) {
+    *PROTOCOL_ERROR_COUNTS
+        .lock()
+        .unwrap()
+        .entry(kind)
+        .or_insert(0) += 1;
+
+    let (peer_ip, listener) = CONN_INFO
+        .get(&conn_id)
+        .map(|info| (info.peer_ip.clone(), info.listener))
+        .unwrap_or_default();
+    let event = ProtocolErrorEvent {
+        conn_id,
+        timestamp_ms: events::now_ms(),
+        peer_ip,
+        listener,
+        kind,
+        error: error.to_string(),
+        sample_hex: to_hex(&sample[..sample.len().min(sample_limit())]),
+    };
+
+    let dropped = wakeup::push_bounded(&mut PROTOCOL_ERROR_QUEUE.lock().unwrap(), event.clone(), MAX_PENDING_EVENTS);
+    if dropped > 0 {
+        warn!("Protocol error queue full, dropped {} oldest events", dropped);
+    }
+    events::emit(ProxyEvent::ProtocolError(event));
+    corpus::save(kind, sample);
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+}
+
+/// Reader that keeps a copy of the first `limit` bytes read through it.
+pub struct SampledReader<'a, R> {
+    inner: &'a mut R,
+    sample: &'a mut Vec<u8>,
+    limit: usize,
+}
+
+impl<'a, R> SampledReader<'a, R> {
+    pub fn new(inner: &'a mut R, sample: &'a mut Vec<u8>, limit: usize) -> Self {
+        Self {
+            inner,
+            sample,
+            limit,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SampledReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut *this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let read = &buf.filled()[before..];
+            let room = this.limit.saturating_sub(this.sample.len());
+            this.sample.extend_from_slice(&read[..read.len().min(room)]);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_sampled_reader() {
+        let data = b"GET / HTTP/1.1\r\n".to_vec();
+        let mut inner = &data[..];
+        let mut sample = Vec::new();
+        let mut reader = SampledReader::new(&mut inner, &mut sample, 4);
+        let mut first = [0u8; 2];
+        reader.read_exact(&mut first).await.unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+
+        assert_eq!(sample, b"GET ");
+        assert_eq!(to_hex(&sample), "47455420");
+    }
+
+    #[test]
+    fn test_report_counts_by_kind() {
+        let before = |kind| {
+            PROTOCOL_ERROR_COUNTS
+                .lock()
+                .unwrap()
+                .get(&kind)
+                .copied()
+                .unwrap_or(0)
+        };
+        let handshake = before(ProtocolErrorKind::Handshake);
+        report(
+            0xbad0_0001,
+            ProtocolErrorKind::Handshake,
+            &"bad varint",
+            &[0xff; 8],
+        );
+        report(
+            0xbad0_0002,
+            ProtocolErrorKind::Handshake,
+            &"bad varint",
+            &[],
+        );
+        assert_eq!(before(ProtocolErrorKind::Handshake), handshake + 2);
+
+        let queue = PROTOCOL_ERROR_QUEUE.lock().unwrap();
+        let event = queue.iter().find(|e| e.conn_id == 0xbad0_0001).unwrap();
+        assert_eq!(event.sample_hex, "ff".repeat(8));
+        assert_eq!(event.error, "bad varint");
+    }
+}
@@ -0,0 +1,341 @@
+//! geofront/src/quic.rs
+//! Tunnels between geofront instances over QUIC, for long edge→origin
+//! links. A route with `proxy = "quic://token@origin:port"` opens a stream
+//! on a connection kept open to the origin's tunnel listener
+//! (`proxy_start_quic_tunnel`) rather than a TCP connection per player, so
+//! players after the first skip the transport and TLS handshakes. The
+//! origin connects each stream to the target the edge asked for, as a
+//! CONNECT proxy would, if the stream carries its token.
+//!
+//! A stream starts with the token (u8 length) and the target `host:port`
+//! (u16 length); the origin answers with a status byte, then relays.
+//!
+//! Requires the `quic` feature.
+
+#[cfg(feature = "quic")]
+use std::io::{Error, ErrorKind, Result};
+#[cfg(feature = "quic")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+#[cfg(not(feature = "quic"))]
+pub use disabled::{Endpoint, bind, open, serve};
+
+#[cfg(feature = "quic")]
+pub use quinn_tunnel::{Endpoint, bind, open, serve};
+
+/// Port of `quic://` proxies without one.
+pub const DEFAULT_PORT: u16 = 25577;
+/// ALPN protocol of tunnel connections.
+#[cfg(feature = "quic")]
+const ALPN: &[u8] = b"geofront-tunnel/1";
+
+#[cfg(feature = "quic")]
+const STATUS_OK: u8 = 0;
+#[cfg(feature = "quic")]
+const STATUS_UNAUTHORIZED: u8 = 1;
+#[cfg(feature = "quic")]
+const STATUS_UNREACHABLE: u8 = 2;
+
+/// The head of a tunnel stream asking for `target`.
+#[cfg(feature = "quic")]
+fn encode_request(token: &str, target: &str) -> Result<Vec<u8>> {
+    let token_len = u8::try_from(token.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "QUIC tunnel token too long"))?;
+    let target_len = u16::try_from(target.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "QUIC tunnel target too long"))?;
+    let mut request = Vec::with_capacity(3 + token.len() + target.len());
+    request.push(token_len);
+    request.extend_from_slice(token.as_bytes());
+    request.extend_from_slice(&target_len.to_be_bytes());
+    request.extend_from_slice(target.as_bytes());
+    Ok(request)
+}
+
+/// Reads the token and target at the head of a tunnel stream.
+#[cfg(feature = "quic")]
+async fn read_request<R: AsyncRead + Unpin>(stream: &mut R) -> Result<(String, String)> {
+    let mut token = vec![0; stream.read_u8().await? as usize];
+    stream.read_exact(&mut token).await?;
+    let mut target = vec![0; stream.read_u16().await? as usize];
+    stream.read_exact(&mut target).await?;
+    let utf8 = |bytes: Vec<u8>| String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+    Ok((utf8(token)?, utf8(target)?))
+}
+
+/// Host and port of a `host:port` target, IPv6 hosts unbracketed.
+#[cfg(feature = "quic")]
+fn split_target(target: &str) -> Result<(&str, u16)> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("invalid tunnel target {}", target)))?;
+    Ok((host.trim_start_matches('[').trim_end_matches(']'), port))
+}
+
+/// The error for a status other than `STATUS_OK`.
+#[cfg(feature = "quic")]
+fn status_error(status: u8) -> Error {
+    match status {
+        STATUS_UNAUTHORIZED => Error::new(ErrorKind::PermissionDenied, "QUIC tunnel refused the token"),
+        STATUS_UNREACHABLE => Error::new(ErrorKind::ConnectionRefused, "QUIC tunnel could not reach the target"),
+        status => Error::new(ErrorKind::InvalidData, format!("unknown QUIC tunnel status {}", status)),
+    }
+}
+
+#[cfg(not(feature = "quic"))]
+mod disabled {
+    use crate::types::{AsyncStream, ProxyListener, QuicTunnelConfig};
+    use std::{
+        io::{Error, ErrorKind, Result},
+        net::SocketAddr,
+    };
+    use url::Url;
+
+    /// Never constructed without the `quic` feature.
+    pub enum Endpoint {}
+
+    fn unsupported() -> Error {
+        Error::new(ErrorKind::Unsupported, "QUIC tunnels require the `quic` feature")
+    }
+
+    pub fn bind(_addr: SocketAddr, _config: &QuicTunnelConfig) -> Result<Endpoint> {
+        Err(unsupported())
+    }
+
+    pub async fn serve(_listener_id: ProxyListener, endpoint: Endpoint, _config: QuicTunnelConfig) {
+        match endpoint {}
+    }
+
+    pub async fn open(_proxy_url: &Url, _target: &str) -> Result<Box<AsyncStream>> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(feature = "quic")]
+mod quinn_tunnel {
+    use super::*;
+    use crate::{
+        outbound::{self, SocketConfig},
+        state::OPTIONS,
+        types::{AsyncStream, ProxyListener, QuicTunnelConfig},
+    };
+    use quinn::{
+        Connection, Incoming, RecvStream, SendStream, TransportConfig, VarInt,
+        crypto::rustls::{QuicClientConfig, QuicServerConfig},
+        rustls::{
+            self, RootCertStore,
+            crypto::ring,
+            pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        },
+    };
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::{Arc, OnceLock},
+        time::Duration,
+    };
+    use tokio::{io::copy_bidirectional, net::lookup_host, task::JoinSet};
+    use tracing::{debug, info, warn};
+    use url::Url;
+
+    pub struct Endpoint(quinn::Endpoint);
+
+    fn invalid(e: impl std::fmt::Display) -> Error {
+        Error::new(ErrorKind::InvalidData, e.to_string())
+    }
+
+    /// Binds a tunnel listener; must run within the proxy runtime.
+    pub fn bind(addr: SocketAddr, config: &QuicTunnelConfig) -> Result<Endpoint> {
+        let certs = CertificateDer::pem_file_iter(&config.cert_path)
+            .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| invalid(format_args!("{}: {}", config.cert_path, e)))?;
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .map_err(|e| invalid(format_args!("{}: {}", config.key_path, e)))?;
+        let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(invalid)?;
+        tls.alpn_protocols = vec![ALPN.to_vec()];
+        let mut server = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls).map_err(invalid)?));
+        let mut transport = TransportConfig::default();
+        transport.max_concurrent_bidi_streams(VarInt::from_u32(config.max_streams));
+        server.transport_config(Arc::new(transport));
+        Ok(Endpoint(quinn::Endpoint::server(server, addr)?))
+    }
+
+    /// Accepts tunnel connections until the listener is stopped.
+    pub async fn serve(listener_id: ProxyListener, endpoint: Endpoint, config: QuicTunnelConfig) {
+        let config = Arc::new(config);
+        // Dropping the set when the listener is aborted closes every tunnel.
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                incoming = endpoint.0.accept() => match incoming {
+                    Some(incoming) => {
+                        connections.spawn(serve_connection(listener_id, incoming, config.clone()));
+                    }
+                    None => break,
+                },
+                Some(_) = connections.join_next() => {}
+            }
+        }
+    }
+
+    async fn serve_connection(listener_id: ProxyListener, incoming: Incoming, config: Arc<QuicTunnelConfig>) {
+        let connection = match incoming.await {
+            Ok(connection) => connection,
+            Err(e) => {
+                debug!(listener = listener_id, "QUIC tunnel handshake failed: {}", e);
+                return;
+            }
+        };
+        let peer = connection.remote_address();
+        info!(listener = listener_id, %peer, "QUIC tunnel connected");
+        let mut streams = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepted = connection.accept_bi() => match accepted {
+                    Ok((send, recv)) => {
+                        streams.spawn(serve_stream(peer, send, recv, config.clone()));
+                    }
+                    Err(e) => {
+                        info!(listener = listener_id, %peer, "QUIC tunnel closed: {}", e);
+                        break;
+                    }
+                },
+                Some(_) = streams.join_next() => {}
+            }
+        }
+    }
+
+    /// Connects one tunnelled player to the target its stream asks for.
+    async fn serve_stream(peer: SocketAddr, mut send: SendStream, mut recv: RecvStream, config: Arc<QuicTunnelConfig>) {
+        let (token, target) = match read_request(&mut recv).await {
+            Ok(request) => request,
+            Err(e) => {
+                debug!(%peer, "Bad QUIC tunnel request: {}", e);
+                return;
+            }
+        };
+        if config.token.as_ref().is_some_and(|expected| *expected != token) {
+            warn!(%peer, "QUIC tunnel stream with a wrong token");
+            let _ = send.write_all(&[STATUS_UNAUTHORIZED]).await;
+            let _ = send.finish();
+            return;
+        }
+        let connected = match split_target(&target) {
+            Ok((host, port)) => outbound::connect_host(host, port, SocketConfig::default()).await,
+            Err(e) => Err(e),
+        };
+        let mut backend = match connected {
+            Ok(backend) => backend,
+            Err(e) => {
+                warn!(%peer, %target, "QUIC tunnel target unreachable: {}", e);
+                let _ = send.write_all(&[STATUS_UNREACHABLE]).await;
+                let _ = send.finish();
+                return;
+            }
+        };
+        if send.write_all(&[STATUS_OK]).await.is_err() {
+            return;
+        }
+        let mut tunnel = tokio::io::join(recv, send);
+        if let Err(e) = copy_bidirectional(&mut tunnel, &mut backend).await {
+            debug!(%peer, %target, "QUIC tunnel stream ended: {}", e);
+        }
+    }
+
+    /// Opens a stream to `target` through the tunnel listener at `proxy_url`.
+    pub async fn open(proxy_url: &Url, target: &str) -> Result<Box<AsyncStream>> {
+        let host = proxy_url.host_str().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = proxy_url.port().unwrap_or(DEFAULT_PORT);
+        let connection = connection_to(host, port).await?;
+        let (mut send, mut recv) = connection.open_bi().await.map_err(Error::other)?;
+        send.write_all(&encode_request(proxy_url.username(), target)?).await?;
+        let status = recv.read_u8().await?;
+        if status != STATUS_OK {
+            return Err(status_error(status));
+        }
+        Ok(Box::new(tokio::io::join(recv, send)))
+    }
+
+    /// The connection kept open to `host:port`, made anew if it closed.
+    /// Players racing to the first connection may each make one; the last
+    /// one made is kept.
+    async fn connection_to(host: &str, port: u16) -> Result<Connection> {
+        static CONNECTIONS: OnceLock<std::sync::Mutex<HashMap<String, Connection>>> = OnceLock::new();
+        let connections = CONNECTIONS.get_or_init(Default::default);
+        let key = format!("{}:{}", host, port);
+        if let Some(connection) = connections.lock().unwrap().get(&key)
+            && connection.close_reason().is_none()
+        {
+            return Ok(connection.clone());
+        }
+        let addr = lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} resolves to no address", host)))?;
+        let (client, server_name) = client_config(host)?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let mut endpoint = quinn::Endpoint::client(local)?;
+        endpoint.set_default_client_config(client);
+        let connection = endpoint
+            .connect(addr, &server_name)
+            .map_err(Error::other)?
+            .await
+            .map_err(Error::other)?;
+        info!(tunnel = %key, "QUIC tunnel connected");
+        connections.lock().unwrap().insert(key, connection.clone());
+        Ok(connection)
+    }
+
+    /// Client config from the `quic` options, with the name to verify the
+    /// origin's certificate against.
+    fn client_config(host: &str) -> Result<(quinn::ClientConfig, String)> {
+        let Some(options) = OPTIONS.read().unwrap().quic.clone() else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "quic:// proxies need the quic.caPath option",
+            ));
+        };
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(&options.ca_path)
+            .map_err(|e| invalid(format_args!("{}: {}", options.ca_path, e)))?
+        {
+            roots
+                .add(cert.map_err(|e| invalid(format_args!("{}: {}", options.ca_path, e)))?)
+                .map_err(invalid)?;
+        }
+        let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(invalid)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![ALPN.to_vec()];
+        let mut client = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).map_err(invalid)?));
+        let mut transport = TransportConfig::default();
+        transport.keep_alive_interval(Some(Duration::from_millis(options.keep_alive_ms.max(1000))));
+        client.transport_config(Arc::new(transport));
+        Ok((client, options.server_name.unwrap_or_else(|| host.to_string())))
+    }
+}
+
+#[cfg(all(test, feature = "quic"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_round_trip() {
+        let request = encode_request("s3cret", "[2001:db8::1]:25565").unwrap();
+        let (token, target) = read_request(&mut request.as_slice()).await.unwrap();
+        assert_eq!(token, "s3cret");
+        assert_eq!(split_target(&target).unwrap(), ("2001:db8::1", 25565));
+        assert!(encode_request(&"x".repeat(256), "backend:25565").is_err());
+        assert!(split_target("backend").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! geofront/src/quota.rs
+//! Byte quotas of a route (`quotaBytes`, `userQuotaBytes`): a session that
+//! uses up its quota is closed, and a player whose retained usage (see
+//! `accounting.rs`) already exceeds `userQuotaBytes` is refused at login
+//! with the `quotaExceeded` message. Both publish a quota event so the host
+//! does not have to poll metrics. Traffic is read from the byte counters, as
+//! for idle timeouts, so a session may overshoot its quota by what it
+//! relays in one check period.
+
+use crate::{
+    accounting,
+    events::{self, ProxyEvent},
+    state::{CONN_METRICS, QUOTA_EVENT_QUEUE},
+    types::{ProxyConnection, QuotaEvent, QuotaScope},
+    wakeup,
+};
+use std::{sync::atomic::Ordering, time::Duration};
+use tracing::warn;
+
+/// How often a relayed session's usage is compared to its quotas.
+const CHECK_PERIOD: Duration = Duration::from_millis(250);
+/// Quota events kept for `proxy_poll_events` before the oldest are dropped.
+const MAX_PENDING_EVENTS: usize = 1024;
+
+/// A quota that was reached: which one, its size and the bytes used.
+pub struct Exceeded {
+    pub scope: QuotaScope,
+    pub quota_bytes: u64,
+    pub used_bytes: u64,
+}
+
+/// The user quota a player has already used up before logging in, if any.
+pub fn check_login(username: &str, user_quota: Option<u64>) -> Option<Exceeded> {
+    let quota_bytes = user_quota?;
+    let used_bytes = accounting::closed_bytes_of_user(username);
+    (used_bytes >= quota_bytes).then_some(Exceeded {
+        scope: QuotaScope::User,
+        quota_bytes,
+        used_bytes,
+    })
+}
+
+/// Completes once the connection has relayed `conn_quota` bytes, or its
+/// player `user_quota` bytes counting retained closed sessions; never
+/// without either.
+pub async fn wait_exceeded(
+    conn_id: ProxyConnection,
+    username: &str,
+    conn_quota: Option<u64>,
+    user_quota: Option<u64>,
+) -> Exceeded {
+    let metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    let Some(metrics) = metrics.filter(|_| conn_quota.is_some() || user_quota.is_some()) else {
+        return std::future::pending().await;
+    };
+    let mut ticker = tokio::time::interval(CHECK_PERIOD);
+    loop {
+        ticker.tick().await;
+        let relayed = metrics.bytes_sent.load(Ordering::SeqCst) + metrics.bytes_recv.load(Ordering::SeqCst);
+        if let Some(quota_bytes) = conn_quota
+            && relayed >= quota_bytes
+        {
+            return Exceeded {
+                scope: QuotaScope::Connection,
+                quota_bytes,
+                used_bytes: relayed,
+            };
+        }
+        if let Some(quota_bytes) = user_quota {
+            let used_bytes = accounting::closed_bytes_of_user(username) + relayed;
+            if used_bytes >= quota_bytes {
+                return Exceeded {
+                    scope: QuotaScope::User,
+                    quota_bytes,
+                    used_bytes,
+                };
+            }
+        }
+    }
+}
+
+/// Queues and emits the event of a connection closed or refused for a quota.
+pub fn publish(conn_id: ProxyConnection, username: &str, peer_ip: &str, exceeded: &Exceeded) {
+    let event = QuotaEvent {
+        conn_id,
+        timestamp_ms: events::now_ms(),
+        username: username.to_string(),
+        peer_ip: peer_ip.to_string(),
+        scope: exceeded.scope,
+        quota_bytes: exceeded.quota_bytes,
+        used_bytes: exceeded.used_bytes,
+    };
+    let dropped = wakeup::push_bounded(&mut QUOTA_EVENT_QUEUE.lock().unwrap(), event.clone(), MAX_PENDING_EVENTS);
+    if dropped > 0 {
+        warn!("Quota event queue full, dropped {} oldest events", dropped);
+    }
+    events::emit(ProxyEvent::QuotaExceeded(event));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ConnInfo;
+
+    #[test]
+    fn test_check_login() {
+        let info = ConnInfo {
+            username: Some("QuotaTest".to_string()),
+            ..Default::default()
+        };
+        accounting::release(&info, 600, 400);
+        assert!(check_login("QuotaTest", None).is_none());
+        assert!(check_login("QuotaTest", Some(1001)).is_none());
+        let exceeded = check_login("QuotaTest", Some(1000)).unwrap();
+        assert_eq!(exceeded.scope, QuotaScope::User);
+        assert_eq!(exceeded.used_bytes, 1000);
+    }
+}
@@ -0,0 +1,91 @@
+//! geofront/src/route_audit.rs
+//! Append-only record of routing decisions (`routeAudit`), for abuse
+//! investigations and compliance. The decision for each login attempt
+//! (where it came from, the backend and proxy chosen or why the player was
+//! refused) is appended to a JSON-lines file and/or queued for
+//! `proxy_poll_events`, besides going to the event sinks as `routed`.
+
+use crate::{
+    events::{self, ProxyEvent},
+    state::{OPTIONS, ROUTE_AUDIT_QUEUE},
+    types::RouteAudit,
+    wakeup,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Result, Write},
+    sync::{Mutex, OnceLock},
+};
+use tracing::warn;
+
+/// Decisions kept for `proxy_poll_events`; older ones are dropped first.
+const MAX_PENDING_AUDITS: usize = 1024;
+
+/// Records a routing decision where `routeAudit` asks and publishes it to
+/// the event sinks.
+pub fn publish(audit: RouteAudit) {
+    let config = OPTIONS.read().unwrap().route_audit.clone();
+    if let Some(config) = config {
+        if let Some(path) = &config.path
+            && let Err(e) = append(path, &audit)
+        {
+            warn!(conn = audit.conn_id, path, "Failed to write route audit: {}", e);
+        }
+        if config.queue {
+            wakeup::push_bounded(&mut ROUTE_AUDIT_QUEUE.lock().unwrap(), audit.clone(), MAX_PENDING_AUDITS);
+        }
+    }
+    events::emit(ProxyEvent::Routed(audit));
+}
+
+/// Appends one line to the audit file, which stays open until the
+/// configured path changes.
+fn append(path: &str, audit: &RouteAudit) -> Result<()> {
+    static FILE: OnceLock<Mutex<Option<(String, File)>>> = OnceLock::new();
+    let mut line = serde_json::to_vec(audit)?;
+    line.push(b'\n');
+    let mut slot = FILE.get_or_init(Default::default).lock().unwrap();
+    if let Some((open, file)) = &mut *slot
+        && open == path
+    {
+        return file.write_all(&line);
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    *slot = Some((path.to_string(), file));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appends_lines() {
+        let path = std::env::temp_dir().join(format!("geofront-route-audit-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let audit = RouteAudit {
+            conn_id: 1,
+            timestamp_ms: 1_700_000_000_000,
+            peer_ip: "198.51.100.7".to_string(),
+            host: "mc.example.com".to_string(),
+            username: "Steve".to_string(),
+            source: "static",
+            backend: Some("10.0.0.1:25565".to_string()),
+            proxy: None,
+            reject_reason: None,
+            metadata: None,
+        };
+        append(path, &audit).unwrap();
+        append(path, &RouteAudit { conn_id: 2, source: "cache", backend: None, reject_reason: Some("Banned".to_string()), ..audit }).unwrap();
+
+        let lines = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines: Vec<serde_json::Value> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["source"], "static");
+        assert_eq!(lines[0]["backend"], "10.0.0.1:25565");
+        assert_eq!(lines[1]["connId"], 2);
+        assert_eq!(lines[1]["rejectReason"], "Banned");
+    }
+}
@@ -0,0 +1,105 @@
+//! geofront/src/route_metrics.rs
+//! Connections and traffic aggregated by handshake host and by the backend
+//! connected to, for per-domain accounting. As for listeners, closed
+//! connections are folded into the totals under the `LISTENER_TOTALS` lock
+//! and live ones are added when a snapshot is taken, so bytes are counted
+//! exactly once.
+
+use crate::{state::ROUTE_TOTALS, types::ConnInfo};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A host's or backend's bucket in `MetricsSnapshot`; bytes include its
+/// live connections.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct RouteMetrics {
+    /// Connections seen since start.
+    pub connections: u64,
+    pub active: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RouteTotals {
+    /// Keyed by lowercased handshake host.
+    pub hosts: HashMap<String, RouteMetrics>,
+    /// Keyed by `host:port` (or `pool:name`) as in `ConnInfo.backend`.
+    pub backends: HashMap<String, RouteMetrics>,
+}
+
+impl RouteTotals {
+    /// Adds a connection's bytes to its host and backend.
+    pub fn add_bytes(&mut self, info: &ConnInfo, bytes_sent: u64, bytes_recv: u64) {
+        for bucket in self.buckets(info) {
+            bucket.bytes_sent += bytes_sent;
+            bucket.bytes_recv += bytes_recv;
+        }
+    }
+
+    fn buckets(&mut self, info: &ConnInfo) -> impl Iterator<Item = &mut RouteMetrics> {
+        let host = info
+            .host
+            .as_deref()
+            .and_then(|host| self.hosts.get_mut(&host.to_ascii_lowercase()));
+        let backend = info
+            .backend
+            .as_deref()
+            .and_then(|backend| self.backends.get_mut(backend));
+        host.into_iter().chain(backend)
+    }
+}
+
+/// Counts a connection that sent a handshake for `host`.
+pub fn count_host(host: &str) {
+    let mut totals = ROUTE_TOTALS.lock().unwrap();
+    let bucket = totals.hosts.entry(host.to_ascii_lowercase()).or_default();
+    bucket.connections += 1;
+    bucket.active += 1;
+}
+
+/// Counts a connection relayed to `backend`.
+pub fn count_backend(backend: &str) {
+    let mut totals = ROUTE_TOTALS.lock().unwrap();
+    let bucket = totals.backends.entry(backend.to_string()).or_default();
+    bucket.connections += 1;
+    bucket.active += 1;
+}
+
+/// Folds a closed connection into the totals of its host and backend.
+/// Called from `cleanup_conn` with `LISTENER_TOTALS` held.
+pub fn release(info: &ConnInfo, bytes_sent: u64, bytes_recv: u64) {
+    let mut totals = ROUTE_TOTALS.lock().unwrap();
+    for bucket in totals.buckets(info) {
+        bucket.active = bucket.active.saturating_sub(1);
+        bucket.bytes_sent += bytes_sent;
+        bucket.bytes_recv += bytes_recv;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_by_host_and_backend() {
+        let info = ConnInfo {
+            host: Some("Play.Route-Metrics.test".to_string()),
+            backend: Some("10.0.0.9:25565".to_string()),
+            ..Default::default()
+        };
+        count_host("play.route-metrics.test");
+        count_host("PLAY.route-metrics.test");
+        count_backend("10.0.0.9:25565");
+        release(&info, 100, 40);
+
+        let mut totals = ROUTE_TOTALS.lock().unwrap().clone();
+        totals.add_bytes(&info, 5, 5);
+        let host = &totals.hosts["play.route-metrics.test"];
+        assert_eq!((host.connections, host.active), (2, 1));
+        assert_eq!((host.bytes_sent, host.bytes_recv), (105, 45));
+        let backend = &totals.backends["10.0.0.9:25565"];
+        assert_eq!((backend.connections, backend.active), (1, 0));
+        assert_eq!(backend.bytes_sent, 105);
+    }
+}
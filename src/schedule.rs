@@ -0,0 +1,199 @@
+//! geofront/src/schedule.rs
+//! Time-based routing rules: a host goes to one destination inside its
+//! opening windows and to another (or is rejected) outside of them.
+
+use crate::{
+    state::OPTIONS,
+    types::{RouteDecision, ScheduleRule, ScheduleWindow},
+};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use tracing::warn;
+
+/// Placeholder in `closed.disconnect` replaced by the next opening time.
+const OPENS_AT: &str = "{opensAt}";
+
+/// The decision of the first schedule matching `host`, if any.
+pub fn route(host: &str) -> Option<RouteDecision> {
+    let options = OPTIONS.read().unwrap();
+    let rule = options
+        .schedules
+        .iter()
+        .find(|rule| host_matches(&rule.host, host))?;
+    Some(decide(rule, Utc::now()))
+}
+
+/// Matches exactly or, for `*.suffix`, any subdomain of `suffix`. Forge
+/// markers after a NUL and a trailing dot are ignored.
+pub(crate) fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.split('\0').next().unwrap_or("").trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.len().checked_sub(suffix.len() + 1).is_some_and(|dot| {
+            host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(suffix)
+        }),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+fn decide(rule: &ScheduleRule, now: DateTime<Utc>) -> RouteDecision {
+    let tz = match rule.timezone.as_deref() {
+        Some(name) => name.parse::<Tz>().unwrap_or_else(|_| {
+            warn!(host = %rule.host, timezone = %name, "Unknown schedule timezone, using UTC");
+            Tz::UTC
+        }),
+        None => Tz::UTC,
+    };
+    let windows: Vec<Window> = rule
+        .windows
+        .iter()
+        .filter_map(|w| {
+            Window::parse(w)
+                .inspect_err(|e| warn!(host = %rule.host, "Ignoring schedule window: {}", e))
+                .ok()
+        })
+        .collect();
+
+    let local = now.with_timezone(&tz);
+    if windows.iter().any(|w| w.contains(&local)) {
+        return rule.open.clone();
+    }
+
+    let mut closed = rule.closed.clone();
+    if let Some(msg) = &closed.disconnect
+        && msg.contains(OPENS_AT)
+    {
+        let opens_at = windows
+            .iter()
+            .filter_map(|w| w.next_start(&local))
+            .min()
+            .map_or_else(
+                || "later".to_string(),
+                |at| at.format("%a %H:%M %Z").to_string(),
+            );
+        closed.disconnect = Some(msg.replace(OPENS_AT, &opens_at));
+    }
+    closed
+}
+
+struct Window {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    fn parse(window: &ScheduleWindow) -> Result<Self, String> {
+        let time = |s: &str| {
+            NaiveTime::parse_from_str(s, "%H:%M").map_err(|e| format!("bad time {:?}: {}", s, e))
+        };
+        let days = window
+            .days
+            .iter()
+            .map(|d| d.parse().map_err(|_| format!("bad weekday {:?}", d)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            days,
+            start: time(&window.start)?,
+            end: time(&window.end)?,
+        })
+    }
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn contains(&self, local: &DateTime<Tz>) -> bool {
+        let (t, day) = (local.time(), local.weekday());
+        if self.start < self.end {
+            self.starts_on(day) && self.start <= t && t < self.end
+        } else {
+            // Spans midnight; equal bounds mean the whole day.
+            (self.starts_on(day) && t >= self.start) || (self.starts_on(day.pred()) && t < self.end)
+        }
+    }
+
+    fn next_start(&self, local: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = local.timezone();
+        (0..8)
+            .map(|d| local.date_naive() + Duration::days(d))
+            .filter(|date| self.starts_on(date.weekday()))
+            .filter_map(|date| {
+                tz.from_local_datetime(&date.and_time(self.start))
+                    .earliest()
+            })
+            .find(|at| at > local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(timezone: Option<&str>, days: &[&str]) -> ScheduleRule {
+        ScheduleRule {
+            host: "event.example.com".to_string(),
+            timezone: timezone.map(str::to_string),
+            windows: vec![ScheduleWindow {
+                days: days.iter().map(|d| d.to_string()).collect(),
+                start: "18:00".to_string(),
+                end: "22:00".to_string(),
+            }],
+            open: RouteDecision {
+                remote_host: Some("event".to_string()),
+                ..Default::default()
+            },
+            closed: RouteDecision {
+                disconnect: Some("Opens at {opensAt}".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("event.example.com", "Event.Example.com."));
+        assert!(host_matches(
+            "event.example.com",
+            "event.example.com\0FML\0"
+        ));
+        assert!(host_matches("*.hub.example.com", "eu.hub.example.com"));
+        assert!(!host_matches("*.hub.example.com", "hub.example.com"));
+        assert!(!host_matches("*.hub.example.com", "xhub.example.com"));
+    }
+
+    #[test]
+    fn test_open_and_closed() {
+        let r = rule(None, &[]);
+        let open = decide(&r, at("2026-10-16T19:30:00Z"));
+        assert_eq!(open.remote_host.as_deref(), Some("event"));
+
+        let closed = decide(&r, at("2026-10-16T22:00:00Z"));
+        assert_eq!(closed.disconnect.as_deref(), Some("Opens at Sat 18:00 UTC"));
+    }
+
+    #[test]
+    fn test_timezone_and_days() {
+        // 17:30 UTC is 19:30 in Berlin (CEST).
+        let r = rule(Some("Europe/Berlin"), &[]);
+        assert!(decide(&r, at("2026-10-16T17:30:00Z")).remote_host.is_some());
+
+        // 2026-10-16 is a Friday.
+        let r = rule(None, &["sat", "sun"]);
+        let closed = decide(&r, at("2026-10-16T19:00:00Z"));
+        assert_eq!(closed.disconnect.as_deref(), Some("Opens at Sat 18:00 UTC"));
+    }
+
+    #[test]
+    fn test_window_spanning_midnight() {
+        let mut r = rule(None, &["fri"]);
+        r.windows[0].start = "22:00".to_string();
+        r.windows[0].end = "02:00".to_string();
+        assert!(decide(&r, at("2026-10-16T23:00:00Z")).remote_host.is_some());
+        assert!(decide(&r, at("2026-10-17T01:00:00Z")).remote_host.is_some());
+        assert!(decide(&r, at("2026-10-17T03:00:00Z")).remote_host.is_none());
+    }
+}
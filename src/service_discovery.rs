@@ -0,0 +1,380 @@
+//! geofront/src/service_discovery.rs
+//! Watchers keeping named backend pools in sync with Consul (`consul` feature)
+//! or an etcd prefix (`etcd` feature).
+
+#[cfg(any(feature = "consul", feature = "etcd"))]
+use crate::{discovery, state::LISTENER_STATE};
+use crate::{
+    state::{NAMED_POOLS, POOL_WATCHERS},
+    types::ServiceDiscoveryConfig,
+};
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// Restarts the watchers for `pools`. Pools that are no longer configured are
+/// dropped; the others keep serving their last members until refreshed.
+pub fn configure(pools: &HashMap<String, ServiceDiscoveryConfig>) {
+    let mut watchers = POOL_WATCHERS.lock().unwrap();
+    for (_, handle) in watchers.drain() {
+        handle.abort();
+    }
+    NAMED_POOLS.retain(|name, _| pools.contains_key(name));
+    for (name, config) in pools {
+        if let Some(handle) = start(name, config) {
+            watchers.insert(name.clone(), handle);
+        }
+    }
+}
+
+fn start(name: &str, config: &ServiceDiscoveryConfig) -> Option<JoinHandle<()>> {
+    match config.clone() {
+        #[cfg(feature = "consul")]
+        ServiceDiscoveryConfig::Consul {
+            address,
+            service,
+            tag,
+            datacenter,
+            token,
+        } => {
+            tracing::info!(pool = %name, %address, %service, "Watching Consul service");
+            let watch = consul::Watch {
+                pool: name.to_string(),
+                address,
+                service,
+                tag,
+                datacenter,
+                token,
+            };
+            Some(runtime().spawn(watch.run()))
+        }
+        #[cfg(feature = "etcd")]
+        ServiceDiscoveryConfig::Etcd { endpoint, prefix } => {
+            tracing::info!(pool = %name, %endpoint, %prefix, "Watching etcd prefix");
+            Some(runtime().spawn(etcd::run(name.to_string(), endpoint, prefix)))
+        }
+        #[allow(unreachable_patterns)]
+        other => {
+            error!(
+                pool = %name,
+                "Service discovery {:?} is not available: geofront was built without the matching feature",
+                other
+            );
+            None
+        }
+    }
+}
+
+#[cfg(any(feature = "consul", feature = "etcd"))]
+fn runtime() -> tokio::runtime::Handle {
+    LISTENER_STATE.lock().unwrap().runtime.handle().clone()
+}
+
+/// Delay before retrying after a registry error.
+#[cfg(any(feature = "consul", feature = "etcd"))]
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Resolves registry entries, which may carry hostnames, to socket addresses.
+#[cfg(any(feature = "consul", feature = "etcd"))]
+async fn resolve_members(entries: Vec<(String, u16)>) -> Vec<std::net::SocketAddr> {
+    let mut members = Vec::new();
+    for (host, port) in entries {
+        match tokio::net::lookup_host((host.as_str(), port)).await {
+            Ok(addrs) => members.extend(addrs),
+            Err(e) => tracing::warn!(%host, "Failed to resolve pool member: {}", e),
+        }
+    }
+    members
+}
+
+#[cfg(feature = "consul")]
+mod consul {
+    use super::*;
+    use reqwest::Client;
+    use serde::Deserialize;
+    use std::time::Duration;
+    use tracing::warn;
+
+    /// Server-side hold time of a blocking query.
+    const WAIT: &str = "55s";
+    /// Must outlive `WAIT` plus Consul's jitter of up to `WAIT / 16`.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(70);
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct HealthEntry {
+        node: NodeEntry,
+        service: ServiceEntry,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct NodeEntry {
+        address: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct ServiceEntry {
+        address: String,
+        port: u16,
+    }
+
+    pub struct Watch {
+        pub pool: String,
+        pub address: String,
+        pub service: String,
+        pub tag: Option<String>,
+        pub datacenter: Option<String>,
+        pub token: Option<String>,
+    }
+
+    impl Watch {
+        /// Follows the passing instances of the service with blocking queries.
+        pub async fn run(self) {
+            let client = match Client::builder().timeout(REQUEST_TIMEOUT).build() {
+                Ok(client) => client,
+                Err(e) => {
+                    error!(pool = %self.pool, "Failed to create Consul client: {}", e);
+                    return;
+                }
+            };
+            let mut index = 0u64;
+            loop {
+                match self.fetch(&client, index).await {
+                    Ok((new_index, entries)) => {
+                        // Consul may reset the index; restart from zero when
+                        // it goes backwards, as its documentation advises.
+                        index = if new_index < index { 0 } else { new_index };
+                        let entries = entries
+                            .into_iter()
+                            .map(|e| {
+                                let host = if e.service.address.is_empty() {
+                                    e.node.address
+                                } else {
+                                    e.service.address
+                                };
+                                (host, e.service.port)
+                            })
+                            .collect();
+                        discovery::update_named_pool(&self.pool, resolve_members(entries).await);
+                    }
+                    Err(e) => {
+                        warn!(pool = %self.pool, "Consul query failed: {}", e);
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    }
+                }
+            }
+        }
+
+        async fn fetch(
+            &self,
+            client: &Client,
+            index: u64,
+        ) -> Result<(u64, Vec<HealthEntry>), Box<dyn std::error::Error + Send + Sync>> {
+            let url = format!(
+                "{}/v1/health/service/{}",
+                self.address.trim_end_matches('/'),
+                self.service
+            );
+            let mut query = vec![
+                ("passing", "true".to_string()),
+                ("index", index.to_string()),
+                ("wait", WAIT.to_string()),
+            ];
+            if let Some(tag) = &self.tag {
+                query.push(("tag", tag.clone()));
+            }
+            if let Some(dc) = &self.datacenter {
+                query.push(("dc", dc.clone()));
+            }
+            let mut request = client.get(url).query(&query);
+            if let Some(token) = &self.token {
+                request = request.header("X-Consul-Token", token);
+            }
+            let response = request.send().await?.error_for_status()?;
+            let new_index = response
+                .headers()
+                .get("X-Consul-Index")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            let body = response.bytes().await?;
+            Ok((new_index, serde_json::from_slice(&body)?))
+        }
+    }
+}
+
+#[cfg(feature = "etcd")]
+mod etcd {
+    use super::*;
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use reqwest::Client;
+    use serde_json::{Value, json};
+    use std::time::Duration;
+    use tracing::warn;
+
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    type EtcdResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Lists the prefix, then holds a watch open until anything under it
+    /// changes, and repeats.
+    pub async fn run(pool: String, endpoint: String, prefix: String) {
+        let client = match Client::builder().connect_timeout(CONNECT_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                error!(%pool, "Failed to create etcd client: {}", e);
+                return;
+            }
+        };
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let key = STANDARD.encode(prefix.as_bytes());
+        let range_end = STANDARD.encode(prefix_end(prefix.as_bytes()));
+        loop {
+            let result = async {
+                let (revision, entries) = range(&client, &endpoint, &key, &range_end).await?;
+                discovery::update_named_pool(&pool, resolve_members(entries).await);
+                watch(&client, &endpoint, &key, &range_end, revision + 1).await
+            }
+            .await;
+            if let Err(e) = result {
+                warn!(%pool, "etcd watch failed: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// The smallest key greater than every key starting with `prefix`.
+    fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < 0xff {
+                end.push(last + 1);
+                return end;
+            }
+        }
+        // All bytes were 0xff: "\0" means "to the end of the keyspace".
+        vec![0]
+    }
+
+    async fn range(
+        client: &Client,
+        endpoint: &str,
+        key: &str,
+        range_end: &str,
+    ) -> EtcdResult<(i64, Vec<(String, u16)>)> {
+        let body = json!({ "key": key, "range_end": range_end }).to_string();
+        let response = client
+            .post(format!("{}/v3/kv/range", endpoint))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        let reply: Value = serde_json::from_slice(&response.bytes().await?)?;
+        // The JSON gateway encodes int64 fields as strings.
+        let revision = reply["header"]["revision"]
+            .as_str()
+            .and_then(|r| r.parse().ok())
+            .unwrap_or(0);
+        let entries = reply["kvs"]
+            .as_array()
+            .map(|kvs| {
+                kvs.iter()
+                    .filter_map(|kv| kv["value"].as_str())
+                    .filter_map(|v| STANDARD.decode(v).ok())
+                    .filter_map(|v| parse_member(&String::from_utf8_lossy(&v)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok((revision, entries))
+    }
+
+    /// Returns once the watch reports a change or is cancelled by the server
+    /// (e.g. after compaction).
+    async fn watch(
+        client: &Client,
+        endpoint: &str,
+        key: &str,
+        range_end: &str,
+        start_revision: i64,
+    ) -> EtcdResult<()> {
+        let body = json!({
+            "create_request": {
+                "key": key,
+                "range_end": range_end,
+                "start_revision": start_revision.to_string(),
+            }
+        })
+        .to_string();
+        let mut response = client
+            .post(format!("{}/v3/watch", endpoint))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // The gateway streams one JSON message per line.
+        let mut buf = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buf.extend_from_slice(&chunk);
+            while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=pos).collect();
+                let Ok(message) = serde_json::from_slice::<Value>(&line) else {
+                    continue;
+                };
+                let result = &message["result"];
+                let changed = result["events"].as_array().is_some_and(|e| !e.is_empty());
+                if changed || result["canceled"].as_bool() == Some(true) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses `host:port` or `{"host": ..., "port": ...}`.
+    fn parse_member(value: &str) -> Option<(String, u16)> {
+        if let Ok(v) = serde_json::from_str::<Value>(value)
+            && v.is_object()
+        {
+            let host = v["host"].as_str()?.to_string();
+            let port = u16::try_from(v["port"].as_u64()?).ok()?;
+            return Some((host, port));
+        }
+        let (host, port) = value.trim().rsplit_once(':')?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        Some((host.to_string(), port.parse().ok()?))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_prefix_end() {
+            assert_eq!(prefix_end(b"/mc/lobby/"), b"/mc/lobby0".to_vec());
+            assert_eq!(prefix_end(b"a\xff"), b"b".to_vec());
+            assert_eq!(prefix_end(b"\xff"), vec![0]);
+        }
+
+        #[test]
+        fn test_parse_member() {
+            assert_eq!(
+                parse_member("10.0.0.1:25565"),
+                Some(("10.0.0.1".to_string(), 25565))
+            );
+            assert_eq!(
+                parse_member("[::1]:25565"),
+                Some(("::1".to_string(), 25565))
+            );
+            assert_eq!(
+                parse_member(r#"{"host": "lobby-1.internal", "port": 25566}"#),
+                Some(("lobby-1.internal".to_string(), 25566))
+            );
+            assert_eq!(parse_member("garbage"), None);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! geofront/src/shared_cache.rs
+//! Redis-backed layer for `RouterMotdCache`, so cached routes and bans are
+//! shared between edge nodes (`redis` feature).
+
+use crate::{cache::CacheEntry, types::SharedCacheConfig};
+use redis::{AsyncCommands, aio::ConnectionManager, aio::ConnectionManagerConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Redis round trips are on the login path, so keep them tightly bounded.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Wire format of an entry stored in Redis; the TTL lives on the key itself.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredEntry {
+    data: Value,
+    is_rejection: bool,
+    reject_reason: Option<String>,
+}
+
+pub struct SharedCache {
+    conn: ConnectionManager,
+    key_prefix: String,
+    /// Upper bound for how long a Redis hit may be served from local memory.
+    pub local_ttl: Option<Duration>,
+}
+
+impl SharedCache {
+    /// Creates a lazily connecting client; must be called within a Tokio runtime.
+    pub fn new(config: &SharedCacheConfig) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let conn = client.get_connection_manager_lazy(
+            ConnectionManagerConfig::new()
+                .set_response_timeout(Some(RESPONSE_TIMEOUT))
+                .set_connection_timeout(Some(RESPONSE_TIMEOUT)),
+        )?;
+        Ok(Self {
+            conn,
+            key_prefix: config.key_prefix.clone(),
+            local_ttl: config.local_ttl_ms.map(Duration::from_millis),
+        })
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix, key)
+    }
+
+    /// Fetches an entry together with its remaining TTL.
+    pub async fn get(&self, key: &str) -> redis::RedisResult<Option<CacheEntry>> {
+        let redis_key = self.redis_key(key);
+        let mut conn = self.conn.clone();
+        let (raw, pttl): (Option<String>, i64) = redis::pipe()
+            .get(&redis_key)
+            .pttl(&redis_key)
+            .query_async(&mut conn)
+            .await?;
+        // PTTL is negative for missing keys or keys without expiry; we never
+        // write the latter, so treat both as a miss.
+        let (Some(raw), true) = (raw, pttl > 0) else {
+            return Ok(None);
+        };
+        let Ok(stored) = serde_json::from_str::<StoredEntry>(&raw) else {
+            return Ok(None);
+        };
+        Ok(Some(CacheEntry {
+            data: stored.data,
+            is_rejection: stored.is_rejection,
+            reject_reason: stored.reject_reason,
+            expires_at: Instant::now() + Duration::from_millis(pttl as u64),
+        }))
+    }
+
+    pub async fn set(&self, key: &str, entry: &CacheEntry, ttl: Duration) -> redis::RedisResult<()> {
+        let stored = StoredEntry {
+            data: entry.data.clone(),
+            is_rejection: entry.is_rejection,
+            reject_reason: entry.reject_reason.clone(),
+        };
+        let raw = serde_json::to_string(&stored).unwrap_or_default();
+        let mut conn = self.conn.clone();
+        conn.pset_ex(self.redis_key(key), raw, ttl.as_millis().max(1) as u64)
+            .await
+    }
+
+    pub async fn remove(&self, key: &str) -> redis::RedisResult<()> {
+        let mut conn = self.conn.clone();
+        conn.del::<_, ()>(self.redis_key(key)).await
+    }
+
+    /// Removes every entry whose key matches the glob `pattern`, scanning in
+    /// batches rather than blocking Redis with `KEYS`.
+    pub async fn remove_matching(&self, pattern: &str) -> redis::RedisResult<()> {
+        let redis_pattern = self.redis_key(pattern);
+        let mut conn = self.conn.clone();
+        let mut cursor = 0u64;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&redis_pattern)
+                .arg("COUNT")
+                .arg(500)
+                .query_async(&mut conn)
+                .await?;
+            if !keys.is_empty() {
+                conn.del::<_, ()>(keys).await?;
+            }
+            if next == 0 {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+}
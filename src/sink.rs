@@ -0,0 +1,258 @@
+//! geofront/src/sink.rs
+//! Optional exporters that stream `ProxyEvent`s to NATS (`nats` feature) or
+//! Kafka (`kafka` feature).
+
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use crate::state::LISTENER_STATE;
+use crate::{events::ProxyEvent, state::EVENT_SINK, types::EventSinkConfig};
+use tokio::sync::mpsc;
+use tracing::error;
+
+/// Capacity of the in-memory buffer between connection tasks and the exporter.
+#[cfg(any(feature = "nats", feature = "kafka"))]
+const SINK_BUFFER: usize = 4096;
+
+/// Installs (or removes, when `config` is `None`) the event exporter.
+/// The previous exporter task stops once its channel sender is dropped.
+pub fn configure(config: Option<&EventSinkConfig>) {
+    *EVENT_SINK.write().unwrap() = config.and_then(start);
+}
+
+fn start(config: &EventSinkConfig) -> Option<mpsc::Sender<ProxyEvent>> {
+    match config.clone() {
+        #[cfg(feature = "nats")]
+        EventSinkConfig::Nats { url, subject } => {
+            let (tx, rx) = mpsc::channel(SINK_BUFFER);
+            tracing::info!(%url, %subject, "Starting NATS event sink");
+            runtime().spawn(nats::run(url, subject, rx));
+            Some(tx)
+        }
+        #[cfg(feature = "kafka")]
+        EventSinkConfig::Kafka {
+            brokers,
+            topic,
+            partition,
+        } => {
+            let (tx, rx) = mpsc::channel(SINK_BUFFER);
+            tracing::info!(?brokers, %topic, partition, "Starting Kafka event sink");
+            runtime().spawn(kafka::run(brokers, topic, partition, rx));
+            Some(tx)
+        }
+        #[allow(unreachable_patterns)]
+        other => {
+            error!(
+                "Event sink {:?} is not available: geofront was built without the matching feature",
+                other
+            );
+            None
+        }
+    }
+}
+
+#[cfg(any(feature = "nats", feature = "kafka"))]
+fn runtime() -> tokio::runtime::Handle {
+    LISTENER_STATE.lock().unwrap().runtime.handle().clone()
+}
+
+/// Delay before reconnecting a sink after a transport error.
+#[cfg(any(feature = "nats", feature = "kafka"))]
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[cfg(feature = "nats")]
+mod nats {
+    //! Minimal NATS publisher speaking the text protocol directly
+    //! (`CONNECT`/`PUB`/`PING`/`PONG`), which is all an exporter needs.
+
+    use super::{ProxyEvent, RECONNECT_DELAY};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpStream,
+        sync::mpsc,
+    };
+    use tracing::{info, warn};
+    use url::Url;
+
+    pub async fn run(url: String, subject: String, mut rx: mpsc::Receiver<ProxyEvent>) {
+        let mut pending: Option<ProxyEvent> = None;
+        loop {
+            let stream = match connect(&url).await {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!(%url, "NATS connect failed: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+            info!(%url, "NATS event sink connected");
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+
+            loop {
+                let event = match pending.take() {
+                    Some(ev) => ev,
+                    None => tokio::select! {
+                        ev = rx.recv() => match ev {
+                            Some(ev) => ev,
+                            // Sink was reconfigured or removed.
+                            None => return,
+                        },
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => {
+                                if line.starts_with("PING") {
+                                    if write_half.write_all(b"PONG\r\n").await.is_err() {
+                                        break;
+                                    }
+                                } else if line.starts_with("-ERR") {
+                                    warn!("NATS server error: {}", line);
+                                }
+                                continue;
+                            }
+                            _ => break,
+                        },
+                    },
+                };
+
+                let payload = match serde_json::to_vec(&event) {
+                    Ok(p) => p,
+                    Err(_) => continue,
+                };
+                let mut frame =
+                    format!("PUB {}.{} {}\r\n", subject, event.name(), payload.len()).into_bytes();
+                frame.extend_from_slice(&payload);
+                frame.extend_from_slice(b"\r\n");
+                if let Err(e) = write_half.write_all(&frame).await {
+                    warn!("NATS publish failed: {}", e);
+                    pending = Some(event);
+                    break;
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn connect(url: &str) -> std::io::Result<TcpStream> {
+        let parsed = Url::parse(url).map_err(std::io::Error::other)?;
+        let host = parsed.host_str().unwrap_or("127.0.0.1");
+        let port = parsed.port().unwrap_or(4222);
+        let mut stream = TcpStream::connect((host, port)).await?;
+
+        // The server greets with an INFO line before accepting commands.
+        let mut info_line = String::new();
+        let mut reader = BufReader::new(&mut stream);
+        reader.read_line(&mut info_line).await?;
+
+        let mut connect = serde_json::json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "geofront",
+        });
+        if !parsed.username().is_empty() {
+            match parsed.password() {
+                Some(pass) => {
+                    connect["user"] = parsed.username().into();
+                    connect["pass"] = pass.into();
+                }
+                None => connect["auth_token"] = parsed.username().into(),
+            }
+        }
+        stream
+            .write_all(format!("CONNECT {}\r\n", connect).as_bytes())
+            .await?;
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    //! Kafka producer built on `rskafka`; events are batched per poll and keyed
+    //! by connection id so a connection's events stay ordered.
+
+    use super::{ProxyEvent, RECONNECT_DELAY};
+    use crate::events;
+    use rskafka::{
+        chrono::DateTime,
+        client::{
+            ClientBuilder,
+            partition::{Compression, PartitionClient, UnknownTopicHandling},
+        },
+        record::Record,
+    };
+    use std::collections::BTreeMap;
+    use tokio::sync::mpsc;
+    use tracing::{info, warn};
+
+    /// Upper bound on records sent in a single produce request.
+    const MAX_BATCH: usize = 256;
+
+    pub async fn run(
+        brokers: Vec<String>,
+        topic: String,
+        partition: i32,
+        mut rx: mpsc::Receiver<ProxyEvent>,
+    ) {
+        let mut batch: Vec<Record> = Vec::new();
+        loop {
+            let client = match connect(&brokers, &topic, partition).await {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!(?brokers, "Kafka connect failed: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+            info!(%topic, "Kafka event sink connected");
+
+            loop {
+                if batch.is_empty() {
+                    match rx.recv().await {
+                        Some(ev) => batch.push(to_record(&ev)),
+                        None => return,
+                    }
+                    while batch.len() < MAX_BATCH {
+                        match rx.try_recv() {
+                            Ok(ev) => batch.push(to_record(&ev)),
+                            Err(_) => break,
+                        }
+                    }
+                }
+                match client
+                    .produce(batch.clone(), Compression::NoCompression)
+                    .await
+                {
+                    Ok(_) => batch.clear(),
+                    Err(e) => {
+                        warn!("Kafka produce failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn connect(
+        brokers: &[String],
+        topic: &str,
+        partition: i32,
+    ) -> rskafka::client::error::Result<PartitionClient> {
+        let client = ClientBuilder::new(brokers.to_vec())
+            .client_id("geofront")
+            .build()
+            .await?;
+        client
+            .partition_client(topic, partition, UnknownTopicHandling::Retry)
+            .await
+    }
+
+    fn to_record(event: &ProxyEvent) -> Record {
+        let mut headers = BTreeMap::new();
+        headers.insert("type".to_string(), event.name().as_bytes().to_vec());
+        Record {
+            key: Some(event.key().into_bytes()),
+            value: serde_json::to_vec(event).ok(),
+            headers,
+            timestamp: DateTime::from_timestamp_millis(events::now_ms() as i64)
+                .unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! geofront/src/snapshot.rs
+//! Snapshots returned to the host on its hot polling paths: metrics, metrics
+//! deltas and batched events, encoded into reusable output buffers as JSON
+//! or (with the `msgpack` feature) MessagePack.
+
+use crate::{
+    latency, proxy_manager,
+    route_metrics::RouteTotals,
+    state::{
+        ACTIVE_CONN, BACKEND_EVENT_QUEUE, CONN_INFO, CONN_METRICS, DISCONNECTION_EVENT_QUEUE,
+        LIFECYCLE_EVENT_QUEUE, LISTENER_TOTALS, METRICS_CURSORS, METRICS_DELTA_BASE, METRICS_EVENT_QUEUE, MOTD_REQUEST_QUEUE, PROTOCOL_ERROR_COUNTS,
+        PROTOCOL_ERROR_QUEUE, PROXY_EVENT_QUEUE, QUOTA_EVENT_QUEUE, RATE_LIMITERS, ROUTE_AUDIT_QUEUE, CONFIG_RELOAD_QUEUE, ROUTE_REQUEST_QUEUE, ROUTE_TOTALS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT, TOTAL_CONN,
+        USAGE_REPORT_QUEUE,
+    },
+    types::{
+        ConnMetricsSnapshot, ConnectionDetails, ListenerMetrics, MetricsDelta, MetricsSnapshot, PollEvents,
+        ProxyConnection, ProxyListener, TagGroupSnapshot, WIRE_JSON, WireFormat,
+    },
+};
+#[cfg(feature = "msgpack")]
+use crate::types::WIRE_MSGPACK;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    ptr, sync::Mutex, sync::atomic::Ordering};
+
+/// Consumers of `metrics_delta_since` remembered at once.
+const MAX_METRICS_CURSORS: usize = 16;
+
+/// Connections as of each outstanding `metrics_delta_since` cursor, oldest
+/// first.
+#[derive(Default)]
+pub struct MetricsCursors {
+    next: u64,
+    bases: VecDeque<(u64, HashMap<ProxyConnection, ConnMetricsSnapshot>)>,
+}
+
+/// Output buffer of one buffer-returning FFI call, kept across calls so
+/// frequent polling does not allocate once the buffer has grown.
+#[derive(Default)]
+pub struct WireBuffer(Mutex<Vec<u8>>);
+
+impl WireBuffer {
+    /// Encodes `value` into the buffer and returns a pointer to the bytes,
+    /// storing their length in `out_len`, or NULL if encoding fails. The bytes
+    /// stay valid until the next call on the same buffer.
+    pub fn encode<T: Serialize>(&self, value: &T, format: WireFormat, out_len: &mut usize) -> *const u8 {
+        let mut buf = self.0.lock().unwrap();
+        buf.clear();
+        *out_len = 0;
+        if let Err(e) = encode_into(&mut buf, value, format) {
+            tracing::error!("Failed to encode snapshot: {}", e);
+            return ptr::null();
+        }
+        *out_len = buf.len();
+        buf.as_ptr()
+    }
+}
+
+fn encode_into<T: Serialize>(buf: &mut Vec<u8>, value: &T, format: WireFormat) -> Result<(), String> {
+    match format {
+        WIRE_JSON => serde_json::to_writer(buf, value).map_err(|e| e.to_string()),
+        #[cfg(feature = "msgpack")]
+        WIRE_MSGPACK => rmp_serde::encode::write_named(buf, value).map_err(|e| e.to_string()),
+        _ => Err(format!("unsupported format {}", format)),
+    }
+}
+
+/// Details of one live connection, or `None` if it is unknown.
+pub fn connection(conn_id: ProxyConnection) -> Option<ConnectionDetails> {
+    let info = CONN_INFO.get(&conn_id)?.clone();
+    let metrics = CONN_METRICS.get(&conn_id).map(|entry| entry.value().clone());
+    let limiters = RATE_LIMITERS.get(&conn_id).map(|entry| entry.value().clone());
+    Some(ConnectionDetails {
+        conn_id,
+        info,
+        bytes_sent: metrics.as_ref().map_or(0, |m| m.bytes_sent.load(Ordering::SeqCst)),
+        bytes_recv: metrics.as_ref().map_or(0, |m| m.bytes_recv.load(Ordering::SeqCst)),
+        send_limit: limiters.as_ref().map(|(send, _)| send.snapshot()),
+        recv_limit: limiters.as_ref().map(|(_, recv)| recv.snapshot()),
+    })
+}
+
+/// Takes a snapshot of all metrics.
+pub fn metrics() -> MetricsSnapshot {
+    // Taken first, as `cleanup_conn` does, so closing connections' bytes
+    // are counted exactly once.
+    let listener_totals_guard = LISTENER_TOTALS.lock().unwrap();
+    let mut route_totals: RouteTotals = ROUTE_TOTALS.lock().unwrap().clone();
+    let mut per_listener: HashMap<ProxyListener, ListenerMetrics> = listener_totals_guard
+        .iter()
+        .map(|(id, totals)| {
+            (
+                *id,
+                ListenerMetrics {
+                    accepted: totals.accepted,
+                    active: totals.active,
+                    bytes_sent: totals.bytes_sent,
+                    bytes_recv: totals.bytes_recv,
+                    acceptor_accepts: totals.acceptor_accepts.clone(),
+                },
+            )
+        })
+        .collect();
+    let connections: HashMap<ProxyConnection, ConnMetricsSnapshot> = CONN_METRICS
+        .iter()
+        .map(|entry| {
+            let (id, metrics) = entry.pair();
+            let info_ref = CONN_INFO.get(id);
+            let info = info_ref.as_deref();
+            let bytes_sent = metrics.bytes_sent.load(Ordering::SeqCst);
+            let bytes_recv = metrics.bytes_recv.load(Ordering::SeqCst);
+            if let Some(listener) = info.and_then(|info| info.listener) {
+                let bucket = per_listener.entry(listener).or_default();
+                bucket.bytes_sent += bytes_sent;
+                bucket.bytes_recv += bytes_recv;
+            }
+            if let Some(info) = info {
+                route_totals.add_bytes(info, bytes_sent, bytes_recv);
+            }
+            (
+                *id,
+                ConnMetricsSnapshot {
+                    bytes_sent,
+                    bytes_recv,
+                    tags: info.map(|info| info.tags.clone()).unwrap_or_default(),
+                    metadata: info.and_then(|info| info.metadata.clone()),
+                    ttfb_ms: info.and_then(|info| info.ttfb_ms),
+                    proxy_hop_ms: info.map(|info| info.proxy_hop_ms.clone()).unwrap_or_default(),
+                    latency_ms: info.and_then(|info| info.latency_ms),
+                },
+            )
+        })
+        .collect();
+    drop(listener_totals_guard);
+
+    let mut tag_groups: HashMap<String, HashMap<String, TagGroupSnapshot>> = HashMap::new();
+    for conn in connections.values() {
+        for (key, value) in &conn.tags {
+            // Group string values by their content, anything else by its JSON form
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            let group = tag_groups
+                .entry(key.clone())
+                .or_default()
+                .entry(value)
+                .or_default();
+            group.connections += 1;
+            group.bytes_sent += conn.bytes_sent;
+            group.bytes_recv += conn.bytes_recv;
+        }
+    }
+
+    MetricsSnapshot {
+        total_conn: TOTAL_CONN.load(Ordering::SeqCst),
+        active_conn: ACTIVE_CONN.load(Ordering::SeqCst),
+        total_bytes_sent: TOTAL_BYTES_SENT.load(Ordering::SeqCst),
+        total_bytes_recv: TOTAL_BYTES_RECV.load(Ordering::SeqCst),
+        connections,
+        tag_groups,
+        protocol_errors: PROTOCOL_ERROR_COUNTS.lock().unwrap().clone(),
+        ttfb: latency::ttfb_summary(),
+        histograms: latency::histograms(),
+        per_listener,
+        per_host: route_totals.hosts,
+        per_backend: route_totals.backends,
+        upstream_proxies: proxy_manager::metrics(),
+    }
+}
+
+/// Takes a metrics snapshot holding only the connections that are new or
+/// changed since the previous delta, and those that closed in between.
+pub fn metrics_delta() -> MetricsDelta {
+    let snapshot = metrics();
+    let mut base_guard = METRICS_DELTA_BASE.lock().unwrap();
+    let full = base_guard.is_none();
+    let base = base_guard.get_or_insert_with(HashMap::new);
+    delta_from(snapshot, base, full, None)
+}
+
+/// Like `metrics_delta`, but relative to `cursor`, a value returned by an
+/// earlier call, so several consumers can each follow their own changes.
+/// An unknown cursor (0, or one evicted after `MAX_METRICS_CURSORS` newer
+/// ones) gets a full delta. Each cursor can be used once.
+pub fn metrics_delta_since(cursor: u64) -> MetricsDelta {
+    let snapshot = metrics();
+    let mut cursors = METRICS_CURSORS.lock().unwrap();
+    let position = cursors.bases.iter().position(|(id, _)| *id == cursor);
+    let (mut base, full) = match position.and_then(|i| cursors.bases.remove(i)) {
+        Some((_, base)) => (base, false),
+        None => (HashMap::new(), true),
+    };
+    cursors.next += 1;
+    let next = cursors.next;
+    let delta = delta_from(snapshot, &mut base, full, Some(next));
+    if cursors.bases.len() >= MAX_METRICS_CURSORS {
+        cursors.bases.pop_front();
+    }
+    cursors.bases.push_back((next, base));
+    delta
+}
+
+/// Forgets every cursor; numbering continues so that no old cursor matches
+/// a new one.
+pub fn reset_metrics_cursors() {
+    METRICS_CURSORS.lock().unwrap().bases.clear();
+}
+
+fn delta_from(
+    snapshot: MetricsSnapshot,
+    base: &mut HashMap<ProxyConnection, ConnMetricsSnapshot>,
+    full: bool,
+    cursor: Option<u64>,
+) -> MetricsDelta {
+    let (connections, closed) = diff_connections(base, snapshot.connections);
+    MetricsDelta {
+        full,
+        cursor,
+        total_conn: snapshot.total_conn,
+        active_conn: snapshot.active_conn,
+        total_bytes_sent: snapshot.total_bytes_sent,
+        total_bytes_recv: snapshot.total_bytes_recv,
+        connections,
+        closed,
+        tag_groups: snapshot.tag_groups,
+        protocol_errors: snapshot.protocol_errors,
+        ttfb: snapshot.ttfb,
+        histograms: snapshot.histograms,
+        per_listener: snapshot.per_listener,
+        per_host: snapshot.per_host,
+        per_backend: snapshot.per_backend,
+        upstream_proxies: snapshot.upstream_proxies,
+    }
+}
+
+/// Splits `current` against `base` into the changed and the closed
+/// connections, and advances `base` to `current`.
+fn diff_connections(
+    base: &mut HashMap<ProxyConnection, ConnMetricsSnapshot>,
+    mut current: HashMap<ProxyConnection, ConnMetricsSnapshot>,
+) -> (HashMap<ProxyConnection, ConnMetricsSnapshot>, Vec<ProxyConnection>) {
+    let closed: Vec<ProxyConnection> = base
+        .keys()
+        .filter(|id| !current.contains_key(id))
+        .copied()
+        .collect();
+    for id in &closed {
+        base.remove(id);
+    }
+    current.retain(|id, conn| base.get(id) != Some(conn));
+    for (id, conn) in &current {
+        base.insert(*id, conn.clone());
+    }
+    (current, closed)
+}
+
+/// Drains every event queue, or returns `None` if all of them are empty.
+pub fn poll_events() -> Option<PollEvents> {
+    let mut route_queue = ROUTE_REQUEST_QUEUE.lock().unwrap();
+    let mut motd_queue = MOTD_REQUEST_QUEUE.lock().unwrap();
+    let mut disconnection_queue = DISCONNECTION_EVENT_QUEUE.lock().unwrap();
+    let mut usage_queue = USAGE_REPORT_QUEUE.lock().unwrap();
+    let mut metrics_queue = METRICS_EVENT_QUEUE.lock().unwrap();
+    let mut backend_queue = BACKEND_EVENT_QUEUE.lock().unwrap();
+    let mut proxy_queue = PROXY_EVENT_QUEUE.lock().unwrap();
+    let mut protocol_error_queue = PROTOCOL_ERROR_QUEUE.lock().unwrap();
+    let mut lifecycle_queue = LIFECYCLE_EVENT_QUEUE.lock().unwrap();
+    let mut quota_queue = QUOTA_EVENT_QUEUE.lock().unwrap();
+    let mut route_audit_queue = ROUTE_AUDIT_QUEUE.lock().unwrap();
+    let mut config_reload_queue = CONFIG_RELOAD_QUEUE.lock().unwrap();
+
+    if route_queue.is_empty()
+        && motd_queue.is_empty()
+        && disconnection_queue.is_empty()
+        && usage_queue.is_empty()
+        && metrics_queue.is_empty()
+        && backend_queue.is_empty()
+        && proxy_queue.is_empty()
+        && protocol_error_queue.is_empty()
+        && lifecycle_queue.is_empty()
+        && quota_queue.is_empty()
+        && route_audit_queue.is_empty()
+        && config_reload_queue.is_empty()
+    {
+        return None;
+    }
+
+    Some(PollEvents {
+        route_requests: route_queue.drain(..).collect(),
+        motd_requests: motd_queue.drain(..).collect(),
+        disconnection_events: disconnection_queue.drain(..).collect(),
+        usage_reports: usage_queue.drain(..).collect(),
+        metrics_events: metrics_queue.drain(..).collect(),
+        backend_events: backend_queue.drain(..).collect(),
+        proxy_health_events: proxy_queue.drain(..).collect(),
+        protocol_errors: protocol_error_queue.drain(..).collect(),
+        lifecycle_events: lifecycle_queue.drain(..).collect(),
+        quota_events: quota_queue.drain(..).collect(),
+        route_audits: route_audit_queue.drain(..).collect(),
+        config_reloads: config_reload_queue.drain(..).collect(),
+    })
+}
+
+/// Drains at most `max_events` (0 for no limit) route requests, then MOTD
+/// requests, then disconnection events, or returns `None` if all three are
+/// empty. The other event queues are left to `poll_events`.
+pub fn poll_all(max_events: usize) -> Option<PollEvents> {
+    let mut route_queue = ROUTE_REQUEST_QUEUE.lock().unwrap();
+    let mut motd_queue = MOTD_REQUEST_QUEUE.lock().unwrap();
+    let mut disconnection_queue = DISCONNECTION_EVENT_QUEUE.lock().unwrap();
+
+    if route_queue.is_empty() && motd_queue.is_empty() && disconnection_queue.is_empty() {
+        return None;
+    }
+
+    let mut budget = if max_events == 0 { usize::MAX } else { max_events };
+    Some(PollEvents {
+        route_requests: drain_up_to(&mut route_queue, &mut budget),
+        motd_requests: drain_up_to(&mut motd_queue, &mut budget),
+        disconnection_events: drain_up_to(&mut disconnection_queue, &mut budget),
+        usage_reports: Vec::new(),
+        metrics_events: Vec::new(),
+        backend_events: Vec::new(),
+        proxy_health_events: Vec::new(),
+        protocol_errors: Vec::new(),
+        lifecycle_events: Vec::new(),
+        quota_events: Vec::new(),
+        route_audits: Vec::new(),
+        config_reloads: Vec::new(),
+    })
+}
+
+/// Takes the oldest items of `queue`, as many as `budget` allows.
+fn drain_up_to<T>(queue: &mut Vec<T>, budget: &mut usize) -> Vec<T> {
+    let n = queue.len().min(*budget);
+    *budget -= n;
+    queue.drain(..n).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_up_to() {
+        let mut budget = 3;
+        let mut first = vec![1, 2];
+        let mut second = vec![3, 4, 5];
+        assert_eq!(drain_up_to(&mut first, &mut budget), [1, 2]);
+        assert_eq!(drain_up_to(&mut second, &mut budget), [3]);
+        assert_eq!(second, [4, 5]);
+        assert!(drain_up_to(&mut second, &mut budget).is_empty());
+    }
+
+    fn conn(bytes_sent: u64) -> ConnMetricsSnapshot {
+        ConnMetricsSnapshot {
+            bytes_sent,
+            bytes_recv: 0,
+            tags: Default::default(),
+            metadata: None,
+            ttfb_ms: None,
+            proxy_hop_ms: Vec::new(),
+            latency_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_connections() {
+        let mut base = HashMap::new();
+        let (changed, closed) = diff_connections(&mut base, HashMap::from([(1, conn(10)), (2, conn(20))]));
+        assert_eq!(changed.len(), 2);
+        assert!(closed.is_empty());
+
+        let (changed, closed) = diff_connections(&mut base, HashMap::from([(1, conn(10)), (3, conn(5))]));
+        assert_eq!(changed.keys().copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(closed, vec![2]);
+
+        let (changed, closed) = diff_connections(&mut base, HashMap::from([(1, conn(15)), (3, conn(5))]));
+        assert_eq!(changed.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert!(closed.is_empty());
+        assert_eq!(base.len(), 2);
+    }
+
+    #[test]
+    fn test_metrics_delta_cursors() {
+        let first = metrics_delta_since(0);
+        assert!(first.full);
+        let cursor = first.cursor.unwrap();
+        let next = metrics_delta_since(cursor);
+        assert!(!next.full);
+        assert_ne!(next.cursor, Some(cursor));
+        // Spent cursors start over.
+        assert!(metrics_delta_since(cursor).full);
+    }
+
+    #[test]
+    fn test_wire_buffer_reuse() {
+        let buffer = WireBuffer::default();
+        let mut len = 0;
+        let first = buffer.encode(&conn(1), WIRE_JSON, &mut len);
+        assert!(!first.is_null());
+        let json = unsafe { std::slice::from_raw_parts(first, len) };
+        assert_eq!(json, br#"{"bytes_sent":1,"bytes_recv":0}"#);
+
+        let second = buffer.encode(&conn(2), WIRE_JSON, &mut len);
+        assert_eq!(first, second);
+        assert!(buffer.encode(&conn(3), 99, &mut len).is_null());
+        assert_eq!(len, 0);
+    }
+}
@@ -0,0 +1,537 @@
+#![cfg(target_os = "linux")]
+
+//! Experimental in-kernel forwarding using a BPF sockhash and an `sk_skb`
+//! stream verdict program. Once both sockets of a connection are in the map,
+//! every segment received on one is redirected to the other without waking
+//! userspace; we only handle setup, half-close propagation, metrics sampling
+//! (via `TCP_INFO`) and teardown.
+//!
+//! Requires CAP_BPF + CAP_NET_ADMIN (or root) and Linux 5.10 or later. When
+//! the program cannot be loaded the caller falls back to splice.
+
+use std::io::{Error, ErrorKind, Result};
+use std::mem::size_of;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{OnceLock, atomic::Ordering};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::state::{CONN_METRICS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT};
+use crate::types::ProxyConnection;
+
+// bpf(2) commands
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_MAP_DELETE_ELEM: libc::c_int = 3;
+const BPF_PROG_LOAD: libc::c_int = 5;
+const BPF_PROG_ATTACH: libc::c_int = 8;
+
+const BPF_MAP_TYPE_HASH: u32 = 1;
+const BPF_MAP_TYPE_SOCKHASH: u32 = 18;
+const BPF_PROG_TYPE_SK_SKB: u32 = 14;
+const BPF_SK_SKB_STREAM_VERDICT: u32 = 5;
+const BPF_ANY: u64 = 0;
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+
+// Helper function ids
+const BPF_FUNC_MAP_LOOKUP_ELEM: i32 = 1;
+const BPF_FUNC_GET_SOCKET_COOKIE: i32 = 46;
+const BPF_FUNC_SK_REDIRECT_HASH: i32 = 72;
+
+const SK_PASS: i32 = 1;
+const SO_COOKIE: libc::c_int = 57;
+
+/// Maximum number of sockets (two per connection) in the fast path at once.
+const MAX_SOCKETS: u32 = 65536;
+/// How often kernel byte counters are folded into the connection metrics.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct BpfInsn {
+    code: u8,
+    /// dst_reg in the low nibble, src_reg in the high nibble.
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+const fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> BpfInsn {
+    BpfInsn {
+        code,
+        regs: (src << 4) | (dst & 0x0f),
+        off,
+        imm,
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapElemAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgAttachAttr {
+    target_fd: u32,
+    attach_bpf_fd: u32,
+    attach_type: u32,
+    attach_flags: u32,
+}
+
+/// Leading part of `struct tcp_info` up to `tcpi_bytes_received` (Linux 4.1+).
+#[repr(C)]
+#[derive(Default)]
+struct TcpInfo {
+    _flags: [u8; 8],
+    _u32_fields: [u32; 24],
+    _pacing_rate: u64,
+    _max_pacing_rate: u64,
+    _bytes_acked: u64,
+    bytes_received: u64,
+}
+
+fn bpf<T>(cmd: libc::c_int, attr: &mut T) -> Result<libc::c_long> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            attr as *mut T as *mut libc::c_void,
+            size_of::<T>() as libc::c_uint,
+        )
+    };
+    if ret < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn create_map(map_type: u32, key_size: u32, value_size: u32) -> Result<RawFd> {
+    let mut attr = MapCreateAttr {
+        map_type,
+        key_size,
+        value_size,
+        max_entries: MAX_SOCKETS,
+        ..Default::default()
+    };
+    bpf(BPF_MAP_CREATE, &mut attr).map(|fd| fd as RawFd)
+}
+
+fn update_elem<K, V>(map_fd: RawFd, key: &K, value: &V) -> Result<()> {
+    let mut attr = MapElemAttr {
+        map_fd: map_fd as u32,
+        key: key as *const K as u64,
+        value: value as *const V as u64,
+        flags: BPF_ANY,
+        ..Default::default()
+    };
+    bpf(BPF_MAP_UPDATE_ELEM, &mut attr).map(drop)
+}
+
+fn delete_elem<K>(map_fd: RawFd, key: &K) {
+    let mut attr = MapElemAttr {
+        map_fd: map_fd as u32,
+        key: key as *const K as u64,
+        ..Default::default()
+    };
+    // ENOENT is expected when the kernel already dropped a closed socket.
+    let _ = bpf(BPF_MAP_DELETE_ELEM, &mut attr);
+}
+
+/// The loaded verdict program and its maps; kept open for the process lifetime.
+struct Program {
+    /// socket cookie -> socket
+    sockets: RawFd,
+    /// socket cookie -> cookie of the socket to redirect to
+    peers: RawFd,
+}
+
+impl Program {
+    fn load() -> Result<Self> {
+        let sockets = create_map(BPF_MAP_TYPE_SOCKHASH, 8, 4)?;
+        let peers = create_map(BPF_MAP_TYPE_HASH, 8, 8)?;
+
+        // r6 = skb
+        // *(u64 *)(r10 - 8) = get_socket_cookie(skb)
+        // peer = map_lookup_elem(peers, r10 - 8); if !peer return SK_PASS
+        // *(u64 *)(r10 - 16) = *peer
+        // return sk_redirect_hash(skb, sockets, r10 - 16, 0)
+        let insns = [
+            insn(0xbf, 6, 1, 0, 0),
+            insn(0x85, 0, 0, 0, BPF_FUNC_GET_SOCKET_COOKIE),
+            insn(0x7b, 10, 0, -8, 0),
+            insn(0x18, 1, BPF_PSEUDO_MAP_FD, 0, peers),
+            insn(0x00, 0, 0, 0, 0),
+            insn(0xbf, 2, 10, 0, 0),
+            insn(0x07, 2, 0, 0, -8),
+            insn(0x85, 0, 0, 0, BPF_FUNC_MAP_LOOKUP_ELEM),
+            insn(0x15, 0, 0, 10, 0),
+            insn(0x79, 1, 0, 0, 0),
+            insn(0x7b, 10, 1, -16, 0),
+            insn(0xbf, 1, 6, 0, 0),
+            insn(0x18, 2, BPF_PSEUDO_MAP_FD, 0, sockets),
+            insn(0x00, 0, 0, 0, 0),
+            insn(0xbf, 3, 10, 0, 0),
+            insn(0x07, 3, 0, 0, -16),
+            insn(0xb7, 4, 0, 0, 0),
+            insn(0x85, 0, 0, 0, BPF_FUNC_SK_REDIRECT_HASH),
+            insn(0x95, 0, 0, 0, 0),
+            insn(0xb7, 0, 0, 0, SK_PASS),
+            insn(0x95, 0, 0, 0, 0),
+        ];
+        let license = c"Dual MIT/GPL";
+        let mut attr = ProgLoadAttr {
+            prog_type: BPF_PROG_TYPE_SK_SKB,
+            insn_cnt: insns.len() as u32,
+            insns: insns.as_ptr() as u64,
+            license: license.as_ptr() as u64,
+            ..Default::default()
+        };
+        let prog = bpf(BPF_PROG_LOAD, &mut attr)? as RawFd;
+
+        let mut attach = ProgAttachAttr {
+            target_fd: sockets as u32,
+            attach_bpf_fd: prog as u32,
+            attach_type: BPF_SK_SKB_STREAM_VERDICT,
+            ..Default::default()
+        };
+        bpf(BPF_PROG_ATTACH, &mut attach)?;
+        Ok(Self { sockets, peers })
+    }
+}
+
+/// Loads the program on first use; `None` if the kernel or privileges do not
+/// allow it, in which case we never retry.
+fn program() -> Option<&'static Program> {
+    static PROGRAM: OnceLock<Option<Program>> = OnceLock::new();
+    PROGRAM
+        .get_or_init(|| match Program::load() {
+            Ok(program) => {
+                info!("Loaded sockmap forwarding program");
+                Some(program)
+            }
+            Err(e) => {
+                warn!("sockmap forwarding unavailable, using splice: {}", e);
+                None
+            }
+        })
+        .as_ref()
+}
+
+fn socket_cookie(fd: RawFd) -> Result<u64> {
+    let mut cookie: u64 = 0;
+    let mut len = size_of::<u64>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            SO_COOKIE,
+            &mut cookie as *mut u64 as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(cookie)
+}
+
+/// Total payload bytes the kernel has received on the socket.
+fn bytes_received(fd: RawFd) -> Result<u64> {
+    let mut info = TcpInfo::default();
+    let mut len = size_of::<TcpInfo>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut TcpInfo as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    if (len as usize) < size_of::<TcpInfo>() {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "tcp_info lacks tcpi_bytes_received",
+        ));
+    }
+    Ok(info.bytes_received)
+}
+
+/// Removes a connection's entries from the maps, including when the relay
+/// task is aborted.
+struct Registration {
+    program: &'static Program,
+    cookies: [u64; 2],
+    linked: bool,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if self.linked {
+            for cookie in &self.cookies {
+                delete_elem(self.program.peers, cookie);
+            }
+        }
+        for cookie in &self.cookies {
+            delete_elem(self.program.sockets, cookie);
+        }
+    }
+}
+
+/// Bytes received but not yet read by userspace.
+fn bytes_queued(fd: RawFd) -> Result<u64> {
+    let mut queued: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::FIONREAD, &mut queued) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(queued as u64)
+}
+
+/// Folds kernel byte counters into the connection and global metrics.
+struct Sampler {
+    conn_id: ProxyConnection,
+    fds: [RawFd; 2],
+    baseline: [u64; 2],
+    reported: [u64; 2],
+    /// A received FIN occupies one sequence number in `tcpi_bytes_received`.
+    eof: [bool; 2],
+}
+
+impl Sampler {
+    fn new(conn_id: ProxyConnection, fds: [RawFd; 2]) -> Result<Self> {
+        // Data still queued is relayed by us, so it counts as relayed.
+        let mut baseline = [0; 2];
+        for (i, fd) in fds.into_iter().enumerate() {
+            baseline[i] = bytes_received(fd)?.saturating_sub(bytes_queued(fd)?);
+        }
+        Ok(Self {
+            conn_id,
+            fds,
+            baseline,
+            reported: [0, 0],
+            eof: [false, false],
+        })
+    }
+
+    fn sample(&mut self) {
+        let mut delta = [0u64; 2];
+        for (i, fd) in self.fds.into_iter().enumerate() {
+            if let Ok(total) = bytes_received(fd) {
+                let seen = total
+                    .saturating_sub(self.baseline[i])
+                    .saturating_sub(self.eof[i] as u64);
+                delta[i] = seen.saturating_sub(self.reported[i]);
+                self.reported[i] = seen.max(self.reported[i]);
+            }
+        }
+        if delta == [0, 0] {
+            return;
+        }
+        let conn_metrics = CONN_METRICS.get(&self.conn_id).map(|entry| entry.value().clone());
+        if let Some(metrics) = conn_metrics {
+            metrics.bytes_sent.fetch_add(delta[0], Ordering::SeqCst);
+            metrics.bytes_recv.fetch_add(delta[1], Ordering::SeqCst);
+        }
+        TOTAL_BYTES_SENT.fetch_add(delta[0], Ordering::SeqCst);
+        TOTAL_BYTES_RECV.fetch_add(delta[1], Ordering::SeqCst);
+    }
+}
+
+/// Reads whatever is queued on `from` in userspace and writes it to `to`.
+/// Returns `Ok(false)` on end of stream.
+async fn forward_queued(from: &TcpStream, to: &mut TcpStream, buf: &mut [u8]) -> Result<bool> {
+    use tokio::io::AsyncWriteExt;
+    loop {
+        match from.try_read(buf) {
+            Ok(0) => return Ok(false),
+            Ok(n) => to.write_all(&buf[..n]).await?,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn shutdown_write(stream: &TcpStream) {
+    unsafe { libc::shutdown(stream.as_raw_fd(), libc::SHUT_WR) };
+}
+
+/// Relays `a` <-> `b` in the kernel. Returns `None` without touching the
+/// streams if the fast path is unavailable, so the caller can fall back.
+pub async fn copy_bidirectional(
+    conn_id: ProxyConnection,
+    a: &mut TcpStream,
+    b: &mut TcpStream,
+) -> Option<Result<(u64, u64)>> {
+    let program = program()?;
+    let fds = [a.as_raw_fd(), b.as_raw_fd()];
+    let cookies = match (socket_cookie(fds[0]), socket_cookie(fds[1])) {
+        (Ok(ca), Ok(cb)) => [ca, cb],
+        (Err(e), _) | (_, Err(e)) => {
+            warn!(conn = conn_id, "Failed to read socket cookies: {}", e);
+            return None;
+        }
+    };
+    let mut sampler = match Sampler::new(conn_id, fds) {
+        Ok(sampler) => sampler,
+        Err(e) => {
+            warn!(conn = conn_id, "TCP_INFO byte counters unavailable: {}", e);
+            return None;
+        }
+    };
+
+    // Both sockets must be in the sockhash before either is given a peer:
+    // redirecting to a missing socket drops the segment.
+    let mut registration = Registration {
+        program,
+        cookies,
+        linked: false,
+    };
+    for (cookie, fd) in cookies.iter().zip(fds) {
+        if let Err(e) = update_elem(program.sockets, cookie, &(fd as u32)) {
+            warn!(conn = conn_id, "Failed to add socket to sockmap: {}", e);
+            return None;
+        }
+    }
+
+    Some(relay(a, b, &mut registration, &mut sampler).await)
+}
+
+async fn relay(
+    a: &mut TcpStream,
+    b: &mut TcpStream,
+    registration: &mut Registration,
+    sampler: &mut Sampler,
+) -> Result<(u64, u64)> {
+    let program = registration.program;
+    let [ca, cb] = registration.cookies;
+    let mut buf = vec![0u8; 16 * 1024];
+
+    // Anything queued before the switch is still delivered to userspace;
+    // flush it first to keep ordering, then link the pair.
+    let mut a_open = forward_queued(a, b, &mut buf).await?;
+    let mut b_open = forward_queued(b, a, &mut buf).await?;
+    sampler.eof = [!a_open, !b_open];
+    update_elem(program.peers, &ca, &cb)?;
+    update_elem(program.peers, &cb, &ca)?;
+    registration.linked = true;
+
+    if !a_open {
+        shutdown_write(b);
+    }
+    if !b_open {
+        shutdown_write(a);
+    }
+
+    let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+    while a_open || b_open {
+        // Redirected segments never wake us; readiness means a FIN, or data
+        // that raced the link and was passed up to userspace.
+        tokio::select! {
+            ready = a.readable(), if a_open => {
+                ready?;
+                a_open = forward_queued(a, b, &mut buf).await?;
+                if !a_open {
+                    sampler.eof[0] = true;
+                    shutdown_write(b);
+                }
+            }
+            ready = b.readable(), if b_open => {
+                ready?;
+                b_open = forward_queued(b, a, &mut buf).await?;
+                if !b_open {
+                    sampler.eof[1] = true;
+                    shutdown_write(a);
+                }
+            }
+            _ = ticker.tick() => sampler.sample(),
+        }
+    }
+    sampler.sample();
+    Ok((sampler.reported[0], sampler.reported[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let addr = listener.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (client.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_relay_through_sockmap() {
+        // Needs privileges to load BPF programs; nothing to test otherwise.
+        if program().is_none() {
+            return;
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, mut proxy_in) = pair(&listener).await;
+        let (mut proxy_out, mut backend) = pair(&listener).await;
+
+        // Queued before the relay starts, so it must be flushed in userspace.
+        client.write_all(b"early").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let relay = tokio::spawn(async move {
+            copy_bidirectional(1, &mut proxy_in, &mut proxy_out)
+                .await
+                .unwrap()
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        client.write_all(b" ping").await.unwrap();
+        let mut buf = [0u8; 10];
+        backend.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"early ping");
+
+        backend.write_all(b"pong").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        drop(client);
+        backend.shutdown().await.unwrap();
+        let (sent, recv) = relay.await.unwrap().unwrap();
+        assert_eq!((sent, recv), (10, 4));
+    }
+}
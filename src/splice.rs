@@ -3,26 +3,28 @@
 use std::future::poll_fn;
 use std::io::{Error, ErrorKind, Result};
 use std::marker::PhantomData;
-use std::num::NonZeroU32;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::pin::Pin;
 use std::sync::{Arc, atomic::Ordering};
 use std::task::{Context, Poll, ready};
+use std::time::Duration;
 
-use governor::{
-    RateLimiter,
-    clock::DefaultClock,
-    state::{InMemoryState, direct::NotKeyed},
-};
 use libc;
 use tokio::io::{AsyncRead, AsyncWrite, Interest};
+use tokio::time::{Instant, interval_at};
 
-use crate::state::{CONN_METRICS, RATE_LIMITERS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT};
+use crate::capture;
+use crate::limiter;
+use crate::state::{CONN_METRICS, TOTAL_BYTES_RECV, TOTAL_BYTES_SENT};
 use crate::types::{ConnMetrics, ProxyConnection};
 
 /// the size of PIPE_BUF
 const PIPE_SIZE: usize = 65536;
 
+/// How often a splicing connection checks whether a rate limit or capture
+/// was set.
+const LIMIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 /// splice()  moves  data between two file descriptors without copying between kernel address space and user address space.
 /// It transfers up to len bytes of data from the file descriptor fd_in to the file descriptor fd_out,
 /// where one of the  file  descriptors must refer to a pipe.
@@ -133,10 +135,7 @@ struct CopyBuffer<R, W> {
     cap: usize,
     amt: u64,
     buf: Pipe,
-    // Rate limiting and metrics
     conn_metrics: Arc<ConnMetrics>,
-    send_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    recv_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
     is_a_to_b: bool, // true if copying from A to B, false if B to A
     //
     _marker_r: PhantomData<R>,
@@ -148,13 +147,7 @@ where
     R: Stream + Unpin,
     W: Stream + Unpin,
 {
-    fn new(
-        buf: Pipe,
-        conn_metrics: Arc<ConnMetrics>,
-        send_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-        recv_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-        is_a_to_b: bool,
-    ) -> Self {
+    fn new(buf: Pipe, conn_metrics: Arc<ConnMetrics>, is_a_to_b: bool) -> Self {
         Self {
             read_done: false,
             need_flush: false,
@@ -163,8 +156,6 @@ where
             amt: 0,
             buf,
             conn_metrics,
-            send_limiter,
-            recv_limiter,
             is_a_to_b,
             _marker_r: PhantomData,
             _marker_w: PhantomData,
@@ -231,44 +222,16 @@ where
 
             match res {
                 Ok(size) => {
-                    if size > 0 {
-                        // Apply rate limiting
-                        let limiter = if self.is_a_to_b {
-                            &self.send_limiter
-                        } else {
-                            &self.recv_limiter
-                        };
-
-                        if let Some(num) = NonZeroU32::new(size as u32) {
-                            // Note: This is a blocking operation within async context
-                            // In a real implementation, you might want to use a non-blocking approach
-                            match limiter.check_n(num) {
-                                Ok(_) => {
-                                    // Update metrics
-                                    if self.is_a_to_b {
-                                        self.conn_metrics
-                                            .bytes_sent
-                                            .fetch_add(size as u64, Ordering::SeqCst);
-                                        TOTAL_BYTES_SENT.fetch_add(size as u64, Ordering::SeqCst);
-                                    } else {
-                                        self.conn_metrics
-                                            .bytes_recv
-                                            .fetch_add(size as u64, Ordering::SeqCst);
-                                        TOTAL_BYTES_RECV.fetch_add(size as u64, Ordering::SeqCst);
-                                    }
-                                    return Poll::Ready(Ok(size));
-                                }
-                                Err(_) => {
-                                    // Rate limit exceeded, return pending to retry later
-                                    return Poll::Pending;
-                                }
-                            }
-                        } else {
-                            return Poll::Ready(Ok(size));
-                        }
+                    // Counted as the bytes leave the pipe, so metrics stay
+                    // live while the relay runs.
+                    let (conn_bytes, total_bytes) = if self.is_a_to_b {
+                        (&self.conn_metrics.bytes_sent, &TOTAL_BYTES_SENT)
                     } else {
-                        return Poll::Ready(Ok(size));
-                    }
+                        (&self.conn_metrics.bytes_recv, &TOTAL_BYTES_RECV)
+                    };
+                    conn_bytes.fetch_add(size as u64, Ordering::SeqCst);
+                    total_bytes.fetch_add(size as u64, Ordering::SeqCst);
+                    return Poll::Ready(Ok(size));
                 }
                 Err(e) => {
                     if e.kind() == ErrorKind::WouldBlock {
@@ -354,6 +317,21 @@ enum TransferState<SR, SW> {
     Done(u64),
 }
 
+impl<SR, SW> TransferState<SR, SW> {
+    /// Whether the direction can be handed to another copier: still open,
+    /// with nothing left in its pipe.
+    fn is_idle(&self) -> bool {
+        matches!(self, TransferState::Running(buf) if buf.pos == buf.cap && !buf.read_done)
+    }
+
+    fn count(&self) -> u64 {
+        match self {
+            TransferState::Running(buf) => buf.amt,
+            TransferState::ShuttingDown(count) | TransferState::Done(count) => *count,
+        }
+    }
+}
+
 fn transfer_one_direction<SL, SR>(
     cx: &mut Context<'_>,
     state: &mut TransferState<SL, SR>,
@@ -389,68 +367,111 @@ pub trait Stream: AsyncRead + AsyncWrite + AsRawFd {
     fn try_io_n<R>(&self, interest: Interest, f: impl FnOnce() -> Result<R>) -> Result<R>;
 }
 
-/// Copies data in both directions between `a` and `b`.
-///
-/// This function returns a future that will read from both streams,
-/// writing any data read to the opposing stream.
-/// This happens in both directions concurrently.
+/// How a splice relay ended. Both carry the bytes copied from `a` to `b`
+/// and from `b` to `a`.
+pub enum Relayed {
+    /// Both directions reached end of stream.
+    Done(u64, u64),
+    /// A rate limit or capture was set on the connection; the rest of the
+    /// relay must go through a copier that enforces it.
+    Limited(u64, u64),
+}
+
+/// Copies data in both directions between `a` and `b` through pipes, so the
+/// bytes never enter userspace. Only for connections without rate limits
+/// or captures: once one is set, the relay stops at the next point where
+/// both pipes are empty and returns [`Relayed::Limited`].
 pub async fn copy_bidirectional<A, B>(
     conn_id: ProxyConnection,
     a: &mut A,
     b: &mut B,
-) -> Result<(u64, u64)>
+) -> Result<Relayed>
 where
     A: Stream + Unpin,
     B: Stream + Unpin,
 {
-    // Get metrics and rate limiters for this connection
     let conn_metrics = CONN_METRICS
-        .lock()
-        .unwrap()
         .get(&conn_id)
-        .cloned()
+        .map(|entry| entry.value().clone())
         .ok_or_else(|| Error::new(ErrorKind::NotFound, "Metrics not found for connection"))?;
 
-    let (send_limiter, recv_limiter) = RATE_LIMITERS
-        .lock()
-        .unwrap()
-        .get(&conn_id)
-        .cloned()
-        .ok_or_else(|| {
-            Error::new(
-                ErrorKind::NotFound,
-                "Rate limiters not found for connection",
-            )
-        })?;
-
     let mut a_to_b = TransferState::Running(CopyBuffer::new(
         Pipe::new()?,
         conn_metrics.clone(),
-        send_limiter.clone(),
-        recv_limiter.clone(),
         true, // is_a_to_b = true
     ));
     let mut b_to_a = TransferState::Running(CopyBuffer::new(
         Pipe::new()?,
         conn_metrics,
-        send_limiter,
-        recv_limiter,
         false, // is_a_to_b = false
     ));
 
+    let mut limit_check = interval_at(Instant::now() + LIMIT_CHECK_INTERVAL, LIMIT_CHECK_INTERVAL);
+    let mut limited = false;
     poll_fn(|cx| {
+        while limit_check.poll_tick(cx).is_ready() {
+            limited = limited || !limiter::is_unlimited(conn_id) || capture::is_active(conn_id);
+        }
+        // A direction that already reached EOF is finished here instead.
+        if limited && a_to_b.is_idle() && b_to_a.is_idle() {
+            return Poll::Ready(Ok(Relayed::Limited(a_to_b.count(), b_to_a.count())));
+        }
+
         let a_to_b = transfer_one_direction(cx, &mut a_to_b, a, b)?;
         let b_to_a = transfer_one_direction(cx, &mut b_to_a, b, a)?;
 
         let a_to_b = ready!(a_to_b);
         let b_to_a = ready!(b_to_a);
 
-        Poll::Ready(Ok((a_to_b, b_to_a)))
+        Poll::Ready(Ok(Relayed::Done(a_to_b, b_to_a)))
     })
     .await
 }
 
-mod tests {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_splice_counts_bytes() {
+        let conn_id = u64::MAX - 1;
+        let metrics = Arc::new(ConnMetrics::default());
+        CONN_METRICS.insert(conn_id, metrics.clone());
+        let (mut client, mut proxy_in) = pair().await;
+        let (mut proxy_out, mut backend) = pair().await;
+        let relay = tokio::spawn(async move {
+            copy_bidirectional(conn_id, &mut proxy_in, &mut proxy_out).await
+        });
+
+        client.write_all(b"ping").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        backend.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"ping");
+        backend.write_all(b"pong!").await.unwrap();
+        backend.shutdown().await.unwrap();
+        received.clear();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"pong!");
+
+        let Relayed::Done(sent, recv) = relay.await.unwrap().unwrap() else {
+            panic!("unlimited relay handed off");
+        };
+        assert_eq!((sent, recv), (4, 5));
+        assert_eq!(metrics.bytes_sent.load(Ordering::SeqCst), 4);
+        assert_eq!(metrics.bytes_recv.load(Ordering::SeqCst), 5);
+        CONN_METRICS.remove(&conn_id);
+    }
+}
 
 use tokio::net::{TcpStream, UnixStream};
 macro_rules! impl_stream_for {
@@ -2,40 +2,109 @@
 //! Global state management.
 
 use crate::types::{
-    ConnMetrics, ConnectionManager, DisconnectionEvent, GeofrontOptions, ListenerState,
-    MotdDecision, MotdRequest, ProxyConnection, RouteDecision, RouteRequest,
+    BackendCheckStatus, BackendEvent, ConfigReload, ConnInfo, ConnMetrics, ConnMetricsSnapshot, DisconnectionEvent, GeofrontOptions,
+    LifecycleEvent, ListenerState, LogRecord, ListenerTotals, MetricsEvent, MotdDecision, MotdRequest, PauseConfig, ProtocolErrorEvent, ProtocolErrorKind, QuotaEvent,
+    ProxyConnection, ProxyHealthEvent, ProxyListener, RouteAudit, RouteDecision, RouteRequest, RuntimeConfig, StaticRoute,
+    UsageReport, VirtualHost,
 };
 use crate::cache::RouterMotdCache;
-use governor::{
-    RateLimiter,
-    clock::DefaultClock,
-    state::{InMemoryState, direct::NotKeyed},
-};
+use crate::capture::Capture;
+use crate::capacity::Admission;
+use crate::connection::LoginSocket;
+use crate::discovery::BackendPool;
+use crate::events::ProxyEvent;
+use crate::geoip::GeoIpDb;
+use crate::accounting::Accounting;
+use crate::bedrock::Association;
+use crate::handler::{DEFAULT_MAX_PENDING_ROUTES, FfiHandler, MotdHandler, RouteHandler};
+use crate::health::BackendHealth;
+use crate::limiter::{ConnLimiter, LimitScope};
+use crate::lifecycle::LifecycleHandler;
+use crate::logging::LogHandler;
+use crate::latency::{Histogram, LATENCY_BOUNDS_MS, SESSION_BOUNDS_MS};
+use crate::limits::IpLimits;
+use crate::loadgen::LoadGenReport;
+use crate::login_throttle::Throttle;
+use crate::mirror::Mirror;
+use crate::snapshot::{MetricsCursors, WireBuffer};
+use crate::transfer::TransferSlot;
+use crate::proxy_manager::ProxyHealth;
+use crate::route_metrics::RouteTotals;
+use crate::wakeup::Wakeup;
+use dashmap::DashMap;
 use lazy_static::lazy_static;
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock, atomic::AtomicU64},
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, OnceLock, RwLock, atomic::{AtomicU64, AtomicUsize}},
+    time::Instant,
+};
+use tokio::{
+    sync::{Mutex, Notify, Semaphore, mpsc, oneshot},
+    task::JoinHandle,
 };
-use tokio::sync::{Mutex, oneshot};
 use tracing_subscriber::{filter::EnvFilter, reload::Handle as ReloadHandle};
 
+/// Per-connection (send, recv) rate limiter pair.
+pub type RateLimiterPair = (Arc<ConnLimiter>, Arc<ConnLimiter>);
+
 // Global metrics counters
 pub static TOTAL_CONN: AtomicU64 = AtomicU64::new(0);
 pub static ACTIVE_CONN: AtomicU64 = AtomicU64::new(0);
 pub static TOTAL_BYTES_SENT: AtomicU64 = AtomicU64::new(0);
 pub static TOTAL_BYTES_RECV: AtomicU64 = AtomicU64::new(0);
+// Events dropped because the external sink could not keep up
+pub static EVENT_SINK_DROPPED: AtomicU64 = AtomicU64::new(0);
+// Sequence number of the last usage report
+pub static USAGE_SEQ: AtomicU64 = AtomicU64::new(0);
+// Handshakes parsed, and the status pings and login starts among them
+pub static HANDSHAKES: AtomicU64 = AtomicU64::new(0);
+pub static STATUS_REQUESTS: AtomicU64 = AtomicU64::new(0);
+pub static LOGINS: AtomicU64 = AtomicU64::new(0);
+// Clients closed for missing `handshakeTimeoutMs`
+pub static HANDSHAKE_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+// PROXY headers sent by peers outside `proxyProtocolTrustedCidrs`
+pub static UNTRUSTED_PROXY_HEADERS: AtomicU64 = AtomicU64::new(0);
+// Rejected clients held in the tarpit now, and since start (see `tarpit.rs`)
+pub static TARPIT_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+pub static TARPIT_TOTAL: AtomicU64 = AtomicU64::new(0);
+// Bytes written to mirror destinations, and dropped instead (see `mirror.rs`)
+pub static MIRROR_BYTES: AtomicU64 = AtomicU64::new(0);
+pub static MIRROR_DROPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+// Log records dropped from a full log queue (see `logging.rs`)
+pub static LOG_RECORDS_DROPPED: AtomicU64 = AtomicU64::new(0);
+// Logins refused for `maxConnections`
+pub static OVERFLOW_REJECTED: AtomicU64 = AtomicU64::new(0);
+// Handle signalled when an event queue becomes non-empty (`proxy_get_event_fd`)
+pub static WAKEUP: OnceLock<Wakeup> = OnceLock::new();
+// Woken when a listener is paused or resumed (see `pause.rs`)
+pub static LISTENER_RESUMED: Notify = Notify::const_new();
+// Parameters of the proxy runtime, fixed when `LISTENER_STATE` builds it
+pub static RUNTIME_CONFIG: OnceLock<RuntimeConfig> = OnceLock::new();
+// Woken alongside it, for hosts waiting in the same process
+pub static EVENT_NOTIFY: Notify = Notify::const_new();
+// Task delivering events to the Node-API event callback (see `node.rs`)
+pub static NODE_EVENT_TASK: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+// Fixed-bucket histograms exported with the metrics (see `latency.rs`)
+pub static BACKEND_CONNECT_HISTOGRAM: Histogram<12> = Histogram::new(LATENCY_BOUNDS_MS);
+pub static ROUTING_HISTOGRAM: Histogram<12> = Histogram::new(LATENCY_BOUNDS_MS);
+pub static SESSION_HISTOGRAM: Histogram<9> = Histogram::new(SESSION_BOUNDS_MS);
 
+// Split into several invocations to stay under the macro recursion limit
 lazy_static! {
     pub static ref OPTIONS: RwLock<GeofrontOptions> = RwLock::new(GeofrontOptions::default());
-    pub static ref CONN_METRICS: std::sync::Mutex<HashMap<ProxyConnection, Arc<ConnMetrics>>> =
-        std::sync::Mutex::new(HashMap::new());
+    // Connection-keyed state touched on every accept and relay is sharded, so
+    // busy accept loops do not serialize on one lock
+    pub static ref CONN_METRICS: DashMap<ProxyConnection, Arc<ConnMetrics>> = DashMap::new();
+    // Session details of live connections, filled in as `handle_conn` progresses
+    pub static ref CONN_INFO: DashMap<ProxyConnection, ConnInfo> = DashMap::new();
     // Map to hold the senders for pending routing decisions
-    pub static ref PENDING_ROUTES: std::sync::Mutex<HashMap<ProxyConnection, oneshot::Sender<RouteDecision>>> =
-        std::sync::Mutex::new(HashMap::new());
+    pub static ref PENDING_ROUTES: DashMap<ProxyConnection, oneshot::Sender<RouteDecision>> = DashMap::new();
     // Map to hold the senders for pending MOTD decisions
-    pub static ref PENDING_MOTDS: std::sync::Mutex<HashMap<ProxyConnection, oneshot::Sender<MotdDecision>>> =
-        std::sync::Mutex::new(HashMap::new());
+    pub static ref PENDING_MOTDS: DashMap<ProxyConnection, oneshot::Sender<MotdDecision>> = DashMap::new();
+}
 
+lazy_static! {
     // Thread-safe queues for polling-based approach (alternative to callbacks)
     pub static ref ROUTE_REQUEST_QUEUE: std::sync::Mutex<Vec<RouteRequest>> =
         std::sync::Mutex::new(Vec::new());
@@ -43,31 +112,162 @@ lazy_static! {
         std::sync::Mutex::new(Vec::new());
     pub static ref DISCONNECTION_EVENT_QUEUE: std::sync::Mutex<Vec<DisconnectionEvent>> =
         std::sync::Mutex::new(Vec::new());
+    pub static ref USAGE_REPORT_QUEUE: std::sync::Mutex<Vec<UsageReport>> =
+        std::sync::Mutex::new(Vec::new());
+    // (sent, recv) totals already covered by usage reports, per connection
+    pub static ref USAGE_REPORTED: DashMap<ProxyConnection, (u64, u64)> = DashMap::new();
+    // Background task emitting periodic usage reports
+    pub static ref USAGE_REPORTER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    pub static ref BACKEND_EVENT_QUEUE: std::sync::Mutex<Vec<BackendEvent>> =
+        std::sync::Mutex::new(Vec::new());
+    // Upstream proxies marked unhealthy or recovered, until polled
+    pub static ref PROXY_EVENT_QUEUE: std::sync::Mutex<Vec<ProxyHealthEvent>> =
+        std::sync::Mutex::new(Vec::new());
+    pub static ref PROTOCOL_ERROR_QUEUE: std::sync::Mutex<Vec<ProtocolErrorEvent>> =
+        std::sync::Mutex::new(Vec::new());
+    pub static ref LIFECYCLE_EVENT_QUEUE: std::sync::Mutex<Vec<LifecycleEvent>> =
+        std::sync::Mutex::new(Vec::new());
+    // Connections closed or refused for a byte quota, until polled
+    pub static ref QUOTA_EVENT_QUEUE: std::sync::Mutex<Vec<QuotaEvent>> = std::sync::Mutex::new(Vec::new());
+    // Routing decisions queued by `routeAudit`, until polled
+    pub static ref ROUTE_AUDIT_QUEUE: std::sync::Mutex<Vec<RouteAudit>> = std::sync::Mutex::new(Vec::new());
+    // Config file loads, until polled
+    pub static ref CONFIG_RELOAD_QUEUE: std::sync::Mutex<Vec<ConfigReload>> = std::sync::Mutex::new(Vec::new());
+    // Embedder callback taking lifecycle events instead of the queue
+    pub static ref LIFECYCLE_HANDLER: RwLock<Option<LifecycleHandler>> = RwLock::new(None);
+    // Log records waiting for `proxy_poll_log_events`, in queue mode
+    pub static ref LOG_RECORD_QUEUE: std::sync::Mutex<Vec<LogRecord>> = std::sync::Mutex::new(Vec::new());
+    // Embedder callback taking log records instead of the queue
+    pub static ref LOG_HANDLER: RwLock<Option<LogHandler>> = RwLock::new(None);
+    // Protocol errors seen since start, by kind
+    pub static ref PROTOCOL_ERROR_COUNTS: std::sync::Mutex<HashMap<ProtocolErrorKind, u64>> =
+        std::sync::Mutex::new(HashMap::new());
+    pub static ref METRICS_EVENT_QUEUE: std::sync::Mutex<Vec<MetricsEvent>> =
+        std::sync::Mutex::new(Vec::new());
+    // Background task pushing periodic metrics events
+    pub static ref METRICS_PUSHER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    // Background task reloading the config file when it changes
+    pub static ref CONFIG_WATCHER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+}
 
+lazy_static! {
+    // Connections as of the previous metrics delta; `None` until the first one
+    pub static ref METRICS_DELTA_BASE: std::sync::Mutex<Option<HashMap<ProxyConnection, ConnMetricsSnapshot>>> =
+        std::sync::Mutex::new(None);
+    // Connections as of each cursor handed out by `proxy_get_metrics_delta`
+    pub static ref METRICS_CURSORS: std::sync::Mutex<MetricsCursors> = std::sync::Mutex::new(MetricsCursors::default());
+    // Per-listener counters; held while reading or removing `CONN_METRICS`
+    // entries so closing connections' bytes are counted exactly once
+    pub static ref LISTENER_TOTALS: std::sync::Mutex<HashMap<ProxyListener, ListenerTotals>> =
+        std::sync::Mutex::new(HashMap::new());
+    // Per-host and per-backend counters, folded into under `LISTENER_TOTALS`
+    pub static ref ROUTE_TOTALS: std::sync::Mutex<RouteTotals> = std::sync::Mutex::new(RouteTotals::default());
+    // Bytes by username and by IP, folded in like `ROUTE_TOTALS`
+    pub static ref ACCOUNTING: std::sync::Mutex<Accounting> = std::sync::Mutex::new(Accounting::default());
+    // Task serving the Prometheus endpoint
+    pub static ref METRICS_EXPORTER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    // Output buffers of `proxy_get_metrics_buf` and `proxy_poll_events_buf`
+    pub static ref METRICS_BUF: WireBuffer = WireBuffer::default();
+    pub static ref EVENTS_BUF: WireBuffer = WireBuffer::default();
+}
+
+lazy_static! {
+    // Per-IP connection and handshake counts (see `limits.rs`)
+    pub static ref IP_LIMITS: std::sync::Mutex<IpLimits> = std::sync::Mutex::new(IpLimits::default());
+    // Login attempts per username and IP (see `login_throttle.rs`)
+    pub static ref LOGIN_THROTTLE: std::sync::Mutex<Option<Throttle>> = std::sync::Mutex::new(None);
+    // Packet captures of connections (see `capture.rs`)
+    pub static ref CAPTURES: DashMap<ProxyConnection, Capture> = DashMap::new();
+    // Tracing spans of open connections, for fields learnt later (see `logging.rs`)
+    pub static ref CONN_SPANS: DashMap<ProxyConnection, tracing::Span> = DashMap::new();
+    // Queues of mirrored connections (see `mirror.rs`)
+    pub static ref MIRRORS: DashMap<ProxyConnection, Mirror> = DashMap::new();
+    // Client associations of Bedrock listeners (see `bedrock.rs`)
+    pub static ref BEDROCK_SESSIONS: DashMap<(ProxyListener, SocketAddr), Arc<Association>> = DashMap::new();
+    // Relay buffers and their size (see `buffer_pool.rs`)
+    pub static ref BUFFER_POOL: std::sync::Mutex<Vec<Vec<u8>>> = std::sync::Mutex::new(Vec::new());
+    pub static ref COPY_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(crate::buffer_pool::DEFAULT_BUFFER_SIZE);
+    // Database opened from `geoipDb` (see `geoip.rs`)
+    pub static ref GEOIP_DB: RwLock<Option<Arc<GeoIpDb>>> = RwLock::new(None);
+    // Connections that can be moved to another backend (see `transfer.rs`)
+    pub static ref TRANSFER_SLOTS: DashMap<ProxyConnection, TransferSlot> = DashMap::new();
+    // Backends of transferred players, by client IP and username, until they log back in
+    pub static ref RETURNING_PLAYERS: std::sync::Mutex<HashMap<(String, String), (RouteDecision, Instant)>> =
+        std::sync::Mutex::new(HashMap::new());
+    // Task removing expired entries of `ROUTER_MOTD_CACHE` (see `cache.rs`)
+    pub static ref CACHE_SWEEPER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    // Players admitted under the `capacity` caps; one lock, as admitting
+    // checks the caps against every entry and inserts atomically
+    pub static ref ADMITTED: std::sync::Mutex<HashMap<ProxyConnection, Admission>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+lazy_static! {
     pub static ref LISTENER_STATE: Arc<std::sync::Mutex<ListenerState>> =
         Arc::new(std::sync::Mutex::new(ListenerState::new()));
-    pub static ref CONN_MANAGER: Arc<std::sync::Mutex<ConnectionManager>> =
-        Arc::new(std::sync::Mutex::new(ConnectionManager::new()));
-    pub static ref RATE_LIMITERS: std::sync::Mutex<
-        HashMap<
-            ProxyConnection,
-            (
-                Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-                Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-            ),
-        >,
-    > = std::sync::Mutex::new(HashMap::new());
+    // Paused listeners and how they answer meanwhile
+    pub static ref PAUSED_LISTENERS: std::sync::Mutex<HashMap<ProxyListener, PauseConfig>> =
+        std::sync::Mutex::new(HashMap::new());
+    // Tasks of live connections
+    pub static ref CONN_MANAGER: DashMap<ProxyConnection, JoinHandle<()>> = DashMap::new();
+    // Client sockets of connections in the login phase, for kick messages
+    pub static ref LOGIN_SOCKETS: DashMap<ProxyConnection, LoginSocket> = DashMap::new();
+    pub static ref RATE_LIMITERS: DashMap<ProxyConnection, RateLimiterPair> = DashMap::new();
+    // Limiters shared by all connections, or all of one listener
+    pub static ref SHARED_LIMITERS: RwLock<HashMap<LimitScope, RateLimiterPair>> = RwLock::new(HashMap::new());
     pub static ref LISTENER_COUNTER: AtomicU64 = AtomicU64::new(1);
     pub static ref CONN_COUNTER: AtomicU64 = AtomicU64::new(1);
     pub static ref RELOAD_HANDLE: std::sync::Mutex<Option<ReloadHandle<EnvFilter, tracing_subscriber::Registry>>> =
         std::sync::Mutex::new(None);
-    // This lock serializes all FFI calls to the router to prevent concurrency issues.
-    pub static ref FFI_ROUTER_LOCK: Mutex<()> = Mutex::new(());
+    // Slots for route requests waiting on the host at once (`maxPendingRoutes`)
+    pub static ref FFI_ROUTE_PERMITS: RwLock<Arc<Semaphore>> =
+        RwLock::new(Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_ROUTES)));
     // This lock serializes all FFI calls to the MOTD callback to prevent concurrency issues.
     pub static ref FFI_MOTD_LOCK: Mutex<()> = Mutex::new(());
     // This lock serializes all FFI calls to the disconnection callback to prevent concurrency issues.
     pub static ref FFI_DISCONNECTION_LOCK: Mutex<()> = Mutex::new(());
+    // Handlers deciding routes and MOTDs; the FFI queues unless replaced through `Geofront`
+    pub static ref ROUTE_HANDLER: RwLock<Arc<dyn RouteHandler>> = RwLock::new(Arc::new(FfiHandler));
+    pub static ref MOTD_HANDLER: RwLock<Arc<dyn MotdHandler>> = RwLock::new(Arc::new(FfiHandler));
     
     // Router/MOTD cache instance
     pub static ref ROUTER_MOTD_CACHE: RouterMotdCache = RouterMotdCache::new();
+    // Routes set through `proxy_set_routes`
+    pub static ref STATIC_ROUTES: RwLock<Vec<StaticRoute>> = RwLock::new(Vec::new());
+
+    // Virtual hosts set through `proxy_set_vhosts`
+    pub static ref VHOSTS: RwLock<Vec<VirtualHost>> = RwLock::new(Vec::new());
+
+    // Sender feeding the external event exporter, if one is configured
+    pub static ref EVENT_SINK: RwLock<Option<mpsc::Sender<ProxyEvent>>> = RwLock::new(None);
+    // Sender feeding the SQLite audit writer, if enabled
+    pub static ref AUDIT_SINK: RwLock<Option<mpsc::Sender<ProxyEvent>>> = RwLock::new(None);
+}
+
+lazy_static! {
+    // DNS-resolved backend pools keyed by `host:port`
+    pub static ref BACKEND_POOLS: DashMap<String, Arc<BackendPool>> = DashMap::new();
+    // Registry-fed backend pools keyed by name (`pool` in route decisions)
+    pub static ref NAMED_POOLS: DashMap<String, Arc<BackendPool>> = DashMap::new();
+    // Passive health of backend addresses, keyed by address
+    pub static ref BACKEND_HEALTH: DashMap<SocketAddr, BackendHealth> = DashMap::new();
+    // Active check results of backends, keyed by `host:port`
+    pub static ref BACKEND_CHECKS: DashMap<String, BackendCheckStatus> = DashMap::new();
+    // Background task running the active checks
+    pub static ref HEALTH_CHECKER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    // Passive health of upstream proxies, keyed by URL
+    pub static ref PROXY_HEALTH: DashMap<String, ProxyHealth> = DashMap::new();
+    // Round-robin position of each upstream proxy pool
+    pub static ref PROXY_POOL_CURSORS: DashMap<String, usize> = DashMap::new();
+    // Watcher tasks keeping `NAMED_POOLS` in sync, keyed by pool name
+    pub static ref POOL_WATCHERS: std::sync::Mutex<HashMap<String, JoinHandle<()>>> =
+        std::sync::Mutex::new(HashMap::new());
+    // Background task re-resolving `BACKEND_POOLS`
+    pub static ref DNS_REFRESHER: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    // TTFB of the most recent proxied sessions, in microseconds
+    pub static ref TTFB_SAMPLES: std::sync::Mutex<VecDeque<u64>> = std::sync::Mutex::new(VecDeque::new());
+    // Load-generation run started through FFI, and the outcome of the last one
+    pub static ref LOADGEN_TASK: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+    pub static ref LOADGEN_RESULT: std::sync::Mutex<Option<Result<LoadGenReport, String>>> =
+        std::sync::Mutex::new(None);
 }
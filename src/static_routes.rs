@@ -0,0 +1,84 @@
+//! geofront/src/static_routes.rs
+//! Routes configured through `proxy_set_routes`: hosts matching a pattern
+//! are routed in Rust without asking the router callback.
+
+use crate::{
+    schedule::host_matches,
+    state::STATIC_ROUTES,
+    types::{RouteDecision, StaticRoute},
+};
+
+/// The decision of the most specific route matching `host`, if any.
+pub fn route(host: &str) -> Option<RouteDecision> {
+    let routes = STATIC_ROUTES.read().unwrap();
+    best_match(&routes, host).map(|route| route.route.clone())
+}
+
+fn best_match<'a>(routes: &'a [StaticRoute], host: &str) -> Option<&'a StaticRoute> {
+    most_specific(routes, |route| &route.host, host)
+}
+
+/// The entry whose host pattern matches `host` most specifically: exact
+/// hosts beat wildcards, longer wildcard suffixes beat shorter ones and `*`
+/// matches anything else; among equals the first entry wins. Also used for
+/// virtual hosts (see `vhosts.rs`).
+pub(crate) fn most_specific<'a, T>(entries: &'a [T], pattern: impl Fn(&T) -> &str, host: &str) -> Option<&'a T> {
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let pattern = pattern(entry);
+            let rank = if pattern == "*" {
+                0
+            } else if !host_matches(pattern, host) {
+                return None;
+            } else if pattern.starts_with("*.") {
+                pattern.len()
+            } else {
+                usize::MAX
+            };
+            Some((rank, std::cmp::Reverse(i), entry))
+        })
+        .max_by_key(|(rank, i, _)| (*rank, *i))
+        .map(|(_, _, entry)| entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(hosts: &[&str]) -> Vec<StaticRoute> {
+        hosts
+            .iter()
+            .map(|host| StaticRoute {
+                host: host.to_string(),
+                route: RouteDecision {
+                    remote_host: Some(host.to_string()),
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
+
+    fn matched<'a>(routes: &'a [StaticRoute], host: &str) -> Option<&'a str> {
+        best_match(routes, host).map(|route| route.host.as_str())
+    }
+
+    #[test]
+    fn test_best_match() {
+        let r = routes(&["*", "*.example.com", "*.hub.example.com", "lobby.hub.example.com"]);
+        assert_eq!(matched(&r, "lobby.hub.example.com"), Some("lobby.hub.example.com"));
+        assert_eq!(matched(&r, "EU.hub.example.com."), Some("*.hub.example.com"));
+        assert_eq!(matched(&r, "hub.example.com"), Some("*.example.com"));
+        assert_eq!(matched(&r, "other.net"), Some("*"));
+        assert_eq!(matched(&r[1..], "other.net"), None);
+    }
+
+    #[test]
+    fn test_first_route_wins_ties() {
+        let mut r = routes(&["*.example.com", "*.example.com"]);
+        r[1].route.remote_host = Some("second".to_string());
+        let route = best_match(&r, "a.example.com").unwrap();
+        assert_eq!(route.route.remote_host.as_deref(), Some("*.example.com"));
+    }
+}
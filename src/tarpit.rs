@@ -0,0 +1,80 @@
+//! geofront/src/tarpit.rs
+//! Tarpit for rejected clients (`tarpit`): connections dropped by the per-IP
+//! limits or refused by a cached rejection are held open instead, reading
+//! and discarding what they send at a trickle for `durationMs`, to slow
+//! scanners and bots down. A tarpitted socket has already left the
+//! connection counts (`cleanup_conn` ran, or it was never admitted), so it
+//! is counted on its own and capped at `maxSockets`.
+
+use crate::{
+    state::{OPTIONS, TARPIT_ACTIVE, TARPIT_TOTAL},
+    types::TarpitConfig,
+};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Whether rejected clients go to the tarpit.
+pub fn enabled() -> bool {
+    OPTIONS.read().unwrap().tarpit.is_some()
+}
+
+/// Holds `stream` in the tarpit, or hands it back when the tarpit is off
+/// or full.
+pub fn try_hold<S>(stream: S) -> Result<(), S>
+where
+    S: AsyncRead + Unpin + Send + 'static,
+{
+    let Some(config) = OPTIONS.read().unwrap().tarpit.clone() else {
+        return Err(stream);
+    };
+    let admitted = TARPIT_ACTIVE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+            (active < config.max_sockets).then_some(active + 1)
+        })
+        .is_ok();
+    if !admitted {
+        return Err(stream);
+    }
+    TARPIT_TOTAL.fetch_add(1, Ordering::SeqCst);
+    tokio::spawn(async move {
+        trickle(stream, &config).await;
+        TARPIT_ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    });
+    Ok(())
+}
+
+/// Reads at most `bytesPerSec` each second until the time is up or the
+/// client gives up; the rest waits in the kernel buffers, stalling its
+/// writes.
+async fn trickle<S: AsyncRead + Unpin>(mut stream: S, config: &TarpitConfig) {
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(config.duration_ms);
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let mut buf = vec![0; config.bytes_per_sec.max(1) as usize];
+    while tokio::time::timeout_at(deadline, ticker.tick()).await.is_ok() {
+        match tokio::time::timeout_at(deadline, stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => {}
+            // Closed, failed or out of time.
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_trickle_reads_at_the_rate() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let config = TarpitConfig {
+            duration_ms: 1_500,
+            bytes_per_sec: 2,
+            max_sockets: 1,
+        };
+        client.write_all(&[0; 64]).await.unwrap();
+        trickle(&mut server, &config).await;
+        // Two ticks (at 0s and 1s) of two bytes each were read.
+        assert_eq!(client.write(&[0; 64]).await.unwrap(), 4);
+    }
+}
@@ -0,0 +1,130 @@
+//! geofront/src/tcp_options.rs
+//! TCP socket options (`tcp`, overridable per route): `TCP_NODELAY`,
+//! keepalive and the kernel buffer sizes, applied to client sockets when
+//! accepted and to backend and upstream proxy sockets when connected. A
+//! route's `tcp` is applied to the client socket once it is routed, and
+//! replaces the options field by field. Unset fields keep the OS defaults.
+
+use crate::types::TcpConfig;
+use socket2::{SockRef, TcpKeepalive};
+use std::{io::Result, time::Duration};
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::warn;
+
+impl TcpConfig {
+    /// These options with the fields set in `route` replacing them.
+    pub fn merged(self, route: Option<TcpConfig>) -> TcpConfig {
+        let Some(route) = route else {
+            return self;
+        };
+        TcpConfig {
+            no_delay: route.no_delay.or(self.no_delay),
+            keepalive_time_ms: route.keepalive_time_ms.or(self.keepalive_time_ms),
+            keepalive_interval_ms: route.keepalive_interval_ms.or(self.keepalive_interval_ms),
+            keepalive_probes: route.keepalive_probes.or(self.keepalive_probes),
+            send_buffer_size: route.send_buffer_size.or(self.send_buffer_size),
+            recv_buffer_size: route.recv_buffer_size.or(self.recv_buffer_size),
+        }
+    }
+
+    fn keepalive(&self) -> Option<TcpKeepalive> {
+        if self.keepalive_time_ms.is_none()
+            && self.keepalive_interval_ms.is_none()
+            && self.keepalive_probes.is_none()
+        {
+            return None;
+        }
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(ms) = self.keepalive_time_ms {
+            keepalive = keepalive.with_time(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.keepalive_interval_ms {
+            keepalive = keepalive.with_interval(Duration::from_millis(ms));
+        }
+        // Windows has no per-socket probe count.
+        #[cfg(not(windows))]
+        if let Some(probes) = self.keepalive_probes {
+            keepalive = keepalive.with_retries(probes);
+        }
+        Some(keepalive)
+    }
+}
+
+/// Sets the buffer sizes on a socket about to connect, so they count for
+/// the window scale negotiated in the handshake.
+pub fn prepare(socket: &TcpSocket, config: &TcpConfig) -> Result<()> {
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+/// Whether connecting needs a `TcpSocket` to `prepare`.
+pub fn needs_prepare(config: &TcpConfig) -> bool {
+    config.send_buffer_size.is_some() || config.recv_buffer_size.is_some()
+}
+
+/// Applies the options to a connected socket. Failures are logged rather
+/// than failing the connection, which works with the OS defaults.
+pub fn apply(stream: &TcpStream, config: &TcpConfig) {
+    if let Err(e) = try_apply(stream, config) {
+        warn!("Failed to set TCP options: {}", e);
+    }
+}
+
+fn try_apply(stream: &TcpStream, config: &TcpConfig) -> Result<()> {
+    if let Some(no_delay) = config.no_delay {
+        stream.set_nodelay(no_delay)?;
+    }
+    let socket = SockRef::from(stream);
+    if let Some(keepalive) = config.keepalive() {
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size as usize)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size as usize)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_route_overrides_field_by_field() {
+        let options = TcpConfig {
+            no_delay: Some(true),
+            keepalive_time_ms: Some(30_000),
+            ..Default::default()
+        };
+        let route = TcpConfig {
+            keepalive_time_ms: Some(5_000),
+            ..Default::default()
+        };
+        let merged = options.merged(Some(route));
+        assert_eq!(merged.no_delay, Some(true));
+        assert_eq!(merged.keepalive_time_ms, Some(5_000));
+        assert_eq!(options.merged(None), options);
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let config = TcpConfig {
+            no_delay: Some(true),
+            keepalive_time_ms: Some(10_000),
+            ..Default::default()
+        };
+        try_apply(&stream, &config).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+}
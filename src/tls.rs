@@ -0,0 +1,251 @@
+//! geofront/src/tls.rs
+//! TLS termination for listeners started with a certificate, for clients
+//! that reach geofront through TLS tunnels. The SNI hostname is kept for the
+//! router next to the handshake host. With `clientCaPath` clients must
+//! present a certificate it signed.
+//!
+//! Backends given as `tls://host:port` are connected to over TLS as
+//! `backendTls` says, with a client certificate if it has one, so an edge
+//! reaches an origin's TLS listener authenticated both ways.
+//!
+//! Requires the `tls` feature.
+
+#[cfg(not(feature = "tls"))]
+pub use disabled::{TlsAcceptor, TlsStream, connect, load_acceptor};
+
+#[cfg(feature = "tls")]
+pub use rustls_acceptor::{TlsAcceptor, TlsStream, connect, load_acceptor};
+
+#[cfg(not(feature = "tls"))]
+mod disabled {
+    use crate::types::AsyncStream;
+    use std::{
+        io::{Error, ErrorKind, Result},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        net::TcpStream,
+    };
+
+    /// Never constructed without the `tls` feature.
+    #[derive(Clone)]
+    pub enum TlsAcceptor {}
+
+    impl TlsAcceptor {
+        pub async fn accept(&self, _stream: TcpStream) -> Result<TlsStream> {
+            match *self {}
+        }
+    }
+
+    /// Never constructed without the `tls` feature.
+    pub enum TlsStream {}
+
+    impl TlsStream {
+        pub fn tcp(&self) -> &TcpStream {
+            match *self {}
+        }
+
+        pub fn server_name(&self) -> Option<&str> {
+            match *self {}
+        }
+    }
+
+    impl AsyncRead for TlsStream {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+            match *self {}
+        }
+    }
+
+    impl AsyncWrite for TlsStream {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, _: &[u8]) -> Poll<Result<usize>> {
+            match *self {}
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+            match *self {}
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+            match *self {}
+        }
+    }
+
+    pub fn load_acceptor(_cert_path: &str, _key_path: &str, _client_ca_path: Option<&str>) -> Result<TlsAcceptor> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "TLS listeners require the `tls` feature",
+        ))
+    }
+
+    pub async fn connect(
+        _stream: Box<AsyncStream>,
+        _host: &str,
+        _server_name: Option<&str>,
+    ) -> Result<Box<AsyncStream>> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "TLS backends require the `tls` feature",
+        ))
+    }
+}
+
+#[cfg(feature = "tls")]
+mod rustls_acceptor {
+    use crate::{
+        state::OPTIONS,
+        types::{AsyncStream, BackendTlsConfig},
+    };
+    use std::{
+        io::{Error, ErrorKind, Result},
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+    use tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        net::TcpStream,
+    };
+    use tokio_rustls::{
+        TlsConnector,
+        rustls::{
+            ClientConfig, RootCertStore, ServerConfig,
+            crypto::ring,
+            pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+            server::WebPkiClientVerifier,
+        },
+    };
+
+    #[derive(Clone)]
+    pub struct TlsAcceptor(tokio_rustls::TlsAcceptor);
+
+    impl TlsAcceptor {
+        pub async fn accept(&self, stream: TcpStream) -> Result<TlsStream> {
+            Ok(TlsStream(self.0.accept(stream).await?))
+        }
+    }
+
+    pub struct TlsStream(tokio_rustls::server::TlsStream<TcpStream>);
+
+    impl TlsStream {
+        pub fn tcp(&self) -> &TcpStream {
+            self.0.get_ref().0
+        }
+
+        /// The SNI hostname the client asked for.
+        pub fn server_name(&self) -> Option<&str> {
+            self.0.get_ref().1.server_name()
+        }
+    }
+
+    impl AsyncRead for TlsStream {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for TlsStream {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+        }
+    }
+
+    fn invalid(e: &dyn std::fmt::Display) -> Error {
+        Error::new(ErrorKind::InvalidData, e.to_string())
+    }
+
+    fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+        CertificateDer::pem_file_iter(path)
+            .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| invalid(&format_args!("{}: {}", path, e)))
+    }
+
+    fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+        PrivateKeyDer::from_pem_file(path).map_err(|e| invalid(&format_args!("{}: {}", path, e)))
+    }
+
+    fn load_roots(path: &str) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(path)? {
+            roots.add(cert).map_err(|e| invalid(&format_args!("{}: {}", path, e)))?;
+        }
+        Ok(roots)
+    }
+
+    /// Builds an acceptor from a PEM certificate chain and private key,
+    /// requiring client certificates signed by `client_ca_path` if given.
+    pub fn load_acceptor(cert_path: &str, key_path: &str, client_ca_path: Option<&str>) -> Result<TlsAcceptor> {
+        let certs = load_certs(cert_path)?;
+        let key = load_key(key_path)?;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid(&e))?;
+        let builder = match client_ca_path {
+            Some(path) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(path)?), provider)
+                    .build()
+                    .map_err(|e| invalid(&e))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder.with_single_cert(certs, key).map_err(|e| invalid(&e))?;
+        Ok(TlsAcceptor(tokio_rustls::TlsAcceptor::from(Arc::new(config))))
+    }
+
+    /// The connector for `backendTls`, rebuilt when the options change.
+    fn connector() -> Result<(TlsConnector, Option<String>)> {
+        static CONNECTOR: std::sync::Mutex<Option<(BackendTlsConfig, TlsConnector)>> = std::sync::Mutex::new(None);
+        let Some(config) = OPTIONS.read().unwrap().backend_tls.clone() else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "tls:// backends need the backendTls option",
+            ));
+        };
+        let mut cached = CONNECTOR.lock().unwrap();
+        if let Some((cached_config, connector)) = &*cached
+            && *cached_config == config
+        {
+            return Ok((connector.clone(), config.server_name));
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid(&e))?
+            .with_root_certificates(load_roots(&config.ca_path)?);
+        let client = match (&config.cert_path, &config.key_path) {
+            (Some(cert_path), Some(key_path)) => builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+                .map_err(|e| invalid(&e))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "backendTls needs both certPath and keyPath",
+                ));
+            }
+        };
+        let connector = TlsConnector::from(Arc::new(client));
+        *cached = Some((config.clone(), connector.clone()));
+        Ok((connector, config.server_name))
+    }
+
+    /// Runs a TLS handshake with the backend `host` over `stream`, sending
+    /// `server_name` (else `backendTls.serverName`, else `host`) as SNI and
+    /// verifying the certificate against it.
+    pub async fn connect(stream: Box<AsyncStream>, host: &str, server_name: Option<&str>) -> Result<Box<AsyncStream>> {
+        let (connector, configured_name) = connector()?;
+        let name = server_name.or(configured_name.as_deref()).unwrap_or(host);
+        let name = ServerName::try_from(name.to_string()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok(Box::new(connector.connect(name, stream).await?))
+    }
+}
@@ -0,0 +1,228 @@
+//! geofront/src/transfer.rs
+//! Moving a player to another backend without a disconnect screen
+//! (`proxy_transfer_connection`).
+//!
+//! A connection still waiting for its route takes the new backend instead.
+//! A relayed 1.20.5+ session (with `allowTransfer`) is sent a Transfer packet
+//! pointing back at the address the client joined through, at the next
+//! packet boundary; the client reconnects with the transfer intent and that
+//! login goes to the new backend without asking the router. Relayed sessions
+//! are only transferable when the login phase could be followed in plain
+//! (see `login_phase.rs`), since packet boundaries must stay known.
+
+use crate::{
+    login_phase::read_varint,
+    protocol::{self, FINISH_CONFIGURATION_ID},
+    state::{CONN_INFO, RETURNING_PLAYERS, TRANSFER_SLOTS},
+    types::{ProxyConnection, RouteDecision},
+};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How long a transferred player has to come back.
+const RETURN_WINDOW: Duration = Duration::from_secs(30);
+/// Body bytes of a frame enough to read its packet ID.
+const INSPECT_BYTES: usize = 10;
+
+/// Where a transfer request for a connection goes.
+pub enum TransferSlot {
+    /// Waiting for its route; holds the backend to use instead.
+    Routing(Option<Box<RouteDecision>>),
+    /// Relayed by a transfer-capable relay.
+    Relaying(mpsc::UnboundedSender<RouteDecision>),
+}
+
+pub enum TransferOutcome {
+    Accepted,
+    /// The connection is known but past the point where it can be moved.
+    NotTransferable,
+    NotFound,
+}
+
+/// Hands `decision` to the connection, if it can still be moved.
+pub fn request(conn_id: ProxyConnection, decision: RouteDecision) -> TransferOutcome {
+    match TRANSFER_SLOTS.get_mut(&conn_id).as_deref_mut() {
+        Some(TransferSlot::Routing(target)) => {
+            *target = Some(Box::new(decision));
+            TransferOutcome::Accepted
+        }
+        Some(TransferSlot::Relaying(tx)) if tx.send(decision).is_ok() => TransferOutcome::Accepted,
+        _ if CONN_INFO.contains_key(&conn_id) => TransferOutcome::NotTransferable,
+        _ => TransferOutcome::NotFound,
+    }
+}
+
+/// Opens the connection to transfers while its route is decided.
+pub fn begin_routing(conn_id: ProxyConnection) {
+    TRANSFER_SLOTS.insert(conn_id, TransferSlot::Routing(None));
+}
+
+/// Closes the routing window, returning the backend a transfer asked for.
+pub fn end_routing(conn_id: ProxyConnection) -> Option<RouteDecision> {
+    match TRANSFER_SLOTS.remove(&conn_id) {
+        Some((_, TransferSlot::Routing(target))) => target.map(|decision| *decision),
+        _ => None,
+    }
+}
+
+/// Opens the connection to transfers while it is relayed.
+pub fn begin_relay(conn_id: ProxyConnection) -> mpsc::UnboundedReceiver<RouteDecision> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    TRANSFER_SLOTS.insert(conn_id, TransferSlot::Relaying(tx));
+    rx
+}
+
+/// Forgets a closed connection.
+pub fn release(conn_id: ProxyConnection) {
+    TRANSFER_SLOTS.remove(&conn_id);
+}
+
+/// Remembers the backend of a player sent a Transfer packet.
+pub fn expect_return(peer_ip: &str, username: &str, decision: RouteDecision) {
+    let now = Instant::now();
+    let mut returning = RETURNING_PLAYERS.lock().unwrap();
+    returning.retain(|_, (_, at)| now.duration_since(*at) < RETURN_WINDOW);
+    returning.insert((peer_ip.to_string(), username.to_string()), (decision, now));
+}
+
+/// The backend of a transferred player logging back in.
+pub fn take_return(peer_ip: &str, username: &str) -> Option<RouteDecision> {
+    let (decision, at) = RETURNING_PLAYERS
+        .lock()
+        .unwrap()
+        .remove(&(peer_ip.to_string(), username.to_string()))?;
+    (at.elapsed() < RETURN_WINDOW).then_some(decision)
+}
+
+/// Follows the packet framing of what the backend sends once the login
+/// phase is over, and whether the client is in the configuration or play
+/// state, so a Transfer packet can be slipped in between two packets.
+pub struct FrameTracker {
+    protocol: i32,
+    compressed: bool,
+    play: bool,
+    /// The current frame's length and first body bytes.
+    head: Vec<u8>,
+    /// Body bytes of the current frame still to pass once inspected.
+    skip: usize,
+    /// The framing could not be read; no boundary is known anymore.
+    lost: bool,
+}
+
+impl FrameTracker {
+    /// Starts after Login Success, the client entering the configuration
+    /// state.
+    pub fn new(protocol: i32, compression_threshold: Option<i32>) -> Self {
+        Self {
+            protocol,
+            compressed: compression_threshold.is_some(),
+            play: false,
+            head: Vec::new(),
+            skip: 0,
+            lost: false,
+        }
+    }
+
+    /// Whether the bytes fed so far end on a packet boundary.
+    pub fn at_boundary(&self) -> bool {
+        !self.lost && self.skip == 0 && self.head.is_empty()
+    }
+
+    /// The Transfer packet for the client's current state.
+    pub fn transfer_packet(&self, host: &str, port: u16) -> Option<Vec<u8>> {
+        protocol::transfer_packet(self.protocol, self.play, self.compressed, host, port)
+    }
+
+    /// Consumes bytes the backend sent to the client.
+    pub fn feed(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() && !self.lost {
+            if self.skip > 0 {
+                let n = self.skip.min(bytes.len());
+                self.skip -= n;
+                bytes = &bytes[n..];
+                continue;
+            }
+            self.head.push(bytes[0]);
+            bytes = &bytes[1..];
+            self.inspect_head();
+        }
+    }
+
+    /// Inspects the current frame once enough of it is known.
+    fn inspect_head(&mut self) {
+        let Some((len, header)) = read_varint(&self.head) else {
+            // At most 5 bytes of length
+            self.lost = self.head.len() >= 5;
+            return;
+        };
+        let Ok(len) = usize::try_from(len) else {
+            self.lost = true;
+            return;
+        };
+        let have = self.head.len() - header;
+        if have < len.min(INSPECT_BYTES) {
+            return;
+        }
+        let head = std::mem::take(&mut self.head);
+        self.skip = len - have;
+        self.packet(&head[header..]);
+    }
+
+    fn packet(&mut self, mut body: &[u8]) {
+        if self.compressed {
+            match read_varint(body) {
+                Some((0, n)) => body = &body[n..],
+                // Compressed packets are never the state changes
+                _ => return,
+            }
+        }
+        let Some((id, _)) = read_varint(body) else {
+            return;
+        };
+        if self.play {
+            if protocol::start_configuration_id(self.protocol) == Some(id) {
+                self.play = false;
+            }
+        } else if id == FINISH_CONFIGURATION_ID {
+            self.play = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::write_varint;
+
+    fn frame(id: i32, body_len: usize) -> Vec<u8> {
+        let mut data = vec![0];
+        write_varint(&mut data, id);
+        data.resize(data.len() + body_len, 0xAB);
+        let mut packet = Vec::new();
+        write_varint(&mut packet, data.len() as i32);
+        packet.extend(data);
+        packet
+    }
+
+    #[test]
+    fn test_boundaries_and_state() {
+        let mut frames = FrameTracker::new(767, Some(256));
+        // Registry data, then Finish Configuration, then a chunk
+        let mut stream = frame(0x07, 300);
+        stream.extend(frame(FINISH_CONFIGURATION_ID, 0));
+        stream.extend(frame(0x27, 5000));
+
+        let (first, rest) = stream.split_at(200);
+        frames.feed(first);
+        assert!(!frames.at_boundary());
+        frames.feed(rest);
+        assert!(frames.at_boundary());
+        assert!(frames.play);
+
+        frames.feed(&frame(0x69, 0)[..1]);
+        assert!(!frames.at_boundary());
+        frames.feed(&frame(0x69, 0)[1..]);
+        assert!(frames.at_boundary());
+        assert!(!frames.play);
+    }
+}
@@ -0,0 +1,147 @@
+//! geofront/src/transport.rs
+//! What a listener speaks below the Minecraft protocol. Plain TCP listeners
+//! hand the socket on as-is; TLS listeners terminate TLS first (see
+//! `tls.rs`) and WebSocket listeners unwrap frames (see `websocket.rs`).
+//! Past the transport handshake the pipeline reads the client's Minecraft
+//! stream through `ClientTransport` either way.
+
+use crate::{
+    tls::{self, TlsAcceptor, TlsStream},
+    types::{ListenerOptions, TransportKind},
+    websocket::{self, WsStream},
+};
+use std::{
+    io::Result,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+#[derive(Clone)]
+pub enum ListenerTransport {
+    Tcp,
+    Tls(TlsAcceptor),
+    WebSocket,
+}
+
+impl ListenerTransport {
+    /// The transport `options` ask for, loading any TLS certificate.
+    pub fn from_options(options: &ListenerOptions) -> Result<Self> {
+        if let Some(config) = &options.tls {
+            Ok(ListenerTransport::Tls(tls::load_acceptor(
+                &config.cert_path,
+                &config.key_path,
+                config.client_ca_path.as_deref(),
+            )?))
+        } else if options.websocket {
+            websocket::ensure_supported()?;
+            Ok(ListenerTransport::WebSocket)
+        } else {
+            Ok(ListenerTransport::Tcp)
+        }
+    }
+
+    /// Runs the transport handshake on an accepted socket, once any PROXY
+    /// header is consumed.
+    pub async fn accept(&self, stream: TcpStream) -> Result<ClientTransport> {
+        match self {
+            ListenerTransport::Tcp => Ok(ClientTransport::Tcp(stream)),
+            ListenerTransport::Tls(acceptor) => {
+                Ok(ClientTransport::Tls(Box::new(acceptor.accept(stream).await?)))
+            }
+            ListenerTransport::WebSocket => {
+                Ok(ClientTransport::WebSocket(Box::new(websocket::accept(stream).await?)))
+            }
+        }
+    }
+}
+
+/// The client's Minecraft stream.
+pub enum ClientTransport {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream>),
+    WebSocket(Box<WsStream<TcpStream>>),
+}
+
+impl ClientTransport {
+    /// The underlying socket.
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            ClientTransport::Tcp(stream) => stream,
+            ClientTransport::Tls(stream) => stream.tcp(),
+            ClientTransport::WebSocket(stream) => stream.get_ref(),
+        }
+    }
+
+    pub fn kind(&self) -> TransportKind {
+        match self {
+            ClientTransport::Tcp(_) => TransportKind::Tcp,
+            ClientTransport::Tls(_) => TransportKind::Tls,
+            ClientTransport::WebSocket(_) => TransportKind::WebSocket,
+        }
+    }
+
+    /// The socket, when the Minecraft stream is carried on it as-is.
+    pub fn plain_mut(&mut self) -> Option<&mut TcpStream> {
+        match self {
+            ClientTransport::Tcp(stream) => Some(stream),
+            ClientTransport::Tls(_) | ClientTransport::WebSocket(_) => None,
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.tcp().local_addr()
+    }
+
+    /// The SNI hostname of a TLS client.
+    pub fn server_name(&self) -> Option<&str> {
+        match self {
+            ClientTransport::Tcp(_) | ClientTransport::WebSocket(_) => None,
+            ClientTransport::Tls(stream) => stream.server_name(),
+        }
+    }
+}
+
+impl AsyncRead for ClientTransport {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            ClientTransport::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            ClientTransport::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+            ClientTransport::WebSocket(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClientTransport {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        match self.get_mut() {
+            ClientTransport::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            ClientTransport::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+            ClientTransport::WebSocket(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            ClientTransport::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            ClientTransport::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+            ClientTransport::WebSocket(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            ClientTransport::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            ClientTransport::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+            ClientTransport::WebSocket(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
@@ -1,23 +1,95 @@
 //! geofront/src/types.rs
 //! Core data structures, type aliases, and constants.
 
+use crate::{latency::{Histograms, LatencySummary}, limiter::LimiterSnapshot, proxy_manager::ProxyMetrics, route_metrics::RouteMetrics};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::atomic::AtomicU64};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicU64},
+};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::task::JoinHandle;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum ProxyProtocolIn {
     Optional,
     Strict,
+    #[default]
     None,
 }
 
-impl Default for ProxyProtocolIn {
-    fn default() -> Self {
-        ProxyProtocolIn::None
-    }
+/// Settings of one listener (`proxy_start_listener_with_options`); unset
+/// fields fall back to the global options.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerOptions {
+    #[serde(default)]
+    pub proxy_protocol_in: Option<ProxyProtocolIn>,
+    /// Decision for logins no schedule or static route matches, made
+    /// without asking the router.
+    #[serde(default)]
+    pub default_route: Option<RouteDecision>,
+    /// Open connections allowed on this listener; more are dropped on accept.
+    #[serde(default)]
+    pub max_connections: Option<u64>,
+    #[serde(default)]
+    pub max_connections_per_ip: Option<usize>,
+    #[serde(default)]
+    pub handshakes_per_ip_per_second: Option<u32>,
+    /// Terminate TLS with this certificate (see `tls.rs`).
+    #[serde(default)]
+    pub tls: Option<TlsListenerConfig>,
+    /// Accept WebSocket clients (see `websocket.rs`); ignored with `tls`.
+    #[serde(default)]
+    pub websocket: bool,
+    /// Sockets bound with `SO_REUSEPORT`, each with its own accept loop
+    /// (see `acceptors.rs`); Linux only, 1 by default.
+    #[serde(default)]
+    pub acceptors: Option<usize>,
+}
+
+/// PEM files of a TLS listener.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsListenerConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// PEM CA certificates clients must present a certificate signed by.
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+/// Where and how the Prometheus endpoint is served (see `prometheus.rs`).
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsExporterConfig {
+    /// `host:port` the exporter is started on; unset leaves it to
+    /// `proxy_start_metrics_exporter`.
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// Token scrapers must send as `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Serve over TLS, requiring client certificates with `clientCaPath`.
+    #[serde(default)]
+    pub tls: Option<TlsListenerConfig>,
+}
+
+/// How `tls://` backends are connected to (see `tls.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendTlsConfig {
+    /// PEM certificates trusted for the backends' certificates.
+    pub ca_path: String,
+    /// Client certificate chain and key, both or neither.
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    #[serde(default)]
+    pub key_path: Option<String>,
+    /// SNI sent and verified; the backend host if unset.
+    #[serde(default)]
+    pub server_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -25,6 +97,770 @@ impl Default for ProxyProtocolIn {
 pub struct GeofrontOptions {
     #[serde(default)]
     pub proxy_protocol_in: ProxyProtocolIn,
+    /// Peers whose inbound PROXY headers are believed; unset trusts every
+    /// peer. The header of anyone else is stripped without its address being
+    /// used, or the connection closed in strict mode.
+    #[serde(default)]
+    pub proxy_protocol_trusted_cidrs: Option<Vec<ipnet::IpNet>>,
+    /// Optional external exporter for lifecycle/audit events.
+    #[serde(default)]
+    pub event_sink: Option<EventSinkConfig>,
+    /// Optional Redis layer shared by all nodes for cached decisions.
+    #[serde(default)]
+    pub shared_cache: Option<SharedCacheConfig>,
+    /// Optional embedded SQLite history of completed connections.
+    #[serde(default)]
+    pub audit_db: Option<AuditDbConfig>,
+    /// When set, backends given by hostname are re-resolved at this interval
+    /// and balanced across all returned addresses.
+    #[serde(default)]
+    pub dns_refresh_ms: Option<u64>,
+    /// Delay before racing the next address of a backend that resolves to
+    /// several (RFC 8305 happy eyeballs), 250 ms by default; 0 tries them one
+    /// at a time.
+    #[serde(default)]
+    pub happy_eyeballs_delay_ms: Option<u64>,
+    /// Named backend pools kept in sync with a service registry, referenced
+    /// by `pool` in route decisions.
+    #[serde(default)]
+    pub pools: HashMap<String, ServiceDiscoveryConfig>,
+    /// Experimental (Linux): forward unlimited TCP relays in-kernel through a
+    /// BPF sockmap. Falls back to splice when the program cannot be loaded.
+    #[serde(default)]
+    pub sockmap: bool,
+    /// Honour `proxyProtocolSource` in route decisions. Off by default: it
+    /// lets the router claim any client address towards the backends.
+    #[serde(default)]
+    pub allow_proxy_protocol_source: bool,
+    /// Disconnect message templates by key, overriding the built-in ones
+    /// (see `messages.rs`): text with `&`/`§` color codes or JSON text
+    /// components, with placeholders.
+    #[serde(default, deserialize_with = "crate::messages::deserialize_templates")]
+    pub messages: HashMap<String, String>,
+    /// When set, per-connection byte deltas are reported at this interval and
+    /// on close (see `usage.rs`).
+    #[serde(default)]
+    pub usage_report_interval_ms: Option<u64>,
+    /// When set, a `MetricsEvent` is queued for polling at this interval.
+    #[serde(default)]
+    pub metrics_push_interval_ms: Option<u64>,
+    /// Time-based routes, evaluated in order before the router callback.
+    #[serde(default)]
+    pub schedules: Vec<ScheduleRule>,
+    #[serde(default)]
+    pub capacity: Option<CapacityConfig>,
+    /// Connections open at once, status pings included; further clients are
+    /// told the proxy is full (see `overflow.rs`).
+    #[serde(default)]
+    pub max_connections: Option<u64>,
+    /// Status response for pings over `maxConnections`, with the
+    /// placeholders of `defaultMotd`.
+    #[serde(default)]
+    pub overflow_motd: Option<serde_json::Value>,
+    /// Ramp-up window for pool members that recovered or just joined.
+    #[serde(default)]
+    pub slow_start_ms: Option<u64>,
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+    /// Active probing of backends; failing ones are tried last.
+    #[serde(default)]
+    pub health_check: Option<HealthCheckConfig>,
+    /// Named groups of upstream proxies, referenced by `proxyPool` in route
+    /// decisions.
+    #[serde(default)]
+    pub proxy_pools: HashMap<String, ProxyPoolConfig>,
+    /// When upstream proxies count as unhealthy; defaults to 3 failures in
+    /// a row and a 30 s cooldown.
+    #[serde(default)]
+    pub proxy_health: Option<ProxyHealthConfig>,
+    /// Accept BungeeCord-style IP/UUID forwarding in the handshake host.
+    #[serde(default)]
+    pub bungee_forwarding: Option<BungeeForwardingConfig>,
+    /// Raw bytes kept in protocol-error events; defaults to 64.
+    #[serde(default)]
+    pub protocol_error_sample_bytes: Option<usize>,
+    /// Save failed parses for replay in tests (see `corpus.rs`).
+    #[serde(default)]
+    pub protocol_corpus: Option<ProtocolCorpusConfig>,
+    /// Record every routing decision (see `route_audit.rs`).
+    #[serde(default)]
+    pub route_audit: Option<RouteAuditConfig>,
+    /// Open connections allowed per client IP (see `limits.rs`).
+    #[serde(default)]
+    pub max_connections_per_ip: Option<usize>,
+    /// New connections, each carrying a handshake, allowed per client IP per
+    /// second.
+    #[serde(default)]
+    pub handshakes_per_ip_per_second: Option<u32>,
+    /// Record per-phase connection events (see `lifecycle.rs`).
+    #[serde(default)]
+    pub lifecycle_events: bool,
+    /// Parse the backend's login packets for the compression threshold and
+    /// player UUID (see `login_phase.rs`).
+    #[serde(default)]
+    pub track_login_phase: bool,
+    /// MaxMind database adding country and ASN to route and MOTD requests
+    /// (see `geoip.rs`).
+    #[serde(default)]
+    pub geoip_db: Option<String>,
+    /// Relay 1.20.5+ sessions so `proxy_transfer_connection` can move them
+    /// with a Transfer packet (see `transfer.rs`); implies tracking the
+    /// login phase and skips the kernel copy paths.
+    #[serde(default)]
+    pub allow_transfer: bool,
+    /// Bytes per relay buffer, 1 KiB to 1 MiB; default 4 KiB (see
+    /// `buffer_pool.rs`).
+    #[serde(default)]
+    pub copy_buffer_size: Option<usize>,
+    /// Cached route and MOTD decisions kept in memory; the least recently
+    /// used are evicted beyond it. Unbounded by default.
+    #[serde(default)]
+    pub cache_max_entries: Option<usize>,
+    /// Limit on a client sending its handshake and then its login start or
+    /// status request; default 5s.
+    #[serde(default)]
+    pub handshake_timeout_ms: Option<u64>,
+    /// Route requests the host may have outstanding at once through the
+    /// FFI queues; further logins wait for one to be answered. Default 1024.
+    #[serde(default)]
+    pub max_pending_routes: Option<usize>,
+    /// How long a login or status request waits for the router or MOTD
+    /// handler; default 10s.
+    #[serde(default)]
+    pub decision_timeout_ms: Option<u64>,
+    /// What a connection gets when no decision arrives in time.
+    #[serde(default)]
+    pub decision_fallback: Option<DecisionFallback>,
+    /// Status response answering every status request without asking the
+    /// MOTD handler, with `{online}`, `{max}` and `{protocol}` filled in
+    /// (see `default_motd.rs`).
+    #[serde(default)]
+    pub default_motd: Option<serde_json::Value>,
+    /// Retention of the usage reported by `proxy_get_usage`; 24h by
+    /// default.
+    #[serde(default)]
+    pub accounting: Option<AccountingConfig>,
+    /// Socket options of client, backend and upstream proxy connections.
+    #[serde(default)]
+    pub tcp: Option<TcpConfig>,
+    /// Login attempts allowed per username and per IP (see
+    /// `login_throttle.rs`).
+    #[serde(default)]
+    pub login_throttle: Option<LoginThrottleConfig>,
+    /// Hold rejected clients open instead of closing them (see `tarpit.rs`).
+    #[serde(default)]
+    pub tarpit: Option<TarpitConfig>,
+    /// How `tls://` backends are connected to (see `tls.rs`).
+    #[serde(default)]
+    pub backend_tls: Option<BackendTlsConfig>,
+    /// How `quic://` proxies are reached (see `quic.rs`).
+    #[serde(default)]
+    pub quic: Option<QuicClientConfig>,
+    /// Address, token and TLS of the Prometheus endpoint.
+    #[serde(default)]
+    pub metrics_exporter: Option<MetricsExporterConfig>,
+    /// Close relayed sessions without traffic in either direction for this
+    /// long. Overridable per route.
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// Close relayed sessions this long after the login. Overridable per route.
+    #[serde(default)]
+    pub max_session_duration_ms: Option<u64>,
+}
+
+/// Backends probed on an interval (see `health_check.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheckConfig {
+    /// `host:port` of each backend to probe.
+    pub backends: Vec<String>,
+    #[serde(default)]
+    pub kind: HealthCheckKind,
+    #[serde(default = "default_health_interval_ms")]
+    pub interval_ms: u64,
+    /// Limit on each probe.
+    #[serde(default = "default_health_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Failed probes in a row that mark a backend unhealthy.
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Passed probes in a row that mark it healthy again.
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+}
+
+fn default_health_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_health_timeout_ms() -> u64 {
+    3_000
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthCheckKind {
+    /// A server list ping, answered by a live Minecraft server only.
+    #[default]
+    Status,
+    /// A TCP connect.
+    Tcp,
+}
+
+/// Latest active check results of one backend.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendCheckStatus {
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub consecutive_successes: u32,
+    pub last_checked_ms: Option<u64>,
+    /// Time to the answer of the last probe, if it passed.
+    pub latency_ms: Option<f64>,
+    pub last_error: Option<String>,
+}
+
+impl Default for BackendCheckStatus {
+    fn default() -> Self {
+        // Backends count as healthy until proven otherwise.
+        Self {
+            healthy: true,
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            last_checked_ms: None,
+            latency_ms: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Thresholds for temporarily ejecting failing backends from pools.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlierDetectionConfig {
+    /// Failures in a row (connect or mid-session) that eject a backend.
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// Failure ratio within `intervalMs` that ejects a backend.
+    pub failure_rate: Option<f64>,
+    /// Attempts needed in the interval before `failureRate` applies.
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u32,
+    #[serde(default = "default_outlier_interval_ms")]
+    pub interval_ms: u64,
+    /// Ejection time, multiplied by how often the backend was ejected.
+    #[serde(default = "default_base_ejection_ms")]
+    pub base_ejection_ms: u64,
+    /// Upper bound on the share of a pool that may be ejected at once.
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+fn default_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_min_requests() -> u32 {
+    20
+}
+
+fn default_outlier_interval_ms() -> u64 {
+    30_000
+}
+
+fn default_base_ejection_ms() -> u64 {
+    30_000
+}
+
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+
+/// Upstream SOCKS5/HTTP proxies used in turn (see `upstream.rs`).
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyPoolConfig {
+    /// `socks5://` or `http://` URLs, optionally with credentials.
+    pub proxies: Vec<String>,
+    #[serde(default)]
+    pub rotation: ProxyRotation,
+}
+
+/// Degraded answers for connections whose router or MOTD handler gave no
+/// decision, e.g. because the host is overloaded. Fallback decisions are
+/// never cached.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionFallback {
+    /// Disconnect message for logins left without a route; defaults to the
+    /// `routingError` template.
+    pub disconnect_message: Option<String>,
+    /// Route taken instead of disconnecting.
+    pub fallback_route: Option<RouteDecision>,
+    /// Status response sent instead of the built-in error MOTD.
+    pub fallback_motd: Option<MotdDecision>,
+}
+
+/// Per-username and per-IP usage (see `accounting.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountingConfig {
+    /// How long a username or IP is remembered after its last connection.
+    #[serde(default = "default_accounting_retention_ms")]
+    pub retention_ms: u64,
+}
+
+fn default_accounting_retention_ms() -> u64 {
+    crate::accounting::DEFAULT_RETENTION_MS
+}
+
+/// Login attempts allowed per key (see `login_throttle.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginThrottleConfig {
+    /// Attempts per `period_ms`; 0 disables the throttle.
+    pub attempts: u32,
+    pub period_ms: u64,
+    #[serde(default = "default_true")]
+    pub by_username: bool,
+    #[serde(default = "default_true")]
+    pub by_ip: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How rejected clients are held (see `tarpit.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TarpitConfig {
+    #[serde(default = "default_tarpit_duration_ms")]
+    pub duration_ms: u64,
+    /// Bytes read from a tarpitted client per second.
+    #[serde(default = "default_tarpit_bytes_per_sec")]
+    pub bytes_per_sec: u32,
+    /// Sockets held at once; rejected clients beyond it are closed.
+    #[serde(default = "default_tarpit_max_sockets")]
+    pub max_sockets: usize,
+}
+
+fn default_tarpit_duration_ms() -> u64 {
+    30_000
+}
+
+fn default_tarpit_bytes_per_sec() -> u32 {
+    1
+}
+
+fn default_tarpit_max_sockets() -> usize {
+    1024
+}
+
+/// Where failed parses are saved (see `corpus.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolCorpusConfig {
+    pub dir: String,
+    #[serde(default = "default_corpus_max_files")]
+    pub max_files: usize,
+    /// Bytes saved per failed parse.
+    #[serde(default = "default_corpus_max_bytes")]
+    pub max_bytes: usize,
+}
+
+fn default_corpus_max_files() -> usize {
+    1000
+}
+
+fn default_corpus_max_bytes() -> usize {
+    4096
+}
+
+/// Where routing decisions are recorded (see `route_audit.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteAuditConfig {
+    /// File the decisions are appended to, one JSON object per line.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Also queue them for `proxy_poll_events` (`routeAudits`).
+    #[serde(default)]
+    pub queue: bool,
+}
+
+/// A QUIC tunnel listener (see `quic.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuicTunnelConfig {
+    /// PEM certificate chain and key presented to edges.
+    pub cert_path: String,
+    pub key_path: String,
+    /// Token edges must give, as the user of their `quic://` URL.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Players tunnelled at once per edge connection.
+    #[serde(default = "default_quic_max_streams")]
+    pub max_streams: u32,
+}
+
+fn default_quic_max_streams() -> u32 {
+    1024
+}
+
+/// How edges connect to QUIC tunnel listeners.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuicClientConfig {
+    /// PEM certificates trusted for the origins' certificates.
+    pub ca_path: String,
+    /// Name verified in the certificate; the URL host if unset.
+    #[serde(default)]
+    pub server_name: Option<String>,
+    #[serde(default = "default_quic_keep_alive_ms")]
+    pub keep_alive_ms: u64,
+}
+
+fn default_quic_keep_alive_ms() -> u64 {
+    10_000
+}
+
+/// A Bedrock listener (see `bedrock.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BedrockConfig {
+    /// Backend `host:port` by the server address clients dialed, as
+    /// `ip:port` or `ip`.
+    #[serde(default)]
+    pub routes: HashMap<String, String>,
+    /// Backend of clients matching no route, and of pings.
+    pub default_backend: String,
+    #[serde(default = "default_bedrock_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+    /// Associations kept at once; datagrams of further clients are dropped.
+    #[serde(default = "default_bedrock_max_sessions")]
+    pub max_sessions: usize,
+}
+
+fn default_bedrock_idle_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_bedrock_max_sessions() -> usize {
+    10_000
+}
+
+/// TCP socket options (see `tcp_options.rs`); unset ones keep the OS
+/// defaults.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TcpConfig {
+    /// `TCP_NODELAY`, sending small packets without waiting to coalesce.
+    #[serde(default)]
+    pub no_delay: Option<bool>,
+    /// Idle time before the first keepalive probe; setting any keepalive
+    /// field enables keepalive.
+    #[serde(default)]
+    pub keepalive_time_ms: Option<u64>,
+    #[serde(default)]
+    pub keepalive_interval_ms: Option<u64>,
+    /// Unanswered probes before the connection is dropped; ignored on
+    /// Windows.
+    #[serde(default)]
+    pub keepalive_probes: Option<u32>,
+    /// `SO_SNDBUF` and `SO_RCVBUF` in bytes.
+    #[serde(default)]
+    pub send_buffer_size: Option<u32>,
+    #[serde(default)]
+    pub recv_buffer_size: Option<u32>,
+}
+
+/// Passive health of upstream proxies (see `proxy_manager.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyHealthConfig {
+    /// Connect failures in a row that mark a proxy unhealthy; 0 never does.
+    #[serde(default = "default_proxy_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long an unhealthy proxy is tried last in its pools.
+    #[serde(default = "default_proxy_cooldown_ms")]
+    pub cooldown_ms: u64,
+}
+
+fn default_proxy_failure_threshold() -> u32 {
+    crate::proxy_manager::DEFAULT_FAILURE_THRESHOLD
+}
+
+fn default_proxy_cooldown_ms() -> u64 {
+    crate::proxy_manager::DEFAULT_COOLDOWN_MS
+}
+
+/// Where a route's session is mirrored (see `mirror.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MirrorConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub direction: MirrorDirection,
+}
+
+/// Which side's bytes are mirrored: what the client sent, what the backend
+/// sent, or both over separate connections.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MirrorDirection {
+    Client,
+    Server,
+    #[default]
+    Both,
+}
+
+/// Which proxy of a pool is tried first; the others follow on connect errors.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProxyRotation {
+    #[default]
+    RoundRobin,
+    /// The same proxy for the same player IP while it stays healthy.
+    Sticky,
+    /// Always in the configured order.
+    Failover,
+}
+
+/// Handshakes forwarded by another BungeeCord/Velocity instance.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BungeeForwardingConfig {
+    /// Peers allowed to forward; the payload of anyone else is discarded.
+    pub trusted_cidrs: Vec<ipnet::IpNet>,
+}
+
+/// Caps on relayed players, checked once a login has been routed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CapacityConfig {
+    pub max_players: Option<u64>,
+    #[serde(default)]
+    pub tenant_max_players: HashMap<String, u64>,
+    /// When full, close the lowest-priority (then oldest) player with a
+    /// strictly lower priority instead of refusing the new one.
+    #[serde(default)]
+    pub evict: bool,
+}
+
+/// Routes `host` to `open` while any window is active, otherwise to `closed`.
+/// `{opensAt}` in `closed.disconnect` is replaced by the next opening time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleRule {
+    /// Exact hostname or `*.suffix` wildcard.
+    pub host: String,
+    /// IANA zone name (e.g. `Europe/Berlin`); UTC when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    pub windows: Vec<ScheduleWindow>,
+    pub open: RouteDecision,
+    pub closed: RouteDecision,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleWindow {
+    /// Weekdays (`mon`..`sun`) the window starts on; every day when empty.
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// `HH:MM` local time. A window ending before it starts spans midnight.
+    pub start: String,
+    pub end: String,
+}
+
+/// Routes hosts matching `host` straight to `route`, skipping the router
+/// callback.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StaticRoute {
+    /// Exact hostname, `*.suffix` wildcard or `*` for any host.
+    pub host: String,
+    pub route: RouteDecision,
+}
+
+/// Defaults for hosts matching `host`, set through `proxy_set_vhosts` (see
+/// `vhosts.rs`). Whatever is left unset is still asked of the callbacks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualHost {
+    /// Exact hostname, `*.suffix` wildcard or `*` for any host.
+    pub host: String,
+    /// Answers pings without asking the MOTD handler.
+    #[serde(default)]
+    pub motd: Option<MotdDecision>,
+    /// Routes logins without asking the router, unless `router_first`.
+    #[serde(default)]
+    pub backend: Option<RemoteBackend>,
+    /// Ask the router first and route to `backend` only when it fails or
+    /// times out, ahead of `fallbackRoute`.
+    #[serde(default)]
+    pub router_first: bool,
+    /// Inclusive `[min, max]` client protocol versions; logins of other
+    /// clients are refused with the `unsupportedVersion` message.
+    #[serde(default)]
+    pub protocol_range: Option<(i32, i32)>,
+    /// Refuse logins with the `maintenance` message; pings are still
+    /// answered.
+    #[serde(default)]
+    pub maintenance: bool,
+}
+
+/// Registry watched for the members of a named backend pool.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ServiceDiscoveryConfig {
+    /// Healthy instances of a Consul service (requires the `consul` feature).
+    Consul {
+        /// Agent HTTP address, e.g. `http://127.0.0.1:8500`
+        address: String,
+        service: String,
+        #[serde(default)]
+        tag: Option<String>,
+        #[serde(default)]
+        datacenter: Option<String>,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// Keys under an etcd v3 prefix whose values are `host:port` or
+    /// `{"host": ..., "port": ...}` (requires the `etcd` feature).
+    Etcd {
+        /// JSON gateway address, e.g. `http://127.0.0.1:2379`
+        endpoint: String,
+        prefix: String,
+    },
+}
+
+/// Redis backing for the decision cache (requires the `redis` feature).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedCacheConfig {
+    /// e.g. `redis://:password@10.0.0.5:6379/0`
+    pub url: String,
+    #[serde(default = "default_shared_cache_prefix")]
+    pub key_prefix: String,
+    /// Caps how long an entry fetched from Redis is served locally, bounding
+    /// staleness after another node clears it. Defaults to the entry's own TTL.
+    #[serde(default)]
+    pub local_ttl_ms: Option<u64>,
+}
+
+fn default_shared_cache_prefix() -> String {
+    "geofront:cache:".to_string()
+}
+
+/// SQLite connection history (requires the `sqlite` feature).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditDbConfig {
+    pub path: String,
+    /// Delete records closed more than this many days ago.
+    #[serde(default)]
+    pub retention_days: Option<u64>,
+    /// Keep at most this many of the newest records.
+    #[serde(default)]
+    pub max_rows: Option<u64>,
+    /// Also record status (server list ping) connections.
+    #[serde(default)]
+    pub include_status: bool,
+}
+
+/// Filter accepted by `proxy_query_audit`; all fields are optional and combined with AND.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub username: Option<String>,
+    pub peer_ip: Option<String>,
+    pub host: Option<String>,
+    pub backend: Option<String>,
+    /// Inclusive lower bound on close time (ms since epoch).
+    pub since_ms: Option<u64>,
+    /// Exclusive upper bound on close time (ms since epoch).
+    pub until_ms: Option<u64>,
+    /// Defaults to 100, capped at 10000.
+    pub limit: Option<u32>,
+}
+
+/// Simulated client load for `proxy_loadgen_start` (requires the `loadgen` feature).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadGenConfig {
+    /// `host:port` to connect to.
+    pub target: String,
+    /// Host sent in the handshake; defaults to the host part of `target`.
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default = "default_loadgen_clients")]
+    pub clients: u32,
+    #[serde(default = "default_loadgen_protocol")]
+    pub protocol: i32,
+    /// Usernames are this prefix followed by the client index.
+    #[serde(default = "default_loadgen_username_prefix")]
+    pub username_prefix: String,
+    /// Clients are started evenly over this window.
+    #[serde(default)]
+    pub ramp_up_ms: u64,
+    /// How long each client stays connected.
+    #[serde(default = "default_loadgen_duration_ms")]
+    pub duration_ms: u64,
+    #[serde(default = "default_loadgen_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    /// Upload per client after login, sent as filler packets; 0 sends
+    /// nothing. Real servers reject the filler, so use a sink backend.
+    #[serde(default)]
+    pub payload_bytes_per_sec: u64,
+    #[serde(default = "default_loadgen_payload_chunk_bytes")]
+    pub payload_chunk_bytes: usize,
+}
+
+fn default_loadgen_clients() -> u32 {
+    10
+}
+
+fn default_loadgen_protocol() -> i32 {
+    767
+}
+
+fn default_loadgen_username_prefix() -> String {
+    "bench".to_string()
+}
+
+fn default_loadgen_duration_ms() -> u64 {
+    10_000
+}
+
+fn default_loadgen_connect_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_loadgen_payload_chunk_bytes() -> usize {
+    1024
+}
+
+/// Destination for exported events. Each variant requires the matching cargo feature.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum EventSinkConfig {
+    /// Publishes to `<subject>.<eventType>`, e.g. `geofront.events.disconnected`.
+    Nats { url: String, subject: String },
+    /// Produces to a single topic partition, keyed by connection id.
+    Kafka {
+        brokers: Vec<String>,
+        topic: String,
+        #[serde(default)]
+        partition: i32,
+    },
 }
 
 // Error codes
@@ -33,6 +869,18 @@ pub const PROXY_OK: ProxyError = 0;
 pub const PROXY_ERR_INTERNAL: ProxyError = -1;
 pub const PROXY_ERR_BAD_PARAM: ProxyError = -2;
 pub const PROXY_ERR_NOT_FOUND: ProxyError = -3;
+/// The connection exists but cannot do what was asked in its current state.
+pub const PROXY_ERR_UNSUPPORTED: ProxyError = -4;
+
+// Encodings of the buffer-returning FFI calls
+pub type WireFormat = u32;
+pub const WIRE_JSON: WireFormat = 0;
+pub const WIRE_MSGPACK: WireFormat = 1;
+
+// Keys of `proxy_get_usage`
+pub type UsageKey = u32;
+pub const USAGE_BY_USERNAME: UsageKey = 0;
+pub const USAGE_BY_IP: UsageKey = 1;
 
 // Handles
 pub type ProxyListener = u64;
@@ -93,19 +941,137 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> AsyncStreamTrait for T
 pub type AsyncStream = dyn AsyncStreamTrait;
 
 // Struct for JS to return routing decision as a JSON string
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct RouteDecision {
     #[serde(rename = "remoteHost")]
     pub remote_host: Option<String>,
     #[serde(rename = "remotePort")]
     pub remote_port: Option<u16>,
-    pub proxy: Option<String>,
+    /// Fallback backends, tried in order when `remoteHost` (or `pool`) is
+    /// unreachable.
+    pub remotes: Option<Vec<RemoteBackend>>,
+    /// Limit on each backend connection attempt.
+    #[serde(rename = "connectTimeoutMs")]
+    pub connect_timeout_ms: Option<u64>,
+    pub proxy: Option<ProxyChain>,
+    /// Named upstream proxy pool; takes precedence over `proxy`.
+    #[serde(rename = "proxyPool")]
+    pub proxy_pool: Option<String>,
     #[serde(rename = "proxyProtocol")]
     pub proxy_protocol: Option<u8>,
+    /// Source `ip` or `ip:port` written to the outgoing PROXY header instead
+    /// of the client's; requires `allowProxyProtocolSource`.
+    #[serde(rename = "proxyProtocolSource")]
+    pub proxy_protocol_source: Option<String>,
+    /// Text with color codes or a JSON text component.
+    #[serde(default, deserialize_with = "crate::messages::deserialize_message")]
     pub disconnect: Option<String>,
+    /// Key of a message template to disconnect with; takes precedence over `disconnect`.
+    #[serde(rename = "disconnectTemplate")]
+    pub disconnect_template: Option<String>,
     #[serde(rename = "rewriteHost")]
     pub rewrite_host: Option<String>,
+    /// Named backend pool to connect to instead of `remoteHost`.
+    pub pool: Option<String>,
+    /// Priority class for `capacity` eviction; higher wins, default 0.
+    pub priority: Option<i32>,
+    /// Tenant counted against `capacity.tenantMaxPlayers`.
+    pub tenant: Option<String>,
+    /// Opaque host data stored with the connection and echoed in its
+    /// events, metrics and audit record.
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Labels set as `true` tags of the connection, to select it by with
+    /// `proxy_list_connections`.
+    pub tags: Option<Vec<String>>,
     pub cache: Option<CacheConfig>,
+    /// Verify the player with the Mojang session server here and forward
+    /// the profile to an offline-mode backend (see `auth.rs`).
+    pub authenticate: Option<bool>,
+    /// Limits installed before the backend is connected, so no traffic
+    /// passes unthrottled; `proxy_set_rate_limit` can still replace them.
+    #[serde(rename = "rateLimit")]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Overrides `idleTimeoutMs` of the options; 0 disables it.
+    #[serde(rename = "idleTimeoutMs")]
+    pub idle_timeout_ms: Option<u64>,
+    /// Overrides `maxSessionDurationMs` of the options; 0 disables it.
+    #[serde(rename = "maxSessionDurationMs")]
+    pub max_session_duration_ms: Option<u64>,
+    /// Local IP address the backend or upstream proxy connection is made
+    /// from (see `outbound.rs`).
+    #[serde(rename = "localAddress")]
+    pub local_address: Option<String>,
+    /// Bytes in both directions after which the session is closed.
+    #[serde(rename = "quotaBytes")]
+    pub quota_bytes: Option<u64>,
+    /// Like `quotaBytes`, but for all the player's sessions that are still
+    /// retained by the accounting (see `quota.rs`); a player already over it
+    /// is refused at login.
+    #[serde(rename = "userQuotaBytes")]
+    pub user_quota_bytes: Option<u64>,
+    /// Copies the session's bytes to a secondary destination.
+    pub mirror: Option<MirrorConfig>,
+    /// Holds the client for up to this long when its backend drops, until
+    /// one of the route's backends is reachable again, then has it replay
+    /// its login there through a Transfer. Needs `allowTransfer` and a
+    /// 1.20.5+ client; keep it under the client's 30 second timeout.
+    #[serde(rename = "reconnectGraceMs")]
+    pub reconnect_grace_ms: Option<u64>,
+    /// SNI of a `tls://` backend, overriding `backendTls.serverName`.
+    #[serde(rename = "tlsServerName")]
+    pub tls_server_name: Option<String>,
+    /// Overrides fields of the `tcp` options for this connection's sockets.
+    pub tcp: Option<TcpConfig>,
+    /// Inclusive `[min, max]` client protocol versions; other clients are
+    /// refused with the `unsupportedVersion` message.
+    #[serde(rename = "allowedProtocolRange")]
+    pub allowed_protocol_range: Option<(i32, i32)>,
+}
+
+/// `proxy` of a route: one upstream proxy URL, or several tunnelled through
+/// in order (see `upstream.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ProxyChain {
+    Single(String),
+    Hops(Vec<String>),
+}
+
+impl ProxyChain {
+    /// The proxy URLs in the order they are tunnelled through.
+    pub fn hops(&self) -> Vec<&str> {
+        let hops: &[String] = match self {
+            ProxyChain::Single(url) => std::slice::from_ref(url),
+            ProxyChain::Hops(urls) => urls,
+        };
+        hops.iter()
+            .map(String::as_str)
+            .filter(|url| !url.is_empty())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for ProxyChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.hops().join(" -> "))
+    }
+}
+
+/// Bytes per second in each direction; 0 or absent is unlimited, and a
+/// burst defaults to the average.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    pub send_avg: Option<u64>,
+    pub send_burst: Option<u64>,
+    pub recv_avg: Option<u64>,
+    pub recv_burst: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemoteBackend {
+    pub host: String,
+    pub port: u16,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -115,6 +1081,9 @@ pub struct CacheConfig {
     pub reject: Option<bool>,
     #[serde(rename = "rejectReason")]
     pub reject_reason: Option<String>,
+    /// TTL in milliseconds for rejections, usually longer than `ttl`.
+    #[serde(rename = "rejectTtl", default)]
+    pub reject_ttl: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -132,11 +1101,31 @@ pub enum CacheGranularity {
 pub struct RouteRequest {
     pub conn_id: ProxyConnection,
     pub peer_ip: String,
+    /// Address and port the client connected to, from the inbound PROXY
+    /// header if there is one, else the listening socket's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
     pub port: u16,
     // Minecraft 协议版本：应使用有符号 i32 以保持与握手解析一致
     pub protocol: i32,
     pub host: String,
     pub username: String,
+    /// Player UUID from a trusted forwarded handshake.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// SNI hostname, for clients of a TLS listener.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// Forge marker the client appended to the host (`FML`, `FML2`, `FML3`
+    /// or `FORGE`); absent for vanilla clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_type: Option<String>,
+    /// What the client connected over.
+    pub transport: TransportKind,
+    #[serde(flatten)]
+    pub geo: GeoInfo,
 }
 
 // Struct for MOTD requests (used in polling API)
@@ -145,10 +1134,32 @@ pub struct RouteRequest {
 pub struct MotdRequest {
     pub conn_id: ProxyConnection,
     pub peer_ip: String,
+    /// As in `RouteRequest`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
     pub port: u16,
     // Minecraft 协议版本：与 RouteRequest 一致使用 i32
     pub protocol: i32,
     pub host: String,
+    #[serde(flatten)]
+    pub geo: GeoInfo,
+}
+
+/// GeoIP fields of a client IP (see `geoip.rs`).
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoInfo {
+    /// ISO 3166-1 country code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Autonomous system number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// Autonomous system organization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
 }
 
 // Struct for disconnection events (used in polling API)
@@ -156,6 +1167,287 @@ pub struct MotdRequest {
 #[serde(rename_all = "camelCase")]
 pub struct DisconnectionEvent {
     pub conn_id: ProxyConnection,
+    /// Tags set through `proxy_set_connection_tag`.
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub tags: serde_json::Map<String, serde_json::Value>,
+    /// `metadata` of the routing decision.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Milliseconds from the login start to the first byte from the backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+    /// Status ping round trip, or backend connect time of a login.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// `host:port` (or `pool:name`) of the backend connected to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Player UUID, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Compression threshold set by the backend, when the login phase is
+    /// tracked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_threshold: Option<i32>,
+    pub reason: DisconnectReason,
+}
+
+/// What a client connected over (see `transport.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Tls,
+    WebSocket,
+}
+
+/// Why a connection ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// Client or backend closed the proxied session.
+    Closed,
+    /// A status (MOTD) exchange completed.
+    StatusDone,
+    /// Handshake, login or status packets could not be parsed.
+    ProtocolError,
+    /// Inbound PROXY protocol header was missing or invalid in strict mode.
+    ProxyProtocol,
+    /// The router (or a cached decision) rejected the login.
+    Rejected,
+    /// No routing decision could be obtained.
+    RoutingFailed,
+    /// The backend (or upstream proxy) could not be reached.
+    BackendUnreachable,
+    /// An I/O error occurred while relaying traffic.
+    RelayError,
+    /// Disconnected through `proxy_disconnect` / `proxy_kick_all`.
+    Kicked,
+    /// Refused because the player cap of `capacity` or `maxConnections`
+    /// was reached.
+    Full,
+    /// Closed to make room for a higher-priority player.
+    Evicted,
+    /// Torn down by `proxy_shutdown`.
+    Shutdown,
+    /// Online-mode authentication by geofront failed.
+    AuthFailed,
+    /// No traffic in either direction for `idleTimeoutMs`.
+    IdleTimeout,
+    /// The session outlived `maxSessionDurationMs`.
+    SessionExpired,
+    /// The client was sent to another backend with a Transfer packet.
+    Transferred,
+    /// The handshake, login start or status request took longer than
+    /// `handshakeTimeoutMs`.
+    HandshakeTimeout,
+    /// The session or its player used up a byte quota of the route.
+    QuotaExceeded,
+    /// The client's protocol version is outside `allowedProtocolRange`.
+    UnsupportedVersion,
+    /// The username or IP attempted too many logins (`loginThrottle`).
+    LoginThrottled,
+}
+
+impl DisconnectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectReason::Closed => "closed",
+            DisconnectReason::StatusDone => "status_done",
+            DisconnectReason::ProtocolError => "protocol_error",
+            DisconnectReason::ProxyProtocol => "proxy_protocol",
+            DisconnectReason::Rejected => "rejected",
+            DisconnectReason::RoutingFailed => "routing_failed",
+            DisconnectReason::BackendUnreachable => "backend_unreachable",
+            DisconnectReason::RelayError => "relay_error",
+            DisconnectReason::Kicked => "kicked",
+            DisconnectReason::Full => "full",
+            DisconnectReason::Evicted => "evicted",
+            DisconnectReason::Shutdown => "shutdown",
+            DisconnectReason::AuthFailed => "auth_failed",
+            DisconnectReason::IdleTimeout => "idle_timeout",
+            DisconnectReason::SessionExpired => "session_expired",
+            DisconnectReason::Transferred => "transferred",
+            DisconnectReason::HandshakeTimeout => "handshake_timeout",
+            DisconnectReason::QuotaExceeded => "quota_exceeded",
+            DisconnectReason::UnsupportedVersion => "unsupported_version",
+            DisconnectReason::LoginThrottled => "login_throttled",
+        }
+    }
+}
+
+/// Session details collected while a connection progresses.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnInfo {
+    pub peer_ip: String,
+    pub connected_at_ms: u64,
+    /// When the login start was read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login_at_ms: Option<u64>,
+    /// When the backend connection was established.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend_connected_at_ms: Option<u64>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub protocol: Option<i32>,
+    pub username: Option<String>,
+    /// Player UUID from a trusted forwarded handshake, online-mode
+    /// authentication, or the backend's Login Success when tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Compression threshold set by the backend, when the login phase is
+    /// tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_threshold: Option<i32>,
+    /// SNI hostname, for clients of a TLS listener.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// What the client connected over, once its transport handshake is done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportKind>,
+    /// `host:port` of the backend actually connected to.
+    pub backend: Option<String>,
+    pub proxy: Option<String>,
+    /// Listener that accepted the connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listener: Option<ProxyListener>,
+    /// Host-defined metadata set through `proxy_set_connection_tag`.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub tags: serde_json::Map<String, serde_json::Value>,
+    /// `metadata` of the routing decision.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Milliseconds from the login start to the first byte from the backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+    /// Milliseconds each upstream proxy hop took to open its tunnel onward.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_hop_ms: Vec<u64>,
+    /// Milliseconds from the status response to the client's ping, or for
+    /// a login, to connect to the backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+/// Bytes relayed for one connection since its previous report.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    /// Global, strictly increasing; gaps reveal dropped reports.
+    pub seq: u64,
+    pub conn_id: ProxyConnection,
+    pub timestamp_ms: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    /// Cumulative counters, so consumers can reconcile across lost reports.
+    pub total_sent: u64,
+    pub total_recv: u64,
+    /// Last report for this connection.
+    #[serde(rename = "final")]
+    pub is_final: bool,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty", default)]
+    pub tags: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Compact global counters pushed on a fixed interval (see `metrics_push.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsEvent {
+    pub timestamp_ms: u64,
+    pub total_conn: u64,
+    pub active_conn: u64,
+    pub total_bytes_sent: u64,
+    pub total_bytes_recv: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendEventKind {
+    Ejected,
+    Readmitted,
+}
+
+/// A backend was ejected from or readmitted to balancing (see `health.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendEvent {
+    pub backend: String,
+    pub kind: BackendEventKind,
+    pub timestamp_ms: u64,
+    /// Why the backend was ejected.
+    pub reason: Option<String>,
+    pub ejected_for_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProxyHealthEventKind {
+    Unhealthy,
+    Recovered,
+}
+
+/// An upstream proxy was marked unhealthy or recovered (see
+/// `proxy_manager.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyHealthEvent {
+    /// The proxy URL with any password masked.
+    pub proxy: String,
+    pub kind: ProxyHealthEventKind,
+    pub timestamp_ms: u64,
+    /// The error that made the proxy unhealthy.
+    pub reason: Option<String>,
+    pub cooldown_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolErrorKind {
+    ProxyProtocol,
+    Handshake,
+    /// Traffic that is not Minecraft at all, dropped at the handshake by
+    /// cheap sanity checks (see `protocol::parse_handshake`).
+    NotMinecraft,
+    UnknownState,
+    Login,
+}
+
+/// A client sent bytes that could not be parsed (see `protocol_errors.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolErrorEvent {
+    pub conn_id: ProxyConnection,
+    pub timestamp_ms: u64,
+    pub peer_ip: String,
+    pub listener: Option<ProxyListener>,
+    pub kind: ProtocolErrorKind,
+    pub error: String,
+    /// The first bytes received from the client, hex-encoded.
+    pub sample_hex: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaScope {
+    /// `quotaBytes`, for the session alone.
+    Connection,
+    /// `userQuotaBytes`, across the player's sessions.
+    User,
+}
+
+/// A connection closed or refused for a byte quota (see `quota.rs`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaEvent {
+    pub conn_id: ProxyConnection,
+    pub timestamp_ms: u64,
+    pub username: String,
+    pub peer_ip: String,
+    pub scope: QuotaScope,
+    pub quota_bytes: u64,
+    pub used_bytes: u64,
 }
 
 // Struct for batch polling events
@@ -165,6 +1457,145 @@ pub struct PollEvents {
     pub route_requests: Vec<RouteRequest>,
     pub motd_requests: Vec<MotdRequest>,
     pub disconnection_events: Vec<DisconnectionEvent>,
+    pub usage_reports: Vec<UsageReport>,
+    pub metrics_events: Vec<MetricsEvent>,
+    pub backend_events: Vec<BackendEvent>,
+    pub proxy_health_events: Vec<ProxyHealthEvent>,
+    pub protocol_errors: Vec<ProtocolErrorEvent>,
+    pub lifecycle_events: Vec<LifecycleEvent>,
+    pub quota_events: Vec<QuotaEvent>,
+    pub route_audits: Vec<RouteAudit>,
+    pub config_reloads: Vec<ConfigReload>,
+}
+
+/// A config file load, or a change to it that failed to apply (see
+/// `config_file.rs`).
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReload {
+    pub timestamp_ms: u64,
+    pub path: String,
+    /// Options whose value changed, by name.
+    pub options_changed: Vec<String>,
+    pub vhosts: HostDiff,
+    pub routes: HostDiff,
+    /// Why the file was not applied; nothing changed then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Host patterns of a vhost or route table that changed.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct HostDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl HostDiff {
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A routing decision applied to a login attempt (see `route_audit.rs`).
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteAudit {
+    pub conn_id: ProxyConnection,
+    pub timestamp_ms: u64,
+    pub peer_ip: String,
+    pub host: String,
+    pub username: String,
+    /// `"callback"`, `"cache"`, `"schedule"`, `"static"`, `"listener"`,
+    /// `"transfer"` or `"fallback"`.
+    pub source: &'static str,
+    pub backend: Option<String>,
+    pub proxy: Option<String>,
+    pub reject_reason: Option<String>,
+    /// `metadata` of the routing decision.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// A tracing event handed to the host in log queue mode (see `logging.rs`).
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRecord {
+    pub timestamp_ms: u64,
+    /// `trace` to `error`.
+    pub level: String,
+    /// The module that logged it, such as `geofront::connection`.
+    pub target: String,
+    /// The connection it is about, from its `conn` or `conn_id` field.
+    pub conn_id: Option<ProxyConnection>,
+    pub message: String,
+    /// The event's other fields, by name.
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// One phase of a connection (see `lifecycle.rs`).
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleEvent {
+    pub conn_id: ProxyConnection,
+    pub timestamp_ms: u64,
+    /// Milliseconds since the connection was accepted.
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub kind: LifecycleKind,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LifecycleKind {
+    #[serde(rename_all = "camelCase")]
+    Connected {
+        peer_ip: String,
+        listener: ProxyListener,
+    },
+    #[serde(rename_all = "camelCase")]
+    HandshakeParsed {
+        host: String,
+        port: u16,
+        protocol: i32,
+        /// 1 for a status ping, 2 for a login, 3 for a transfer.
+        next_state: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Routed {
+        /// `"callback"`, `"cache"`, `"schedule"`, `"static"`, `"listener"`,
+        /// `"transfer"` or `"fallback"`.
+        source: &'static str,
+        /// Whether the decision rejected the login.
+        rejected: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    BackendConnected {
+        backend: String,
+        /// Time spent connecting, including failed candidates.
+        connect_ms: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    LoginForwarded {
+        username: String,
+    },
+    /// The backend sent Login Success; only with `trackLoginPhase`.
+    #[serde(rename_all = "camelCase")]
+    LoginSucceeded {
+        uuid: Option<String>,
+        compression_threshold: Option<i32>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Disconnected {
+        reason: DisconnectReason,
+        bytes_sent: u64,
+        bytes_recv: u64,
+    },
 }
 
 // Per-connection metrics
@@ -172,6 +1603,9 @@ pub struct PollEvents {
 pub struct ConnMetrics {
     pub bytes_sent: AtomicU64,
     pub bytes_recv: AtomicU64,
+    /// Set while the client is held for its backend to come back, which
+    /// idle timeouts do not count.
+    pub held: AtomicBool,
 }
 
 impl Default for ConnMetrics {
@@ -179,6 +1613,7 @@ impl Default for ConnMetrics {
         Self {
             bytes_sent: AtomicU64::new(0),
             bytes_recv: AtomicU64::new(0),
+            held: AtomicBool::new(false),
         }
     }
 }
@@ -191,48 +1626,200 @@ pub struct MetricsSnapshot {
     pub total_bytes_sent: u64,
     pub total_bytes_recv: u64,
     pub connections: HashMap<ProxyConnection, ConnMetricsSnapshot>,
+    /// Live connections aggregated by tag key, then by tag value.
+    pub tag_groups: HashMap<String, HashMap<String, TagGroupSnapshot>>,
+    /// Protocol errors seen since start, by kind.
+    pub protocol_errors: HashMap<ProtocolErrorKind, u64>,
+    /// Time-to-first-byte over the most recent sessions.
+    pub ttfb: LatencySummary,
+    /// Connect, routing and session times since start.
+    pub histograms: Histograms,
+    /// Totals of each running listener.
+    pub per_listener: HashMap<ProxyListener, ListenerMetrics>,
+    /// Totals by handshake host (lowercased), since start.
+    pub per_host: HashMap<String, RouteMetrics>,
+    /// Totals by backend connected to, since start.
+    pub per_backend: HashMap<String, RouteMetrics>,
+    /// Health and connect latency of each upstream proxy used so far, keyed
+    /// by URL with any password masked.
+    pub upstream_proxies: HashMap<String, ProxyMetrics>,
 }
 
+/// Metrics of the connections that changed since the previous delta, with
+/// the same totals and aggregates as `MetricsSnapshot`.
 #[derive(Serialize)]
+pub struct MetricsDelta {
+    /// Set on the first delta and after a shutdown: `connections` then holds
+    /// every live connection and replaces what the caller has.
+    pub full: bool,
+    /// Cursor to pass to the next `proxy_get_metrics_delta`; unset for the
+    /// deltas of `proxy_get_metrics_buf`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<u64>,
+    pub total_conn: u64,
+    pub active_conn: u64,
+    pub total_bytes_sent: u64,
+    pub total_bytes_recv: u64,
+    /// Connections opened or updated since the previous delta.
+    pub connections: HashMap<ProxyConnection, ConnMetricsSnapshot>,
+    /// Connections closed since the previous delta.
+    pub closed: Vec<ProxyConnection>,
+    pub tag_groups: HashMap<String, HashMap<String, TagGroupSnapshot>>,
+    pub protocol_errors: HashMap<ProtocolErrorKind, u64>,
+    pub ttfb: LatencySummary,
+    pub histograms: Histograms,
+    pub per_listener: HashMap<ProxyListener, ListenerMetrics>,
+    pub per_host: HashMap<String, RouteMetrics>,
+    pub per_backend: HashMap<String, RouteMetrics>,
+    pub upstream_proxies: HashMap<String, ProxyMetrics>,
+}
+
+/// Criteria selecting live connections (see `conn_query.rs`); those left
+/// unset match any connection.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnFilter {
+    /// Tags the connection must all have, with any value but `false` or
+    /// `null`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Exact hostname or `*.suffix` wildcard, matched against the handshake.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// `host:port` of the backend connected to.
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Player name, case-insensitively.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Client IP or CIDR.
+    #[serde(default, deserialize_with = "crate::conn_query::deserialize_ip")]
+    pub ip: Option<ipnet::IpNet>,
+}
+
+/// Everything known about a live connection (`proxy_get_connection_info`).
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionDetails {
+    pub conn_id: ProxyConnection,
+    #[serde(flatten)]
+    pub info: ConnInfo,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    /// The connection's own limiters; global and listener caps are not
+    /// included.
+    pub send_limit: Option<LimiterSnapshot>,
+    pub recv_limit: Option<LimiterSnapshot>,
+}
+
+#[derive(Serialize, Clone, PartialEq)]
 pub struct ConnMetricsSnapshot {
     pub bytes_sent: u64,
     pub bytes_recv: u64,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub tags: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub proxy_hop_ms: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+/// Cumulative counters of a listener: connections accepted, and bytes of
+/// those already closed.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerTotals {
+    pub accepted: u64,
+    /// `accepted` split by acceptor socket (see `acceptors.rs`).
+    pub acceptor_accepts: Vec<u64>,
+    /// Connections currently open.
+    pub active: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+/// A listener's bucket in `MetricsSnapshot.per_listener`; bytes include
+/// its live connections.
+#[derive(Serialize, Default)]
+pub struct ListenerMetrics {
+    pub accepted: u64,
+    pub active: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    /// Accepts of each `SO_REUSEPORT` acceptor socket, in bind order.
+    pub acceptor_accepts: Vec<u64>,
+}
+
+#[derive(Serialize, Default)]
+pub struct TagGroupSnapshot {
+    pub connections: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
 }
 
 pub struct ListenerState {
     pub runtime: tokio::runtime::Runtime,
     pub listeners: HashMap<ProxyListener, JoinHandle<()>>,
+    /// `addr:port` each listener was started on.
+    pub bind_addrs: HashMap<ProxyListener, String>,
 }
 
-impl ListenerState {
-    pub fn new() -> Self {
-        ListenerState {
-            runtime: tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .build()
-                .unwrap(),
-            listeners: HashMap::new(),
-        }
-    }
+/// How a listener behaves while paused (see `pause.rs`); with neither field
+/// it stops accepting.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseConfig {
+    /// Status response served instead of asking the MOTD handler.
+    #[serde(default)]
+    pub motd: Option<serde_json::Value>,
+    /// Disconnect message for logins; the `maintenance` template if unset.
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
-pub struct ConnectionManager {
-    pub connections: HashMap<ProxyConnection, JoinHandle<()>>,
+/// Parameters of the proxy runtime (`proxy_init_runtime`); unset or 0 keeps
+/// Tokio's default.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeConfig {
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    #[serde(default)]
+    pub max_blocking_threads: Option<usize>,
+    #[serde(default)]
+    pub thread_name: Option<String>,
 }
 
-impl ConnectionManager {
+impl ListenerState {
+    /// Builds the runtime from `RUNTIME_CONFIG`, fixing it to the default if
+    /// nothing was configured yet.
     pub fn new() -> Self {
-        ConnectionManager {
-            connections: HashMap::new(),
+        let config = crate::state::RUNTIME_CONFIG.get_or_init(RuntimeConfig::default);
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(threads) = config.worker_threads.filter(|&n| n > 0) {
+            builder.worker_threads(threads);
+        }
+        if let Some(threads) = config.max_blocking_threads.filter(|&n| n > 0) {
+            builder.max_blocking_threads(threads);
+        }
+        if let Some(name) = &config.thread_name {
+            builder.thread_name(name.clone());
+        }
+        ListenerState {
+            runtime: builder.build().unwrap(),
+            listeners: HashMap::new(),
+            bind_addrs: HashMap::new(),
         }
     }
+}
 
-    pub fn insert(&mut self, id: ProxyConnection, h: JoinHandle<()>) {
-        self.connections.insert(id, h);
-    }
-
-    pub fn remove(&mut self, id: &ProxyConnection) -> Option<JoinHandle<()>> {
-        self.connections.remove(id)
+impl Default for ListenerState {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -241,37 +1828,85 @@ pub struct HandshakeData {
     pub protocol_version: i32,
     pub host: String,
     pub port: u16,
-    #[allow(dead_code)]
     pub next_state: i32,
+    /// Player identity appended to `host` by an upstream BungeeCord/Velocity,
+    /// once split off and accepted (see `forwarding.rs`).
+    pub forwarded: Option<ForwardedIdentity>,
+    /// Modded client marker split off `host`, such as `FML2`; re-appended
+    /// to the host sent to the backend.
+    pub client_type: Option<String>,
+}
+
+/// The `\0`-separated payload of a BungeeCord forwarded handshake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForwardedIdentity {
+    pub ip: std::net::IpAddr,
+    /// Undashed, as sent by the upstream proxy.
+    pub uuid: String,
+    /// Raw JSON array of profile properties (skins), if any.
+    pub properties: Option<String>,
 }
 
 // MOTD decision structure
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct MotdDecision {
     pub version: Option<MotdVersion>,
     pub players: Option<MotdPlayers>,
     pub description: Option<serde_json::Value>, // Can be string or component object
     pub favicon: Option<String>,
-    pub disconnect: Option<String>, // If present, disconnect with this message instead
+    // If present, disconnect with this message instead: text with color
+    // codes or a JSON text component.
+    #[serde(default, deserialize_with = "crate::messages::deserialize_message")]
+    pub disconnect: Option<String>,
+    #[serde(rename = "disconnectTemplate")]
+    pub disconnect_template: Option<String>, // Template key, takes precedence over `disconnect`
     pub cache: Option<CacheConfig>,
+    /// Relay the backend's own status response and ping instead; the fields
+    /// above are sent if it cannot be reached.
+    pub passthrough: Option<StatusPassthrough>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusPassthrough {
+    pub host: String,
+    pub port: u16,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MotdVersion {
     pub name: String,
     pub protocol: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MotdPlayers {
     pub max: i32,
     #[serde(default)]
     pub online: Option<i32>,
     #[serde(default)]
     pub sample: Vec<MotdPlayerSample>,
+    /// Fill `online` and `sample` from the proxy's own connections (see
+    /// `motd_players.rs`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto: Option<AutoPlayers>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoPlayers {
+    /// Usernames in the sample; 5 by default.
+    #[serde(default = "default_sample_size")]
+    pub sample_size: usize,
+    /// Show each player as "Anonymous Player".
+    #[serde(default)]
+    pub anonymize: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+fn default_sample_size() -> usize {
+    5
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum MotdPlayerSample {
     Full { name: String, id: String },
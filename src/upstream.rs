@@ -0,0 +1,312 @@
+//! geofront/src/upstream.rs
+//! Upstream SOCKS5/HTTP proxies, chains of them tunnelled through in order,
+//! and named pools of them rotated between connections. Every hop feeds the
+//! passive health tracking of `proxy_manager.rs`.
+
+use crate::{
+    outbound::{self, SocketConfig},
+    proxy_manager, quic,
+    state::{OPTIONS, PROXY_POOL_CURSORS},
+    types::{AsyncStream, ProxyRotation},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    time::Instant,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_socks::tcp::Socks5Stream;
+use tracing::warn;
+use url::Url;
+
+/// Upper bound on an HTTP CONNECT response head.
+const MAX_RESPONSE_HEAD: usize = 8192;
+
+/// Opens a tunnel to `target` (`host:port`) through each proxy of `hops` in
+/// turn, every hop being asked to connect to the next one, connecting to the
+/// first as `socket` says. Also returns how long each hop took to open
+/// its tunnel onward, the first one including the connect to it.
+pub async fn connect_chain(
+    hops: &[&str],
+    target: &str,
+    socket: SocketConfig,
+) -> Result<(Box<AsyncStream>, Vec<u64>)> {
+    let urls = hops
+        .iter()
+        .map(|hop| Url::parse(hop).map_err(|e| Error::new(ErrorKind::InvalidInput, e)))
+        .collect::<Result<Vec<_>>>()?;
+    let Some(first) = urls.first() else {
+        return Err(Error::new(ErrorKind::InvalidInput, "empty proxy chain"));
+    };
+    let started = Instant::now();
+    // A QUIC hop opens its stream straight to the hop after it.
+    let quic_first = first.scheme() == "quic";
+    let connected = if quic_first {
+        match next_hop(&urls, 0, target) {
+            Ok(next) => quic::open(first, &next).await,
+            Err(e) => Err(e),
+        }
+    } else {
+        match proxy_addr(first) {
+            Ok((host, port)) => outbound::connect_host(&host, port, socket)
+                .await
+                .map(|stream| Box::new(stream) as Box<AsyncStream>),
+            Err(e) => Err(e),
+        }
+    };
+    let mut stream = match connected {
+        Ok(stream) => stream,
+        Err(e) => {
+            proxy_manager::record(hops[0], Err(&e));
+            return Err(e);
+        }
+    };
+    let mut hop_ms = Vec::with_capacity(urls.len());
+    for (i, url) in urls.iter().enumerate() {
+        let next = next_hop(&urls, i, target)?;
+        let hop_started = if i == 0 { started } else { Instant::now() };
+        let tunnelled = if i == 0 && quic_first {
+            Ok(stream)
+        } else {
+            tunnel(stream, url, &next).await
+        };
+        match tunnelled {
+            Ok(tunnelled) => {
+                let elapsed = hop_started.elapsed();
+                proxy_manager::record(hops[i], Ok(elapsed));
+                hop_ms.push(elapsed.as_millis() as u64);
+                stream = tunnelled;
+            }
+            Err(e) if hops.len() > 1 => {
+                proxy_manager::record(hops[i], Err(&e));
+                return Err(Error::new(
+                    e.kind(),
+                    format!("proxy hop {} ({}): {}", i + 1, proxy_manager::redact(hops[i]), e),
+                ));
+            }
+            Err(e) => {
+                proxy_manager::record(hops[i], Err(&e));
+                return Err(e);
+            }
+        }
+    }
+    Ok((stream, hop_ms))
+}
+
+/// What hop `i` is asked to connect to: the next hop or the target.
+fn next_hop(urls: &[Url], i: usize, target: &str) -> Result<String> {
+    match urls.get(i + 1) {
+        // Kept bracketed, as a CONNECT or SOCKS5 target.
+        Some(next) => Ok(format!("{}:{}", next.host_str().unwrap_or_default(), proxy_addr(next)?.1)),
+        None => Ok(target.to_string()),
+    }
+}
+
+/// Host and port of a proxy URL, checking its scheme.
+fn proxy_addr(proxy_url: &Url) -> Result<(String, u16)> {
+    let port = match proxy_url.scheme() {
+        "socks5" => proxy_url.port().unwrap_or(1080),
+        "http" => proxy_url.port_or_known_default().unwrap_or(80),
+        "quic" => proxy_url.port().unwrap_or(quic::DEFAULT_PORT),
+        scheme => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported proxy scheme {}", scheme),
+            ));
+        }
+    };
+    let host = proxy_url.host_str().unwrap_or_default();
+    // IPv6 hosts come bracketed, which neither resolving nor SOCKS5 take.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host.to_string(), port))
+}
+
+/// Asks the proxy at the other end of `stream` to connect to `target`.
+async fn tunnel(mut stream: Box<AsyncStream>, proxy_url: &Url, target: &str) -> Result<Box<AsyncStream>> {
+    let username = proxy_url.username();
+    let password = proxy_url.password().unwrap_or_default();
+    match proxy_url.scheme() {
+        "socks5" => {
+            let stream = if !username.is_empty() {
+                Socks5Stream::connect_with_password_and_socket(stream, target, username, password)
+                    .await
+            } else {
+                Socks5Stream::connect_with_socket(stream, target).await
+            };
+            stream
+                .map(|s| Box::new(s) as Box<AsyncStream>)
+                .map_err(Error::other)
+        }
+        "http" => {
+            let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+            if !username.is_empty() {
+                let credentials = STANDARD.encode(format!("{}:{}", username, password));
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await?;
+            check_connect_response(&read_response_head(&mut stream).await?)?;
+            Ok(stream)
+        }
+        "quic" => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "a quic proxy can only be the first hop",
+        )),
+        scheme => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unsupported proxy scheme {}", scheme),
+        )),
+    }
+}
+
+/// Reads up to the blank line one byte at a time, so that no tunnelled
+/// bytes are consumed.
+async fn read_response_head<S: AsyncRead + Unpin + ?Sized>(stream: &mut S) -> Result<Vec<u8>> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "oversized CONNECT response",
+            ));
+        }
+        head.push(stream.read_u8().await?);
+    }
+    Ok(head)
+}
+
+fn check_connect_response(head: &[u8]) -> Result<()> {
+    let head = String::from_utf8_lossy(head);
+    let status_line = head.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("proxy refused CONNECT: {}", status_line),
+        )),
+    }
+}
+
+/// Connects through the proxies of pool `name` in rotation order, falling
+/// over to the next one on error. Returns the URL of the proxy used.
+pub async fn connect_pool(
+    name: &str,
+    peer_ip: &str,
+    target: &str,
+    socket: SocketConfig,
+) -> Result<(String, Box<AsyncStream>)> {
+    let mut last_error = None;
+    for proxy in candidates(name, peer_ip)? {
+        match connect_chain(&[proxy.as_str()], target, socket).await {
+            Ok((stream, _)) => return Ok((proxy, stream)),
+            Err(e) => {
+                warn!(pool = name, proxy = %proxy_manager::redact(&proxy), "Upstream proxy failed: {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| Error::new(ErrorKind::NotFound, "no upstream proxy")))
+}
+
+/// The proxies of pool `name`, in the order they should be tried.
+fn candidates(name: &str, peer_ip: &str) -> Result<Vec<String>> {
+    let config = OPTIONS
+        .read()
+        .unwrap()
+        .proxy_pools
+        .get(name)
+        .filter(|config| !config.proxies.is_empty())
+        .cloned()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("proxy pool {} has no proxies", name),
+            )
+        })?;
+    let start = match config.rotation {
+        ProxyRotation::RoundRobin => {
+            let mut cursor = PROXY_POOL_CURSORS.entry(name.to_string()).or_insert(0);
+            *cursor = cursor.wrapping_add(1);
+            *cursor - 1
+        }
+        ProxyRotation::Sticky => {
+            let mut hasher = DefaultHasher::new();
+            peer_ip.hash(&mut hasher);
+            hasher.finish() as usize
+        }
+        ProxyRotation::Failover => 0,
+    };
+    let now = Instant::now();
+    Ok(order(config.proxies, start, |proxy| proxy_manager::is_down(proxy, now)))
+}
+
+/// Rotates by `start`, then moves proxies that are cooling down to the back
+/// while keeping the rotation order otherwise.
+fn order(mut proxies: Vec<String>, start: usize, is_down: impl Fn(&str) -> bool) -> Vec<String> {
+    let len = proxies.len();
+    proxies.rotate_left(start % len);
+    proxies.sort_by_key(|proxy| is_down(proxy));
+    proxies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("socks5://p{}:1080", i)).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        assert_eq!(
+            order(proxies(3), 4, |_| false),
+            ["socks5://p1:1080", "socks5://p2:1080", "socks5://p0:1080"]
+        );
+        // Proxies cooling down are only tried last.
+        assert_eq!(
+            order(proxies(3), 0, |p| p == "socks5://p0:1080"),
+            ["socks5://p1:1080", "socks5://p2:1080", "socks5://p0:1080"]
+        );
+    }
+
+    #[test]
+    fn test_connect_response() {
+        assert!(check_connect_response(b"HTTP/1.1 200 Connection established\r\n\r\n").is_ok());
+        let err = check_connect_response(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("407"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_chain() {
+        use tokio::net::TcpListener;
+
+        // One listener plays both hops: it is asked to CONNECT to the
+        // second proxy, then (through the tunnel) to the target.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let head = read_response_head(&mut socket).await.unwrap();
+                requests.push(String::from_utf8(head).unwrap());
+                socket
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+            requests
+        });
+
+        let first = format!("http://{}", addr);
+        let hops = [first.as_str(), "http://second.test:3128"];
+        let (_, hop_ms) = connect_chain(&hops, "backend.test:25565", SocketConfig::default()).await.unwrap();
+        assert_eq!(hop_ms.len(), 2);
+        let requests = proxy.await.unwrap();
+        assert!(requests[0].starts_with("CONNECT second.test:3128 "));
+        assert!(requests[1].starts_with("CONNECT backend.test:25565 "));
+    }
+}
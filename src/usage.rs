@@ -0,0 +1,151 @@
+//! geofront/src/usage.rs
+//! Per-connection usage reports for billing: byte deltas emitted on a fixed
+//! interval and on close, each with a global sequence number.
+
+use crate::{
+    events::{self, ProxyEvent},
+    state::{
+        CONN_INFO, CONN_METRICS, LISTENER_STATE, OPTIONS, USAGE_REPORT_QUEUE, USAGE_REPORTED,
+        USAGE_REPORTER, USAGE_SEQ,
+    },
+    types::{ProxyConnection, UsageReport},
+    wakeup,
+};
+use serde_json::{Map, Value};
+use std::{sync::atomic::Ordering, time::Duration};
+use tracing::warn;
+
+/// Reports kept for polling at most; the oldest are dropped beyond this.
+const MAX_PENDING_REPORTS: usize = 65536;
+
+/// Starts, restarts or stops periodic reporting according to `interval_ms`.
+pub fn configure(interval_ms: Option<u64>) {
+    let mut reporter = USAGE_REPORTER.lock().unwrap();
+    if let Some(handle) = reporter.take() {
+        handle.abort();
+    }
+    let Some(interval_ms) = interval_ms else {
+        USAGE_REPORTED.clear();
+        return;
+    };
+    let interval = Duration::from_millis(interval_ms.max(100));
+    let runtime = LISTENER_STATE.lock().unwrap().runtime.handle().clone();
+    *reporter = Some(runtime.spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            report_all();
+        }
+    }));
+}
+
+fn enabled() -> bool {
+    OPTIONS.read().unwrap().usage_report_interval_ms.is_some()
+}
+
+/// Emits a report for every live connection that moved bytes since its last one.
+fn report_all() {
+    let totals: Vec<(ProxyConnection, u64, u64)> = CONN_METRICS
+        .iter()
+        .map(|entry| {
+            (
+                *entry.key(),
+                entry.bytes_sent.load(Ordering::SeqCst),
+                entry.bytes_recv.load(Ordering::SeqCst),
+            )
+        })
+        .collect();
+    for (conn_id, sent, recv) in totals {
+        report(conn_id, sent, recv, false, || {
+            CONN_INFO
+                .get(&conn_id)
+                .map(|info| info.tags.clone())
+                .unwrap_or_default()
+        });
+    }
+}
+
+/// Emits the final report of a closing connection. Called from `cleanup_conn`
+/// once the connection's metrics and info have been removed.
+pub fn finish(
+    conn_id: ProxyConnection,
+    total_sent: u64,
+    total_recv: u64,
+    tags: &Map<String, Value>,
+) {
+    if enabled() {
+        report(conn_id, total_sent, total_recv, true, || tags.clone());
+    }
+    USAGE_REPORTED.remove(&conn_id);
+}
+
+fn report(
+    conn_id: ProxyConnection,
+    total_sent: u64,
+    total_recv: u64,
+    is_final: bool,
+    tags: impl FnOnce() -> Map<String, Value>,
+) {
+    let (bytes_sent, bytes_recv) = {
+        let mut last = USAGE_REPORTED.entry(conn_id).or_insert((0, 0));
+        let delta = (
+            total_sent.saturating_sub(last.0),
+            total_recv.saturating_sub(last.1),
+        );
+        *last = (total_sent.max(last.0), total_recv.max(last.1));
+        delta
+    };
+    // Idle connections only get their final report.
+    if !is_final && bytes_sent == 0 && bytes_recv == 0 {
+        return;
+    }
+
+    let report = UsageReport {
+        seq: USAGE_SEQ.fetch_add(1, Ordering::SeqCst) + 1,
+        conn_id,
+        timestamp_ms: events::now_ms(),
+        bytes_sent,
+        bytes_recv,
+        total_sent,
+        total_recv,
+        is_final,
+        tags: tags(),
+    };
+
+    let dropped = wakeup::push_bounded(&mut USAGE_REPORT_QUEUE.lock().unwrap(), report.clone(), MAX_PENDING_REPORTS);
+    if dropped > 0 {
+        warn!("Usage report queue full, dropped {} oldest reports", dropped);
+    }
+    events::emit(ProxyEvent::Usage(report));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deltas_and_sequence() {
+        let conn_id = 0xfeed_0001;
+        USAGE_REPORT_QUEUE.lock().unwrap().clear();
+        report(conn_id, 100, 40, false, Map::new);
+        // Nothing moved: no report.
+        report(conn_id, 100, 40, false, Map::new);
+        report(conn_id, 250, 40, false, Map::new);
+        report(conn_id, 250, 40, true, Map::new);
+
+        let reports: Vec<UsageReport> = USAGE_REPORT_QUEUE
+            .lock()
+            .unwrap()
+            .drain(..)
+            .filter(|r| r.conn_id == conn_id)
+            .collect();
+        let deltas: Vec<_> = reports
+            .iter()
+            .map(|r| (r.bytes_sent, r.bytes_recv, r.is_final))
+            .collect();
+        assert_eq!(deltas, [(100, 40, false), (150, 0, false), (0, 0, true)]);
+        assert!(reports.windows(2).all(|w| w[1].seq > w[0].seq));
+        assert_eq!(reports[2].total_sent, 250);
+    }
+}
@@ -0,0 +1,92 @@
+//! geofront/src/vhosts.rs
+//! Virtual hosts configured through `proxy_set_vhosts`: per-hostname
+//! defaults consulted before the callbacks, so geofront can serve hosts on
+//! its own. A matching vhost's MOTD answers pings and its backend routes
+//! logins; a host without a vhost, or a vhost leaving either unset, is still
+//! asked of the MOTD handler or router. A vhost with `routerFirst` asks the
+//! router instead and keeps its backend for when the router fails or times
+//! out. Maintenance and the protocol range are enforced before any routing.
+
+use crate::{
+    state::VHOSTS,
+    static_routes::most_specific,
+    types::{MotdDecision, RouteDecision, VirtualHost},
+};
+
+/// The most specific vhost matching `host`, if any.
+pub fn lookup(host: &str) -> Option<VirtualHost> {
+    let vhosts = VHOSTS.read().unwrap();
+    most_specific(&vhosts, |vhost| &vhost.host, host).cloned()
+}
+
+/// The status response `host` is answered with, if its vhost has one.
+pub fn motd(host: &str) -> Option<MotdDecision> {
+    lookup(host)?.motd
+}
+
+/// The route logins to `host` take without asking the router, if its vhost
+/// has a backend.
+pub fn route(host: &str) -> Option<RouteDecision> {
+    lookup(host).filter(|vhost| !vhost.router_first).and_then(backend_route)
+}
+
+/// The route logins to `host` take when the router fails, if its vhost has
+/// a backend and asks the router first.
+pub fn fallback(host: &str) -> Option<RouteDecision> {
+    lookup(host).filter(|vhost| vhost.router_first).and_then(backend_route)
+}
+
+fn backend_route(vhost: VirtualHost) -> Option<RouteDecision> {
+    let backend = vhost.backend?;
+    Some(RouteDecision {
+        remote_host: Some(backend.host),
+        remote_port: Some(backend.port),
+        ..Default::default()
+    })
+}
+
+/// Why a login to its vhost is refused before routing.
+pub enum Refusal {
+    Maintenance,
+    /// The client's protocol is outside `(min, max)`.
+    Protocol(i32, i32),
+}
+
+/// Checks a login against the vhost of `host`.
+pub fn check_login(host: &str, protocol: i32) -> Option<Refusal> {
+    let vhost = lookup(host)?;
+    if vhost.maintenance {
+        return Some(Refusal::Maintenance);
+    }
+    match vhost.protocol_range {
+        Some((min, max)) if !(min..=max).contains(&protocol) => Some(Refusal::Protocol(min, max)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_router_first_backend_is_a_fallback() {
+        let vhosts: Vec<VirtualHost> = serde_json::from_str(
+            r#"[
+                {"host": "direct.vhost.test", "backend": {"host": "10.0.0.1", "port": 25565}},
+                {"host": "asked.vhost.test", "backend": {"host": "10.0.0.2", "port": 25565}, "routerFirst": true}
+            ]"#,
+        )
+        .unwrap();
+        *VHOSTS.write().unwrap() = vhosts;
+
+        let direct = route("direct.vhost.test").unwrap();
+        assert_eq!(direct.remote_host.as_deref(), Some("10.0.0.1"));
+        assert!(fallback("direct.vhost.test").is_none());
+
+        assert!(route("asked.vhost.test").is_none());
+        let fallback = fallback("asked.vhost.test").unwrap();
+        assert_eq!(fallback.remote_host.as_deref(), Some("10.0.0.2"));
+
+        VHOSTS.write().unwrap().clear();
+    }
+}
@@ -0,0 +1,195 @@
+//! geofront/src/wakeup.rs
+//! A handle hosts can wait on instead of polling the event queues in a loop
+//! (`proxy_get_event_fd`): an eventfd on Linux, the read end of a pipe on
+//! other Unix systems, an auto-reset event on Windows. It is signalled
+//! whenever one of the queues drained by `proxy_poll_events` goes from empty
+//! to non-empty, so after a wakeup the host should poll until nothing is
+//! left; on Unix it also has to read the handle to re-arm it.
+
+use crate::state::{EVENT_NOTIFY, WAKEUP};
+use std::io::Result;
+
+/// The waitable handle, created on first use.
+pub struct Wakeup {
+    /// Handed to the host.
+    wait: isize,
+    /// Written to signal it; the same as `wait` except for pipes.
+    signal: isize,
+}
+
+/// The handle to wait on, creating it on first use.
+pub fn handle() -> Result<isize> {
+    if let Some(wakeup) = WAKEUP.get() {
+        return Ok(wakeup.wait);
+    }
+    let created = sys::create()?;
+    match WAKEUP.set(created) {
+        Ok(()) => {}
+        // Another thread was first; ours is not needed.
+        Err(ours) => sys::close(&ours),
+    }
+    Ok(WAKEUP.get().map(|wakeup| wakeup.wait).unwrap_or(-1))
+}
+
+/// Waits until an event queue goes from empty to non-empty, for hosts in the
+/// same process (see `node.rs`). A wakeup sent while nobody was waiting is
+/// kept for the next call.
+pub async fn wait() {
+    EVENT_NOTIFY.notified().await;
+}
+
+/// Signals the handle, if a host asked for it, and wakes `wait`.
+pub fn notify() {
+    EVENT_NOTIFY.notify_one();
+    if let Some(wakeup) = WAKEUP.get() {
+        sys::signal(wakeup);
+    }
+}
+
+/// Pushes onto an event queue, signalling the handle when the queue was
+/// empty.
+pub fn push<T>(queue: &mut Vec<T>, item: T) {
+    let was_empty = queue.is_empty();
+    queue.push(item);
+    if was_empty {
+        notify();
+    }
+}
+
+/// `push` onto a queue holding at most `capacity` items, dropping the
+/// oldest ones to make room. Returns how many were dropped.
+pub fn push_bounded<T>(queue: &mut Vec<T>, item: T, capacity: usize) -> usize {
+    let excess = (queue.len() + 1).saturating_sub(capacity).min(queue.len());
+    queue.drain(..excess);
+    push(queue, item);
+    excess
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::Wakeup;
+    use std::io::{Error, Result};
+
+    pub fn create() -> Result<Wakeup> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(Wakeup {
+            wait: fd as isize,
+            signal: fd as isize,
+        })
+    }
+
+    pub fn signal(wakeup: &Wakeup) {
+        let one: u64 = 1;
+        // Only fails once the counter is about to overflow, which still
+        // leaves it readable.
+        unsafe {
+            libc::write(
+                wakeup.signal as libc::c_int,
+                &one as *const u64 as *const libc::c_void,
+                size_of::<u64>(),
+            )
+        };
+    }
+
+    pub fn close(wakeup: &Wakeup) {
+        unsafe { libc::close(wakeup.wait as libc::c_int) };
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod sys {
+    use super::Wakeup;
+    use std::io::{Error, Result};
+
+    pub fn create() -> Result<Wakeup> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        for fd in fds {
+            unsafe {
+                libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+        }
+        Ok(Wakeup {
+            wait: fds[0] as isize,
+            signal: fds[1] as isize,
+        })
+    }
+
+    pub fn signal(wakeup: &Wakeup) {
+        // A full pipe is readable already.
+        unsafe { libc::write(wakeup.signal as libc::c_int, [1u8].as_ptr().cast(), 1) };
+    }
+
+    pub fn close(wakeup: &Wakeup) {
+        unsafe {
+            libc::close(wakeup.wait as libc::c_int);
+            libc::close(wakeup.signal as libc::c_int);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::Wakeup;
+    use std::{
+        io::{Error, Result},
+        ptr,
+    };
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::Threading::{CreateEventW, SetEvent},
+    };
+
+    pub fn create() -> Result<Wakeup> {
+        // Auto-reset: a satisfied wait re-arms it.
+        let handle = unsafe { CreateEventW(ptr::null(), 0, 0, ptr::null()) };
+        if handle.is_null() {
+            return Err(Error::last_os_error());
+        }
+        Ok(Wakeup {
+            wait: handle as isize,
+            signal: handle as isize,
+        })
+    }
+
+    pub fn signal(wakeup: &Wakeup) {
+        unsafe { SetEvent(wakeup.signal as HANDLE) };
+    }
+
+    pub fn close(wakeup: &Wakeup) {
+        unsafe { CloseHandle(wakeup.wait as HANDLE) };
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_signals_handle() {
+        let fd = handle().unwrap() as libc::c_int;
+        let mut buf = [0u8; 8];
+        // Start from a drained handle.
+        while unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+
+        let mut queue = Vec::new();
+        push(&mut queue, 1);
+        assert!(unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) } > 0);
+        assert_eq!(handle().unwrap(), fd as isize);
+    }
+
+    #[test]
+    fn test_push_bounded_drops_oldest() {
+        let mut queue = vec![1, 2, 3];
+        assert_eq!(push_bounded(&mut queue, 4, 3), 1);
+        assert_eq!(queue, [2, 3, 4]);
+        assert_eq!(push_bounded(&mut queue, 5, 5), 0);
+        assert_eq!(queue, [2, 3, 4, 5]);
+    }
+}
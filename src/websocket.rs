@@ -0,0 +1,331 @@
+//! geofront/src/websocket.rs
+//! WebSocket listeners for browser-based clients (Eaglercraft-style): the
+//! HTTP upgrade is answered here and the client's binary frames are
+//! unwrapped into the plain Minecraft stream the rest of the pipeline reads.
+//! What geofront sends back goes out as one binary frame per write.
+//!
+//! Requires the `websocket` feature.
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Upgrade requests larger than this are refused.
+const MAX_REQUEST_BYTES: usize = 8192;
+/// Largest frame payload accepted from a client.
+const MAX_FRAME_BYTES: u64 = 1 << 20;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Fails unless geofront was built with the `websocket` feature.
+pub fn ensure_supported() -> Result<()> {
+    if cfg!(feature = "websocket") {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "WebSocket listeners require the `websocket` feature",
+        ))
+    }
+}
+
+/// Answers the client's HTTP upgrade request.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> Result<WsStream<S>> {
+    let mut buf = Vec::new();
+    let end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() >= MAX_REQUEST_BYTES {
+            return Err(invalid("upgrade request too large"));
+        }
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let request = String::from_utf8_lossy(&buf[..end]);
+    let mut lines = request.split("\r\n");
+    let is_get = lines.next().is_some_and(|line| line.starts_with("GET "));
+    let mut upgrade = false;
+    let mut key = None;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        if name.eq_ignore_ascii_case("upgrade") {
+            upgrade = value.trim().eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+            key = Some(value.trim().to_string());
+        }
+    }
+    let Some(key) = key.filter(|_| is_get && upgrade) else {
+        let _ = stream
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+        return Err(invalid("not a WebSocket upgrade request"));
+    };
+
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)?
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(WsStream::new(stream, buf.split_off(end)))
+}
+
+#[cfg(feature = "websocket")]
+fn accept_key(key: &str) -> Result<String> {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use sha1::{Digest, Sha1};
+    const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+    Ok(STANDARD.encode(hasher.finalize()))
+}
+
+#[cfg(not(feature = "websocket"))]
+fn accept_key(_key: &str) -> Result<String> {
+    ensure_supported().map(|_| String::new())
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Server side of an upgraded WebSocket connection, read and written as a
+/// plain byte stream.
+pub struct WsStream<S> {
+    inner: S,
+    /// Bytes read from the client and not decoded yet.
+    read_buf: Vec<u8>,
+    /// Payload of the last data frame, handed out from `payload_pos`.
+    payload: Vec<u8>,
+    payload_pos: usize,
+    /// Encoded frames reported as written but not sent yet.
+    pending: Vec<u8>,
+    sent: usize,
+    /// The client closed the connection.
+    eof: bool,
+    close_sent: bool,
+}
+
+impl<S> WsStream<S> {
+    /// Wraps an upgraded stream; `read_buf` holds bytes read past the
+    /// upgrade request.
+    pub fn new(inner: S, read_buf: Vec<u8>) -> Self {
+        Self {
+            inner,
+            read_buf,
+            payload: Vec::new(),
+            payload_pos: 0,
+            pending: Vec::new(),
+            sent: 0,
+            eof: false,
+            close_sent: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Takes the next complete frame off `read_buf`.
+    fn decode(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let buf = &self.read_buf;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let opcode = buf[0] & 0x0F;
+        if buf[1] & 0x80 == 0 {
+            return Err(invalid("client frames must be masked"));
+        }
+        let (len, mut pos) = match buf[1] & 0x7F {
+            126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+            127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+            126 | 127 => return Ok(None),
+            len => (len as u64, 2),
+        };
+        if len > MAX_FRAME_BYTES {
+            return Err(invalid("frame too large"));
+        }
+        let len = len as usize;
+        if buf.len() < pos + 4 + len {
+            return Ok(None);
+        }
+        let mask = [buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]];
+        pos += 4;
+        let payload = buf[pos..pos + len]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        self.read_buf.drain(..pos + len);
+        Ok(Some((opcode, payload)))
+    }
+
+    fn queue_frame(&mut self, opcode: u8, payload: &[u8]) {
+        self.pending.push(0x80 | opcode);
+        match payload.len() {
+            len @ 0..=125 => self.pending.push(len as u8),
+            len @ 126..=0xFFFF => {
+                self.pending.push(126);
+                self.pending.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                self.pending.push(127);
+                self.pending.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        self.pending.extend_from_slice(payload);
+    }
+
+    fn queue_close(&mut self, payload: &[u8]) {
+        if !self.close_sent {
+            self.close_sent = true;
+            self.queue_frame(OP_CLOSE, payload);
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> WsStream<S> {
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.sent < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.sent..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.sent += n;
+        }
+        self.pending.clear();
+        self.sent = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, out: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.payload_pos < this.payload.len() {
+                let n = out.remaining().min(this.payload.len() - this.payload_pos);
+                out.put_slice(&this.payload[this.payload_pos..this.payload_pos + n]);
+                this.payload_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+            // Pongs and close replies go out whenever the client is read.
+            if let Poll::Ready(Err(e)) = this.poll_send_pending(cx) {
+                return Poll::Ready(Err(e));
+            }
+            match this.decode()? {
+                Some((OP_CONTINUATION | OP_BINARY, payload)) => {
+                    this.payload = payload;
+                    this.payload_pos = 0;
+                }
+                Some((OP_TEXT, _)) => return Poll::Ready(Err(invalid("text frames are not supported"))),
+                Some((OP_CLOSE, payload)) => {
+                    this.queue_close(payload.get(..2).unwrap_or_default());
+                    this.eof = true;
+                    let _ = this.poll_send_pending(cx);
+                }
+                Some((OP_PING, payload)) => this.queue_frame(OP_PONG, &payload),
+                Some((OP_PONG, _)) => {}
+                Some(_) => return Poll::Ready(Err(invalid("unknown frame opcode"))),
+                None => {
+                    let mut chunk = [0u8; 4096];
+                    let mut chunk_buf = ReadBuf::new(&mut chunk);
+                    ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+                    if chunk_buf.filled().is_empty() {
+                        this.eof = true;
+                    }
+                    this.read_buf.extend_from_slice(chunk_buf.filled());
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for WsStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_pending(cx))?;
+        if this.close_sent {
+            return Poll::Ready(Err(ErrorKind::BrokenPipe.into()));
+        }
+        this.queue_frame(OP_BINARY, buf);
+        if let Poll::Ready(Err(e)) = this.poll_send_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        // Normal closure
+        this.queue_close(&1000u16.to_be_bytes());
+        ready!(this.poll_send_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_frames() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let mut stream = WsStream::new(server, client_frame(OP_BINARY, b"hand"));
+        client.write_all(&client_frame(OP_PING, b"hi")).await.unwrap();
+        client.write_all(&client_frame(OP_CONTINUATION, b"shake")).await.unwrap();
+
+        let mut buf = [0u8; 9];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"handshake");
+
+        stream.write_all(b"ok").await.unwrap();
+        let mut reply = [0u8; 6];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [0x80 | OP_PONG, 2, b'h', b'i', 0x80 | OP_BINARY, 2]);
+
+        client.write_all(&client_frame(OP_CLOSE, &[0x03, 0xE8])).await.unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455, section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==").unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}
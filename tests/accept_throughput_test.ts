@@ -0,0 +1,97 @@
+import { describe, test, expect, beforeAll, afterAll } from "bun:test";
+import { createServer, connect, type Server } from "net";
+import { Geofront } from "../src/geofront";
+import {
+  createHandshakePacket,
+  createLoginStartPacket,
+  TEST_CONSTANTS,
+  getRandomPort,
+} from "./helpers";
+
+// 以 10k conn/s 的速率持续建立连接，验证接入路径（CONN_INFO、CONN_METRICS 等按连接分片的状态）跟得上。
+// 会打开两万个套接字，默认的 `bun test` 跳过，用 `bun run bench:accept` 运行
+const BENCH_ENABLED = !!process.env.GEOFRONT_BENCH;
+const RATE_PER_SEC = 10_000;
+const DURATION_MS = 2_000;
+const TICK_MS = 10;
+const TOTAL = (RATE_PER_SEC * DURATION_MS) / 1000;
+
+describe.skipIf(!BENCH_ENABLED)("Geofront Accept Throughput", () => {
+  let proxy: Geofront.GeofrontProxy;
+  let backendServer: Server;
+  let PROXY_PORT: number;
+  let BACKEND_PORT: number;
+
+  beforeAll(async () => {
+    PROXY_PORT = getRandomPort();
+    BACKEND_PORT = getRandomPort();
+    // 后端收到登录后立即关闭，连接只经历接入、路由与清理
+    backendServer = createServer((socket) => {
+      socket.once("data", () => socket.end());
+      socket.on("error", () => {});
+    });
+    await new Promise<void>((resolve) =>
+      backendServer.listen(BACKEND_PORT, TEST_CONSTANTS.BACKEND_HOST, () => resolve())
+    );
+
+    proxy = Geofront.createProxy();
+    // 静态路由，不经过 JS 路由回调
+    proxy.setRoutes([
+      {
+        host: TEST_CONSTANTS.TEST_HOST,
+        route: { target: { host: TEST_CONSTANTS.BACKEND_HOST, port: BACKEND_PORT } },
+      },
+    ]);
+    await proxy.listen({ host: "127.0.0.1", port: PROXY_PORT, proxyProtocol: "none" });
+  });
+
+  afterAll(async () => {
+    if (proxy) {
+      await proxy.shutdown();
+    }
+    if (backendServer) {
+      backendServer.close();
+    }
+  });
+
+  test(`should accept ${TOTAL} connections opened at ${RATE_PER_SEC}/s`, async () => {
+    const hello = Buffer.concat([
+      createHandshakePacket(TEST_CONSTANTS.TEST_PROTOCOL_VERSION, TEST_CONSTANTS.TEST_HOST, PROXY_PORT, 2),
+      createLoginStartPacket(TEST_CONSTANTS.TEST_USERNAME),
+    ]);
+    const before = proxy.getMetrics().connections.total;
+    let failed = 0;
+
+    const openOne = () =>
+      new Promise<void>((resolve) => {
+        const socket = connect(PROXY_PORT, "127.0.0.1", () => socket.write(hello));
+        socket.on("data", () => {});
+        socket.on("error", () => {
+          failed++;
+        });
+        socket.on("close", () => resolve());
+      });
+
+    const started = performance.now();
+    const sockets: Promise<void>[] = [];
+    const perTick = (RATE_PER_SEC * TICK_MS) / 1000;
+    for (let tick = 0; tick < DURATION_MS / TICK_MS; tick++) {
+      for (let i = 0; i < perTick; i++) {
+        sockets.push(openOne());
+      }
+      const next = started + (tick + 1) * TICK_MS;
+      await new Promise((resolve) => setTimeout(resolve, Math.max(0, next - performance.now())));
+    }
+    await Promise.all(sockets);
+    const elapsedMs = performance.now() - started;
+
+    const accepted = proxy.getMetrics().connections.total - before;
+    const rate = (accepted / elapsedMs) * 1000;
+    console.log(
+      `[接入吞吐] 接入 ${accepted}/${TOTAL}，失败 ${failed}，耗时 ${elapsedMs.toFixed(0)}ms，${rate.toFixed(0)} conn/s`
+    );
+
+    expect(accepted).toBe(TOTAL);
+    expect(failed).toBe(0);
+  }, 30000);
+});